@@ -2,4 +2,22 @@ use crate::game::Game;
 
 pub trait Agent<G: Game> {
     fn transition(&mut self, state: G) -> G;
+
+    /// Whether the agent resigns, having just moved into `state`. Agents play on by default.
+    fn resign(&mut self, _state: &G) -> bool {
+        false
+    }
+
+    /// Whether the agent offers a draw, having just moved into `state`. The offer stands
+    /// until its opponent has replied with a move of its own.
+    fn offer_draw(&mut self, _state: &G) -> bool {
+        false
+    }
+
+    /// Whether the agent accepts the draw its opponent offered, having just replied to the
+    /// offer by moving into `state`. By default, an agent accepts a draw when it would have
+    /// offered one itself.
+    fn accept_draw(&mut self, state: &G) -> bool {
+        self.offer_draw(state)
+    }
 }
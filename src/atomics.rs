@@ -0,0 +1,146 @@
+//! Numbers that the threads of a parallel search update at once, through shared references:
+//! the statistics of the nodes of the tree they share. Every access is relaxed, as each number
+//! is a statistic of its own, and the structure of the tree, which is only changed under a lock,
+//! is ordered by the lock. On one thread, loads and stores cost what those of a plain number do.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering::Relaxed},
+};
+
+/// Defines a float over an atomic integer of its bits.
+macro_rules! float {
+    ($(#[$doc:meta])* $name:ident($atomic:ty, $float:ty)) => {
+        $(#[$doc])*
+        #[derive(Default)]
+        pub struct $name($atomic);
+
+        impl $name {
+            pub const fn new(x: $float) -> Self {
+                Self(<$atomic>::new(x.to_bits()))
+            }
+
+            pub fn get(&self) -> $float {
+                <$float>::from_bits(self.0.load(Relaxed))
+            }
+
+            pub fn set(&self, x: $float) {
+                self.0.store(x.to_bits(), Relaxed);
+            }
+
+            /// Replaces the float with `f` of it, trying again if another thread
+            /// changes it in between, so that no update is lost.
+            pub fn update(&self, f: impl Fn($float) -> $float) {
+                let _ = self.0.fetch_update(Relaxed, Relaxed, |bits| {
+                    Some(f(<$float>::from_bits(bits)).to_bits())
+                });
+            }
+        }
+    };
+}
+
+/// A count, as of the visits of a node.
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new(n: u64) -> Self {
+        Self(AtomicU64::new(n))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Relaxed)
+    }
+
+    pub fn set(&self, n: u64) {
+        self.0.store(n, Relaxed);
+    }
+
+    /// Adds `n` to the count, returning the new count.
+    pub fn add(&self, n: u64) -> u64 {
+        self.0.fetch_add(n, Relaxed) + n
+    }
+
+    /// Raises the count to `n`, if it is less.
+    pub fn raise(&self, n: u64) {
+        self.0.fetch_max(n, Relaxed);
+    }
+}
+
+/// A count that stays small, as of the iterations in flight through a node.
+#[derive(Default)]
+pub struct SmallCounter(AtomicU16);
+
+impl SmallCounter {
+    pub const fn new(n: u16) -> Self {
+        Self(AtomicU16::new(n))
+    }
+
+    pub fn get(&self) -> u16 {
+        self.0.load(Relaxed)
+    }
+
+    pub fn add(&self, n: u16) {
+        self.0.fetch_add(n, Relaxed);
+    }
+
+    pub fn sub(&self, n: u16) {
+        self.0.fetch_sub(n, Relaxed);
+    }
+}
+
+/// Gives a number type the traits that a plain number has.
+macro_rules! number_traits {
+    ($($name:ident),*) => {$(
+        impl Clone for $name {
+            fn clone(&self) -> Self {
+                Self::new(self.get())
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.get() == other.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.get().fmt(f)
+            }
+        }
+    )*};
+}
+
+float!(
+    /// An f64, as the mean value of a node.
+    AtomicF64(AtomicU64, f64)
+);
+float!(
+    /// An f32, as the mean length of the rollouts through a node.
+    AtomicF32(AtomicU32, f32)
+);
+number_traits!(Counter, SmallCounter, AtomicF64, AtomicF32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_from_many_threads_are_all_kept() {
+        let count = Counter::default();
+        let sum = AtomicF64::default();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        count.add(1);
+                        sum.update(|x| x + 0.5);
+                    }
+                });
+            }
+        });
+        assert_eq!(count.get(), 40_000);
+        assert!((sum.get() - 20_000.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+
+use crate::constants::MIN_HASH_MB;
+
+/// The memory for the search trees of the engines of one process, such as the sessions of
+/// `serve`, which is shared out between them, so that however many engines there are, they
+/// reserve no more than the total between them.
+#[derive(Debug)]
+pub struct HashBudget {
+    /// The megabytes not reserved by any engine.
+    free_mb: Mutex<usize>,
+}
+
+impl HashBudget {
+    pub fn new(total_mb: usize) -> Arc<Self> {
+        Arc::new(Self {
+            free_mb: Mutex::new(total_mb),
+        })
+    }
+
+    /// Reserves `wanted_mb` megabytes for an engine, or as many as are free if fewer are,
+    /// until the reservation is dropped. A tree smaller than [`MIN_HASH_MB`] is not worth
+    /// searching with, so if fewer than that are free, nothing is reserved, and this is `None`.
+    pub fn reserve(self: &Arc<Self>, wanted_mb: usize) -> Option<HashReservation> {
+        let mb = {
+            let mut free_mb = self.free_mb.lock().unwrap();
+            let mb = wanted_mb.min(*free_mb);
+            if mb < MIN_HASH_MB.min(wanted_mb) || mb == 0 {
+                return None;
+            }
+            *free_mb -= mb;
+            mb
+        };
+        Some(HashReservation {
+            budget: Arc::clone(self),
+            mb,
+        })
+    }
+
+    pub fn free_mb(&self) -> usize {
+        *self.free_mb.lock().unwrap()
+    }
+}
+
+/// Memory reserved from a [`HashBudget`], which is given back when this is dropped.
+#[derive(Debug)]
+pub struct HashReservation {
+    budget: Arc<HashBudget>,
+    mb: usize,
+}
+
+impl HashReservation {
+    /// The megabytes reserved, for [`Behaviour::hash_mb`](crate::mcts::Behaviour::hash_mb).
+    pub const fn mb(&self) -> usize {
+        self.mb
+    }
+}
+
+impl Drop for HashReservation {
+    fn drop(&mut self) {
+        *self.budget.free_mb.lock().unwrap() += self.mb;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_share_out_the_budget() {
+        let budget = HashBudget::new(100);
+        let first = budget.reserve(60).unwrap();
+        assert_eq!(first.mb(), 60);
+        let second = budget.reserve(60).unwrap();
+        assert_eq!(second.mb(), 40);
+        assert_eq!(budget.free_mb(), 0);
+        assert!(budget.reserve(MIN_HASH_MB).is_none());
+        drop(first);
+        assert_eq!(budget.free_mb(), 60);
+        assert_eq!(budget.reserve(10).unwrap().mb(), 10);
+        assert_eq!(budget.free_mb(), 60);
+        drop(second);
+        assert_eq!(budget.free_mb(), 100);
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    game::{Game, MoveBuffer},
+    priors,
+};
+
+/// What the searches of one position have found.
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    /// The win rate of the side to move, averaged over the searches by their visits.
+    value: f64,
+    /// The visits of each root move, by name, summed over the searches.
+    visits: Vec<(String, u64)>,
+}
+
+impl Entry {
+    fn total_visits(&self) -> u64 {
+        self.visits.iter().map(|&(_, visits)| visits).sum()
+    }
+}
+
+/// A record on disk of the searches of positions, keyed by position hash, which lets the
+/// searches of later sessions start from what earlier ones found: the visits kept for a
+/// position become the priors of its root moves. Every search adds its visits to those
+/// already kept, so that the cache of a game grows into a book of all the positions it
+/// has searched. A cache holds the positions of one game, as those of different games
+/// can share a hash.
+///
+/// The file format is one position per line:
+/// `<hash in hex> <value> <move>:<visits> <move>:<visits> ...`
+/// where the value is the win rate of the side to move, and moves are written the way
+/// the game displays them. Blank lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisCache {
+    path: PathBuf,
+    entries: HashMap<u64, Entry>,
+}
+
+impl AnalysisCache {
+    /// Opens the cache at `path`, which is empty if there is no file there yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match File::open(&path) {
+            Ok(file) => read_entries(BufReader::new(file))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of positions in the cache.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The visits that earlier searches gave `state`, and the win rate they found for
+    /// the side to move, if it has been searched.
+    pub fn lookup<G: Game>(&self, state: &G) -> Option<(u64, f64)> {
+        let entry = self.entries.get(&state.hash())?;
+        Some((entry.total_visits(), entry.value))
+    }
+
+    /// Returns the priors over the legal moves of `state`, in move generation order, in
+    /// proportion to the visits earlier searches gave them, plus one, so that a move that
+    /// they never tried is still tried. Returns `None` if the position is not in the cache.
+    pub fn root_priors<G: Game>(&self, state: &G) -> Option<Vec<f32>> {
+        let entry = self.entries.get(&state.hash())?;
+        #[allow(clippy::cast_precision_loss)]
+        priors::over_legal_moves(
+            state,
+            entry
+                .visits
+                .iter()
+                .map(|(name, visits)| (name.as_str(), *visits as f32 + 1.0)),
+        )
+    }
+
+    /// Adds a search of `state` to the cache, which gave the legal moves `visits`, in
+    /// move generation order, and found the win rate `value` for the side to move.
+    pub fn record<G: Game>(&mut self, state: &G, visits: &[u64], value: f64) {
+        let mut moves = G::Buffer::default();
+        state.legal_moves(&mut moves);
+        let entry = self.entries.entry(state.hash()).or_insert_with(|| Entry {
+            value,
+            visits: Vec::new(),
+        });
+        let old = entry.total_visits();
+        let new = visits.iter().sum::<u64>();
+        if old + new > 0 {
+            #[allow(clippy::cast_precision_loss)]
+            let (old, new) = (old as f64, new as f64);
+            entry.value = entry.value.mul_add(old, value * new) / (old + new);
+        }
+        for (m, &v) in moves.iter().zip(visits) {
+            let name = m.to_string();
+            match entry.visits.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, total)) => *total = total.saturating_add(v),
+                None => entry.visits.push((name, v)),
+            }
+        }
+    }
+
+    /// Writes the cache back to its file, replacing it only once it is fully written.
+    pub fn save(&self) -> io::Result<()> {
+        let partial = self.path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        self.write_to(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(partial, &self.path)
+    }
+
+    /// Writes the cache to the given `io::Write`, in order of hash, so that caches with
+    /// the same positions are written alike.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut hashes = self.entries.keys().copied().collect::<Vec<_>>();
+        hashes.sort_unstable();
+        for hash in hashes {
+            let entry = &self.entries[&hash];
+            write!(writer, "{hash:x} {}", entry.value)?;
+            for (name, visits) in &entry.visits {
+                write!(writer, " {name}:{visits}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_entries<R: BufRead>(reader: R) -> io::Result<HashMap<u64, Entry>> {
+    let mut entries = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |msg: &str| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid analysis cache, {msg} on line {}: {line}", i + 1),
+            )
+        };
+        let mut words = line.split_whitespace();
+        let hash = words.next().ok_or_else(|| invalid("no hash"))?;
+        let hash = u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+            .map_err(|_| invalid("could not parse hash"))?;
+        let value = words
+            .next()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| (0.0..=1.0).contains(value))
+            .ok_or_else(|| invalid("expected a value from 0 to 1"))?;
+        let mut visits = Vec::new();
+        for word in words {
+            let (m, n) = word
+                .split_once(':')
+                .ok_or_else(|| invalid("no colon separator in move visits"))?;
+            let n = n
+                .parse::<u64>()
+                .map_err(|_| invalid("could not parse visits"))?;
+            visits.push((m.to_string(), n));
+        }
+        entries.insert(hash, Entry { value, visits });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::connectfour::Connect4;
+
+    #[test]
+    fn searches_accumulate_and_round_trip() {
+        let path = std::env::temp_dir().join(format!("iridium-cache-{}.txt", std::process::id()));
+        let state = Connect4::new();
+        let mut cache = AnalysisCache::open(&path).unwrap();
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.root_priors(&state), None);
+        cache.record(&state, &[0, 0, 1, 5, 1, 0, 0], 0.5);
+        cache.record(&state, &[0, 0, 0, 7, 0, 0, 0], 0.75);
+        assert_eq!(cache.lookup(&state), Some((14, 0.625)));
+        // a move that was never visited keeps a share of one visit.
+        let priors = cache.root_priors(&state).unwrap();
+        assert_eq!(
+            priors,
+            [1.0, 1.0, 2.0, 13.0, 2.0, 1.0, 1.0].map(|w| w / 21.0)
+        );
+        cache.save().unwrap();
+        let reopened = AnalysisCache::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened, cache);
+        assert!(read_entries(&b"ab 2 4:1\n"[..]).is_err());
+        assert!(read_entries(&b"ab 0.5 4=1\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+/// Predicted win rates and actual results of one bin of a [`Calibration`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Bin {
+    predicted: f64,
+    actual: f64,
+    count: u32,
+}
+
+/// A comparison of the win rates predicted by search against the results of the games,
+/// with predictions grouped into equal-width bins.
+///
+/// A well-calibrated search wins about 70% of the positions it rates at 70%.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    bins: Vec<Bin>,
+    squared_error: f64,
+}
+
+impl Calibration {
+    pub fn new(bins: usize) -> Self {
+        assert!(bins > 0, "calibration needs at least one bin");
+        Self {
+            bins: vec![Bin::default(); bins],
+            squared_error: 0.0,
+        }
+    }
+
+    /// Records a position rated at `predicted` whose game scored `actual` (1, 0.5, or 0).
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn add(&mut self, predicted: f64, actual: f64) {
+        let predicted = predicted.clamp(0.0, 1.0);
+        let n = self.bins.len();
+        let index = ((predicted * n as f64) as usize).min(n - 1);
+        let bin = &mut self.bins[index];
+        bin.predicted += predicted;
+        bin.actual += actual;
+        bin.count += 1;
+        self.squared_error += (predicted - actual).powi(2);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.bins.iter().map(|bin| bin.count).sum()
+    }
+
+    /// The mean squared difference between predictions and results.
+    pub fn brier_score(&self) -> f64 {
+        self.squared_error / f64::from(self.count().max(1))
+    }
+
+    /// The expected calibration error: the gap between the mean prediction and the
+    /// mean result of each bin, weighted by the number of positions in the bin.
+    pub fn expected_error(&self) -> f64 {
+        let total = f64::from(self.count().max(1));
+        self.bins
+            .iter()
+            .filter(|bin| bin.count > 0)
+            .map(|bin| (bin.predicted - bin.actual).abs() / total)
+            .sum()
+    }
+}
+
+impl Display for Calibration {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let width = 1.0 / self.bins.len() as f64;
+        writeln!(
+            f,
+            "   predicted | positions | mean predicted | mean result |    gap"
+        )?;
+        for (i, bin) in self.bins.iter().enumerate() {
+            let low = i as f64 * width;
+            write!(f, "{low:.2} - {:.2} | {:>9} |", low + width, bin.count)?;
+            if bin.count == 0 {
+                writeln!(f, "              - |           - |      -")?;
+                continue;
+            }
+            let count = f64::from(bin.count);
+            let predicted = bin.predicted / count;
+            let actual = bin.actual / count;
+            writeln!(
+                f,
+                " {predicted:>14.3} | {actual:>11.3} | {:>+6.3}",
+                actual - predicted
+            )?;
+        }
+        writeln!(f, "positions: {}", self.count())?;
+        writeln!(f, "Brier score: {:.4}", self.brier_score())?;
+        write!(
+            f,
+            "expected calibration error: {:.4}",
+            self.expected_error()
+        )
+    }
+}
+
+/// Reads a dataset's value file, as written by data generation:
+/// one `predicted,actual` pair per line.
+pub fn read_values(path: &Path) -> io::Result<Vec<(f64, f64)>> {
+    let text = fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let parse = |field: Option<&str>| field.and_then(|v| v.trim().parse::<f64>().ok());
+            let mut fields = line.split(',');
+            parse(fields.next())
+                .zip(parse(fields.next()))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}:{}: expected `predicted,actual`: {line}",
+                            path.display(),
+                            i + 1
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predictions_are_binned() {
+        let mut calibration = Calibration::new(4);
+        calibration.add(0.9, 1.0);
+        calibration.add(0.8, 0.0);
+        calibration.add(0.1, 0.0);
+        calibration.add(1.0, 1.0);
+        assert_eq!(calibration.count(), 4);
+        assert_eq!(calibration.bins[0].count, 1);
+        assert_eq!(calibration.bins[3].count, 3);
+        assert!((calibration.brier_score() - (0.01 + 0.64 + 0.01) / 4.0).abs() < 1e-9);
+        // the top bin predicts 0.9 on average, and scores 2/3.
+        let gap = (0.9 - 2.0 / 3.0) * 3.0 / 4.0 + 0.1 / 4.0;
+        assert!((calibration.expected_error() - gap).abs() < 1e-9);
+        let table = calibration.to_string();
+        assert!(table.contains("0.75 - 1.00 |         3 |"), "{table}");
+    }
+}
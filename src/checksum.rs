@@ -0,0 +1,102 @@
+use std::io::{self, Read, Write};
+
+/// The CRC-32 lookup table, for the reflected IEEE polynomial used by zlib and PNG.
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0u32;
+    while i < 256 {
+        let mut crc = i;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i as usize] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A CRC-32 of bytes that arrive in pieces.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { state: u32::MAX }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state =
+                TABLE[((self.state ^ u32::from(byte)) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    pub const fn finish(self) -> u32 {
+        !self.state
+    }
+}
+
+/// The CRC-32 of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// A reader or writer that keeps a CRC-32 of everything that passes through it.
+pub struct Hashing<T> {
+    pub inner: T,
+    pub crc: Crc32,
+}
+
+impl<T> Hashing<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        let mut hashing = Hashing::new(Vec::new());
+        hashing.write_all(b"1234").unwrap();
+        hashing.write_all(b"56789").unwrap();
+        assert_eq!(hashing.crc.finish(), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    game::Game,
+    mcts::{self, Behaviour, Limit, QualityScaling, RolloutPolicy},
+    ucb::SelectionPolicy,
+};
+
+/// The directory that training data is written to if none is configured.
+pub const DEFAULT_DATA_DIR: &str = "datasets";
+
+/// User configuration, read at startup from `$XDG_CONFIG_HOME/iridium/config.toml`
+/// (or `~/.config/iridium/config.toml`), and overridable by command-line flags.
+///
+/// The file is a flat list of `key = value` lines, with `#` comments:
+/// ```toml
+/// limit = "time:5000"          # "rollouts:<n>", "time:<ms>", or "infinite"
+/// rollout_policy = "decisive"
+/// quality_scaling = "exp:0.04" # "exp:<rate>", "linear:<rate>", or "none", for the *_quality_scaled policies
+/// selection = "puct"          # "ucb1", "ucb1_tuned", or "puct", the formula that scores children
+/// exp_factor = 1.41            # the exploration constant of ucb1
+/// cpuct_base = 1.8             # the exploration constant is cpuct_factor * ln((N + cpuct_base + 1) / cpuct_base)
+/// cpuct_factor = 1.0
+/// expansion_threshold = 8      # visits before a leaf is expanded
+/// fpu_reduction = 0.3          # value unvisited children below their parent, rather than trying each first
+/// root_symmetry = true         # search one of each class of symmetric root moves
+/// root_pruning = true          # stop searching root moves that cannot catch up in time
+/// tactical_expansion = true    # prove immediate wins on expansion, and expand forced lines at once
+/// transpositions = true        # share statistics between the nodes of the same position
+/// hash = 512                   # node pool size, in megabytes
+/// threads = 1                  # threads searching the tree together
+/// data_dir = "/data/iridium"
+/// cp_scale = 400               # for converting win rates into centipawn scores
+/// deterministic = false        # one thread and a fixed seed, for reproducible searches
+/// json_info = false            # write search output as JSON lines
+/// ```
+/// Every key is optional. The rule variant to play is not a key, as it depends on the game:
+/// it is given with `--variant`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub limit: Option<Limit>,
+    pub rollout_policy: Option<RolloutPolicy>,
+    pub quality_scaling: Option<QualityScaling>,
+    pub selection: Option<SelectionPolicy>,
+    pub exp_factor: Option<f32>,
+    pub cpuct_base: Option<f32>,
+    pub cpuct_factor: Option<f32>,
+    pub expansion_threshold: Option<u32>,
+    pub fpu_reduction: Option<f32>,
+    pub root_symmetry: Option<bool>,
+    pub root_pruning: Option<bool>,
+    pub tactical_expansion: Option<bool>,
+    pub transpositions: Option<bool>,
+    pub hash_mb: Option<usize>,
+    pub threads: Option<usize>,
+    pub data_dir: Option<PathBuf>,
+    pub cp_scale: Option<f64>,
+    pub deterministic: Option<bool>,
+    pub json_info: Option<bool>,
+    /// The rule variant of the game, one of [`Game::VARIANTS`], or `None` for the standard rules.
+    pub variant: Option<String>,
+}
+
+impl Config {
+    /// The default location of the config file, if a home directory can be found.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("iridium").join("config.toml"))
+    }
+
+    /// Loads the config file at `path`. A missing file is treated as an empty config.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse().map_err(|e: String| {
+                io::Error::new(ErrorKind::InvalidData, format!("{}: {e}", path.display()))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Loads the config file, then removes any recognised flags from `args` and applies
+    /// them on top. `--config <path>` selects a different config file, and
+    /// `--hash <mb>`, `--threads <n>`, `--data-dir <dir>`, `--deterministic`, and `--json-info`
+    /// override the file's values, and `--variant <name>` selects a rule variant.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut path = Self::default_path();
+        let mut overrides = Vec::new();
+        let mut variant = None;
+        let mut i = 0;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            if matches!(flag, "--deterministic" | "--json-info") {
+                overrides.push((
+                    flag.trim_start_matches("--").replace('-', "_"),
+                    "true".to_string(),
+                ));
+                args.remove(i);
+                continue;
+            }
+            if !matches!(
+                flag,
+                "--config" | "--hash" | "--threads" | "--data-dir" | "--variant"
+            ) {
+                i += 1;
+                continue;
+            }
+            let value = args
+                .get(i + 1)
+                .cloned()
+                .ok_or_else(|| format!("no value given for {flag}"))?;
+            if flag == "--config" {
+                path = Some(PathBuf::from(value));
+            } else if flag == "--variant" {
+                variant = Some(value);
+            } else {
+                overrides.push((flag.trim_start_matches("--").replace('-', "_"), value));
+            }
+            args.drain(i..i + 2);
+        }
+        let mut config = match path {
+            Some(path) => Self::load(path).map_err(|e| e.to_string())?,
+            None => Self::default(),
+        };
+        for (key, value) in overrides {
+            config.set(&key, &value)?;
+        }
+        config.variant = variant;
+        Ok(config)
+    }
+
+    /// Sets the option named by `key` from its unquoted textual value.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "limit" => self.limit = Some(value.parse()?),
+            "rollout_policy" => self.rollout_policy = Some(value.parse()?),
+            "quality_scaling" => self.quality_scaling = Some(value.parse()?),
+            "selection" => self.selection = Some(value.parse()?),
+            "exp_factor" => {
+                self.exp_factor = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("could not parse exp_factor: {value}"))?,
+                );
+            }
+            "cpuct_base" => self.cpuct_base = Some(mcts::parse_cpuct_base(value)?),
+            "cpuct_factor" => self.cpuct_factor = Some(mcts::parse_cpuct_factor(value)?),
+            "expansion_threshold" => {
+                self.expansion_threshold = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("could not parse expansion_threshold: {value}"))?,
+                );
+            }
+            "fpu_reduction" => self.fpu_reduction = Some(mcts::parse_fpu_reduction(value)?),
+            "root_symmetry" => {
+                self.root_symmetry = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("root_symmetry must be true or false: {value}"))?,
+                );
+            }
+            "root_pruning" => {
+                self.root_pruning = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("root_pruning must be true or false: {value}"))?,
+                );
+            }
+            "tactical_expansion" => {
+                self.tactical_expansion =
+                    Some(value.parse().map_err(|_| {
+                        format!("tactical_expansion must be true or false: {value}")
+                    })?);
+            }
+            "transpositions" => {
+                self.transpositions = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("transpositions must be true or false: {value}"))?,
+                );
+            }
+            "deterministic" => {
+                self.deterministic = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("deterministic must be true or false: {value}"))?,
+                );
+            }
+            "json_info" => {
+                self.json_info = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("json_info must be true or false: {value}"))?,
+                );
+            }
+            "hash" => self.hash_mb = Some(positive(key, value)?),
+            "threads" => self.threads = Some(positive(key, value)?),
+            "data_dir" => self.data_dir = Some(PathBuf::from(value)),
+            "cp_scale" => self.cp_scale = Some(parse_cp_scale(value)?),
+            _ => return Err(format!("unknown config key: {key}")),
+        }
+        Ok(())
+    }
+
+    /// Applies every configured search option to `behaviour`.
+    pub fn apply(&self, behaviour: &mut Behaviour) {
+        if let Some(limit) = self.limit {
+            behaviour.limit = limit;
+        }
+        if let Some(rollout_policy) = &self.rollout_policy {
+            behaviour.rollout_policy = rollout_policy.clone();
+        }
+        if let Some(quality_scaling) = self.quality_scaling {
+            behaviour.quality_scaling = quality_scaling;
+        }
+        if let Some(selection) = self.selection {
+            behaviour.selection = selection;
+        }
+        if let Some(exp_factor) = self.exp_factor {
+            behaviour.exp_factor = exp_factor;
+        }
+        if let Some(cpuct_base) = self.cpuct_base {
+            behaviour.cpuct_base = cpuct_base;
+        }
+        if let Some(cpuct_factor) = self.cpuct_factor {
+            behaviour.cpuct_factor = cpuct_factor;
+        }
+        if let Some(expansion_threshold) = self.expansion_threshold {
+            behaviour.expansion_threshold = expansion_threshold;
+        }
+        if let Some(fpu_reduction) = self.fpu_reduction {
+            behaviour.fpu_reduction = Some(fpu_reduction);
+        }
+        if let Some(root_symmetry) = self.root_symmetry {
+            behaviour.root_symmetry = root_symmetry;
+        }
+        if let Some(root_pruning) = self.root_pruning {
+            behaviour.root_pruning = root_pruning;
+        }
+        if let Some(tactical_expansion) = self.tactical_expansion {
+            behaviour.tactical_expansion = tactical_expansion;
+        }
+        if let Some(transpositions) = self.transpositions {
+            behaviour.transpositions = transpositions;
+        }
+        if let Some(cp_scale) = self.cp_scale {
+            behaviour.cp_scale = cp_scale;
+        }
+        if let Some(json_info) = self.json_info {
+            behaviour.json_info = json_info;
+        }
+        self.apply_resources(behaviour);
+    }
+
+    /// Applies only the resource options (hash, threads, and determinism) to `behaviour`,
+    /// for callers that choose the search parameters themselves.
+    /// Deterministic searches are always single-threaded.
+    pub const fn apply_resources(&self, behaviour: &mut Behaviour) {
+        if let Some(hash_mb) = self.hash_mb {
+            behaviour.hash_mb = hash_mb;
+        }
+        if let Some(threads) = self.threads {
+            behaviour.threads = threads;
+        }
+        if let Some(deterministic) = self.deterministic {
+            behaviour.deterministic = deterministic;
+        }
+        if behaviour.deterministic {
+            behaviour.threads = 1;
+        }
+    }
+
+    /// The starting position of the configured variant of `G`.
+    pub fn start<G: Game>(&self) -> Result<G, String> {
+        self.variant
+            .as_deref()
+            .map_or_else(|| Ok(G::default()), G::with_variant)
+    }
+
+    /// The directory that training data is written to.
+    pub fn data_dir(&self) -> &Path {
+        self.data_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new(DEFAULT_DATA_DIR))
+    }
+}
+
+/// Parses a centipawn scale, which must be positive and finite.
+pub fn parse_cp_scale(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|&scale| scale.is_finite() && scale > 0.0)
+        .ok_or_else(|| format!("cp_scale must be a positive number: {value}"))
+}
+
+fn positive(key: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("{key} must be a positive integer: {value}"))
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            // strip comments, taking care not to cut a quoted value short.
+            let line = match line.find('#') {
+                Some(hash) if line[..hash].matches('"').count() % 2 == 0 => &line[..hash],
+                _ => line,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`: {line}", i + 1))?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            config
+                .set(key.trim(), value)
+                .map_err(|e| format!("line {}: {e}", i + 1))?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::games::{connectfour::Connect4, gomoku::Gomoku};
+
+    #[test]
+    fn config_file_is_parsed() {
+        let text = "# engine defaults\n\nlimit = \"time:500\"\nhash = 64 # megabytes\nthreads=2\ncpuct_factor = 2.5\nfpu_reduction = 0.3\ndata_dir = \"/tmp/#data\"\n";
+        let config = text.parse::<Config>().unwrap();
+        assert_eq!(config.limit, Some(Limit::Time(Duration::from_millis(500))));
+        assert_eq!(config.hash_mb, Some(64));
+        assert_eq!(config.threads, Some(2));
+        assert_eq!(config.cpuct_factor, Some(2.5));
+        assert_eq!(config.fpu_reduction, Some(0.3));
+        assert_eq!(config.data_dir(), Path::new("/tmp/#data"));
+        assert_eq!(config.rollout_policy, None);
+    }
+
+    #[test]
+    fn bad_config_is_rejected() {
+        assert!("colour = \"blue\"".parse::<Config>().is_err());
+        assert!("hash = 0".parse::<Config>().is_err());
+        assert!("cpuct_base = 0".parse::<Config>().is_err());
+        assert!("selection = \"ucb2\"".parse::<Config>().is_err());
+        assert!("fpu_reduction = -0.5".parse::<Config>().is_err());
+        assert!("threads".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn flags_override_and_are_removed() {
+        let mut args = [
+            "iridium",
+            "--config",
+            "/nonexistent/config.toml",
+            "match",
+            "--hash",
+            "16",
+            "connect4",
+            "--deterministic",
+            "--threads",
+            "4",
+            "--json-info",
+            "--variant",
+            "misere",
+        ]
+        .map(String::from)
+        .to_vec();
+        let config = Config::from_args(&mut args).unwrap();
+        assert_eq!(args, ["iridium", "match", "connect4"]);
+        assert_eq!(config.hash_mb, Some(16));
+        assert_eq!(config.deterministic, Some(true));
+        assert_eq!(config.json_info, Some(true));
+        assert_eq!(config.variant.as_deref(), Some("misere"));
+        assert_ne!(config.start::<Connect4>().unwrap(), Connect4::new());
+        assert!(config.start::<Gomoku<9>>().is_err());
+        let mut behaviour = Behaviour::default();
+        config.apply_resources(&mut behaviour);
+        assert!(behaviour.deterministic);
+        assert_eq!(behaviour.threads, 1);
+    }
+}
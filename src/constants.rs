@@ -1,9 +1,44 @@
+use std::time::Duration;
+
 pub const TREE_PRINT_DEPTH: usize = 2;
-pub const MAX_NODEPOOL_MEM: usize = 2 * 1024 * 1024 * 1024; // 2GB
+pub const DEFAULT_HASH_MB: usize = 2 * 1024; // 2GB
+/// The smallest node pool chosen for a game by [`MCTSExt::hash_mb`](crate::mcts::MCTSExt::hash_mb),
+/// which chooses none larger than [`DEFAULT_HASH_MB`].
+pub const MIN_HASH_MB: usize = 16;
+/// The number of rollouts a default-sized node pool has room for.
+pub const HASH_SIZING_ROLLOUTS: usize = 2_000_000;
 pub const ROOT_IDX: usize = 0;
-
-pub const INF: i32 = i32::max_value();
-pub const N_INF: i32 = -INF;
+/// The temperature at which training games sample their moves from the visits of the root
+/// moves: at one, in proportion to the visits, and nearer zero, closer to the most-visited.
+pub const TRAINING_TEMPERATURE: f64 = 1.0;
+/// The random seed used by every search in deterministic mode.
+pub const DETERMINISTIC_SEED: u64 = 0x1D1D_1D1D;
 
 pub const DEFAULT_EXP_FACTOR: f32 = std::f32::consts::SQRT_2;
+/// The visit count at which the PUCT exploration constant starts to grow, see [`ucb::Cpuct`](crate::ucb::Cpuct).
+pub const DEFAULT_CPUCT_BASE: f32 = 1.8;
+/// The scale of the PUCT exploration constant, see [`ucb::Cpuct`](crate::ucb::Cpuct).
+pub const DEFAULT_CPUCT_FACTOR: f32 = 1.0;
+/// The rate at which quality-scaled rollout results decay with the length of the rollout.
+pub const DEFAULT_QUALITY_DECAY: f32 = 0.04;
+/// The longest a rollout may run, in plies, unless a game or configuration says otherwise.
+pub const DEFAULT_MAX_ROLLOUT_PLIES: usize = 100_000;
 pub const NODE_UNVISITED_VALUE: f32 = f32::MAX;
+/// How often, in rollouts, a search checks for commands on stdin and for a due autosave.
+pub const SEARCH_CHECK_INTERVAL: u64 = 1024;
+/// The most threads a search may use, as virtual losses are counted in 16 bits.
+pub const MAX_THREADS: usize = 1024;
+/// How often the main thread of a parallel search wakes to check for commands on stdin,
+/// print the readout, and autosave, while the worker threads search.
+pub const PARALLEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the tree of a search is saved, if autosaving is enabled.
+pub const DEFAULT_AUTOSAVE_MINUTES: u64 = 5;
+/// The port that `serve` listens on if none is given.
+pub const DEFAULT_SERVE_PORT: u16 = 7878;
+/// The largest fraction of resignations that may be wrong, for a threshold that `resign` recommends.
+pub const DEFAULT_RESIGN_MAX_WRONG: f64 = 0.05;
+/// The fewest visits, as a fraction of the most-visited move's, that a move needs to be
+/// chosen by lower confidence bound.
+pub const LCB_MIN_VISIT_FRACTION: f64 = 0.1;
+/// The number of candidate moves the readout of a move played against a human shows.
+pub const READOUT_CANDIDATES: usize = 3;
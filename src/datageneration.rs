@@ -1,13 +1,18 @@
 use std::{
     fmt::{self, Debug, Display, Formatter},
-    fs::File,
+    fs::{File, OpenOptions},
+    io,
     ops::Add,
+    path::{Path, PathBuf},
 };
 
 use crate::{
+    dataset,
     game::{Game, MoveBuffer},
     gamerunner::GameRunner,
+    interrupt,
     mcts::{self, Behaviour, SearchResults, MCTS, Limit},
+    record::{GameRecord, GameRecordEntry, GameRecordHeader},
 };
 
 /// A bitvector representation of a single game state.
@@ -23,6 +28,10 @@ pub struct PolicyVector {
 pub struct Entry {
     pub outcome: i8,
     pub move_count: u32,
+    /// The side to move, 1 for the first player and -1 for the second.
+    pub turn: i8,
+    /// The win rate predicted by the search, from the perspective of the side to move.
+    pub win_rate: f64,
     pub state: StateVector,
     pub policy: PolicyVector,
 }
@@ -55,6 +64,14 @@ impl Debug for Entry {
     }
 }
 
+impl Entry {
+    /// The result of the game from the perspective of the side to move:
+    /// 1 for a win, 0.5 for a draw, and 0 for a loss.
+    pub fn score(&self) -> f64 {
+        f64::from(self.outcome * self.turn).midpoint(1.0)
+    }
+}
+
 /// Display-wrapper for Entry that prints the input for prediction.
 struct X<'a>(&'a Entry);
 
@@ -71,6 +88,40 @@ impl Display for X<'_> {
     }
 }
 
+/// Display-wrapper for Entry that prints the predicted win rate and the actual result,
+/// both from the perspective of the side to move.
+struct V<'a>(&'a Entry);
+
+impl Display for V<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4},{}", self.0.win_rate, self.0.score())
+    }
+}
+
+/// The header of one game in a dataset.
+pub struct GameHeader {
+    /// The moves from the initial position to the position the game started from.
+    pub opening: Vec<String>,
+    /// The number of entries the game contributed.
+    pub entries: usize,
+    pub outcome: i8,
+    /// Whether the game was an evaluation game, played greedily rather than for training.
+    pub evaluation: bool,
+}
+
+impl Display for GameHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let kind = if self.evaluation { "eval" } else { "train" };
+        write!(
+            f,
+            "{},{},{kind},{}",
+            self.outcome,
+            self.entries,
+            self.opening.join(" ")
+        )
+    }
+}
+
 /// Display-wrapper for Entry that prints the output for prediction.
 struct Y<'a>(&'a Entry);
 
@@ -97,7 +148,7 @@ pub trait VectoriseState: Game {
     fn policy_vector(&self, policy: &[f64]) -> PolicyVector {
         let mut out = vec![0.0; Self::action_space()];
         let mut buf = Self::Buffer::default();
-        self.generate_moves(&mut buf);
+        self.legal_moves(&mut buf);
         assert_eq!(policy.len(), buf.len());
         for (i, &m) in buf.iter().enumerate() {
             let index = Self::index_move(m);
@@ -113,23 +164,70 @@ pub trait VectoriseState: Game {
 //       be done in training and not generation or saving.
 pub struct GameData {
     pub entries: Vec<Entry>,
+    /// One header for each game, in the order their entries appear.
+    pub games: Vec<GameHeader>,
+    /// Each game as a binary game record, with the rollouts of every move and the move played.
+    pub records: Vec<GameRecord>,
     pub state_dimensions: Vec<usize>,
     pub action_space: usize,
 }
 
 impl GameData {
-    pub fn save<G: VectoriseState>(&self, id: &str) -> Result<(), std::io::Error> {
+    /// Writes the data to the dataset named by `prefix` (see [`dataset_paths`]),
+    /// creating its directory if needed. Existing files are only overwritten if `force` is set.
+    pub fn save<G: VectoriseState>(
+        &self,
+        prefix: &Path,
+        force: bool,
+    ) -> Result<(), std::io::Error> {
         use std::io::Write;
-        std::fs::create_dir_all("datasets")?;
-        let mut file_x = File::create(format!("datasets/{id}-x.txt"))?;
-        let mut file_y = File::create(format!("datasets/{id}-y.txt"))?;
+        if let Some(dir) = prefix.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let [path_x, path_y, path_v, path_g] = dataset_paths(prefix);
+        let open = |path: &Path| {
+            if force {
+                File::create(path)
+            } else {
+                // create_new fails if the file appeared since we checked, e.g. on shared storage.
+                OpenOptions::new().write(true).create_new(true).open(path)
+            }
+        };
+        let mut file_x = open(&path_x)?;
+        let mut file_y = open(&path_y)?;
+        let mut file_v = open(&path_v)?;
+        let mut file_g = open(&path_g)?;
         for entry in &self.entries {
             writeln!(file_x, "{}", X(entry))?;
             writeln!(file_y, "{}", Y(entry))?;
+            writeln!(file_v, "{}", V(entry))?;
+        }
+        for game in &self.games {
+            writeln!(file_g, "{game}")?;
         }
         Ok(())
     }
 
+    /// Writes every game as a binary game record to the file at `path`, with
+    /// [`dataset::Writer`], returning the number of games written. The file is only
+    /// overwritten if `force` is set.
+    pub fn save_records(&self, path: &Path, force: bool) -> io::Result<u64> {
+        if !force && path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                ),
+            ));
+        }
+        let mut writer = dataset::Writer::create(path)?;
+        for record in &self.records {
+            writer.write(record)?;
+        }
+        writer.finish()
+    }
+
     pub fn summary(&self) {
         #[allow(clippy::cast_precision_loss)]
         let len_float = self.entries.len() as f64;
@@ -142,14 +240,169 @@ impl GameData {
                     .sum::<i32>()
             ) / len_float
         );
+        let evaluations = self.games.iter().filter(|g| g.evaluation).collect::<Vec<_>>();
+        if !evaluations.is_empty() {
+            #[allow(clippy::cast_precision_loss)]
+            let count = evaluations.len() as f64;
+            let outcome = evaluations.iter().map(|g| f64::from(g.outcome)).sum::<f64>() / count;
+            #[allow(clippy::cast_precision_loss)]
+            let length = evaluations.iter().map(|g| g.entries).sum::<usize>() as f64 / count;
+            println!(
+                "{} evaluation games: mean outcome {outcome:.3}, mean length {length:.1} moves",
+                evaluations.len()
+            );
+        }
+    }
+}
+
+/// The paths of the input, target, value, and game files of the dataset named by `prefix`,
+/// i.e. `<prefix>-x.txt`, `<prefix>-y.txt`, `<prefix>-v.txt`, and `<prefix>-g.txt`.
+///
+/// Each line of the value file holds the search's predicted win rate and the actual
+/// result of the game (1, 0.5, or 0), both for the side to move.
+/// Each line of the game file is the header of one game: its outcome, its number of
+/// entries, `eval` for an evaluation game or `train` otherwise, and the space-separated
+/// moves of its opening (empty for the initial position).
+pub fn dataset_paths(prefix: &Path) -> [PathBuf; 4] {
+    ["-x.txt", "-y.txt", "-v.txt", "-g.txt"].map(|suffix| {
+        let mut path = prefix.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    })
+}
+
+/// Works out the prefix a dataset is written to.
+///
+/// With a `name`, the dataset goes into the directory `out` (or `data_dir`, if `out` is not given).
+/// Without one, `out` is itself the prefix, and so must not name a directory.
+pub fn output_prefix(
+    out: Option<&Path>,
+    data_dir: &Path,
+    name: Option<&str>,
+) -> Result<PathBuf, String> {
+    match (out, name) {
+        (out, Some(name)) => Ok(out.unwrap_or(data_dir).join(name)),
+        (Some(out), None) => {
+            let is_dir = out.is_dir()
+                || out
+                    .as_os_str()
+                    .to_string_lossy()
+                    .ends_with(std::path::MAIN_SEPARATOR);
+            if is_dir {
+                Err(format!(
+                    "{} is a directory, so a dataset name is needed",
+                    out.display()
+                ))
+            } else {
+                Ok(out.to_path_buf())
+            }
+        }
+        (None, None) => Err("no dataset name or --out path given".to_string()),
+    }
+}
+
+/// Fails if the dataset at `prefix` already exists, unless `force` is set.
+pub fn check_overwrite(prefix: &Path, force: bool) -> io::Result<()> {
+    if force {
+        return Ok(());
+    }
+    for path in dataset_paths(prefix) {
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a line of space-separated moves, played from `start`.
+pub fn parse_opening<G: Game>(start: &G, line: &str) -> Result<Vec<G::Move>, String> {
+    let mut state = start.clone();
+    let mut moves = Vec::new();
+    for text in line.split_whitespace() {
+        if state.is_terminal() {
+            return Err(format!("the game is over before {text}"));
+        }
+        let m = state.parse_move(text)?;
+        state.push(m);
+        moves.push(m);
+    }
+    if state.is_terminal() {
+        return Err(format!("the game is already over after {line}"));
+    }
+    Ok(moves)
+}
+
+/// Reads a file of openings, one line of moves (see [`parse_opening`]) per opening.
+/// Blank lines are skipped.
+pub fn read_openings<G: Game>(start: &G, path: &Path) -> io::Result<Vec<Vec<G::Move>>> {
+    let text = std::fs::read_to_string(path)?;
+    let openings = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_opening(start, line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {e}", path.display(), i + 1),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if openings.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} contains no openings", path.display()),
+        ));
     }
+    Ok(openings)
 }
 
-impl<'a, G: VectoriseState + mcts::MCTSExt> GameRunner<'a, G> {
-    pub fn play_training_game(flags: &Behaviour) -> GameData {
-        let mut state = G::default();
+/// Plays `plies` uniformly random moves from `start`,
+/// starting again whenever the game ends before they have all been played.
+pub fn random_opening<G: Game>(start: &G, plies: usize, rng: &fastrand::Rng) -> Vec<G::Move> {
+    let mut buffer = G::Buffer::default();
+    'retry: loop {
+        let mut state = start.clone();
+        let mut moves = Vec::with_capacity(plies);
+        for _ in 0..plies {
+            buffer.clear();
+            state.legal_moves(&mut buffer);
+            let m = buffer[rng.usize(..buffer.len())];
+            state.push(m);
+            moves.push(m);
+            if state.is_terminal() {
+                continue 'retry;
+            }
+        }
+        return moves;
+    }
+}
+
+impl<G: VectoriseState + mcts::MCTSExt> GameRunner<'_, G> {
+    /// Plays a game of self-play from the position after `opening` is played from `start`,
+    /// recording every position.
+    /// Games searched without [`Behaviour::training`] are recorded as evaluation games.
+    /// Returns `None` if the game was interrupted by Ctrl+C.
+    pub fn play_training_game(
+        flags: &Behaviour,
+        start: &G,
+        opening: &[G::Move],
+    ) -> Option<GameData> {
+        let mut state = start.clone();
+        for &m in opening {
+            state.push(m);
+        }
         let mut states = Vec::new();
         let mut policies = Vec::new();
+        let mut predictions = Vec::new();
+        let mut record_entries = Vec::new();
         let mut engine = MCTS::new(flags);
         while !state.is_terminal() {
             // clear the terminal
@@ -162,20 +415,30 @@ impl<'a, G: VectoriseState + mcts::MCTSExt> GameRunner<'a, G> {
                 rollout_distribution,
                 new_node,
                 new_node_idx: _,
+                best_move,
                 rollouts,
-                win_rate: _,
+                win_rate,
+                overshoot: _,
+                game_lengths: _,
+                skipped_expansions: _,
             } = engine.search(&s);
+            if interrupt::requested() {
+                return None;
+            }
             assert!(match flags.limit {
-                Limit::Time(_) => true,
+                Limit::Time(_) | Limit::Infinite => true,
                 Limit::Rollouts(x) => x == rollouts,
             });
+            record_entries.push(record_entry(&s, &rollout_distribution, best_move, win_rate));
+            #[allow(clippy::cast_precision_loss)]
             let legal_policy = rollout_distribution
                 .into_iter()
-                .map(|rs| f64::from(rs) / f64::from(rollouts))
+                .map(|rs| rs as f64 / rollouts as f64)
                 .collect::<Vec<_>>();
             let policy = s.policy_vector(&legal_policy);
             states.push(s.vectorise_state());
             policies.push(policy);
+            predictions.push((s.turn(), win_rate));
             state = new_node;
         }
         let outcome = state.evaluate();
@@ -184,19 +447,63 @@ impl<'a, G: VectoriseState + mcts::MCTSExt> GameRunner<'a, G> {
         let entries = states
             .into_iter()
             .zip(policies)
+            .zip(predictions)
             .enumerate()
-            .map(|(i, (s, p))| Entry {
+            .map(|(i, ((s, p), (turn, win_rate)))| Entry {
                 outcome,
                 move_count: i as u32,
+                turn,
+                win_rate,
                 state: s,
                 policy: p,
             })
-            .collect();
-        GameData {
+            .collect::<Vec<_>>();
+        let games = vec![GameHeader {
+            opening: opening.iter().map(ToString::to_string).collect(),
+            entries: entries.len(),
+            outcome,
+            evaluation: !flags.training,
+        }];
+        let mut record = GameRecord::new(GameRecordHeader {
+            move_count: u32::try_from(record_entries.len()).unwrap_or(u32::MAX),
+            outcome,
+            action_space_dimensions: vec![G::action_space()],
+            game_id: String::new(),
+            engine_version: crate::VERSION.to_string(),
+            extension: Vec::new(),
+        });
+        for entry in record_entries {
+            record.add_entry(entry);
+        }
+        Some(GameData {
             entries,
+            games,
+            records: vec![record],
             state_dimensions: G::state_vector_dimensions(),
             action_space: G::action_space(),
-        }
+        })
+    }
+}
+
+/// The entry of a game record for a search of `state`, which gave each legal move the
+/// rollouts of `rollout_distribution`, in the order of the legal moves, and chose `best_move`.
+/// Rollout counts too large for the format are capped.
+fn record_entry<G: VectoriseState>(
+    state: &G,
+    rollout_distribution: &[u64],
+    best_move: G::Move,
+    win_rate: f64,
+) -> GameRecordEntry {
+    let mut policy = vec![0; G::action_space()];
+    let mut buf = G::Buffer::default();
+    state.legal_moves(&mut buf);
+    for (&m, &rollouts) in buf.iter().zip(rollout_distribution) {
+        policy[G::index_move(m)] = u16::try_from(rollouts).unwrap_or(u16::MAX);
+    }
+    GameRecordEntry {
+        policy,
+        chosen_move: u32::try_from(G::index_move(best_move)).unwrap(),
+        evaluation: win_rate,
     }
 }
 
@@ -208,8 +515,14 @@ impl Add for GameData {
         assert_eq!(self.action_space, other.action_space);
         let mut entries = self.entries;
         entries.append(&mut other.entries);
+        let mut games = self.games;
+        games.append(&mut other.games);
+        let mut records = self.records;
+        records.append(&mut other.records);
         Self {
             entries,
+            games,
+            records,
             state_dimensions: self.state_dimensions.clone(),
             action_space: self.action_space,
         }
@@ -235,3 +548,66 @@ impl Debug for GameData {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_prefix_resolution() {
+        let data_dir = Path::new("datasets");
+        let out = Path::new("/shared/run1");
+        assert_eq!(
+            output_prefix(None, data_dir, Some("c4")),
+            Ok(PathBuf::from("datasets/c4"))
+        );
+        assert_eq!(
+            output_prefix(Some(out), data_dir, Some("c4")),
+            Ok(PathBuf::from("/shared/run1/c4"))
+        );
+        assert_eq!(
+            output_prefix(Some(out), data_dir, None),
+            Ok(PathBuf::from("/shared/run1"))
+        );
+        assert!(output_prefix(Some(Path::new("/shared/")), data_dir, None).is_err());
+        assert!(output_prefix(None, data_dir, None).is_err());
+        assert_eq!(
+            dataset_paths(out),
+            [
+                PathBuf::from("/shared/run1-x.txt"),
+                PathBuf::from("/shared/run1-y.txt"),
+                PathBuf::from("/shared/run1-v.txt"),
+                PathBuf::from("/shared/run1-g.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn openings_are_parsed() {
+        use crate::games::connectfour::Connect4;
+        let opening = parse_opening(&Connect4::new(), "4 4 3").unwrap();
+        assert_eq!(
+            opening.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["4", "4", "3"]
+        );
+        assert!(parse_opening(&Connect4::new(), "4 9").is_err());
+        // the first player has won.
+        assert!(parse_opening(&Connect4::new(), "1 2 1 2 1 2 1").is_err());
+
+        let header = GameHeader {
+            opening: opening.iter().map(ToString::to_string).collect(),
+            entries: 20,
+            outcome: -1,
+            evaluation: true,
+        };
+        assert_eq!(header.to_string(), "-1,20,eval,4 4 3");
+
+        let rng = fastrand::Rng::with_seed(1);
+        for plies in [0, 5, 20] {
+            let opening = random_opening(&Connect4::new(), plies, &rng);
+            assert_eq!(opening.len(), plies);
+            let line = opening.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ");
+            assert_eq!(parse_opening(&Connect4::new(), &line).unwrap(), opening);
+        }
+    }
+}
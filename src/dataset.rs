@@ -0,0 +1,835 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    checksum::{crc32, Crc32},
+    record::{
+        GameRecord, GameRecordEntry, GameRecordHeader, CHECKSUM_VERSION, DENSE_POLICY,
+        RECORD_MAGIC, RECORD_VERSION, SPARSE_POLICY_VERSION,
+    },
+};
+
+/// The bytes of a chosen move and an evaluation, before the policy of an entry.
+const ENTRY_PREFIX_BYTES: usize = 4 + 8;
+
+/// The first bytes of the digest that ends a file written by a [`Writer`].
+const DIGEST_MAGIC: [u8; 4] = *b"IRGD";
+/// The length of the digest: its magic, the number of games as a u64, and a CRC-32 of
+/// the checksums of every game, in order.
+const DIGEST_BYTES: usize = 4 + 8 + 4;
+
+/// A read-only view of a whole file, memory-mapped where the platform allows it.
+struct Mapping {
+    #[cfg(unix)]
+    ptr: *mut libc::c_void,
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(not(unix))]
+    bytes: Vec<u8>,
+}
+
+impl Mapping {
+    #[cfg(unix)]
+    fn new(file: &File) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "file too large to map"))?;
+        if len == 0 {
+            // empty mappings are not allowed, and there is nothing to map anyway.
+            return Ok(Self {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+        // SAFETY: the mapping is private and read-only, and is unmapped when dropped.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    #[cfg(not(unix))]
+    fn new(mut file: &File) -> io::Result<Self> {
+        use std::io::Read;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(Self { bytes })
+    }
+
+    #[cfg(unix)]
+    const fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping is `len` bytes long, and lives as long as `self`.
+        // The file must not be truncated while it is mapped.
+        unsafe { std::slice::from_raw_parts(self.ptr.cast::<u8>(), self.len) }
+    }
+
+    #[cfg(not(unix))]
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: `ptr` and `len` are exactly what mmap returned and was given.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+// SAFETY: the mapping is read-only, so it can be read from any thread.
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+/// Reads a file of binary game records, as written by [`GameRecord::write_to`],
+/// one after another. The file is memory-mapped, and games are read in place:
+/// opening a file only finds where each game starts and checks it for corruption,
+/// so any game can be read by its index.
+pub struct Reader {
+    mapping: Mapping,
+    /// The byte offset of each game.
+    offsets: Vec<usize>,
+}
+
+impl Reader {
+    /// Opens the file at `path`, failing if any game in it is corrupt, or if it has
+    /// been cut short.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mapping = Mapping::new(&File::open(path)?)?;
+        let verification = Verification::of(mapping.bytes());
+        if let Some(problem) = verification.problems.first() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {problem}", path.display()),
+            ));
+        }
+        Ok(Self {
+            mapping,
+            offsets: verification.offsets,
+        })
+    }
+
+    /// The number of games in the file.
+    pub const fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The game at `index`, in the order they were written.
+    pub fn game(&self, index: usize) -> Option<GameView<'_>> {
+        let offset = *self.offsets.get(index)?;
+        // every game was parsed when the file was opened.
+        Some(GameView::parse(&self.mapping.bytes()[offset..]).expect("game was checked on opening"))
+    }
+
+    pub fn games(&self) -> impl ExactSizeIterator<Item = GameView<'_>> + '_ {
+        (0..self.len()).map(|i| self.game(i).expect("index is in range"))
+    }
+}
+
+/// One game of a [`Reader`], borrowed from the mapped file.
+#[derive(Clone, Copy, Debug)]
+pub struct GameView<'a> {
+    version: u8,
+    game_id: &'a str,
+    engine_version: &'a str,
+    extension: &'a [u8],
+    move_count: u32,
+    outcome: i8,
+    dimensions: &'a [u8],
+    policy_len: usize,
+    entries: &'a [u8],
+    entry_count: usize,
+    /// The checksum stored at the end of the record, if it was written with one.
+    checksum: Option<u32>,
+    /// The length of the whole record, in bytes.
+    byte_len: usize,
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// The length of the entry at the start of `bytes`, if it is all there and its policy is
+/// well-formed. `tagged` entries start their policy with its length or [`DENSE_POLICY`].
+fn entry_len(bytes: &[u8], tagged: bool, policy_len: usize) -> Option<usize> {
+    let dense = policy_len.checked_mul(2)?.checked_add(ENTRY_PREFIX_BYTES)?;
+    if !tagged {
+        return (dense <= bytes.len()).then_some(dense);
+    }
+    let policy = bytes.get(ENTRY_PREFIX_BYTES..)?;
+    let pairs = u16_at(policy.get(..2)?, 0);
+    if pairs == DENSE_POLICY {
+        return (dense + 2 <= bytes.len()).then_some(dense + 2);
+    }
+    let pairs = policy.get(2..2 + 4 * usize::from(pairs))?;
+    // indices must be in range and in order, for Policy to find them.
+    let mut next = 0;
+    for pair in pairs.chunks_exact(4) {
+        let index = usize::from(u16_at(pair, 0));
+        if index < next || index >= policy_len {
+            return None;
+        }
+        next = index + 1;
+    }
+    Some(ENTRY_PREFIX_BYTES + 2 + pairs.len())
+}
+
+/// Takes the next `len` bytes from `bytes`, starting at `*pos`.
+fn take<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let taken = bytes
+        .get(*pos..pos.saturating_add(len))
+        .ok_or_else(|| "the record is truncated".to_string())?;
+    *pos += len;
+    Ok(taken)
+}
+
+/// The fields that version 1 added before the version 0 layout, as
+/// (version, game identifier, engine version, extension, length in bytes).
+type Prefix<'a> = (u8, &'a str, &'a str, &'a [u8], usize);
+
+/// Parses the fields before the move count, which version 0 records do not have.
+fn parse_prefix(bytes: &[u8]) -> Result<Prefix<'_>, String> {
+    if !bytes.starts_with(&RECORD_MAGIC) {
+        return Ok((0, "", "", &[], 0));
+    }
+    let mut pos = RECORD_MAGIC.len();
+    let version = take(bytes, &mut pos, 1)?[0];
+    if !(1..=RECORD_VERSION).contains(&version) {
+        return Err(format!("unsupported record version {version}"));
+    }
+    let mut text = || -> Result<&str, String> {
+        let len = u16::from_le_bytes(take(bytes, &mut pos, 2)?.try_into().unwrap());
+        std::str::from_utf8(take(bytes, &mut pos, usize::from(len))?)
+            .map_err(|_| "a record string is not UTF-8".to_string())
+    };
+    let game_id = text()?;
+    let engine_version = text()?;
+    let len = u32_at(take(bytes, &mut pos, 4)?, 0) as usize;
+    let extension = take(bytes, &mut pos, len)?;
+    Ok((version, game_id, engine_version, extension, pos))
+}
+
+impl<'a> GameView<'a> {
+    /// Parses the header of the record at the start of `bytes`, checking that the whole
+    /// record is there.
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let (version, game_id, engine_version, extension, prefix_len) = parse_prefix(bytes)?;
+        let bytes = &bytes[prefix_len..];
+        let truncated = || "the record is truncated".to_string();
+        if bytes.len() < 9 {
+            return Err(truncated());
+        }
+        let move_count = u32_at(bytes, 0);
+        let outcome = i8::from_le_bytes([bytes[4]]);
+        let dimension_count = u32_at(bytes, 5) as usize;
+        let dimensions_end = dimension_count
+            .checked_mul(4)
+            .and_then(|len| len.checked_add(9))
+            .ok_or_else(truncated)?;
+        if bytes.len() < dimensions_end + 4 {
+            return Err(truncated());
+        }
+        let dimensions = &bytes[9..dimensions_end];
+        let policy_len = dimensions
+            .chunks_exact(4)
+            .try_fold(1usize, |len, d| len.checked_mul(u32_at(d, 0) as usize))
+            .ok_or_else(|| "the action space is too large".to_string())?;
+        let entry_count = u32_at(bytes, dimensions_end) as usize;
+        let entries_start = dimensions_end + 4;
+        let len = if version >= SPARSE_POLICY_VERSION {
+            // entries differ in length, so each must be looked at to find the end.
+            let mut len = entries_start;
+            for _ in 0..entry_count {
+                len += entry_len(&bytes[len..], true, policy_len)
+                    .ok_or_else(|| "an entry is truncated or malformed".to_string())?;
+            }
+            len
+        } else {
+            policy_len
+                .checked_mul(2)
+                .and_then(|policy| policy.checked_add(ENTRY_PREFIX_BYTES))
+                .and_then(|entry| entry.checked_mul(entry_count))
+                .and_then(|entries| entries.checked_add(entries_start))
+                .filter(|&len| len <= bytes.len())
+                .ok_or_else(truncated)?
+        };
+        let checksum = if version >= CHECKSUM_VERSION {
+            let checksum = bytes.get(len..len + 4).ok_or_else(truncated)?;
+            Some(u32_at(checksum, 0))
+        } else {
+            None
+        };
+        Ok(Self {
+            version,
+            game_id,
+            engine_version,
+            extension,
+            move_count,
+            outcome,
+            dimensions,
+            policy_len,
+            entries: &bytes[entries_start..len],
+            entry_count,
+            checksum,
+            byte_len: prefix_len + len + checksum.map_or(0, |_| 4),
+        })
+    }
+
+    /// The format version the game was written in, where 0 is from before versioning.
+    pub const fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub const fn game_id(&self) -> &'a str {
+        self.game_id
+    }
+
+    pub const fn engine_version(&self) -> &'a str {
+        self.engine_version
+    }
+
+    /// Fields added to the format after this reader was written.
+    pub const fn extension(&self) -> &'a [u8] {
+        self.extension
+    }
+
+    pub const fn move_count(&self) -> u32 {
+        self.move_count
+    }
+
+    pub const fn outcome(&self) -> i8 {
+        self.outcome
+    }
+
+    pub fn action_space_dimensions(&self) -> impl Iterator<Item = usize> + 'a {
+        self.dimensions
+            .chunks_exact(4)
+            .map(|d| u32_at(d, 0) as usize)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.entry_count
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entry_count == 0
+    }
+
+    /// The entry at `index`, one for each position of the game.
+    pub fn entry(&self, index: usize) -> Option<EntryView<'a>> {
+        self.entries().nth(index)
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = EntryView<'a>> + 'a {
+        let tagged = self.version >= SPARSE_POLICY_VERSION;
+        let policy_len = self.policy_len;
+        let mut rest = self.entries;
+        (0..self.entry_count).map(move |_| {
+            let len = entry_len(rest, tagged, policy_len).expect("entry was checked on parsing");
+            let (bytes, tail) = rest.split_at(len);
+            rest = tail;
+            EntryView {
+                bytes,
+                tagged,
+                policy_len,
+            }
+        })
+    }
+
+    /// Copies the game out of the file.
+    pub fn to_record(self) -> GameRecord {
+        let mut record = GameRecord::new(GameRecordHeader {
+            move_count: self.move_count,
+            outcome: self.outcome,
+            action_space_dimensions: self.action_space_dimensions().collect(),
+            game_id: self.game_id.to_string(),
+            engine_version: self.engine_version.to_string(),
+            extension: self.extension.to_vec(),
+        });
+        for entry in self.entries() {
+            record.add_entry(GameRecordEntry {
+                policy: entry.policy().collect(),
+                chosen_move: entry.chosen_move(),
+                evaluation: entry.evaluation(),
+            });
+        }
+        record
+    }
+}
+
+/// One position of a [`GameView`], borrowed from the mapped file.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryView<'a> {
+    bytes: &'a [u8],
+    /// Whether the policy starts with its length, as it may be stored sparsely.
+    tagged: bool,
+    policy_len: usize,
+}
+
+impl<'a> EntryView<'a> {
+    pub fn chosen_move(&self) -> u32 {
+        u32_at(self.bytes, 0)
+    }
+
+    pub fn evaluation(&self) -> f64 {
+        f64::from_le_bytes(self.bytes[4..12].try_into().unwrap())
+    }
+
+    /// The rollouts given to each move, decoded as they are read, however they are stored.
+    pub fn policy(&self) -> impl ExactSizeIterator<Item = u16> + 'a {
+        let policy = &self.bytes[ENTRY_PREFIX_BYTES..];
+        let (bytes, sparse) = match self.tagged.then(|| u16_at(policy, 0)) {
+            None => (policy, false),
+            Some(DENSE_POLICY) => (&policy[2..], false),
+            Some(_) => (&policy[2..], true),
+        };
+        Policy {
+            bytes,
+            sparse,
+            index: 0,
+            len: self.policy_len,
+        }
+    }
+}
+
+/// The rollouts of every move of an [`EntryView`].
+struct Policy<'a> {
+    /// The rollouts of every move, or (index, rollouts) pairs for the moves with any if sparse.
+    bytes: &'a [u8],
+    sparse: bool,
+    index: usize,
+    len: usize,
+}
+
+impl Iterator for Policy<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.index == self.len {
+            return None;
+        }
+        let rollouts = if !self.sparse {
+            u16_at(self.bytes, 2 * self.index)
+        } else if self.bytes.len() >= 4 && usize::from(u16_at(self.bytes, 0)) == self.index {
+            let rollouts = u16_at(self.bytes, 2);
+            self.bytes = &self.bytes[4..];
+            rollouts
+        } else {
+            0
+        };
+        self.index += 1;
+        Some(rollouts)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len - self.index, Some(self.len - self.index))
+    }
+}
+
+impl ExactSizeIterator for Policy<'_> {}
+
+/// Writes games to a file that a [`Reader`] can check: each game carries its own checksum,
+/// and [`finish`](Self::finish) ends the file with a digest of all of them, so that a file
+/// that was not finished is known to be incomplete.
+pub struct Writer<W: Write> {
+    inner: W,
+    games: u64,
+    digest: Crc32,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub const fn new(inner: W) -> Self {
+        Self {
+            inner,
+            games: 0,
+            digest: Crc32::new(),
+        }
+    }
+
+    pub fn write(&mut self, record: &GameRecord) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes)?;
+        // records end with their checksum.
+        self.digest.update(&bytes[bytes.len() - 4..]);
+        self.games += 1;
+        self.inner.write_all(&bytes)
+    }
+
+    /// Writes the digest and flushes the file, returning the number of games written.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.inner.write_all(&DIGEST_MAGIC)?;
+        self.inner.write_all(&self.games.to_le_bytes())?;
+        self.inner.write_all(&self.digest.finish().to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.games)
+    }
+}
+
+/// The result of checking every game of a file against its checksum, and the file
+/// against its digest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// The byte offset of each game that could be parsed.
+    offsets: Vec<usize>,
+    /// Games whose checksums match.
+    pub checked: usize,
+    /// Games from before records had checksums, which cannot be checked.
+    pub unchecked: usize,
+    /// Whether the file ends with a digest that matches its games.
+    pub digest: bool,
+    /// Everything found to be wrong with the file, in order.
+    pub problems: Vec<String>,
+}
+
+impl Verification {
+    fn of(bytes: &[u8]) -> Self {
+        let mut verification = Self::default();
+        let mut digest = Crc32::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            let index = verification.offsets.len();
+            if rest.starts_with(&DIGEST_MAGIC) {
+                verification.check_digest(rest, digest.finish(), offset);
+                return verification;
+            }
+            let game = match GameView::parse(rest) {
+                Ok(game) => game,
+                // records before version 1 have no magic, so only the failure to read one
+                // shows that a file is of something else.
+                Err(_) if index == 0 && !rest.starts_with(&RECORD_MAGIC) => {
+                    verification.problems.push(
+                        "the file is not of binary game records, such as generate writes with --records"
+                            .to_string(),
+                    );
+                    return verification;
+                }
+                Err(e) => {
+                    // without the length of this game, the next one cannot be found.
+                    verification
+                        .problems
+                        .push(format!("game {index} at byte {offset}: {e}"));
+                    return verification;
+                }
+            };
+            if let Some(checksum) = game.checksum {
+                if crc32(&rest[..game.byte_len - 4]) == checksum {
+                    verification.checked += 1;
+                } else {
+                    verification.problems.push(format!(
+                        "game {index} at byte {offset}: the checksum does not match, so the game is corrupt"
+                    ));
+                }
+                digest.update(&checksum.to_le_bytes());
+            } else {
+                verification.unchecked += 1;
+            }
+            verification.offsets.push(offset);
+            offset += game.byte_len;
+        }
+        if verification.checked + verification.problems.len() > 0 {
+            verification
+                .problems
+                .push("the file has no digest, so it may have been cut short".to_string());
+        }
+        verification
+    }
+
+    /// Checks the digest at the start of `rest`, against the games before it.
+    fn check_digest(&mut self, rest: &[u8], expected: u32, offset: usize) {
+        if rest.len() != DIGEST_BYTES {
+            self.problems.push(format!(
+                "the digest at byte {offset} is {} bytes long rather than {DIGEST_BYTES}",
+                rest.len()
+            ));
+            return;
+        }
+        let games = u64::from_le_bytes(rest[4..12].try_into().unwrap());
+        if games != self.offsets.len() as u64 {
+            self.problems.push(format!(
+                "the digest is of {games} games, but the file has {}",
+                self.offsets.len()
+            ));
+        } else if u32_at(rest, 12) == expected {
+            self.digest = true;
+        } else {
+            self.problems
+                .push("the digest does not match the checksums of the games".to_string());
+        }
+    }
+
+    pub const fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "games with matching checksums: {}", self.checked)?;
+        writeln!(f, "games without checksums: {}", self.unchecked)?;
+        let digest = if self.digest {
+            "matches"
+        } else {
+            "does not match"
+        };
+        write!(f, "file digest: {digest}")?;
+        for problem in &self.problems {
+            write!(f, "\n{problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every game of the file at `path`, reporting all of the problems found rather
+/// than only the first, as [`Reader::open`] does.
+pub fn verify<P: AsRef<Path>>(path: P) -> io::Result<Verification> {
+    let mapping = Mapping::new(&File::open(path)?)?;
+    Ok(Verification::of(mapping.bytes()))
+}
+
+/// Totals over every game of a dataset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub games: usize,
+    pub positions: usize,
+    /// First-player wins, draws, and second-player wins.
+    pub outcomes: [usize; 3],
+    /// Games written in an older version of the format, which [`migrate`] can update.
+    pub legacy: usize,
+}
+
+impl Stats {
+    pub fn of(reader: &Reader) -> Self {
+        let mut stats = Self::default();
+        for game in reader.games() {
+            stats.games += 1;
+            stats.positions += game.len();
+            stats.legacy += usize::from(game.version() < RECORD_VERSION);
+            stats.outcomes[match game.outcome() {
+                1 => 0,
+                -1 => 2,
+                _ => 1,
+            }] += 1;
+        }
+        stats
+    }
+}
+
+impl Display for Stats {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let [wins, draws, losses] = self.outcomes;
+        writeln!(f, "games: {}", self.games)?;
+        writeln!(f, "positions: {}", self.positions)?;
+        writeln!(
+            f,
+            "mean game length: {:.1}",
+            self.positions as f64 / self.games.max(1) as f64
+        )?;
+        write!(
+            f,
+            "first player wins: {wins}, draws: {draws}, second player wins: {losses}"
+        )?;
+        if self.legacy > 0 {
+            write!(
+                f,
+                "\n{} games are in an older format, and can be migrated",
+                self.legacy
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites every game of `input` into `output` in the current format, returning the number
+/// of games. Games without an identifier are named after the input file and their index.
+pub fn migrate(input: &Path, output: &Path) -> io::Result<usize> {
+    let reader = Reader::open(input)?;
+    let stem = input
+        .file_stem()
+        .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+    let mut writer = Writer::create(output)?;
+    for (i, game) in reader.games().enumerate() {
+        let mut record = game.to_record();
+        if record.header.game_id.is_empty() {
+            record.header.game_id = format!("{stem}:{i}");
+        }
+        writer.write(&record)?;
+    }
+    writer.finish()?;
+    Ok(reader.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(outcome: i8, entries: u16) -> GameRecord {
+        let mut record = GameRecord::new(GameRecordHeader {
+            move_count: u32::from(entries),
+            outcome,
+            action_space_dimensions: vec![2, 3],
+            game_id: String::new(),
+            engine_version: String::new(),
+            extension: Vec::new(),
+        });
+        for i in 0..entries {
+            record.add_entry(GameRecordEntry {
+                policy: (0..6).map(|p| p * 10 + i).collect(),
+                chosen_move: u32::from(i),
+                evaluation: f64::from(i) / 10.0,
+            });
+        }
+        record
+    }
+
+    #[test]
+    fn games_are_read_in_place() {
+        let path = std::env::temp_dir().join(format!("iridium-dataset-{}.bin", std::process::id()));
+        let records = [record(1, 3), record(0, 0), record(-1, 5)];
+        let mut bytes = Vec::new();
+        let mut writer = Writer::new(&mut bytes);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), 3);
+        std::fs::write(&path, &bytes).unwrap();
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.len(), 3);
+        for (game, record) in reader.games().zip(&records) {
+            assert_eq!(&game.to_record(), record);
+        }
+        let last = reader.game(2).unwrap();
+        assert_eq!(last.entry(4).unwrap().policy().nth(1), Some(14));
+        assert!(last.entry(5).is_none() && reader.game(3).is_none());
+        let stats = Stats::of(&reader);
+        assert_eq!((stats.positions, stats.outcomes), (8, [1, 1, 1]));
+
+        // a file cut off in the middle of a game is rejected.
+        std::fs::write(&path, &bytes[..bytes.len() - DIGEST_BYTES - 1]).unwrap();
+        assert!(Reader::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sparse_policies_are_read_in_full() {
+        let mut sparse = record(0, 2);
+        sparse.entries[0].policy = vec![0, 0, 5, 0, 0, 0];
+        sparse.entries[1].policy = vec![3, 0, 0, 0, 0, 9];
+        let mut bytes = Vec::new();
+        sparse.write_to(&mut bytes).unwrap();
+        let game = GameView::parse(&bytes).unwrap();
+        assert_eq!(game.byte_len, bytes.len());
+        let second = game.entry(1).unwrap();
+        assert_eq!(second.policy().len(), 6);
+        assert_eq!(second.policy().collect::<Vec<_>>(), [3, 0, 0, 0, 0, 9]);
+        assert_eq!(game.to_record(), sparse);
+
+        // an index past the end of the policy is rejected.
+        let index = bytes.len() - 4 - 4;
+        bytes[index] = 6;
+        assert!(GameView::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn corruption_and_truncation_are_found() {
+        let mut bytes = Vec::new();
+        let mut writer = Writer::new(&mut bytes);
+        for record in [record(1, 3), record(0, 4), record(-1, 5)] {
+            writer.write(&record).unwrap();
+        }
+        writer.finish().unwrap();
+        let verification = Verification::of(&bytes);
+        assert!(verification.is_ok() && verification.digest);
+        assert_eq!(verification.checked, 3);
+
+        // flip a bit of the last policy entry of the second game.
+        let second = verification.offsets[1];
+        let mut corrupt = bytes.clone();
+        corrupt[verification.offsets[2] - 5] ^= 1;
+        let verification = Verification::of(&corrupt);
+        assert_eq!(verification.checked, 2);
+        assert_eq!(verification.problems.len(), 1);
+        assert!(verification.problems[0].starts_with(&format!("game 1 at byte {second}")));
+
+        // a file that ends cleanly between two games is missing its last game and digest.
+        let cut = Verification::of(&bytes[..verification.offsets[2]]);
+        assert!(!cut.digest);
+        assert_eq!(
+            cut.problems,
+            ["the file has no digest, so it may have been cut short"]
+        );
+        let mut dropped = bytes[..verification.offsets[2]].to_vec();
+        dropped.extend_from_slice(&bytes[bytes.len() - DIGEST_BYTES..]);
+        assert!(!Verification::of(&dropped).is_ok());
+
+        // the text datasets of generate are not game records at all.
+        let text = Verification::of(b"1,0,0,1,0,1,0.250,0.750\n");
+        assert_eq!(text.problems.len(), 1);
+        assert!(text.problems[0].starts_with("the file is not of binary game records"));
+    }
+
+    #[test]
+    fn legacy_games_are_migrated() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("iridium-legacy-{}.bin", std::process::id()));
+        let output = dir.join(format!("iridium-migrated-{}.bin", std::process::id()));
+        let mut bytes = Vec::new();
+        for record in [record(1, 2), record(-1, 1)] {
+            record.write_as(&mut bytes, 0).unwrap();
+        }
+        std::fs::write(&input, &bytes).unwrap();
+        let legacy = Reader::open(&input).unwrap();
+        assert_eq!(legacy.game(1).unwrap().version(), 0);
+        assert_eq!(Stats::of(&legacy).legacy, 2);
+
+        assert_eq!(migrate(&input, &output).unwrap(), 2);
+        let migrated = Reader::open(&output).unwrap();
+        let stem = input.file_stem().unwrap().to_str().unwrap();
+        let game = migrated.game(1).unwrap();
+        assert_eq!(game.version(), RECORD_VERSION);
+        assert_eq!(game.game_id(), format!("{stem}:1"));
+        assert_eq!((game.outcome(), game.len()), (-1, 1));
+        assert_eq!(Stats::of(&migrated).legacy, 0);
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+}
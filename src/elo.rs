@@ -1,24 +1,71 @@
 use std::f64::consts::{LN_10, PI};
 
+/// The confidence level of error margins, unless another is asked for.
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+
+/// The Elo difference estimated from a match, from the first player's perspective.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::struct_field_names)]
 pub struct Difference {
     pub difference: f64,
+    /// The half-width of the confidence interval around `difference`.
     pub error: f64,
+    /// Normalized Elo: the score's distance from even, in units of its per-game standard
+    /// deviation, so that results at different draw ratios can be compared.
+    pub nelo: f64,
+    /// The half-width of the confidence interval around `nelo`.
+    pub nelo_error: f64,
+    /// The fraction of games that were drawn.
+    pub draw_ratio: f64,
+    /// The confidence level of the error margins, in (0, 1).
+    pub confidence: f64,
+    /// The wins, draws, and losses of the first player.
+    pub results: [i32; 3],
+}
+
+impl Difference {
+    /// Whether the match shows that one player is stronger, i.e. whether an even score
+    /// lies outside the confidence interval. A match of only draws is never significant.
+    pub fn is_significant(&self) -> bool {
+        self.nelo.abs() > self.nelo_error
+    }
 }
 
-pub fn difference(wins: i32, losses: i32, draws: i32) -> Difference {
+/// Estimates the Elo difference of a match result, with error margins at the
+/// given `confidence` level (e.g. [`DEFAULT_CONFIDENCE`]).
+pub fn difference(wins: i32, losses: i32, draws: i32, confidence: f64) -> Difference {
+    assert!(
+        confidence > 0.0 && confidence < 1.0,
+        "confidence must be between 0 and 1, got {confidence}"
+    );
+    let results = [wins, draws, losses];
     let wins = f64::from(wins);
     let losses = f64::from(losses);
     let draws = f64::from(draws);
-    let score = wins + draws / 2.0;
     let total = wins + draws + losses;
-    let percentage = score / total;
-    let difference = -400.0 * (1.0 / percentage - 1.0).ln() / LN_10;
+    let (percentage, std_deviation) = score_deviation(wins, draws, losses);
+    let difference = elo_diff_from_percent(percentage);
 
-    let error = error_margin(wins, draws, losses);
-    Difference { difference, error }
+    let error = error_margin(percentage, std_deviation / total.sqrt(), confidence);
+
+    // nElo scales the score's excess over 0.5 by its per-game standard deviation.
+    // The standard error of that ratio is about 1 / sqrt(games).
+    let nelo_scale = 800.0 / LN_10;
+    let nelo = nelo_scale * (percentage - 0.5) / std_deviation;
+    let nelo_error = nelo_scale * phi_inv(confidence.midpoint(1.0)) / total.sqrt();
+    Difference {
+        difference,
+        error,
+        nelo,
+        nelo_error,
+        draw_ratio: draws / total,
+        confidence,
+        results,
+    }
 }
 
-fn error_margin(wins: f64, draws: f64, losses: f64) -> f64 {
+/// The mean score of a match, and the standard deviation of the score of a single game.
+fn score_deviation(wins: f64, draws: f64, losses: f64) -> (f64, f64) {
     let total = wins + draws + losses;
     let win_p = wins / total;
     let draw_p = draws / total;
@@ -27,9 +74,10 @@ fn error_margin(wins: f64, draws: f64, losses: f64) -> f64 {
     let wins_dev = win_p * f64::powf(1.0 - percentage, 2.0);
     let draws_dev = draw_p * f64::powf(0.5 - percentage, 2.0);
     let losses_dev = loss_p * f64::powf(0.0 - percentage, 2.0);
-    let std_deviation = f64::sqrt(wins_dev + draws_dev + losses_dev) / f64::sqrt(total);
+    (percentage, f64::sqrt(wins_dev + draws_dev + losses_dev))
+}
 
-    let confidence_p = 0.95;
+fn error_margin(percentage: f64, std_deviation: f64, confidence_p: f64) -> f64 {
     let min_confidence_p = (1.0 - confidence_p) / 2.0;
     let max_confidence_p = 1.0 - min_confidence_p;
     let dev_min = phi_inv(min_confidence_p).mul_add(std_deviation, percentage);
@@ -44,7 +92,8 @@ fn elo_diff_from_percent(percentage: f64) -> f64 {
     -400.0 * (1.0 / percentage - 1.0).ln() / LN_10
 }
 
-fn phi_inv(p: f64) -> f64 {
+/// The inverse of the cumulative distribution function of the standard normal distribution.
+pub fn phi_inv(p: f64) -> f64 {
     f64::sqrt(2.0) * inverse_error(2.0f64.mul_add(p, -1.0))
 }
 
@@ -62,3 +111,33 @@ fn inverse_error(x: f64) -> f64 {
 
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn margins_follow_confidence() {
+        let even = difference(40, 40, 20, DEFAULT_CONFIDENCE);
+        assert!(even.difference.abs() < 1e-9);
+        assert!(even.nelo.abs() < 1e-9);
+        assert!((even.draw_ratio - 0.2).abs() < 1e-9);
+
+        let ahead = difference(60, 30, 10, DEFAULT_CONFIDENCE);
+        assert!(ahead.difference > 0.0 && ahead.nelo > 0.0);
+        let wider = difference(60, 30, 10, 0.99);
+        assert!(wider.error > ahead.error);
+        assert!(wider.nelo_error > ahead.nelo_error);
+        assert!((wider.difference - ahead.difference).abs() < 1e-9);
+
+        // with more draws, the same score is more significant.
+        let drawish = difference(35, 5, 60, DEFAULT_CONFIDENCE);
+        assert!((drawish.difference - ahead.difference).abs() < 1e-9);
+        assert!(drawish.nelo > ahead.nelo);
+
+        assert!(ahead.is_significant());
+        assert!(!difference(12, 8, 10, DEFAULT_CONFIDENCE).is_significant());
+        assert!(!difference(0, 0, 10, DEFAULT_CONFIDENCE).is_significant());
+        assert!(difference(10, 0, 0, DEFAULT_CONFIDENCE).is_significant());
+    }
+}
@@ -0,0 +1,206 @@
+#![allow(clippy::cast_precision_loss)]
+
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// How the root statistics of several trees, searched independently from the same
+/// position by root parallelism, are combined to choose a move.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Merge {
+    /// Adds up the visits of each move over all of the trees.
+    #[default]
+    SumVisits,
+    /// Plays the move that the most trees would have played, breaking ties by total visits.
+    MajorityVote,
+    /// Averages the visit distributions of the trees, each normalised to sum to one,
+    /// so that a tree that did more rollouts does not count for more.
+    AveragePolicy,
+}
+
+impl FromStr for Merge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Self::SumVisits),
+            "vote" => Ok(Self::MajorityVote),
+            "average" => Ok(Self::AveragePolicy),
+            _ => Err(format!(
+                "unknown merge strategy {s}, expected one of sum, vote, and average"
+            )),
+        }
+    }
+}
+
+impl Display for Merge {
+    /// Writes the merge strategy in the form accepted by [`Merge::from_str`].
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::SumVisits => write!(f, "sum"),
+            Self::MajorityVote => write!(f, "vote"),
+            Self::AveragePolicy => write!(f, "average"),
+        }
+    }
+}
+
+/// The index of the largest of `values`, the earliest if several are largest.
+fn argmax<T: PartialOrd + Copy>(values: &[T]) -> usize {
+    let mut best = 0;
+    for (i, &value) in values.iter().enumerate() {
+        if value > values[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/// The visits of `distribution`, scaled to sum to one, or all zero if there are none.
+fn normalise(distribution: &[u64]) -> Vec<f64> {
+    let total = distribution.iter().sum::<u64>().max(1) as f64;
+    distribution
+        .iter()
+        .map(|&visits| visits as f64 / total)
+        .collect()
+}
+
+/// The visits of each move, summed over `distributions`.
+pub fn sum_visits(distributions: &[Vec<u64>]) -> Vec<u64> {
+    let moves = distributions.first().map_or(0, Vec::len);
+    (0..moves)
+        .map(|i| distributions.iter().map(|d| d[i]).sum())
+        .collect()
+}
+
+impl Merge {
+    /// Scores every move by `distributions`, the root visit counts of each tree in
+    /// move generation order. The move to play is the one with the highest score.
+    pub fn scores(self, distributions: &[Vec<u64>]) -> Vec<f64> {
+        let summed = sum_visits(distributions);
+        match self {
+            Self::SumVisits => summed.iter().map(|&visits| visits as f64).collect(),
+            Self::MajorityVote => {
+                // the share of the total visits is less than one, so it only breaks ties.
+                let tiebreak = normalise(&summed);
+                let mut votes = tiebreak
+                    .iter()
+                    .map(|&share| share * 0.5)
+                    .collect::<Vec<_>>();
+                for distribution in distributions {
+                    votes[argmax(distribution)] += 1.0;
+                }
+                votes
+            }
+            Self::AveragePolicy => {
+                let mut mean = vec![0.0; summed.len()];
+                for distribution in distributions {
+                    for (m, p) in mean.iter_mut().zip(normalise(distribution)) {
+                        *m += p / distributions.len() as f64;
+                    }
+                }
+                mean
+            }
+        }
+    }
+
+    /// The index of the move to play, given the root visit counts of each tree.
+    pub fn choose(self, distributions: &[Vec<u64>]) -> usize {
+        argmax(&self.scores(distributions))
+    }
+}
+
+/// How closely trees searched independently from the same position agree,
+/// which shows how much the result of a single search depends on chance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Agreement {
+    pub trees: usize,
+    /// The trees whose most-visited move is the move that was chosen.
+    pub agreeing: usize,
+    /// The mean total variation distance between the visit distribution of each tree and
+    /// the average of them all: 0 if every tree shares out its visits alike, and at most 1.
+    pub policy_distance: f64,
+}
+
+impl Agreement {
+    /// Measures the agreement of the trees whose root visit counts are `distributions`,
+    /// of which the move at index `chosen` was played.
+    pub fn new(distributions: &[Vec<u64>], chosen: usize) -> Self {
+        let mean = Merge::AveragePolicy.scores(distributions);
+        let trees = distributions.len();
+        let agreeing = distributions
+            .iter()
+            .filter(|distribution| argmax(distribution) == chosen)
+            .count();
+        let policy_distance = distributions
+            .iter()
+            .map(|distribution| {
+                let policy = normalise(distribution);
+                policy
+                    .iter()
+                    .zip(&mean)
+                    .map(|(p, m)| (p - m).abs())
+                    .sum::<f64>()
+                    / 2.0
+            })
+            .sum::<f64>()
+            / trees.max(1) as f64;
+        Self {
+            trees,
+            agreeing,
+            policy_distance,
+        }
+    }
+}
+
+impl Display for Agreement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} trees agree on the move, mean policy distance {:.3}",
+            self.agreeing, self.trees, self.policy_distance
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategies_can_disagree() {
+        // two trees narrowly prefer the first move, and one the second by far.
+        let narrow = [vec![51, 49, 0], vec![51, 49, 0], vec![0, 100, 0]];
+        assert_eq!(Merge::SumVisits.choose(&narrow), 1);
+        assert_eq!(Merge::MajorityVote.choose(&narrow), 0);
+        assert_eq!(Merge::AveragePolicy.choose(&narrow), 1);
+        // one tree did far more rollouts than the others.
+        let uneven = [vec![1000, 0, 0], vec![0, 10, 0], vec![0, 10, 0]];
+        assert_eq!(Merge::SumVisits.choose(&uneven), 0);
+        assert_eq!(Merge::MajorityVote.choose(&uneven), 1);
+        assert_eq!(Merge::AveragePolicy.choose(&uneven), 1);
+        // the vote is tied, and total visits break the tie.
+        assert_eq!(Merge::MajorityVote.choose(&[vec![5, 4], vec![3, 9]]), 1);
+        for merge in ["sum", "vote", "average"] {
+            assert_eq!(merge.parse::<Merge>().unwrap().to_string(), merge);
+        }
+        assert!("mean".parse::<Merge>().is_err());
+        let behaviour = "limit=rollouts:1,rollout_policy=random,merge=vote"
+            .parse::<crate::mcts::Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.root_merge, Merge::MajorityVote);
+    }
+
+    #[test]
+    fn agreement_is_measured_against_the_average() {
+        let narrow = [vec![51, 49, 0], vec![51, 49, 0], vec![0, 100, 0]];
+        let agreement = Agreement::new(&narrow, 1);
+        assert_eq!(agreement.trees, 3);
+        assert_eq!(agreement.agreeing, 1);
+        // the mean policy is [0.34, 0.66, 0], 0.17 from the first two trees and 0.34 from the last.
+        assert!((agreement.policy_distance - (0.17 + 0.17 + 0.34) / 3.0).abs() < 1e-9);
+        let same = Agreement::new(&[vec![3, 1], vec![6, 2]], 0);
+        assert_eq!(same.agreeing, 2);
+        assert!(same.policy_distance.abs() < 1e-12);
+    }
+}
@@ -1,28 +1,237 @@
 use std::{
     fmt::{Debug, Display},
+    hash::{Hash, Hasher},
     ops::Index,
 };
 
 use crate::treenode::Node;
 
-pub trait MoveBuffer<Move>: Debug + Default + Index<usize, Output = Move> + Display {
-    fn iter(&self) -> std::slice::Iter<Move>;
+pub trait MoveBuffer<Move>:
+    Debug + Default + Clone + Index<usize, Output = Move> + Display
+{
+    fn iter(&self) -> std::slice::Iter<'_, Move>;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
     fn push(&mut self, m: Move);
     fn capacity(&self) -> usize;
+    /// Removes all moves, keeping the storage so the buffer can be reused.
+    fn clear(&mut self);
+}
+
+/// A move buffer with a fixed capacity, stored inline so that filling it never allocates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayBuffer<M, const CAP: usize> {
+    moves: [M; CAP],
+    len: usize,
+}
+
+impl<M: Copy + Default, const CAP: usize> Default for ArrayBuffer<M, CAP> {
+    fn default() -> Self {
+        Self {
+            moves: [M::default(); CAP],
+            len: 0,
+        }
+    }
+}
+
+impl<M, const CAP: usize> Index<usize> for ArrayBuffer<M, CAP> {
+    type Output = M;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.moves[..self.len][index]
+    }
+}
+
+impl<M: Display, const CAP: usize> Display for ArrayBuffer<M, CAP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for (i, m) in self.moves[..self.len].iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{m}")?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl<M: Copy + Default + Debug + Display, const CAP: usize> MoveBuffer<M> for ArrayBuffer<M, CAP> {
+    #[inline]
+    fn iter(&self) -> std::slice::Iter<'_, M> {
+        self.moves[..self.len].iter()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn push(&mut self, m: M) {
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    fn capacity(&self) -> usize {
+        CAP
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// The splitmix64 finaliser, used by games to build position hashes.
+pub const fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The number of symmetries of a square board: four rotations, each optionally mirrored.
+pub const DIHEDRAL_SYMMETRIES: usize = 8;
+
+/// Maps `square`, a row-major index into an `n` by `n` board, through symmetry `sym`
+/// of the board, where symmetry 0 is the identity.
+pub const fn transform_square(square: usize, n: usize, sym: usize) -> usize {
+    let (row, col) = (square / n, square % n);
+    let last = n - 1;
+    let (row, col) = match sym {
+        0 => (row, col),
+        1 => (col, last - row),
+        2 => (last - row, last - col),
+        3 => (last - col, row),
+        4 => (row, last - col),
+        5 => (last - row, col),
+        6 => (col, row),
+        7 => (last - col, last - row),
+        _ => panic!("transform_square: not a symmetry of a square board"),
+    };
+    row * n + col
 }
 
 pub trait Game: Clone + Eq + Debug + Display + Default + Send + Sync {
     type Move: Copy + Eq + Ord + Debug + Display + Default + Send + Sync;
-    type Buffer: MoveBuffer<Self::Move>;
+    type Buffer: MoveBuffer<Self::Move> + Send + Sync;
+
+    /// Whether the game supports unmaking moves with [`pop`](Self::pop).
+    /// Games that do not are copied instead wherever a move needs to be undone.
+    const CAN_POP: bool = false;
+    /// The number of distinct values of [`move_index`](Self::move_index).
+    const MOVE_SPACE: usize;
+    /// The number of symmetries of the board understood by [`transform_move`](Self::transform_move),
+    /// counting the identity as symmetry 0.
+    const SYMMETRIES: usize = 1;
+    /// The rule variants that the game can be played with, besides the standard rules.
+    const VARIANTS: &'static [&'static str] = &[];
+    /// The number of times a position must occur for the game to be drawn by repetition,
+    /// for games in which positions can repeat, which keep a [`RepetitionHistory`].
+    const REPETITION_LIMIT: Option<usize> = None;
 
     fn turn(&self) -> i8;
     fn generate_moves(&self, moves: &mut Self::Buffer);
     fn is_terminal(&self) -> bool;
     fn evaluate(&self) -> i8;
     fn push(&mut self, m: Self::Move);
+    /// Unmakes `m`, which must be the last move pushed. Only called if [`CAN_POP`](Self::CAN_POP) is set.
+    fn pop(&mut self, _m: Self::Move) {
+        unimplemented!("this game does not support unmaking moves");
+    }
     fn push_random(&mut self, rng: &mut fastrand::Rng);
+    /// Whether the side to move has no legal moves but the game goes on, so that they
+    /// must play [`pass_move`](Self::pass_move). Games with passes override both;
+    /// their `generate_moves` and `push_random` only ever deal with real moves.
+    fn must_pass(&self) -> bool {
+        false
+    }
+    /// The move that passes the turn.
+    fn pass_move() -> Self::Move {
+        unimplemented!("this game has no pass move");
+    }
+    /// Generates the legal moves, which is only the pass move if the side to move must pass.
+    fn legal_moves(&self, moves: &mut Self::Buffer) {
+        if self.must_pass() {
+            moves.push(Self::pass_move());
+        } else {
+            self.generate_moves(moves);
+        }
+    }
+    /// Plays a random legal move, passing if the side to move must pass.
+    fn push_random_or_pass(&mut self, rng: &mut fastrand::Rng) {
+        if self.must_pass() {
+            self.push(Self::pass_move());
+        } else {
+            self.push_random(rng);
+        }
+    }
+    /// Parses `text` as a legal move in this position.
+    ///
+    /// Move names must round-trip: `state.parse_move(&m.to_string())` is `Ok(m)` for every
+    /// legal move `m`. By default, `text` is compared with the name of every legal move,
+    /// ignoring ASCII case. Games override this to accept other notations as well.
+    fn parse_move(&self, text: &str) -> Result<Self::Move, String> {
+        let text = text.trim();
+        let mut moves = Self::Buffer::default();
+        self.legal_moves(&mut moves);
+        moves
+            .iter()
+            .find(|m| m.to_string().eq_ignore_ascii_case(text))
+            .copied()
+            .ok_or_else(|| format!("illegal move: {text}"))
+    }
+    /// The starting position with `side` (1 for the first player, -1 for the second)
+    /// receiving odds, such as an extra stone, or `None` if the game has no handicap.
+    fn with_odds(_side: i8) -> Option<Self> {
+        None
+    }
+    /// The starting position of the rule variant named `variant`, one of
+    /// [`VARIANTS`](Self::VARIANTS). The rules are part of the position, so every
+    /// position played on from it is of the same variant.
+    fn with_variant(variant: &str) -> Result<Self, String> {
+        Err(unknown_variant::<Self>(variant))
+    }
+    /// A 64-bit hash of the position, stable across runs and platforms.
+    fn hash(&self) -> u64;
+    /// The positions reached so far, for games with a [`REPETITION_LIMIT`](Self::REPETITION_LIMIT).
+    fn repetition_history(&self) -> Option<&RepetitionHistory> {
+        None
+    }
+    /// Whether the latest position has occurred [`REPETITION_LIMIT`](Self::REPETITION_LIMIT)
+    /// times, so that the game is drawn. Games with repetitions check this in `is_terminal`,
+    /// and so end rollouts that go round in circles.
+    fn is_repetition(&self) -> bool {
+        Self::REPETITION_LIMIT
+            .zip(self.repetition_history())
+            .is_some_and(|(limit, history)| history.repetitions() >= limit)
+    }
+    /// A dense index of `m` in `0..MOVE_SPACE`, used to key per-move statistics during search.
+    fn move_index(m: Self::Move) -> usize;
+    /// Whether the position is unchanged by symmetry `sym`.
+    fn is_symmetric(&self, sym: usize) -> bool {
+        sym == 0
+    }
+    /// The move that `m` becomes when the board is transformed by symmetry `sym`.
+    fn transform_move(m: Self::Move, _sym: usize) -> Self::Move {
+        m
+    }
+    /// The move that was played to reach this position, which displays highlight, or `None`
+    /// at the start of a game and wherever it is not known, as after a [`pop`](Self::pop) in
+    /// games that do not keep their history.
+    fn last_move(&self) -> Option<Self::Move> {
+        None
+    }
+    /// A static evaluation of the position, from -1 if the second player is winning to 1 if
+    /// the first player is, which [`RolloutPolicy::Heuristic`](crate::mcts::RolloutPolicy::Heuristic)
+    /// takes in place of a rollout, or `None` if the game has no evaluation.
+    fn heuristic_eval(&self) -> Option<f32> {
+        None
+    }
 
     fn outcome(&self) -> Option<&str> {
         if self.is_terminal() {
@@ -46,3 +255,194 @@ pub trait Game: Clone + Eq + Debug + Display + Default + Send + Sync {
         1.0
     }
 }
+
+/// The positions of a game by hash, for games in which a position can occur again.
+///
+/// A game keeps one in its state, recording the starting position and then every position
+/// it reaches in [`Game::push`] (and forgetting it again in [`Game::pop`]), and checks
+/// [`Game::is_repetition`] in [`Game::is_terminal`]. Positions from before an irreversible
+/// move, such as a capture, can never recur, so they are not searched for repetitions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepetitionHistory {
+    /// The hash of each position, and whether it was reached by an irreversible move.
+    positions: Vec<(u64, bool)>,
+}
+
+impl RepetitionHistory {
+    /// Records the position with `hash`, reached by an irreversible move if `irreversible`.
+    /// The starting position counts as irreversible.
+    pub fn push(&mut self, hash: u64, irreversible: bool) {
+        self.positions.push((hash, irreversible));
+    }
+
+    /// Forgets the latest position.
+    pub fn pop(&mut self) {
+        self.positions.pop();
+    }
+
+    /// The number of times the latest position has occurred, counting itself.
+    pub fn repetitions(&self) -> usize {
+        let Some(&(latest, _)) = self.positions.last() else {
+            return 0;
+        };
+        let mut count = 0;
+        for &(hash, irreversible) in self.positions.iter().rev() {
+            count += usize::from(hash == latest);
+            if irreversible {
+                break;
+            }
+        }
+        count
+    }
+}
+
+/// The terminal escape that displays highlight the last move with, reverse video.
+pub const HIGHLIGHT: &str = "\u{001b}[7m";
+
+/// The move that led to a position, which a game keeps so that [`Game::last_move`] can
+/// show it. It is not part of the position: states reached by different moves still compare
+/// and hash alike.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LastMove<M>(pub Option<M>);
+
+impl<M> PartialEq for LastMove<M> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<M> Eq for LastMove<M> {}
+
+impl<M> Hash for LastMove<M> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// The error for a variant that `G` does not have.
+pub fn unknown_variant<G: Game>(variant: &str) -> String {
+    if G::VARIANTS.is_empty() {
+        format!("unknown variant {variant}: this game has no variants")
+    } else {
+        format!(
+            "unknown variant {variant}: the variants are {}",
+            G::VARIANTS.join(", ")
+        )
+    }
+}
+
+/// Counts the lines of play from `state` that are `depth` plies long, or that end
+/// in a finished game sooner. Used to test move generation.
+pub fn perft<G: Game>(state: &mut G, depth: usize) -> u64 {
+    if depth == 0 || state.is_terminal() {
+        return 1;
+    }
+    let mut moves = G::Buffer::default();
+    state.legal_moves(&mut moves);
+    moves
+        .iter()
+        .map(|&m| {
+            if G::CAN_POP {
+                state.push(m);
+                let count = perft(state, depth - 1);
+                state.pop(m);
+                count
+            } else {
+                let mut child = state.clone();
+                child.push(m);
+                perft(&mut child, depth - 1)
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::{
+        chess::Chess,
+        connectfour::Connect4,
+        gomoku::Gomoku,
+        tictactoe::{TicTacToe, TicTacToeMove},
+        ultimatetictactoe::UltimateTicTacToe,
+    };
+
+    #[test]
+    fn repetitions_are_counted_back_to_the_last_irreversible_move() {
+        let mut history = RepetitionHistory::default();
+        assert_eq!(history.repetitions(), 0);
+        for (hash, irreversible) in [(1, true), (2, false), (1, false), (3, true), (1, false)] {
+            history.push(hash, irreversible);
+        }
+        // the first two occurrences of 1 were before the irreversible move to 3.
+        assert_eq!(history.repetitions(), 1);
+        history.pop();
+        history.pop();
+        assert_eq!(history.repetitions(), 2);
+    }
+
+    /// Checks that every legal move parses back from its name, through a few random games.
+    fn assert_move_names_round_trip<G: Game>() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut moves = G::Buffer::default();
+        for _ in 0..5 {
+            let mut state = G::default();
+            for _ in 0..40 {
+                if state.is_terminal() {
+                    break;
+                }
+                moves.clear();
+                state.legal_moves(&mut moves);
+                for &m in moves.iter() {
+                    assert_eq!(state.parse_move(&m.to_string()), Ok(m), "in\n{state}");
+                    assert_eq!(state.parse_move(&m.to_string().to_uppercase()), Ok(m));
+                }
+                state.push_random_or_pass(&mut rng);
+            }
+            assert!(state.parse_move("not a move").is_err());
+        }
+    }
+
+    #[test]
+    fn move_names_round_trip() {
+        assert_move_names_round_trip::<TicTacToe>();
+        assert_move_names_round_trip::<Connect4>();
+        assert_move_names_round_trip::<Gomoku<9>>();
+        assert_move_names_round_trip::<UltimateTicTacToe>();
+        assert_move_names_round_trip::<Chess>();
+    }
+
+    /// Checks that the first move of `G` is kept as its last move, and highlighted.
+    fn assert_last_move_is_shown<G: Game>() {
+        let start = G::default();
+        assert_eq!(start.last_move(), None);
+        assert!(!start.to_string().contains(HIGHLIGHT));
+        let mut moves = G::Buffer::default();
+        start.legal_moves(&mut moves);
+        let m = *moves.iter().last().unwrap();
+        let mut state = start.clone();
+        state.push(m);
+        assert_eq!(state.last_move(), Some(m));
+        assert!(state.to_string().contains(HIGHLIGHT), "in\n{state}");
+        if G::CAN_POP {
+            state.pop(m);
+            assert_eq!(state, start);
+        }
+    }
+
+    #[test]
+    fn last_moves_are_shown_but_not_compared() {
+        assert_last_move_is_shown::<TicTacToe>();
+        assert_last_move_is_shown::<Connect4>();
+        assert_last_move_is_shown::<Gomoku<9>>();
+        assert_last_move_is_shown::<UltimateTicTacToe>();
+        assert_last_move_is_shown::<Chess>();
+        // the same position, reached in a different order.
+        let mut a = TicTacToe::new();
+        let mut b = TicTacToe::new();
+        for i in [0, 4, 8] {
+            a.push(TicTacToeMove::new(i));
+            b.push(TicTacToeMove::new(8 - i));
+        }
+        assert_ne!(a.last_move(), b.last_move());
+        assert_eq!(a, b);
+    }
+}
@@ -1,15 +1,146 @@
-#![allow(dead_code)]
-
-use std::io::Write;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    str::FromStr,
+};
 
 use crate::{
     agent::Agent,
     elo,
     game::{Game, MoveBuffer},
-    mcts::{MCTSExt, MCTS},
+    interrupt,
+    mcts::{Behaviour, Limit, MCTSExt, Schedule, MCTS},
+    progress::Progress,
+    verify,
 };
 
+/// The fewest games a match plays before it may stop early, so that a short run of
+/// wins is not mistaken for a real difference in strength.
+const MIN_GAMES_BEFORE_STOPPING: usize = 16;
+
+/// The most legal moves listed for a human to choose from. Longer lists, such as every
+/// empty point of a large Gomoku board, are too long to read.
+const MAX_LISTED_MOVES: usize = 64;
+
+const RED: &str = "\u{001b}[31m";
+const GREEN: &str = "\u{001b}[32m";
+const RESET: &str = "\u{001b}[0m";
+
+/// The move number, counted from one as in chess, of the move after `plies` plies.
+fn move_number(plies: usize) -> u32 {
+    u32::try_from(plies / 2 + 1).unwrap_or(u32::MAX)
+}
+
+/// A disadvantage given to one player of a match, to measure how much stronger it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Handicap {
+    /// The fraction of its rollout limit that the player may use.
+    pub nodes: Option<f64>,
+    /// The fraction of its time limit that the player may use.
+    pub time: Option<f64>,
+    /// Whether the player gives odds, so that its opponent starts each game ahead.
+    /// Only games with [`Game::with_odds`] support this.
+    pub odds: bool,
+}
+
+impl Handicap {
+    /// Scales the search limit of `behaviour`, and those of its schedule, by the handicap's
+    /// ratio. A ratio that does not match the kind of limit is an error.
+    pub fn apply(&self, behaviour: &mut Behaviour) -> Result<(), String> {
+        behaviour.limit = self.scale(behaviour.limit)?;
+        for limit in behaviour.schedule.iter_mut().flat_map(Schedule::limits_mut) {
+            *limit = self.scale(*limit)?;
+        }
+        Ok(())
+    }
+
+    fn scale(&self, limit: Limit) -> Result<Limit, String> {
+        match limit {
+            Limit::Rollouts(_) if self.time.is_some() => {
+                Err("a time handicap needs a time limit".to_string())
+            }
+            Limit::Time(_) if self.nodes.is_some() => {
+                Err("a node handicap needs a rollout limit".to_string())
+            }
+            Limit::Rollouts(rollouts) => {
+                let ratio = self.nodes.unwrap_or(1.0);
+                #[allow(
+                    clippy::cast_possible_truncation,
+                    clippy::cast_precision_loss,
+                    clippy::cast_sign_loss
+                )]
+                let rollouts = (rollouts as f64 * ratio).round() as u64;
+                Ok(Limit::Rollouts(rollouts.max(1)))
+            }
+            Limit::Time(time) => Ok(Limit::Time(time.mul_f64(self.time.unwrap_or(1.0)))),
+            Limit::Infinite if self.nodes.is_some() || self.time.is_some() => {
+                Err("an infinite search cannot be handicapped".to_string())
+            }
+            Limit::Infinite => Ok(Limit::Infinite),
+        }
+    }
+}
+
+impl FromStr for Handicap {
+    type Err = String;
+
+    /// Parses a comma-separated list of `nodes=<ratio>`, `time=<ratio>`, and `odds`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut handicap = Self::default();
+        for part in s.split(',').map(str::trim) {
+            if part == "odds" {
+                handicap.odds = true;
+                continue;
+            }
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid handicap, expected `key=value`: {part}"))?;
+            let ratio = value
+                .parse::<f64>()
+                .ok()
+                .filter(|&r| r.is_finite() && r > 0.0)
+                .ok_or_else(|| {
+                    format!("invalid handicap, {key} must be a positive ratio: {value}")
+                })?;
+            match key {
+                "nodes" => handicap.nodes = Some(ratio),
+                "time" => handicap.time = Some(ratio),
+                _ => return Err(format!("invalid handicap, unknown key: {key}")),
+            }
+        }
+        Ok(handicap)
+    }
+}
+
+/// How a match is played and reported, and when it may finish early.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchOptions {
+    /// The confidence level of the Elo error margins, and of the early stopping test.
+    pub confidence: f64,
+    /// Print the running Elo estimate after every this many games.
+    pub report_every: Option<usize>,
+    /// Stop as soon as the result is significant at the confidence level.
+    pub stop_when_significant: bool,
+    /// The handicaps of the first and second players.
+    pub handicaps: [Handicap; 2],
+    /// End games as soon as an alpha-beta search of this many plies proves their result.
+    pub adjudicate: Option<usize>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            confidence: elo::DEFAULT_CONFIDENCE,
+            report_every: None,
+            stop_when_significant: false,
+            handicaps: [Handicap::default(); 2],
+            adjudicate: None,
+        }
+    }
+}
+
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Player<'a, G: Game> {
     Human,
     Computer(MCTS<'a, G>),
@@ -21,20 +152,25 @@ impl<G: Game + MCTSExt> Agent<G> for Player<'_, G> {
         match self {
             Self::Human => {
                 let mut buffer = G::Buffer::default();
-                state.generate_moves(&mut buffer);
-                println!("Your options are:");
-                println!("{buffer}");
+                state.legal_moves(&mut buffer);
+                if buffer.len() <= MAX_LISTED_MOVES {
+                    println!("Your options are:");
+                    println!("{buffer}");
+                } else {
+                    println!(
+                        "You have {} legal moves, such as {}.",
+                        buffer.len(),
+                        buffer[0]
+                    );
+                }
                 let user_move = loop {
                     print!("Enter move: ");
                     std::io::stdout().flush().unwrap();
                     let mut user_input = String::new();
                     std::io::stdin().read_line(&mut user_input).unwrap();
-                    let user_input = user_input.trim().to_uppercase();
-                    let needle = buffer
-                        .iter()
-                        .find(|&&m| format!("{m}").to_uppercase() == user_input);
-                    if let Some(needle) = needle {
-                        break *needle;
+                    match state.parse_move(&user_input) {
+                        Ok(m) => break m,
+                        Err(e) => println!("{e}"),
                     }
                 };
 
@@ -44,10 +180,140 @@ impl<G: Game + MCTSExt> Agent<G> for Player<'_, G> {
             Self::Computer(agent) => agent.best_next_board(&state),
         }
     }
+
+    fn resign(&mut self, _state: &G) -> bool {
+        match self {
+            Self::Human => false,
+            Self::Computer(engine) => engine.wants_to_resign(),
+        }
+    }
+
+    fn offer_draw(&mut self, _state: &G) -> bool {
+        match self {
+            Self::Human => false,
+            Self::Computer(engine) => engine.wants_a_draw(),
+        }
+    }
+}
+
+/// A move played in a match, with what the engine that played it thought of the position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnnotatedMove<M> {
+    pub played: M,
+    /// The search results, or `None` if a human played the move.
+    pub search: Option<MoveStats>,
+}
+
+/// What an engine's search reported about the move it chose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStats {
+    /// The win rate after the search, from the perspective of the player who moved.
+    pub win_rate: f64,
+    /// The number of rollouts spent on the chosen move.
+    pub visits: u64,
+    /// The number of rollouts in the whole search.
+    pub rollouts: u64,
+}
+
+impl<M: Display> Display for AnnotatedMove<M> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.played)?;
+        if let Some(stats) = self.search {
+            write!(
+                f,
+                " {{wr {:.3}, {}/{} visits}}",
+                stats.win_rate, stats.visits, stats.rollouts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How a game of a match ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Termination {
+    /// The game was played until its rules ended it.
+    #[default]
+    Rules,
+    /// The game was ended early, with its result proven by search.
+    Adjudicated,
+    /// The player who moved last resigned.
+    Resignation,
+    /// One player offered a draw, and the other accepted it.
+    Agreement,
+}
+
+impl Termination {
+    /// The ways a game can end before its rules end it.
+    const EARLY: [Self; 3] = [Self::Adjudicated, Self::Resignation, Self::Agreement];
+
+    /// The label of the termination in game logs and match summaries.
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Rules => "normal",
+            Self::Adjudicated => "adjudicated",
+            Self::Resignation => "resigned",
+            Self::Agreement => "drawn by agreement",
+        }
+    }
+}
+
+/// A finished game of a match: every move with what the engine that played it thought of
+/// the position, and how the game ended. Match statistics and the game log are both
+/// worked out from it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameTrace<M> {
+    /// The side to move in the starting position.
+    pub first_mover: i8,
+    /// Whether the players were swapped, so that the second player of the match was X.
+    pub flip: bool,
+    pub moves: Vec<AnnotatedMove<M>>,
+    /// 1 if X won, -1 if O won, and 0 for a draw.
+    pub result: i8,
+    pub termination: Termination,
+}
+
+impl<M> GameTrace<M> {
+    /// The result from the perspective of the first player of the match.
+    pub const fn first_player_result(&self) -> i8 {
+        if self.flip {
+            -self.result
+        } else {
+            self.result
+        }
+    }
+
+    /// The win rate for X after each move, as the engine that played it saw it,
+    /// or `None` for the moves of human players.
+    pub fn eval_history(&self) -> impl Iterator<Item = Option<f64>> + '_ {
+        let mut mover = self.first_mover;
+        self.moves.iter().map(move |m| {
+            let win_rate = m.search.map(|stats| {
+                if mover == 1 {
+                    stats.win_rate
+                } else {
+                    1.0 - stats.win_rate
+                }
+            });
+            mover = -mover;
+            win_rate
+        })
+    }
+}
+
+/// Where match games are written to, and the names of the players to record.
+struct GameLog {
+    writer: Box<dyn Write>,
+    names: [String; 2],
 }
 
 pub struct GameRunner<'a, G: Game> {
     players: [Player<'a, G>; 2],
+    game_log: Option<GameLog>,
+    /// The traces of the games of matches so far, if they are being kept.
+    traces: Option<Vec<GameTrace<G::Move>>>,
+    /// The position that games start from, if not the standard starting position.
+    start: Option<G>,
 }
 
 impl<'a, G: Game + Default + MCTSExt> GameRunner<'a, G> {
@@ -58,15 +324,44 @@ impl<'a, G: Game + Default + MCTSExt> GameRunner<'a, G> {
     {
         Self {
             players: [player1, player2],
+            game_log: None,
+            traces: None,
+            start: None,
         }
     }
 
+    /// Starts subsequent games from `start`, such as the starting position of a variant,
+    /// rather than the standard starting position.
+    pub fn start_from(&mut self, start: G) {
+        self.start = Some(start);
+    }
+
+    fn start(&self) -> G {
+        self.start.clone().unwrap_or_default()
+    }
+
+    /// Keeps the trace of every game of subsequent matches, for [`traces`](Self::traces).
+    pub fn keep_traces(&mut self) {
+        self.traces.get_or_insert_with(Vec::new);
+    }
+
+    /// The traces of the games played since [`keep_traces`](Self::keep_traces) was called.
+    pub fn traces(&self) -> &[GameTrace<G::Move>] {
+        self.traces.as_deref().unwrap_or_default()
+    }
+
+    /// Writes every game of subsequent matches to `writer`, with each move annotated with
+    /// the mover's win rate and visit counts. `names` are the names of the two players.
+    pub fn log_games_to(&mut self, writer: Box<dyn Write>, names: [String; 2]) {
+        self.game_log = Some(GameLog { writer, names });
+    }
+
     fn do_printout(&self) -> bool {
         self.players.iter().any(|p| matches!(p, Player::Human))
     }
 
     pub fn run(&mut self) {
-        self.run_with(G::default());
+        self.run_with(self.start());
     }
 
     pub fn run_with(&mut self, state: G) {
@@ -84,6 +379,11 @@ impl<'a, G: Game + Default + MCTSExt> GameRunner<'a, G> {
             if self.do_printout() {
                 println!();
             }
+            if interrupt::requested() {
+                println!("{state}");
+                println!("game interrupted");
+                return;
+            }
         }
         if self.do_printout() {
             println!("{state}");
@@ -91,101 +391,495 @@ impl<'a, G: Game + Default + MCTSExt> GameRunner<'a, G> {
         }
     }
 
-    /// Returns the result of the encounter, where 1 means X won, -1 means the O won, and 0 means a draw.
+    /// Plays the encounter from `start`, returning `None` if it was interrupted by Ctrl+C.
     /// The `flip` parameter indicates whether the players are flipped.
-    fn do_encounter(players: &mut [Player<G>; 2], flip: bool) -> i8 {
-        let mut state = G::default();
+    /// If `adjudicate` is given, the encounter ends as soon as a search of that many plies
+    /// proves its result.
+    pub fn play_game(
+        &mut self,
+        start: G,
+        flip: bool,
+        adjudicate: Option<usize>,
+    ) -> Option<GameTrace<G::Move>> {
+        Self::do_encounter(&mut self.players, flip, start, adjudicate)
+    }
+
+    fn do_encounter(
+        players: &mut [Player<G>; 2],
+        flip: bool,
+        start: G,
+        adjudicate: Option<usize>,
+    ) -> Option<GameTrace<G::Move>> {
+        let first_mover = start.turn();
+        let mut state = start;
+        let mut moves = Vec::new();
         let alternator = if flip { -1 } else { 1 };
+        // whether the player who moved last offered a draw.
+        let mut draw_offered = false;
         while !state.is_terminal() {
+            if let Some(result) = adjudicate.and_then(|depth| verify::solve(&state, depth)) {
+                return Some(GameTrace {
+                    first_mover,
+                    flip,
+                    moves,
+                    result,
+                    termination: Termination::Adjudicated,
+                });
+            }
             let turn = state.turn() * alternator;
             let player = match turn {
                 1 => &mut players[0],
                 -1 => &mut players[1],
                 _ => panic!("Invalid turn"),
             };
-            state = player.transition(state);
+            let moving = state.turn();
+            let mut legal = G::Buffer::default();
+            state.legal_moves(&mut legal);
+            let annotated = match player {
+                Player::Computer(engine) => {
+                    engine.set_move_number(move_number(moves.len()));
+                    let results = engine.search(&state);
+                    let index = legal
+                        .iter()
+                        .position(|&m| m == results.best_move)
+                        .expect("the engine chose an illegal move");
+                    state = results.new_node;
+                    AnnotatedMove {
+                        played: results.best_move,
+                        search: Some(MoveStats {
+                            win_rate: results.win_rate,
+                            visits: results.rollout_distribution[index],
+                            rollouts: results.rollouts,
+                        }),
+                    }
+                }
+                Player::Human => {
+                    let before = state.clone();
+                    state = player.transition(state);
+                    let human_move = *legal
+                        .iter()
+                        .find(|&&m| {
+                            let mut after = before.clone();
+                            after.push(m);
+                            after == state
+                        })
+                        .expect("the human's move is not legal");
+                    AnnotatedMove {
+                        played: human_move,
+                        search: None,
+                    }
+                }
+            };
+            moves.push(annotated);
+            if interrupt::requested() {
+                return None;
+            }
+            if state.is_terminal() {
+                break;
+            }
+            let ending = if player.resign(&state) {
+                Some((-moving, Termination::Resignation))
+            } else if draw_offered && player.accept_draw(&state) {
+                Some((0, Termination::Agreement))
+            } else {
+                draw_offered = player.offer_draw(&state);
+                None
+            };
+            if let Some((result, termination)) = ending {
+                return Some(GameTrace {
+                    first_mover,
+                    flip,
+                    moves,
+                    result,
+                    termination,
+                });
+            }
+        }
+        Some(GameTrace {
+            first_mover,
+            flip,
+            moves,
+            result: state.evaluate(),
+            termination: Termination::Rules,
+        })
+    }
+
+    /// Writes one game to the game log, if there is one, in a PGN-like format.
+    fn log_game(&mut self, number: usize, trace: &GameTrace<G::Move>) {
+        let Some(log) = &mut self.game_log else {
+            return;
+        };
+        let GameTrace {
+            flip,
+            moves,
+            result,
+            termination,
+            ..
+        } = trace;
+        let [first, second] = &log.names;
+        let (first, second) = if *flip {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let result = match result {
+            1 => "1-0",
+            -1 => "0-1",
+            _ => "1/2-1/2",
+        };
+        let write = |writer: &mut dyn Write| -> io::Result<()> {
+            writeln!(writer, "[Game \"{number}\"]")?;
+            writeln!(writer, "[First \"{first}\"]")?;
+            writeln!(writer, "[Second \"{second}\"]")?;
+            writeln!(writer, "[Result \"{result}\"]")?;
+            if *termination != Termination::Rules {
+                writeln!(writer, "[Termination \"{}\"]", termination.label())?;
+            }
+            for (i, pair) in moves.chunks(2).enumerate() {
+                write!(writer, "{}. {}", i + 1, pair[0])?;
+                if let Some(reply) = pair.get(1) {
+                    write!(writer, " {reply}")?;
+                }
+                writeln!(writer)?;
+            }
+            writeln!(writer, "{result}")?;
+            writeln!(writer)?;
+            writer.flush()
+        };
+        write(&mut log.writer).unwrap_or_else(|e| panic!("failed to write to the game log: {e}"));
+    }
+
+    /// The running score of a match from the first player's perspective, with an Elo estimate.
+    fn score_message(results: [i32; 3], confidence: f64) -> String {
+        let [wins, draws, losses] = results;
+        let elo = elo::difference(wins, losses, draws, confidence);
+        if elo.difference.is_finite() && elo.error.is_finite() {
+            format!(
+                "W-D-L {wins}-{draws}-{losses} | Elo {:+.0} ± {:.0}",
+                elo.difference, elo.error
+            )
+        } else {
+            format!("W-D-L {wins}-{draws}-{losses}")
         }
-        state.evaluate()
     }
 
-    pub fn play_match(&mut self, games: usize) {
-        const RED: &str = "\u{001b}[31m";
-        const GREEN: &str = "\u{001b}[32m";
-        const RESET: &str = "\u{001b}[0m";
+    /// The starting positions of games with the first player moving first, and moving
+    /// second, if either player gives odds.
+    fn odds_starts(handicaps: &[Handicap; 2]) -> Option<[G; 2]> {
+        // the side that receives odds when the first player moves first.
+        let side = match handicaps.map(|h| h.odds) {
+            [false, false] => return None,
+            [true, false] => -1,
+            [false, true] => 1,
+            [true, true] => panic!("only one player can give odds"),
+        };
+        let odds = |side| G::with_odds(side).expect("this game does not support odds");
+        Some([odds(side), odds(-side)])
+    }
 
+    /// Plays a match of up to `games` games, and returns the Elo difference of the first
+    /// player over the second, with error margins at the confidence level of `options`.
+    ///
+    /// Colours alternate halfway through the match, or after every game if the match
+    /// may stop early, so that it is always fair when it stops.
+    pub fn play_match(&mut self, games: usize, options: &MatchOptions) -> elo::Difference {
+        let confidence = options.confidence;
         println!("Running a {games}-game match...");
         assert_eq!(games % 2, 0, "Number of games must be even");
+        assert!(
+            options.adjudicate.is_none() || G::CAN_POP,
+            "adjudication needs a game that can unmake moves"
+        );
         let mut results = [0; 3];
+        // results of the games that were adjudicated, resigned, and drawn by agreement.
+        let mut ended_early = Termination::EARLY.map(|termination| (termination, [0; 3]));
+        // results with the first player moving first, and moving second.
+        let mut by_colour = [[0; 3]; 2];
         let mut first_player_wins = 0;
         let mut second_player_wins = 0;
-        for i in 0..games / 2 {
-            print!(" Game {}/{games}    \r", i + 1);
-            std::io::stdout().flush().unwrap();
-            let result = Self::do_encounter(&mut self.players, false);
-            match result {
-                1 => results[0] += 1,  // X wins, so the first player wins
-                0 => results[1] += 1,  // Draw, so no one wins
-                -1 => results[2] += 1, // O wins, so the second player wins
+        let handicapped_start = Self::odds_starts(&options.handicaps);
+        assert!(
+            handicapped_start.is_none() || self.start.is_none(),
+            "odds can only be given from the standard starting position"
+        );
+        let mut progress = Progress::new(games as u64, "games");
+        for game in 0..games {
+            let flip = if options.stop_when_significant {
+                game % 2 == 1
+            } else {
+                game >= games / 2
+            };
+            let start = handicapped_start
+                .as_ref()
+                .map_or_else(|| self.start(), |starts| starts[usize::from(flip)].clone());
+            let adjudicate = options.adjudicate;
+            let Some(trace) = Self::do_encounter(&mut self.players, flip, start, adjudicate) else {
+                progress.println(&format!("interrupted: stopping after {game} games"));
+                break;
+            };
+            self.log_game(game + 1, &trace);
+            let outcome = match trace.first_player_result() {
+                1 => 0,  // the first player wins
+                0 => 1,  // Draw, so no one wins
+                -1 => 2, // the second player wins
                 _ => panic!("Invalid result"),
+            };
+            results[outcome] += 1;
+            if let Some((_, counts)) = ended_early
+                .iter_mut()
+                .find(|(t, _)| *t == trace.termination)
+            {
+                counts[outcome] += 1;
             }
-            match result {
+            by_colour[usize::from(flip)][outcome] += 1;
+            match trace.result {
                 1 => first_player_wins += 1,
                 -1 => second_player_wins += 1,
                 _ => (),
             }
-        }
-        let first_half = results;
-        for i in games / 2..games {
-            print!(" Game {}/{games}    \r", i + 1);
-            std::io::stdout().flush().unwrap();
-            let result = Self::do_encounter(&mut self.players, true);
-            match result {
-                1 => results[2] += 1,  // X wins, so the second player wins
-                0 => results[1] += 1,  // Draw, so no one wins
-                -1 => results[0] += 1, // O wins, so the first player wins
-                _ => panic!("Invalid result"),
+            if let Some(traces) = &mut self.traces {
+                traces.push(trace);
             }
-            match result {
-                1 => first_player_wins += 1,
-                -1 => second_player_wins += 1,
-                _ => (),
+            progress.set_message(Self::score_message(results, confidence));
+            progress.inc();
+
+            let played = game + 1;
+            if options.report_every.is_some_and(|k| played % k == 0) {
+                progress.println(&format!(
+                    "after {played} games: {}",
+                    Self::score_message(results, confidence)
+                ));
+            }
+            if options.stop_when_significant
+                && played % 2 == 0
+                && played >= MIN_GAMES_BEFORE_STOPPING
+                && played < games
+                && elo::difference(results[0], results[2], results[1], confidence).is_significant()
+            {
+                progress.println(&format!(
+                    "stopping after {played} games: the result is significant at {:.0}% confidence",
+                    confidence * 100.0
+                ));
+                break;
             }
         }
-        let second_half = [
-            results[0] - first_half[0],
-            results[1] - first_half[1],
-            results[2] - first_half[2],
-        ];
-        println!(" Game {games}/{games}    ");
+        let [first, second] = if options.stop_when_significant {
+            [" (moving first)", " (moving second)"]
+        } else {
+            [" (first half)", " (second half)"]
+        };
+        progress.finish();
         println!("{RESET}");
-        println!(
-            "wins: {GREEN}{}{RESET}, draws: {}, losses: {RED}{}{RESET}",
-            results[0], results[1], results[2]
-        );
-        println!(
-            "wins: {GREEN}{}{RESET}, draws: {}, losses: {RED}{}{RESET} (first half)",
-            first_half[0], first_half[1], first_half[2]
-        );
-        println!(
-            "wins: {GREEN}{}{RESET}, draws: {}, losses: {RED}{}{RESET} (second half)",
-            second_half[0], second_half[1], second_half[2]
-        );
+        let mut rows = vec![
+            (results, String::new()),
+            (by_colour[0], first.to_string()),
+            (by_colour[1], second.to_string()),
+        ];
+        rows.extend(Self::early_ending_rows(ended_early, options));
+        Self::print_rows(rows);
         println!(
             "going first resulted in {GREEN}{first_player_wins}{RESET} wins, {RED}{second_player_wins}{RESET} losses"
         );
-        let elo = elo::difference(results[0], results[2], results[1]);
+        let elo = elo::difference(results[0], results[2], results[1], confidence);
+        Self::print_elo(&elo);
+        elo
+    }
+
+    /// The rows of the match summary for the games that ended early: adjudicated games
+    /// whenever adjudication is on, and resigned games and agreed draws if there were any.
+    fn early_ending_rows(
+        ended_early: [(Termination, [i32; 3]); 3],
+        options: &MatchOptions,
+    ) -> impl Iterator<Item = ([i32; 3], String)> + '_ {
+        ended_early
+            .into_iter()
+            .filter(|&(termination, results)| {
+                if termination == Termination::Adjudicated {
+                    options.adjudicate.is_some()
+                } else {
+                    results.iter().sum::<i32>() > 0
+                }
+            })
+            .map(|(termination, results)| (results, format!(" ({})", termination.label())))
+    }
+
+    /// Prints the wins, draws, and losses of each row of the match summary.
+    fn print_rows(rows: Vec<([i32; 3], String)>) {
+        for ([wins, draws, losses], label) in rows {
+            println!(
+                "wins: {GREEN}{wins}{RESET}, draws: {draws}, losses: {RED}{losses}{RESET}{label}"
+            );
+        }
+    }
+
+    /// Prints the Elo difference of a match, and whether it is significant.
+    fn print_elo(elo: &elo::Difference) {
         let control = if elo.difference > 0.0 { GREEN } else { RED };
         println!(
-            "Elo difference: {control}{:+.1}{RESET}, error: ±{:.1}",
-            elo.difference, elo.error
+            "Elo difference: {control}{:+.1}{RESET}, error: ±{:.1} ({:.0}% confidence)",
+            elo.difference,
+            elo.error,
+            elo.confidence * 100.0
+        );
+        println!(
+            "nElo difference: {control}{:+.1}{RESET}, error: ±{:.1}, draw ratio: {:.1}%",
+            elo.nelo,
+            elo.nelo_error,
+            elo.draw_ratio * 100.0
         );
         println!(
             "Test results significant? {}",
-            if elo.difference.abs() < elo.error {
-                format!("{RED}NO{RESET}")
-            } else {
+            if elo.is_significant() {
                 format!("{GREEN}YES{RESET}")
+            } else {
+                format!("{RED}NO{RESET}")
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::games::{chess::Chess, gomoku::Gomoku, tictactoe::TicTacToe};
+
+    #[test]
+    fn handicaps_scale_the_limit() {
+        let handicap = "nodes=0.25,odds".parse::<Handicap>().unwrap();
+        assert!(handicap.odds);
+        let mut behaviour = Behaviour {
+            limit: Limit::Rollouts(1000),
+            ..Behaviour::default()
+        };
+        handicap.apply(&mut behaviour).unwrap();
+        assert_eq!(behaviour.limit, Limit::Rollouts(250));
+
+        behaviour.limit = Limit::Time(Duration::from_secs(1));
+        assert!(handicap.apply(&mut behaviour).is_err());
+        "time=0.5"
+            .parse::<Handicap>()
+            .unwrap()
+            .apply(&mut behaviour)
+            .unwrap();
+        assert_eq!(behaviour.limit, Limit::Time(Duration::from_millis(500)));
+
+        behaviour.limit = Limit::Rollouts(1000);
+        behaviour.schedule = Some("10@rollouts:400".parse().unwrap());
+        handicap.apply(&mut behaviour).unwrap();
+        assert_eq!(
+            behaviour.schedule.unwrap().limit_at(10, behaviour.limit),
+            Limit::Rollouts(100)
+        );
+
+        assert!("nodes=0".parse::<Handicap>().is_err());
+        assert!("stones=1".parse::<Handicap>().is_err());
+    }
+
+    #[test]
+    fn match_games_are_traced() {
+        let behaviour = Behaviour {
+            limit: Limit::Rollouts(200),
+            ..Behaviour::default()
+        };
+        let mut runner = GameRunner::<TicTacToe>::new(
+            Player::Computer(MCTS::new(&behaviour)),
+            Player::Computer(MCTS::new(&behaviour)),
+        );
+        runner.keep_traces();
+        runner.play_match(2, &MatchOptions::default());
+        let traces = runner.traces();
+        assert_eq!(traces.len(), 2);
+        assert!(!traces[0].flip && traces[1].flip);
+        for trace in traces {
+            let mut state = TicTacToe::new();
+            for m in &trace.moves {
+                state.push(m.played);
             }
+            assert!(state.is_terminal());
+            assert_eq!(trace.result, state.evaluate());
+            let evals = trace.eval_history().collect::<Vec<_>>();
+            assert_eq!(evals.len(), trace.moves.len());
+            assert!(evals
+                .iter()
+                .all(|e| e.is_some_and(|e| (0.0..=1.0).contains(&e))));
+        }
+
+        // adjudicated games stop before the end.
+        let trace = runner.play_game(TicTacToe::new(), false, Some(9)).unwrap();
+        assert!(trace.termination == Termination::Adjudicated && trace.moves.is_empty());
+        assert_eq!(trace.result, 0);
+    }
+
+    #[test]
+    fn engines_resign_and_agree_draws() {
+        let playing_on = Behaviour {
+            limit: Limit::Rollouts(100),
+            ..Behaviour::default()
+        };
+        let hopeless = Behaviour {
+            resign_below: Some(1.0),
+            ..playing_on.clone()
+        };
+        let peaceful = Behaviour {
+            draw_margin: Some(0.5),
+            ..playing_on.clone()
+        };
+        // the second player resigns as soon as it has moved.
+        let mut runner = GameRunner::<TicTacToe>::new(
+            Player::Computer(MCTS::new(&playing_on)),
+            Player::Computer(MCTS::new(&hopeless)),
+        );
+        let trace = runner.play_game(TicTacToe::new(), false, None).unwrap();
+        assert_eq!(trace.termination, Termination::Resignation);
+        assert_eq!((trace.moves.len(), trace.result), (2, 1));
+
+        // a draw offered by the first player is only agreed if the second accepts it.
+        let mut runner = GameRunner::<TicTacToe>::new(
+            Player::Computer(MCTS::new(&peaceful)),
+            Player::Computer(MCTS::new(&playing_on)),
+        );
+        let trace = runner.play_game(TicTacToe::new(), false, None).unwrap();
+        assert_eq!(trace.termination, Termination::Rules);
+        let mut runner = GameRunner::<TicTacToe>::new(
+            Player::Computer(MCTS::new(&peaceful)),
+            Player::Computer(MCTS::new(&peaceful)),
         );
+        let trace = runner.play_game(TicTacToe::new(), false, None).unwrap();
+        assert_eq!(trace.termination, Termination::Agreement);
+        assert_eq!((trace.moves.len(), trace.result), (2, 0));
+
+        assert!("limit=rollouts:1,rollout_policy=random,resign=1.5"
+            .parse::<Behaviour>()
+            .is_err());
+        let behaviour = "limit=rollouts:1,rollout_policy=random,resign=0.05,draw=0.1"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(
+            (behaviour.resign_below, behaviour.draw_margin),
+            (Some(0.05), Some(0.1))
+        );
+    }
+
+    #[test]
+    fn odds_start_the_receiver_ahead() {
+        let [first, second] = GameRunner::<Gomoku<9>>::odds_starts(&[
+            Handicap::default(),
+            Handicap {
+                odds: true,
+                ..Handicap::default()
+            },
+        ])
+        .unwrap();
+        // the first player receives odds, as X and then as O, and X always moves first.
+        assert_eq!(first.turn(), 1);
+        assert!(first.to_string().contains('X') && !first.to_string().contains('O'));
+        assert!(second.to_string().contains('O') && !second.to_string().contains('X'));
+
+        let knight_odds = Chess::with_odds(-1).unwrap();
+        assert_eq!(knight_odds.to_string().matches('N').count(), 1);
+        assert_eq!(knight_odds.to_string().matches('n').count(), 2);
+        assert!(TicTacToe::with_odds(1).is_none());
     }
 }
@@ -0,0 +1,438 @@
+use std::fmt::{Debug, Display};
+
+use cozy_chess::{BitBoard, Color, File, Piece, Rank, Square};
+
+use crate::{
+    datageneration::{StateVector, VectoriseState},
+    game::{splitmix64, ArrayBuffer, Game, LastMove, MoveBuffer, RepetitionHistory},
+    games::chess::write_board,
+    mcts::MCTSExt,
+    nneval::{Evaluation, Network},
+};
+
+/// An upper bound on the number of legal moves in an antichess position: sixteen pieces
+/// that each have as many targets as a queen in the centre. Promotions multiply the moves
+/// of a pawn by five, but a pawn has at most three targets, so they stay within the bound.
+const MAX_MOVES: usize = 16 * 27;
+
+/// The pieces a pawn can promote to. In antichess, a pawn can also become a king.
+const PROMOTIONS: [Piece; 5] = [
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
+
+/// Antichess, or giveaway chess: captures are compulsory, the king is an ordinary piece
+/// that can be captured, and a player wins by losing all of their pieces, or by having
+/// no legal moves. There is no castling, and games are drawn by the fifty-move rule
+/// and by threefold repetition.
+///
+/// `cozy_chess` boards always have a king of each colour, so positions are kept here
+/// as bitboards, with moves generated from `cozy_chess`'s attack tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Antichess {
+    pieces: [BitBoard; Piece::NUM],
+    colours: [BitBoard; Color::NUM],
+    side: Color,
+    /// The square passed over by a pawn that has just moved two squares, if one has.
+    en_passant: Option<Square>,
+    /// The number of plies since the last capture or pawn move.
+    halfmove_clock: u8,
+    history: RepetitionHistory,
+    last_move: LastMove<AntichessMove>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct AntichessMove {
+    from: Square,
+    to: Square,
+    promotion: Option<Piece>,
+}
+
+impl Default for AntichessMove {
+    fn default() -> Self {
+        Self {
+            from: Square::A1,
+            to: Square::A1,
+            promotion: None,
+        }
+    }
+}
+
+/// Moves are written in UCI notation, with `k` for a promotion to a king.
+impl Display for AntichessMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{promotion}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Antichess {
+    /// Parses a FEN. Castling rights are ignored, as there is no castling in antichess,
+    /// and any number of pieces of each kind, including none, is allowed.
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let invalid = |why: &str| format!("invalid fen {fen}: {why}");
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or_else(|| invalid("it is empty"))?;
+        let mut pos = Self {
+            pieces: [BitBoard::EMPTY; Piece::NUM],
+            colours: [BitBoard::EMPTY; Color::NUM],
+            side: Color::White,
+            en_passant: None,
+            halfmove_clock: 0,
+            history: RepetitionHistory::default(),
+            last_move: LastMove(None),
+        };
+        let ranks = placement.split('/').collect::<Vec<_>>();
+        if ranks.len() != Rank::NUM {
+            return Err(invalid("expected eight ranks"));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let rank_index = Rank::index(Rank::NUM - 1 - i);
+            let mut file = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as usize;
+                    continue;
+                }
+                let piece = Piece::try_from(c.to_ascii_lowercase())
+                    .map_err(|_| invalid(&format!("unknown piece {c}")))?;
+                if file >= File::NUM {
+                    return Err(invalid("a rank has more than eight squares"));
+                }
+                let colour = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                pos.toggle(Square::new(File::index(file), rank_index), piece, colour);
+                file += 1;
+            }
+            if file != File::NUM {
+                return Err(invalid("a rank does not have eight squares"));
+            }
+        }
+        pos.side = match fields.next() {
+            Some("w") | None => Color::White,
+            Some("b") => Color::Black,
+            Some(_) => return Err(invalid("the side to move is not w or b")),
+        };
+        let _castling = fields.next();
+        pos.en_passant = match fields.next() {
+            None | Some("-") => None,
+            Some(square) => Some(
+                square
+                    .parse()
+                    .map_err(|_| invalid("the en passant square is not a square"))?,
+            ),
+        };
+        pos.halfmove_clock = fields
+            .next()
+            .map_or(Ok(0), str::parse)
+            .map_err(|_| invalid("the halfmove clock is not a number"))?;
+        pos.history.push(pos.hash(), true);
+        Ok(pos)
+    }
+
+    fn toggle(&mut self, square: Square, piece: Piece, colour: Color) {
+        self.pieces[piece as usize] ^= square.bitboard();
+        self.colours[colour as usize] ^= square.bitboard();
+    }
+
+    fn piece_on(&self, square: Square) -> Option<Piece> {
+        Piece::ALL
+            .into_iter()
+            .find(|&piece| self.pieces[piece as usize].has(square))
+    }
+
+    fn colour_on(&self, square: Square) -> Option<Color> {
+        Color::ALL
+            .into_iter()
+            .find(|&colour| self.colours[colour as usize].has(square))
+    }
+
+    /// Calls `f` with every capture, if `captures` is set, and otherwise with every move
+    /// that is not a capture, ignoring whether captures are compulsory.
+    fn for_each_move(&self, captures: bool, mut f: impl FnMut(AntichessMove)) {
+        let ours = self.colours[self.side as usize];
+        let theirs = self.colours[!self.side as usize];
+        let occupied = ours | theirs;
+        let last_rank = Rank::Eighth.relative_to(self.side);
+        for from in ours {
+            let piece = self.piece_on(from).expect("a square of ours has no piece");
+            let targets = match piece {
+                Piece::Pawn if captures => {
+                    let en_passant = self.en_passant.map_or(BitBoard::EMPTY, Square::bitboard);
+                    cozy_chess::get_pawn_attacks(from, self.side) & (theirs | en_passant)
+                }
+                Piece::Pawn => cozy_chess::get_pawn_quiets(from, self.side, occupied),
+                Piece::Knight => cozy_chess::get_knight_moves(from),
+                Piece::Bishop => cozy_chess::get_bishop_moves(from, occupied),
+                Piece::Rook => cozy_chess::get_rook_moves(from, occupied),
+                Piece::Queen => {
+                    cozy_chess::get_bishop_moves(from, occupied)
+                        | cozy_chess::get_rook_moves(from, occupied)
+                }
+                Piece::King => cozy_chess::get_king_moves(from),
+            };
+            let targets = if piece == Piece::Pawn {
+                targets
+            } else if captures {
+                targets & theirs
+            } else {
+                targets & !occupied
+            };
+            for to in targets {
+                if piece == Piece::Pawn && to.rank() == last_rank {
+                    for promotion in PROMOTIONS {
+                        f(AntichessMove {
+                            from,
+                            to,
+                            promotion: Some(promotion),
+                        });
+                    }
+                } else {
+                    f(AntichessMove {
+                        from,
+                        to,
+                        promotion: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether the side to move has a move, capture or not.
+    fn has_moves(&self) -> bool {
+        let mut any = false;
+        self.for_each_move(true, |_| any = true);
+        if !any {
+            self.for_each_move(false, |_| any = true);
+        }
+        any
+    }
+}
+
+impl Default for Antichess {
+    fn default() -> Self {
+        Self::from_fen(STARTPOS).expect("the starting position is valid")
+    }
+}
+
+impl Display for Antichess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let highlighted = self
+            .last_move
+            .0
+            .map_or_else(Vec::new, |m| vec![m.from, m.to]);
+        write_board(
+            f,
+            |square| {
+                let piece = char::from(self.piece_on(square)?);
+                Some(if self.colour_on(square)? == Color::White {
+                    piece.to_ascii_uppercase()
+                } else {
+                    piece
+                })
+            },
+            &highlighted,
+        )
+    }
+}
+
+impl Game for Antichess {
+    type Move = AntichessMove;
+    type Buffer = ArrayBuffer<AntichessMove, MAX_MOVES>;
+
+    /// Moves are indexed by their from and to squares, as in chess.
+    const MOVE_SPACE: usize = 64 * 64;
+    const REPETITION_LIMIT: Option<usize> = Some(3);
+
+    fn turn(&self) -> i8 {
+        if self.side == Color::White {
+            1
+        } else {
+            -1
+        }
+    }
+
+    /// Generates the captures, if there are any, as one of them must be played.
+    fn generate_moves(&self, moves: &mut Self::Buffer) {
+        self.for_each_move(true, |m| moves.push(m));
+        if moves.is_empty() {
+            self.for_each_move(false, |m| moves.push(m));
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.halfmove_clock >= 100 || self.is_repetition() || !self.has_moves()
+    }
+
+    /// The side to move wins if it has no moves, which it also has if it has no pieces.
+    fn evaluate(&self) -> i8 {
+        if self.has_moves() {
+            0
+        } else {
+            self.turn()
+        }
+    }
+
+    fn push(&mut self, m: Self::Move) {
+        let us = self.side;
+        let piece = self.piece_on(m.from).expect("no piece on the from square");
+        let captured = self.piece_on(m.to);
+        self.toggle(m.from, piece, us);
+        if let Some(victim) = captured {
+            self.toggle(m.to, victim, !us);
+        } else if piece == Piece::Pawn && m.from.file() != m.to.file() {
+            // en passant.
+            self.toggle(Square::new(m.to.file(), m.from.rank()), Piece::Pawn, !us);
+        }
+        self.toggle(m.to, m.promotion.unwrap_or(piece), us);
+        let double_push = piece == Piece::Pawn
+            && m.from.rank() == Rank::Second.relative_to(us)
+            && m.to.rank() == Rank::Fourth.relative_to(us);
+        self.en_passant =
+            double_push.then(|| Square::new(m.from.file(), Rank::Third.relative_to(us)));
+        self.halfmove_clock = if piece == Piece::Pawn || captured.is_some() {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+        self.side = !us;
+        self.history.push(self.hash(), self.halfmove_clock == 0);
+        self.last_move = LastMove(Some(m));
+    }
+
+    fn push_random(&mut self, rng: &mut fastrand::Rng) {
+        let mut moves = Self::Buffer::default();
+        self.generate_moves(&mut moves);
+        self.push(moves[rng.usize(..moves.len())]);
+    }
+
+    fn hash(&self) -> u64 {
+        let en_passant = self.en_passant.map_or(64, |square| square as u64);
+        let start = splitmix64(en_passant << 1 | self.side as u64);
+        self.pieces
+            .iter()
+            .chain(&self.colours)
+            .fold(start, |hash, bb| splitmix64(hash ^ bb.0))
+    }
+
+    fn repetition_history(&self) -> Option<&RepetitionHistory> {
+        Some(&self.history)
+    }
+
+    fn move_index(m: Self::Move) -> usize {
+        m.from as usize * 64 + m.to as usize
+    }
+
+    fn last_move(&self) -> Option<Self::Move> {
+        self.last_move.0
+    }
+}
+
+impl MCTSExt for Antichess {
+    fn typical_game_length() -> usize {
+        60
+    }
+
+    /// Compulsory captures keep the number of legal moves well below that of chess.
+    fn branching_factor(_ply: usize) -> usize {
+        20
+    }
+    fn network_evaluations(states: &[Self], network: &Network) -> Result<Vec<Evaluation>, String> {
+        network.evaluate_all(states)
+    }
+}
+
+impl VectoriseState for Antichess {
+    fn csv_header() -> String {
+        String::new()
+    }
+
+    fn vectorise_state(&self) -> StateVector {
+        StateVector { data: Vec::new() }
+    }
+
+    fn index_move(_m: Self::Move) -> usize {
+        0
+    }
+
+    fn action_space() -> usize {
+        0
+    }
+
+    fn state_vector_dimensions() -> Vec<usize> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::perft;
+
+    fn move_names(state: &Antichess) -> Vec<String> {
+        let mut moves = ArrayBuffer::default();
+        state.legal_moves(&mut moves);
+        moves.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn shallow_perft() {
+        let mut board = Antichess::default();
+        for (depth, count) in [(1, 20), (2, 400), (3, 8067), (4, 153_299)] {
+            assert_eq!(perft(&mut board, depth), count);
+        }
+    }
+
+    #[test]
+    fn threefold_repetition_draws() {
+        let mut board = Antichess::default();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for m in shuffle.iter().chain(&shuffle[..3]) {
+            let m = board.parse_move(m).unwrap();
+            board.push(m);
+            assert!(!board.is_terminal());
+        }
+        // the starting position, for the third time.
+        let m = board.parse_move("f6g8").unwrap();
+        board.push(m);
+        assert!(board.is_terminal());
+        assert_eq!(board.evaluate(), 0);
+    }
+
+    #[test]
+    fn captures_are_compulsory() {
+        let mut board = Antichess::default();
+        for m in ["e2e3", "b7b5"] {
+            let m = board.parse_move(m).unwrap();
+            board.push(m);
+        }
+        assert_eq!(move_names(&board), ["f1b5"]);
+        // the king can be captured, and a pawn can promote to a king.
+        let board = Antichess::from_fen("8/1P6/8/8/8/8/8/k6K w - - 0 1").unwrap();
+        assert!(move_names(&board).contains(&"b7b8k".to_string()));
+        let board = Antichess::from_fen("8/8/8/8/8/8/1k6/K7 w - - 0 1").unwrap();
+        assert_eq!(move_names(&board), ["a1b2"]);
+    }
+
+    #[test]
+    fn losing_every_piece_wins() {
+        let mut board = Antichess::from_fen("8/8/8/8/8/8/1k6/K7 w - - 0 1").unwrap();
+        assert!(!board.is_terminal());
+        board.push(board.parse_move("a1b2").unwrap());
+        // black has no pieces left, and so wins.
+        assert!(board.is_terminal());
+        assert_eq!(board.evaluate(), -1);
+        assert!(Antichess::from_fen("8/8/8/8 w - - 0 1").is_err());
+    }
+}
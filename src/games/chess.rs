@@ -1,61 +1,114 @@
-use std::{fmt::Display, ops::Index};
+use std::fmt::Display;
 
 use crate::{
-    game::{Game, MoveBuffer},
+    game::{splitmix64, unknown_variant, ArrayBuffer, Game, LastMove, MoveBuffer, HIGHLIGHT},
     mcts::{self, MCTSExt}, datageneration::{VectoriseState, StateVector},
+    nneval::{Evaluation, Network},
 };
 
+/// The maximum number of legal moves in any chess position.
+const MAX_MOVES: usize = 218;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Chess {
     inner: cozy_chess::Board,
+    /// Whether castling moves are written king-takes-rook, as in Chess960.
+    chess960: bool,
+    /// Whether a stalemated player loses, rather than the game being drawn.
+    stalemate_loses: bool,
+    last_move: LastMove<Move>,
 }
 
 impl Default for Chess {
     fn default() -> Self {
         Self {
             inner: cozy_chess::Board::startpos(),
+            chess960: false,
+            stalemate_loses: false,
+            last_move: LastMove(None),
         }
     }
 }
 
 impl Display for Chess {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for rank in [7, 6, 5, 4, 3, 2, 1, 0] {
-            write!(f, "{} ", rank + 1)?;
-            for file in 0..8 {
-                let square = cozy_chess::Square::new(
-                    cozy_chess::File::index(file),
-                    cozy_chess::Rank::index(rank),
-                );
-                let Some(piece) = self.inner.piece_on(square) else {
-                    write!(f, ". ")?;
-                    continue;
-                };
-                let ch = piece.to_string();
-                if self.inner.color_on(square).unwrap() == cozy_chess::Color::White {
-                    write!(f, "{} ", ch.to_ascii_uppercase())?;
-                } else {
-                    write!(f, "{ch} ")?;
-                }
+        // a castling king is highlighted where it lands, not on its rook.
+        let highlighted = self.last_move.0.map_or_else(Vec::new, |m| {
+            vec![m.inner.from, m.castle_to.unwrap_or(m.inner.to)]
+        });
+        write_board(
+            f,
+            |square| {
+                let piece = char::from(self.inner.piece_on(square)?);
+                Some(
+                    if self.inner.color_on(square)? == cozy_chess::Color::White {
+                        piece.to_ascii_uppercase()
+                    } else {
+                        piece
+                    },
+                )
+            },
+            &highlighted,
+        )
+    }
+}
+
+/// Writes a chess board from White's side, with the ranks and files labelled on every side,
+/// given the letter of the piece on each square, and highlighting the squares `highlighted`.
+pub fn write_board(
+    f: &mut std::fmt::Formatter<'_>,
+    piece_on: impl Fn(cozy_chess::Square) -> Option<char>,
+    highlighted: &[cozy_chess::Square],
+) -> std::fmt::Result {
+    const RESET: &str = "\u{001b}[0m";
+    writeln!(f, "  a b c d e f g h")?;
+    for rank in (0..8).rev() {
+        write!(f, "{} ", rank + 1)?;
+        for file in 0..8 {
+            let square = cozy_chess::Square::new(
+                cozy_chess::File::index(file),
+                cozy_chess::Rank::index(rank),
+            );
+            let ch = piece_on(square).unwrap_or('.');
+            if highlighted.contains(&square) {
+                write!(f, "{HIGHLIGHT}{ch}{RESET} ")?;
+            } else {
+                write!(f, "{ch} ")?;
             }
-            writeln!(f)?;
         }
-        writeln!(f, "  a b c d e f g h")
+        writeln!(f, "{}", rank + 1)?;
     }
+    writeln!(f, "  a b c d e f g h")
 }
 
+/// A chess move. Castling is stored king-takes-rook, which is how `cozy_chess` plays it,
+/// but is written as the king moving two squares unless the game is Chess960.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Move(cozy_chess::Move);
+pub struct Move {
+    inner: cozy_chess::Move,
+    /// The square the king lands on, if this is a castling move written in standard notation.
+    castle_to: Option<cozy_chess::Square>,
+}
 
 impl Default for Move {
     fn default() -> Self {
-        Self("a1a1".parse().unwrap())
+        Self {
+            inner: cozy_chess::Move {
+                from: cozy_chess::Square::A1,
+                to: cozy_chess::Square::A1,
+                promotion: None,
+            },
+            castle_to: None,
+        }
     }
 }
 
 impl Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match self.castle_to {
+            Some(to) => write!(f, "{}{to}", self.inner.from),
+            None => write!(f, "{}", self.inner),
+        }
     }
 }
 
@@ -71,67 +124,13 @@ impl Ord for Move {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct _MoveBuffer {
-    buf: [Move; 218],
-    siz: usize,
-}
-
-impl Default for _MoveBuffer {
-    fn default() -> Self {
-        Self {
-            buf: [Move::default(); 218],
-            siz: 0,
-        }
-    }
-}
-
-impl Display for _MoveBuffer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let moves = self
-            .buf
-            .iter()
-            .take(self.siz)
-            .map(ToString::to_string)
-            .collect::<Vec<_>>();
-        write!(f, "{}", moves.join(" "))
-    }
-}
-
-impl Index<usize> for _MoveBuffer {
-    type Output = Move;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.buf[..self.siz][index]
-    }
-}
-
-impl MoveBuffer<Move> for _MoveBuffer {
-    fn iter(&self) -> std::slice::Iter<Move> {
-        self.buf[..self.siz].iter()
-    }
-
-    fn len(&self) -> usize {
-        self.siz
-    }
-
-    fn is_empty(&self) -> bool {
-        self.siz == 0
-    }
-
-    fn push(&mut self, m: Move) {
-        self.buf[self.siz] = m;
-        self.siz += 1;
-    }
-
-    fn capacity(&self) -> usize {
-        self.buf.len()
-    }
-}
-
 impl Game for Chess {
     type Move = Move;
-    type Buffer = _MoveBuffer;
+    type Buffer = ArrayBuffer<Move, MAX_MOVES>;
+
+    /// Moves are indexed by their from and to squares, so promotions to different pieces share an index.
+    const MOVE_SPACE: usize = 64 * 64;
+    const VARIANTS: &'static [&'static str] = &["stalemate-loss"];
 
     fn turn(&self) -> i8 {
         if self.inner.side_to_move() == cozy_chess::Color::White {
@@ -141,9 +140,33 @@ impl Game for Chess {
         }
     }
 
+    /// Parses a move in UCI notation. Standard chess also accepts castling written
+    /// king-takes-rook, as in Chess960.
+    fn parse_move(&self, text: &str) -> Result<Self::Move, String> {
+        let text = text.trim();
+        let parsed = text
+            .to_ascii_lowercase()
+            .parse::<cozy_chess::Move>()
+            .map_err(|_| format!("not a move in UCI notation: {text}"))?;
+        let mut moves = Self::Buffer::default();
+        self.legal_moves(&mut moves);
+        moves
+            .iter()
+            .find(|m| {
+                m.inner == parsed
+                    || m.castle_to.is_some_and(|to| {
+                        m.inner.from == parsed.from && to == parsed.to && parsed.promotion.is_none()
+                    })
+            })
+            .copied()
+            .ok_or_else(|| format!("illegal move: {text}"))
+    }
+
     fn generate_moves(&self, moves: &mut Self::Buffer) {
         self.inner.generate_moves(|m| {
-            m.into_iter().map(Move).for_each(|m| moves.push(m));
+            m.into_iter()
+                .map(|m| self.wrap_move(m))
+                .for_each(|m| moves.push(m));
             false
         });
     }
@@ -153,41 +176,206 @@ impl Game for Chess {
     }
 
     fn evaluate(&self) -> i8 {
-        match self.inner.status() {
-            cozy_chess::GameStatus::Drawn | cozy_chess::GameStatus::Ongoing => 0,
-            cozy_chess::GameStatus::Won => {
-                if self.inner.side_to_move() == cozy_chess::Color::White {
-                    -1
-                } else {
-                    1
-                }
+        let lost = match self.inner.status() {
+            cozy_chess::GameStatus::Ongoing => false,
+            // a drawn game with moves left on the fifty-move clock is a stalemate.
+            cozy_chess::GameStatus::Drawn => {
+                self.stalemate_loses && self.inner.halfmove_clock() < 100
             }
+            cozy_chess::GameStatus::Won => true,
+        };
+        match (lost, self.inner.side_to_move()) {
+            (false, _) => 0,
+            (true, cozy_chess::Color::White) => -1,
+            (true, cozy_chess::Color::Black) => 1,
         }
     }
 
     fn push(&mut self, m: Self::Move) {
-        self.inner.play(m.0);
+        self.inner.play(m.inner);
+        self.last_move = LastMove(Some(m));
     }
 
     fn push_random(&mut self, rng: &mut fastrand::Rng) {
-        let mut moves = Vec::new();
+        // count the moves, then walk the piece move sets again to find the chosen one,
+        // so that no move list has to be materialised.
+        let mut n_moves = 0;
+        self.inner.generate_moves(|m| {
+            n_moves += m.len();
+            false
+        });
+        let mut choice = rng.usize(..n_moves);
+        let mut chosen = None;
         self.inner.generate_moves(|m| {
-            moves.extend(m);
+            if choice < m.len() {
+                chosen = m.into_iter().nth(choice);
+                return true;
+            }
+            choice -= m.len();
             false
         });
-        self.inner.play(moves[rng.usize(..moves.len())]);
+        let chosen = chosen.expect("chosen move index was out of range");
+        self.push(self.wrap_move(chosen));
+    }
+
+    /// Knight odds: the other side starts without its queen's knight.
+    fn with_odds(side: i8) -> Option<Self> {
+        let fen = if side == 1 {
+            "r1bqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        } else {
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1"
+        };
+        Some(Self::from_fen(fen, false).expect("the odds positions are valid"))
+    }
+
+    /// The stalemate-loss variant: a player with no legal moves loses, whether or not
+    /// they are in check.
+    fn with_variant(variant: &str) -> Result<Self, String> {
+        match variant {
+            "stalemate-loss" => Ok(Self {
+                stalemate_loses: true,
+                ..Self::default()
+            }),
+            _ => Err(unknown_variant::<Self>(variant)),
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        // the same position is a different game under different rules.
+        if self.stalemate_loses {
+            splitmix64(self.inner.hash())
+        } else {
+            self.inner.hash()
+        }
+    }
+
+    fn move_index(m: Self::Move) -> usize {
+        m.inner.from as usize * 64 + m.inner.to as usize
+    }
+
+    fn last_move(&self) -> Option<Self::Move> {
+        self.last_move.0
+    }
+
+    /// The balance of material, by the piece values of [`piece_value`], squashed so that
+    /// a queen up is worth about 0.7.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)]
+    fn heuristic_eval(&self) -> Option<f32> {
+        use cozy_chess::{Color, Piece};
+        let material = |color| {
+            Piece::ALL
+                .into_iter()
+                .filter(|&piece| piece != Piece::King)
+                .map(|piece| {
+                    piece_value(piece) * self.inner.colored_pieces(color, piece).len() as i32
+                })
+                .sum::<i32>()
+        };
+        let balance = (material(Color::White) - material(Color::Black)) as f32;
+        Some((balance / 10.0).tanh())
     }
 }
 
 impl MCTSExt for Chess {
+    fn rollout_cutoff_length() -> usize {
+        50
+    }
+
+    fn typical_game_length() -> usize {
+        80
+    }
+
+    fn branching_factor(_ply: usize) -> usize {
+        35
+    }
+
     fn rollout_policy() -> mcts::RolloutPolicy {
-        mcts::RolloutPolicy::DecisiveCutoff { moves: 50 }
+        mcts::RolloutPolicy::DecisiveCutoff {
+            moves: Self::rollout_cutoff_length(),
+        }
+    }
+
+    /// Orders captures by MVV-LVA (most valuable victim, then least valuable attacker),
+    /// ahead of queen promotions, then checks, then quiet moves.
+    fn rollout_priority(&self, m: Self::Move) -> i32 {
+        let board = &self.inner;
+        let m = m.inner;
+        let attacker = board.piece_on(m.from).expect("no piece on the from square");
+        let mut priority = 0;
+        let victim = if board.color_on(m.to) == Some(!board.side_to_move()) {
+            board.piece_on(m.to)
+        } else if attacker == cozy_chess::Piece::Pawn && m.from.file() != m.to.file() {
+            // en passant.
+            Some(cozy_chess::Piece::Pawn)
+        } else {
+            None
+        };
+        if let Some(victim) = victim {
+            priority += 1000 + 10 * piece_value(victim) - piece_value(attacker);
+        }
+        if m.promotion == Some(cozy_chess::Piece::Queen) {
+            priority += 500;
+        }
+        let mut after = board.clone();
+        after.play_unchecked(m);
+        if !after.checkers().is_empty() {
+            priority += 100;
+        }
+        priority
+    }
+    fn network_evaluations(states: &[Self], network: &Network) -> Result<Vec<Evaluation>, String> {
+        network.evaluate_all(states)
+    }
+}
+
+/// The value of a piece for move ordering, in pawns. The king is valued
+/// highest, so that it is the least favoured attacker.
+const fn piece_value(piece: cozy_chess::Piece) -> i32 {
+    match piece {
+        cozy_chess::Piece::Pawn => 1,
+        cozy_chess::Piece::Knight | cozy_chess::Piece::Bishop => 3,
+        cozy_chess::Piece::Rook => 5,
+        cozy_chess::Piece::Queen => 9,
+        cozy_chess::Piece::King => 20,
     }
 }
 
 impl Chess {
-    pub const fn from_raw_board(board: cozy_chess::Board) -> Self {
-        Self { inner: board }
+    pub const fn from_raw_board(board: cozy_chess::Board, chess960: bool) -> Self {
+        Self {
+            inner: board,
+            chess960,
+            stalemate_loses: false,
+            last_move: LastMove(None),
+        }
+    }
+
+    /// Parses a FEN, in either the standard or the Shredder castling notation.
+    pub fn from_fen(fen: &str, chess960: bool) -> Result<Self, String> {
+        // Shredder-FEN is the usual notation for Chess960, so try it first there.
+        let board = cozy_chess::Board::from_fen(fen, chess960)
+            .or_else(|_| cozy_chess::Board::from_fen(fen, !chess960))
+            .map_err(|e| format!("invalid fen {fen}: {e:?}"))?;
+        Ok(Self::from_raw_board(board, chess960))
+    }
+
+    /// Wraps a move in this position, noting how it should be written if it castles.
+    fn wrap_move(&self, m: cozy_chess::Move) -> Move {
+        let board = &self.inner;
+        let castles = board.piece_on(m.from) == Some(cozy_chess::Piece::King)
+            && board.color_on(m.to) == Some(board.side_to_move());
+        let castle_to = (castles && !self.chess960).then(|| {
+            let file = if m.to > m.from {
+                cozy_chess::File::G
+            } else {
+                cozy_chess::File::C
+            };
+            cozy_chess::Square::new(file, m.from.rank())
+        });
+        Move {
+            inner: m,
+            castle_to,
+        }
     }
 }
 
@@ -211,4 +399,100 @@ impl VectoriseState for Chess {
     fn state_vector_dimensions() -> Vec<usize> {
         vec![1]
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_names(state: &Chess) -> Vec<String> {
+        let mut buffer = <Chess as Game>::Buffer::default();
+        state.generate_moves(&mut buffer);
+        buffer.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn heuristic_eval_counts_material() {
+        assert_eq!(Chess::default().heuristic_eval(), Some(0.0));
+        // White is a queen up, and then Black is a rook and a knight up.
+        let up = Chess::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", false).unwrap();
+        let down = Chess::from_fen("rn2k3/8/8/8/8/8/8/4K3 w - - 0 1", false).unwrap();
+        let up = up.heuristic_eval().unwrap();
+        assert!(up > 0.7 && up < 0.75);
+        assert!(down.heuristic_eval().unwrap() < -0.6);
+    }
+
+    #[test]
+    fn rollout_priority_prefers_valuable_captures() {
+        // the e4 pawn and the d1 queen can both take the queen on d5, and the queen can take a
+        // pawn on g4, or give check on a4.
+        let state = Chess::from_fen("4k3/8/8/3q4/4P1p1/8/8/3QK3 w - - 0 1", false).unwrap();
+        let mut buffer = <Chess as Game>::Buffer::default();
+        state.generate_moves(&mut buffer);
+        let priority = |name: &str| {
+            let m = *buffer.iter().find(|m| m.to_string() == name).unwrap();
+            state.rollout_priority(m)
+        };
+        assert!(priority("e4d5") > priority("d1d5"));
+        assert!(priority("d1d5") > priority("d1g4"));
+        assert!(priority("d1g4") > priority("d1a4"));
+        assert!(priority("d1a4") > priority("d1d2"));
+    }
+
+    #[test]
+    fn stalemate_loses_in_its_variant() {
+        // black to move, and stalemated.
+        let fen = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        let standard = Chess::from_fen(fen, false).unwrap();
+        let variant = Chess {
+            stalemate_loses: true,
+            ..standard.clone()
+        };
+        assert!(standard.is_terminal() && variant.is_terminal());
+        assert_eq!(standard.evaluate(), 0);
+        assert_eq!(variant.evaluate(), 1);
+        assert!(Chess::with_variant("stalemate-loss").unwrap().stalemate_loses);
+    }
+
+    #[test]
+    fn castling_notation_follows_variant() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let standard = move_names(&Chess::from_fen(fen, false).unwrap());
+        assert!(standard.contains(&"e1g1".to_string()));
+        assert!(standard.contains(&"e1c1".to_string()));
+        assert!(!standard.contains(&"e1h1".to_string()));
+        // the same position, with Shredder-FEN castling rights.
+        let shredder = "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1";
+        let chess960 = move_names(&Chess::from_fen(shredder, true).unwrap());
+        assert!(chess960.contains(&"e1h1".to_string()));
+        assert!(chess960.contains(&"e1a1".to_string()));
+        assert!(!chess960.contains(&"e1g1".to_string()));
+    }
+
+    #[test]
+    fn castling_parses_in_either_notation() {
+        let state = Chess::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        let castle = state.parse_move("e1g1").unwrap();
+        assert_eq!(state.parse_move("e1h1"), Ok(castle));
+        assert_eq!(castle.to_string(), "e1g1");
+        assert!(state.parse_move("e1e3").is_err());
+        assert!(state.parse_move("castle").is_err());
+    }
+
+    #[test]
+    fn boards_are_labelled_on_every_side() {
+        let mut state = Chess::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        state.push(state.parse_move("e1g1").unwrap());
+        let board = state.to_string();
+        let lines = board.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "  a b c d e f g h");
+        assert_eq!(lines[9], "  a b c d e f g h");
+        assert_eq!(lines[1], "8 r . . . k . . r 8");
+        // the king is highlighted where it left and where it landed, not on its rook.
+        let reset = "\u{001b}[0m";
+        assert_eq!(
+            lines[8],
+            format!("1 R . . . {HIGHLIGHT}.{reset} R {HIGHLIGHT}K{reset} . 1")
+        );
+    }
+}
@@ -1,14 +1,12 @@
 #![allow(clippy::cast_possible_truncation)]
 
-use std::{
-    fmt::{Debug, Display},
-    ops::Index,
-};
+use std::fmt::{Debug, Display};
 
 use crate::{
     datageneration::{StateVector, VectoriseState},
-    game::{Game, MoveBuffer},
+    game::{splitmix64, unknown_variant, ArrayBuffer, Game, LastMove, MoveBuffer, HIGHLIGHT},
     mcts::MCTSExt,
+    nneval::{Evaluation, Network},
 };
 
 type Bitrow = u8;
@@ -36,6 +34,9 @@ impl Display for C4Move {
 pub struct Connect4 {
     board: [[Bitrow; ROWS as usize]; 2],
     moves: u8,
+    /// Whether this is misère Connect 4, in which making a line of four loses.
+    misere: bool,
+    last_move: LastMove<C4Move>,
 }
 
 impl Connect4 {
@@ -43,6 +44,8 @@ impl Connect4 {
         Self {
             board: [[0; ROWS as usize]; 2],
             moves: 0,
+            misere: false,
+            last_move: LastMove(None),
         }
     }
 
@@ -124,6 +127,26 @@ impl Connect4 {
         }
         0
     }
+
+    /// The player who has made a line of four, or 0 if neither has.
+    fn line_maker(&self) -> i8 {
+        let h = self.horizontal_eval();
+        if h != 0 {
+            return h;
+        }
+
+        let v = self.vertical_eval();
+        if v != 0 {
+            return v;
+        }
+
+        let du = self.diag_up_eval();
+        if du != 0 {
+            return du;
+        }
+
+        self.diag_down_eval()
+    }
 }
 
 impl Default for Connect4 {
@@ -145,6 +168,13 @@ impl Display for Connect4 {
         const RESET: &str = "\u{001b}[0m";
         for row in 0..ROWS {
             for col in 0..COLS {
+                // the last piece dropped is the top one of its column.
+                if self.last_move.0 == Some(C4Move(col))
+                    && self.filled(row, col)
+                    && (row == 0 || !self.filled(row - 1, col))
+                {
+                    write!(f, "{HIGHLIGHT}")?;
+                }
                 match self.player_at(row, col) {
                     1 => write!(f, "{RED}X{RESET} ")?,
                     -1 => write!(f, "{YELLOW}O{RESET} ")?,
@@ -159,10 +189,14 @@ impl Display for Connect4 {
 
 impl Game for Connect4 {
     type Move = C4Move;
-    type Buffer = MoveBuf;
+    type Buffer = ArrayBuffer<C4Move, { COLS as usize }>;
+
+    const CAN_POP: bool = true;
+    const MOVE_SPACE: usize = COLS as usize;
+    const VARIANTS: &'static [&'static str] = &["misere"];
 
     fn turn(&self) -> i8 {
-        if self.moves % 2 == 0 {
+        if self.moves.is_multiple_of(2) {
             1
         } else {
             -1
@@ -189,22 +223,21 @@ impl Game for Connect4 {
     }
 
     fn evaluate(&self) -> i8 {
-        let h = self.horizontal_eval();
-        if h != 0 {
-            return h;
-        }
-
-        let v = self.vertical_eval();
-        if v != 0 {
-            return v;
+        if self.misere {
+            -self.line_maker()
+        } else {
+            self.line_maker()
         }
+    }
 
-        let du = self.diag_up_eval();
-        if du != 0 {
-            return du;
+    fn with_variant(variant: &str) -> Result<Self, String> {
+        match variant {
+            "misere" => Ok(Self {
+                misere: true,
+                ..Self::new()
+            }),
+            _ => Err(unknown_variant::<Self>(variant)),
         }
-
-        self.diag_down_eval()
     }
 
     fn push(&mut self, m: Self::Move) {
@@ -218,21 +251,23 @@ impl Game for Connect4 {
         self.board[(self.moves & 1) as usize][row as usize - 1] |= 1 << m.0;
 
         self.moves += 1;
+        self.last_move = LastMove(Some(m));
     }
 
-    // fn pop(&mut self, m: Self::Move) {
-    //     assert!(self.filled(ROWS, m.0));
+    fn pop(&mut self, m: Self::Move) {
+        assert!(self.filled(ROWS - 1, m.0));
 
-    //     self.moves -= 1;
+        self.moves -= 1;
 
-    //     let mut row = 0;
-    //     while !self.filled(row, m.0) {
-    //         row += 1;
-    //     }
+        let mut row = 0;
+        while !self.filled(row, m.0) {
+            row += 1;
+        }
 
-    //     assert!(row < ROWS);
-    //     self.board[(self.moves & 1) as usize][row as usize] &= !(1 << m.0);
-    // }
+        assert!(row < ROWS);
+        self.board[(self.moves & 1) as usize][row as usize] &= !(1 << m.0);
+        self.last_move = LastMove(None);
+    }
 
     fn push_random(&mut self, rng: &mut fastrand::Rng) {
         let bb = self.board[0][0] | self.board[1][0];
@@ -252,6 +287,23 @@ impl Game for Connect4 {
 
         self.push(C4Move(bb.trailing_zeros() as u8));
     }
+
+    fn hash(&self) -> u64 {
+        let [first, second] = self.board.map(|rows| {
+            rows.iter().enumerate().fold(0, |acc, (row, &bits)| {
+                acc | u64::from(bits) << (row * COLS as usize)
+            })
+        });
+        splitmix64(splitmix64(first) ^ second) ^ u64::from(self.misere)
+    }
+
+    fn move_index(m: Self::Move) -> usize {
+        m.0 as usize
+    }
+
+    fn last_move(&self) -> Option<Self::Move> {
+        self.last_move.0
+    }
 }
 
 impl VectoriseState for Connect4 {
@@ -286,60 +338,30 @@ impl VectoriseState for Connect4 {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct MoveBuf {
-    moves: [C4Move; COLS as usize],
-    n_moves: usize,
-}
-
-impl Default for MoveBuf {
-    fn default() -> Self {
-        Self {
-            moves: [C4Move(0); COLS as usize],
-            n_moves: 0,
-        }
-    }
-}
-
-impl MoveBuffer<C4Move> for MoveBuf {
-    fn iter(&self) -> std::slice::Iter<C4Move> {
-        self.moves[..self.n_moves].iter()
-    }
-
-    fn len(&self) -> usize {
-        self.n_moves
+impl MCTSExt for Connect4 {
+    fn typical_game_length() -> usize {
+        36
     }
 
-    fn is_empty(&self) -> bool {
-        self.n_moves == 0
-    }
-
-    fn push(&mut self, m: C4Move) {
-        self.moves[self.n_moves] = m;
-        self.n_moves += 1;
+    fn branching_factor(_ply: usize) -> usize {
+        COLS as usize
     }
-
-    fn capacity(&self) -> usize {
-        self.moves.len()
+    fn network_evaluations(states: &[Self], network: &Network) -> Result<Vec<Evaluation>, String> {
+        network.evaluate_all(states)
     }
 }
 
-impl Index<usize> for MoveBuf {
-    type Output = C4Move;
+#[cfg(test)]
+mod tests {
+    use crate::game::perft;
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.moves[index]
-    }
-}
+    use super::Connect4;
 
-impl Display for MoveBuf {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[")?;
-        for m in &self.moves[..self.n_moves - 1] {
-            write!(f, "{m}, ")?;
+    #[test]
+    fn shallow_perft() {
+        let mut board = Connect4::new();
+        for (depth, count) in [(1, 7), (2, 49), (3, 343), (4, 2401)] {
+            assert_eq!(perft(&mut board, depth), count);
         }
-        write!(f, "{}]", self.moves[self.n_moves - 1])
     }
 }
-
-impl MCTSExt for Connect4 {}
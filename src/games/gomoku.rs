@@ -3,11 +3,12 @@
 use std::{
     cmp::Ordering,
     fmt::{self, Display, Formatter},
-    ops::Index,
 };
 
 use crate::{
-    game::{Game, MoveBuffer},
+    game::{
+        splitmix64, transform_square, ArrayBuffer, Game, MoveBuffer, DIHEDRAL_SYMMETRIES, HIGHLIGHT,
+    },
     mcts::MCTSExt, datageneration::VectoriseState,
 };
 
@@ -18,12 +19,17 @@ const EMPTY: i8 = 0;
 const X: i8 = 1;
 const O: i8 = -1;
 const ROWS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// The number of cells on the largest supported board, which bounds the number of legal moves.
+const MAX_CELLS: usize = 19 * 19;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Gomoku<const N: usize> {
     board: [[i8; N]; N],
     moves: usize,
+    /// Stones placed on the board as a handicap, before the first move.
+    handicap_stones: usize,
     last_move: Move<N>,
+    hash: u64,
 }
 
 type MoveInnerRepr = u16;
@@ -58,7 +64,7 @@ impl<const N: usize> Display for Move<N> {
 
 impl<const N: usize> PartialOrd for Move<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.loc.cmp(&other.loc))
+        Some(self.cmp(other))
     }
 }
 
@@ -75,7 +81,9 @@ impl<const N: usize> Gomoku<N> {
         Self {
             board: [[0; N]; N],
             moves: 0,
+            handicap_stones: 0,
             last_move: Move::new(0),
+            hash: 0,
         }
     }
 
@@ -146,65 +154,6 @@ impl<const N: usize> Gomoku<N> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Buffer<const N: usize> {
-    moves: Vec<Move<N>>,
-}
-
-impl<const N: usize> Buffer<N> {
-    fn new() -> Self {
-        Self {
-            moves: Vec::with_capacity(N * N),
-        }
-    }
-}
-
-impl<const N: usize> Display for Buffer<N> {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "[")?;
-        for m in &self.moves[..self.moves.len() - 1] {
-            write!(f, "{m}, ")?;
-        }
-        write!(f, "{}]", self.moves[self.moves.len() - 1])
-    }
-}
-
-impl<const N: usize> Index<usize> for Buffer<N> {
-    type Output = Move<N>;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.moves[index]
-    }
-}
-
-impl<const N: usize> Default for Buffer<N> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<const N: usize> MoveBuffer<Move<N>> for Buffer<N> {
-    fn iter(&self) -> std::slice::Iter<Move<N>> {
-        self.moves.iter()
-    }
-
-    fn len(&self) -> usize {
-        self.moves.len()
-    }
-
-    fn is_empty(&self) -> bool {
-        self.moves.is_empty()
-    }
-
-    fn push(&mut self, m: Move<N>) {
-        self.moves.push(m);
-    }
-
-    fn capacity(&self) -> usize {
-        self.moves.capacity()
-    }
-}
-
 impl<const N: usize> Default for Gomoku<N> {
     fn default() -> Self {
         Self::new()
@@ -229,7 +178,10 @@ impl<const N: usize> Display for Gomoku<N> {
         writeln!(f)?;
         for (i, row) in self.board.iter().enumerate() {
             write!(f, "{} ", ROWS[i] as char)?;
-            for &cell in row {
+            for (j, &cell) in row.iter().enumerate() {
+                if self.last_move() == Some(Move::new(i * N + j)) {
+                    write!(f, "{HIGHLIGHT}")?;
+                }
                 write!(
                     f,
                     "{}",
@@ -250,10 +202,13 @@ impl<const N: usize> Display for Gomoku<N> {
 impl<const N: usize> Game for Gomoku<N> {
     type Move = Move<N>;
 
-    type Buffer = Buffer<N>;
+    type Buffer = ArrayBuffer<Move<N>, MAX_CELLS>;
+
+    const MOVE_SPACE: usize = N * N;
+    const SYMMETRIES: usize = DIHEDRAL_SYMMETRIES;
 
     fn turn(&self) -> i8 {
-        if self.moves % 2 == 0 {
+        if self.moves.is_multiple_of(2) {
             1
         } else {
             -1
@@ -261,11 +216,50 @@ impl<const N: usize> Game for Gomoku<N> {
     }
 
     fn is_terminal(&self) -> bool {
-        self.moves >= N * N || self.evaluate() != 0
+        self.moves + self.handicap_stones >= N * N || self.evaluate() != 0
+    }
+
+    /// Parses a square as its row letter and column number, as in `K10`, ignoring case and
+    /// spaces between them, and says what is wrong with squares off the board or taken.
+    fn parse_move(&self, text: &str) -> Result<Self::Move, String> {
+        let text = text.trim();
+        let last_row = ROWS[N - 1] as char;
+        let malformed = || {
+            format!(
+                "expected a row from A to {last_row} and then a column from 1 to {N}, as in {}: {text}",
+                Move::<N>::new(N * N / 2)
+            )
+        };
+        let mut chars = text.chars();
+        let letter = chars
+            .next()
+            .filter(char::is_ascii_alphabetic)
+            .ok_or_else(malformed)?;
+        let col = chars
+            .as_str()
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| malformed())?;
+        let row = ROWS
+            .iter()
+            .position(|&row| char::from(row) == letter.to_ascii_uppercase())
+            .filter(|&row| row < N)
+            .ok_or_else(|| format!("there is no row {letter}, the rows go from A to {last_row}"))?;
+        if !(1..=N).contains(&col) {
+            return Err(format!(
+                "there is no column {col}, the columns go from 1 to {N}"
+            ));
+        }
+        let m = Move::new(row * N + col - 1);
+        if self.board[row][col - 1] != EMPTY {
+            return Err(format!("{m} is already taken"));
+        }
+        Ok(m)
     }
 
     fn push(&mut self, m: Self::Move) {
         self.board[m.row()][m.col()] = self.turn();
+        self.hash ^= splitmix64(u64::from(m.loc) * 2 + u64::from(self.turn() == X));
         self.moves += 1;
         self.last_move = m;
     }
@@ -286,6 +280,16 @@ impl<const N: usize> Game for Gomoku<N> {
         }
     }
 
+    /// The receiving side starts with a stone on the centre point.
+    fn with_odds(side: i8) -> Option<Self> {
+        let mut state = Self::new();
+        let centre = Move::<N>::new(N * N / 2);
+        state.board[centre.row()][centre.col()] = side;
+        state.hash ^= splitmix64(u64::from(centre.loc) * 2 + u64::from(side == X));
+        state.handicap_stones = 1;
+        Some(state)
+    }
+
     fn generate_moves(&self, moves: &mut Self::Buffer) {
         assert!(moves.capacity() >= N * N);
         let mut i = 0;
@@ -301,11 +305,11 @@ impl<const N: usize> Game for Gomoku<N> {
 
     fn push_random(&mut self, rng: &mut fastrand::Rng) {
         #![allow(clippy::cast_precision_loss)]
-        let filled_factor = self.moves as f64 / (N * N) as f64;
+        let filled_factor = (self.moves + self.handicap_stones) as f64 / (N * N) as f64;
         // if the board is mostly full, generate moves and then select.
         // otherwise, just guess moves until we find an empty square.
         if filled_factor > 0.95 {
-            let mut moves = Buffer::new();
+            let mut moves = Self::Buffer::default();
             self.generate_moves(&mut moves);
             let index = rng.usize(..moves.len());
             return self.push(moves[index]);
@@ -321,6 +325,29 @@ impl<const N: usize> Game for Gomoku<N> {
         self.push(Move::new(index));
     }
 
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn move_index(m: Self::Move) -> usize {
+        m.loc as usize
+    }
+
+    fn is_symmetric(&self, sym: usize) -> bool {
+        (0..N * N).all(|sq| {
+            let image = transform_square(sq, N, sym);
+            self.board[sq / N][sq % N] == self.board[image / N][image % N]
+        })
+    }
+
+    fn transform_move(m: Self::Move, sym: usize) -> Self::Move {
+        Move::new(transform_square(m.loc as usize, N, sym))
+    }
+
+    fn last_move(&self) -> Option<Self::Move> {
+        (self.moves > 0).then_some(self.last_move)
+    }
+
     fn policy(&self, node: &crate::treenode::Node<Self>) -> f32 {
         #![allow(clippy::cast_possible_truncation)]
         let move_that_lead_to_it = node.inbound_edge();
@@ -380,4 +407,41 @@ impl<const N: usize> VectoriseState for Gomoku<N> {
     }
 }
 
-impl<const N: usize> MCTSExt for Gomoku<N> {}
\ No newline at end of file
+impl<const N: usize> MCTSExt for Gomoku<N> {
+    fn typical_game_length() -> usize {
+        N * 4
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squares_are_parsed_by_coordinates() {
+        let mut state = Gomoku::<19>::new();
+        assert_eq!(state.parse_move("K10"), Ok(Move::new(10 * 19 + 9)));
+        assert_eq!(state.parse_move(" k 10\n"), Ok(Move::new(10 * 19 + 9)));
+        assert_eq!(state.parse_move("S19"), Ok(Move::new(19 * 19 - 1)));
+        state.push(Move::new(0));
+        assert_eq!(
+            state.parse_move("a1"),
+            Err("A1 is already taken".to_string())
+        );
+        assert!(state.parse_move("T1").unwrap_err().contains("no row T"));
+        assert!(state
+            .parse_move("A20")
+            .unwrap_err()
+            .contains("no column 20"));
+        assert!(state.parse_move("A0").unwrap_err().contains("no column 0"));
+        for text in ["", "10K", "K", "K1.5", "pass"] {
+            assert!(
+                state.parse_move(text).unwrap_err().contains("as in J10"),
+                "{text}"
+            );
+        }
+        assert!(Gomoku::<9>::new()
+            .parse_move("J1")
+            .unwrap_err()
+            .contains("A to I"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod antichess;
 pub mod chess;
 pub mod connectfour;
 pub mod gomoku;
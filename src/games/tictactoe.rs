@@ -1,20 +1,24 @@
 #![allow(clippy::unusual_byte_groupings)]
 
-use std::{
-    fmt::{Debug, Display},
-    ops::Index,
-};
+use std::fmt::{Debug, Display};
 
 use crate::{
     datageneration::{StateVector, VectoriseState},
-    game::{Game, MoveBuffer},
+    game::{
+        transform_square, unknown_variant, ArrayBuffer, Game, LastMove, MoveBuffer,
+        DIHEDRAL_SYMMETRIES, HIGHLIGHT,
+    },
     mcts::MCTSExt,
+    nneval::{Evaluation, Network},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TicTacToe {
     board: [u16; 2],
     moves: usize,
+    /// Whether this is misère tic-tac-toe, in which making a line loses.
+    misere: bool,
+    last_move: LastMove<TicTacToeMove>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -44,71 +48,13 @@ impl Display for TicTacToeMove {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TTTMoveBuf {
-    data: [TicTacToeMove; 9],
-    n_moves: usize,
-}
-
-impl Default for TTTMoveBuf {
-    fn default() -> Self {
-        Self {
-            data: [TicTacToeMove(0); 9],
-            n_moves: 0,
-        }
-    }
-}
-
-impl Index<usize> for TTTMoveBuf {
-    type Output = TicTacToeMove;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.data[index]
-    }
-}
-
-impl Display for TTTMoveBuf {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[")?;
-        for m in &self.data[..self.n_moves - 1] {
-            write!(f, "{m}, ")?;
-        }
-        write!(f, "{}]", self.data[self.n_moves - 1])
-    }
-}
-
-impl MoveBuffer<TicTacToeMove> for TTTMoveBuf {
-    #[inline]
-    fn iter(&self) -> std::slice::Iter<TicTacToeMove> {
-        self.data.iter()
-    }
-
-    #[inline]
-    fn len(&self) -> usize {
-        self.data.len()
-    }
-
-    #[inline]
-    fn is_empty(&self) -> bool {
-        self.data.is_empty()
-    }
-
-    #[inline]
-    fn push(&mut self, m: TicTacToeMove) {
-        self.data[self.n_moves] = m;
-        self.n_moves += 1;
-    }
-
-    fn capacity(&self) -> usize {
-        self.data.len()
-    }
-}
-
 impl TicTacToe {
     pub const fn new() -> Self {
         Self {
             board: [0; 2],
             moves: 0,
+            misere: false,
+            last_move: LastMove(None),
         }
     }
 
@@ -140,22 +86,9 @@ impl TicTacToe {
             '.'
         }
     }
-}
 
-impl Game for TicTacToe {
-    type Move = TicTacToeMove;
-    type Buffer = TTTMoveBuf;
-
-    #[inline]
-    fn turn(&self) -> i8 {
-        if self.moves & 1 == 0 {
-            1
-        } else {
-            -1
-        }
-    }
-
-    fn evaluate(&self) -> i8 {
+    /// The player who has made a line, or 0 if neither has.
+    fn line_maker(&self) -> i8 {
         // check first diagonal
         if self.probe_spot(0) && self.probe_spot(4) && self.probe_spot(8) {
             return -self.turn();
@@ -181,6 +114,33 @@ impl Game for TicTacToe {
 
         0
     }
+}
+
+impl Game for TicTacToe {
+    type Move = TicTacToeMove;
+    type Buffer = ArrayBuffer<TicTacToeMove, 9>;
+
+    const CAN_POP: bool = true;
+    const MOVE_SPACE: usize = 9;
+    const SYMMETRIES: usize = DIHEDRAL_SYMMETRIES;
+    const VARIANTS: &'static [&'static str] = &["misere"];
+
+    #[inline]
+    fn turn(&self) -> i8 {
+        if self.moves & 1 == 0 {
+            1
+        } else {
+            -1
+        }
+    }
+
+    fn evaluate(&self) -> i8 {
+        if self.misere {
+            -self.line_maker()
+        } else {
+            self.line_maker()
+        }
+    }
 
     fn is_terminal(&self) -> bool {
         self.moves == 9 || self.evaluate() != 0
@@ -195,9 +155,26 @@ impl Game for TicTacToe {
         }
     }
 
+    fn with_variant(variant: &str) -> Result<Self, String> {
+        match variant {
+            "misere" => Ok(Self {
+                misere: true,
+                ..Self::new()
+            }),
+            _ => Err(unknown_variant::<Self>(variant)),
+        }
+    }
+
     fn push(&mut self, m: Self::Move) {
         self.board[self.moves & 1] |= 1 << m.0;
         self.moves += 1;
+        self.last_move = LastMove(Some(m));
+    }
+
+    fn pop(&mut self, m: Self::Move) {
+        self.moves -= 1;
+        self.board[self.moves & 1] &= !(1 << m.0);
+        self.last_move = LastMove(None);
     }
 
     fn push_random(&mut self, rng: &mut fastrand::Rng) {
@@ -210,6 +187,31 @@ impl Game for TicTacToe {
         }
         self.push(TicTacToeMove::new(bb.trailing_zeros() as usize));
     }
+
+    fn hash(&self) -> u64 {
+        u64::from(self.board[0]) | u64::from(self.board[1]) << 16 | u64::from(self.misere) << 32
+    }
+
+    fn move_index(m: Self::Move) -> usize {
+        m.0
+    }
+
+    fn is_symmetric(&self, sym: usize) -> bool {
+        (0..9).all(|sq| {
+            let image = transform_square(sq, 3, sym);
+            self.board
+                .iter()
+                .all(|bb| (bb >> sq) & 1 == (bb >> image) & 1)
+        })
+    }
+
+    fn transform_move(m: Self::Move, sym: usize) -> Self::Move {
+        TicTacToeMove::new(transform_square(m.0, 3, sym))
+    }
+
+    fn last_move(&self) -> Option<Self::Move> {
+        self.last_move.0
+    }
 }
 
 impl VectoriseState for TicTacToe {
@@ -220,7 +222,7 @@ impl VectoriseState for TicTacToe {
     fn vectorise_state(&self) -> StateVector {
         let mut v: Vec<u8> = Vec::with_capacity(3 * 3 * 2);
 
-        for shift in 0..7 {
+        for shift in 0..9 {
             v.push(((self.board[1] >> shift) & 1) as u8);
             v.push(((self.board[0] >> shift) & 1) as u8);
         }
@@ -249,6 +251,9 @@ impl Display for TicTacToe {
         const RESET: &str = "\u{001b}[0m";
         for y in 0..3 {
             for x in 0..3 {
+                if self.last_move.0 == Some(TicTacToeMove::new(y * 3 + x)) {
+                    write!(f, "{HIGHLIGHT}")?;
+                }
                 match self.char_at(x, y) {
                     '.' => write!(f, ". ")?,
                     'X' => write!(f, "{RED}X{RESET} ")?,
@@ -269,29 +274,52 @@ impl Default for TicTacToe {
     }
 }
 
-impl MCTSExt for TicTacToe {}
+impl MCTSExt for TicTacToe {
+    fn typical_game_length() -> usize {
+        9
+    }
+    fn network_evaluations(states: &[Self], network: &Network) -> Result<Vec<Evaluation>, String> {
+        network.evaluate_all(states)
+    }
+}
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::perft::perft;
+#[cfg(test)]
+mod tests {
+    use crate::game::{perft, Game};
 
-//     use super::TicTacToe;
+    use super::{TicTacToe, TicTacToeMove};
 
-//     #[test]
-//     fn depth1() {
-//         let mut board = TicTacToe::new();
-//         assert_eq!(perft(&mut board, 1), 9);
-//     }
+    #[test]
+    fn depth1() {
+        let mut board = TicTacToe::new();
+        assert_eq!(perft(&mut board, 1), 9);
+    }
 
-//     #[test]
-//     fn depth2() {
-//         let mut board = TicTacToe::new();
-//         assert_eq!(perft(&mut board, 2), 72);
-//     }
+    #[test]
+    fn depth2() {
+        let mut board = TicTacToe::new();
+        assert_eq!(perft(&mut board, 2), 72);
+    }
 
-//     #[test]
-//     fn fullperft() {
-//         let mut board = TicTacToe::new();
-//         assert_eq!(perft(&mut board, 10), 255168);
-//     }
-// }
+    #[test]
+    fn fullperft() {
+        let mut board = TicTacToe::new();
+        assert_eq!(perft(&mut board, 10), 255_168);
+    }
+
+    #[test]
+    fn making_a_line_loses_in_misere() {
+        let mut standard = TicTacToe::new();
+        let mut misere = TicTacToe::with_variant("misere").unwrap();
+        // X makes the top row.
+        for m in [0, 3, 1, 4, 2] {
+            standard.push(TicTacToeMove::new(m));
+            misere.push(TicTacToeMove::new(m));
+        }
+        assert!(standard.is_terminal() && misere.is_terminal());
+        assert_eq!(standard.evaluate(), 1);
+        assert_eq!(misere.evaluate(), -1);
+        assert_ne!(standard.hash(), misere.hash());
+        assert!(TicTacToe::with_variant("antichess").is_err());
+    }
+}
@@ -1,41 +1,83 @@
-use std::{
-    fmt::{self, Display, Formatter},
-    ops::Index,
-};
+use std::fmt::{Display, Formatter};
 
 use crate::{
-    game::{Game, MoveBuffer},
+    datageneration::{StateVector, VectoriseState},
+    game::{splitmix64, ArrayBuffer, Game, LastMove, MoveBuffer, HIGHLIGHT},
     mcts::MCTSExt,
+    nneval::{Evaluation, Network},
+    treenode::Node,
 };
 
 const BOARD_WIDTH: usize = 9;
 const BOARD_HEIGHT: usize = 9;
-// const SUB_BOARD_WIDTH: usize = 3;
-// const SUB_BOARD_HEIGHT: usize = 3;
+const SUB_BOARD_WIDTH: usize = 3;
+const SUB_BOARD_HEIGHT: usize = 3;
 
 const NO_PIECE: u8 = 0;
 const X: u8 = 1;
 const O: u8 = 2;
+/// The state of a small board that is full without either side winning it.
+const DRAWN: u8 = 3;
 const NO_FORCED_BOX: u8 = 10;
 const NO_MOVE: Move = Move(81);
 
+/// The lines of three cells that win a 3x3 board, as indices into the board.
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Undo {
     forced_box: u8,
+    last_move: LastMove<Move>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct Move(u8);
+pub struct Move(u8);
 
+impl Move {
+    const fn row(self) -> usize {
+        self.0 as usize / BOARD_WIDTH
+    }
+
+    const fn col(self) -> usize {
+        self.0 as usize % BOARD_WIDTH
+    }
+
+    /// The small board that the move is played in.
+    const fn box_index(self) -> usize {
+        self.row() / SUB_BOARD_HEIGHT * SUB_BOARD_WIDTH + self.col() / SUB_BOARD_WIDTH
+    }
+
+    /// The cell of its small board that the move is played in,
+    /// which is also the small board that the opponent is sent to.
+    const fn cell_index(self) -> usize {
+        self.row() % SUB_BOARD_HEIGHT * SUB_BOARD_WIDTH + self.col() % SUB_BOARD_WIDTH
+    }
+
+    /// The square on the big board of `cell` within small board `box_index`.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn from_box_and_cell(box_index: usize, cell: usize) -> Self {
+        let row = box_index / SUB_BOARD_WIDTH * SUB_BOARD_HEIGHT + cell / SUB_BOARD_WIDTH;
+        let col = box_index % SUB_BOARD_WIDTH * SUB_BOARD_WIDTH + cell % SUB_BOARD_WIDTH;
+        Self((row * BOARD_WIDTH + col) as u8)
+    }
+}
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         #![allow(clippy::cast_possible_truncation)]
         if self == &NO_MOVE {
             return write!(f, "NO_MOVE");
         }
-        let row = self.0 / BOARD_WIDTH as u8;
-        let col = self.0 % BOARD_WIDTH as u8;
-        write!(f, "{}{}", (b'A' + row) as char, col + 1)
+        let row = self.row() as u8;
+        write!(f, "{}{}", (b'A' + row) as char, self.col() + 1)
     }
 }
 
@@ -46,23 +88,68 @@ impl Default for Move {
 }
 
 /// Representation of a single game state in Ultimate Tic-Tac-Toe.
+///
+/// Each move sends the opponent to the small board matching the cell just played in,
+/// unless that board is already decided, in which case they may play in any open board.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct UltimateTicTacToe {
+pub struct UltimateTicTacToe {
     pieces: [u8; BOARD_WIDTH * BOARD_HEIGHT],
+    /// The state of each small board: `NO_PIECE` while undecided, the winner, or `DRAWN`.
+    boxes: [u8; 9],
     moves: u8,
     forced_box: u8,
     history: Vec<Undo>,
+    last_move: LastMove<Move>,
 }
 
 impl UltimateTicTacToe {
     pub const fn new() -> Self {
         Self {
             pieces: [NO_PIECE; BOARD_WIDTH * BOARD_HEIGHT],
+            boxes: [NO_PIECE; 9],
             moves: 0,
             forced_box: NO_FORCED_BOX,
             history: Vec::new(),
+            last_move: LastMove(None),
+        }
+    }
+
+    const fn side_to_move(&self) -> u8 {
+        if self.moves.is_multiple_of(2) {
+            X
+        } else {
+            O
+        }
+    }
+
+    /// The winner of a 3x3 board given by `cell`, or `NO_PIECE` if there is none.
+    fn line_winner(cell: impl Fn(usize) -> u8) -> u8 {
+        LINES
+            .iter()
+            .map(|line| line.map(&cell))
+            .find(|&[a, b, c]| (a == X || a == O) && a == b && b == c)
+            .map_or(NO_PIECE, |[a, _, _]| a)
+    }
+
+    /// Works out the state of small board `box_index` from its pieces.
+    fn box_status(&self, box_index: usize) -> u8 {
+        let piece = |cell| self.pieces[Move::from_box_and_cell(box_index, cell).0 as usize];
+        match Self::line_winner(piece) {
+            NO_PIECE if (0..9).all(|cell| piece(cell) != NO_PIECE) => DRAWN,
+            winner => winner,
         }
     }
+
+    /// The winner of the big board, or `NO_PIECE` if there is none.
+    fn winner(&self) -> u8 {
+        Self::line_winner(|b| self.boxes[b])
+    }
+
+    /// Whether the side to move may play in small board `box_index`.
+    const fn box_open(&self, box_index: usize) -> bool {
+        self.boxes[box_index] == NO_PIECE
+            && (self.forced_box == NO_FORCED_BOX || self.forced_box as usize == box_index)
+    }
 }
 
 impl Default for UltimateTicTacToe {
@@ -73,8 +160,16 @@ impl Default for UltimateTicTacToe {
 
 impl Display for UltimateTicTacToe {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        const RESET: &str = "\u{001b}[0m";
+        let last_square = self.last_move.0.map(|m| m.0 as usize);
         for y in 0..BOARD_HEIGHT {
+            if y != 0 && y % SUB_BOARD_HEIGHT == 0 {
+                writeln!(f, "------+-------+------")?;
+            }
             for x in 0..BOARD_WIDTH {
+                if x != 0 && x % SUB_BOARD_WIDTH == 0 {
+                    write!(f, "| ")?;
+                }
                 let piece = self.pieces[y * BOARD_WIDTH + x];
                 let piece_char = match piece {
                     NO_PIECE => '.',
@@ -82,7 +177,11 @@ impl Display for UltimateTicTacToe {
                     O => 'O',
                     _ => unreachable!(),
                 };
-                write!(f, "{piece_char} ")?;
+                if last_square == Some(y * BOARD_WIDTH + x) {
+                    write!(f, "{HIGHLIGHT}{piece_char}{RESET} ")?;
+                } else {
+                    write!(f, "{piece_char} ")?;
+                }
             }
             writeln!(f)?;
         }
@@ -90,90 +189,222 @@ impl Display for UltimateTicTacToe {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Buffer {
-    moves: [Move; 81],
-    n_moves: usize,
-}
+impl Game for UltimateTicTacToe {
+    type Move = Move;
+
+    type Buffer = ArrayBuffer<Move, 81>;
 
-impl Display for Buffer {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "[")?;
-        for m in &self.moves[..self.moves.len() - 1] {
-            write!(f, "{m}, ")?;
+    const CAN_POP: bool = true;
+    const MOVE_SPACE: usize = BOARD_WIDTH * BOARD_HEIGHT;
+
+    fn turn(&self) -> i8 {
+        if self.moves.is_multiple_of(2) {
+            1
+        } else {
+            -1
         }
-        write!(f, "{}]", self.moves[self.moves.len() - 1])
     }
-}
 
-impl Index<usize> for Buffer {
-    type Output = Move;
+    fn generate_moves(&self, moves: &mut Self::Buffer) {
+        if self.winner() != NO_PIECE {
+            return;
+        }
+        for box_index in (0..9).filter(|&b| self.box_open(b)) {
+            for cell in 0..9 {
+                let m = Move::from_box_and_cell(box_index, cell);
+                if self.pieces[m.0 as usize] == NO_PIECE {
+                    moves.push(m);
+                }
+            }
+        }
+    }
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.moves[..self.n_moves][index]
+    fn is_terminal(&self) -> bool {
+        self.winner() != NO_PIECE || self.boxes.iter().all(|&b| b != NO_PIECE)
     }
-}
 
-impl Default for Buffer {
-    fn default() -> Self {
-        Self {
-            moves: [NO_MOVE; 81],
-            n_moves: 0,
+    fn evaluate(&self) -> i8 {
+        match self.winner() {
+            X => 1,
+            O => -1,
+            _ => 0,
         }
     }
-}
 
-impl MoveBuffer<Move> for Buffer {
-    fn iter(&self) -> std::slice::Iter<Move> {
-        self.moves[..self.n_moves].iter()
+    #[allow(clippy::cast_possible_truncation)]
+    fn push(&mut self, m: Self::Move) {
+        self.history.push(Undo {
+            forced_box: self.forced_box,
+            last_move: self.last_move,
+        });
+        self.pieces[m.0 as usize] = self.side_to_move();
+        let box_index = m.box_index();
+        self.boxes[box_index] = self.box_status(box_index);
+        let target = m.cell_index();
+        self.forced_box = if self.boxes[target] == NO_PIECE {
+            target as u8
+        } else {
+            NO_FORCED_BOX
+        };
+        self.moves += 1;
+        self.last_move = LastMove(Some(m));
     }
 
-    fn len(&self) -> usize {
-        self.n_moves
+    fn pop(&mut self, m: Self::Move) {
+        let undo = self
+            .history
+            .pop()
+            .expect("pop called with no moves to undo");
+        self.moves -= 1;
+        self.pieces[m.0 as usize] = NO_PIECE;
+        self.boxes[m.box_index()] = self.box_status(m.box_index());
+        self.forced_box = undo.forced_box;
+        self.last_move = undo.last_move;
     }
 
-    fn is_empty(&self) -> bool {
-        self.n_moves == 0
+    fn push_random(&mut self, rng: &mut fastrand::Rng) {
+        let mut moves = Self::Buffer::default();
+        self.generate_moves(&mut moves);
+        self.push(moves[rng.usize(..moves.len())]);
     }
 
-    fn push(&mut self, m: Move) {
-        self.moves[self.n_moves] = m;
-        self.n_moves += 1;
+    fn hash(&self) -> u64 {
+        let pieces = self
+            .pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, &piece)| piece != NO_PIECE)
+            .fold(0, |hash, (i, &piece)| {
+                hash ^ splitmix64(i as u64 * 2 + u64::from(piece == X))
+            });
+        splitmix64(pieces ^ u64::from(self.forced_box))
     }
 
-    fn capacity(&self) -> usize {
-        self.moves.len()
+    fn move_index(m: Self::Move) -> usize {
+        m.0 as usize
     }
-}
 
-impl Game for UltimateTicTacToe {
-    type Move = Move;
+    fn last_move(&self) -> Option<Self::Move> {
+        self.last_move.0
+    }
 
-    type Buffer = Buffer;
+    fn policy(&self, node: &Node<Self>) -> f32 {
+        let m = node.inbound_edge();
+        let mut after = self.clone();
+        after.push(m);
+        if after.boxes[m.box_index()] == after.pieces[m.0 as usize] {
+            // winning a small board is usually good.
+            3.0
+        } else if after.forced_box == NO_FORCED_BOX {
+            // sending the opponent to a decided board lets them play anywhere.
+            0.5
+        } else {
+            1.0
+        }
+    }
+}
 
-    fn turn(&self) -> i8 {
-        todo!()
+impl VectoriseState for UltimateTicTacToe {
+    fn csv_header() -> String {
+        String::new()
     }
 
-    fn generate_moves(&self, _moves: &mut Self::Buffer) {
-        todo!()
+    /// Three 9x9 planes: the side to move's pieces, the opponent's pieces,
+    /// and the squares in the boards that the side to move may play in.
+    fn vectorise_state(&self) -> StateVector {
+        let us = self.side_to_move();
+        let them = if us == X { O } else { X };
+        let mut v: Vec<u8> = Vec::with_capacity(BOARD_WIDTH * BOARD_HEIGHT * 3);
+        v.extend(self.pieces.iter().map(|&p| u8::from(p == us)));
+        v.extend(self.pieces.iter().map(|&p| u8::from(p == them)));
+        v.extend((0..BOARD_WIDTH * BOARD_HEIGHT).map(|sq| {
+            #[allow(clippy::cast_possible_truncation)]
+            let m = Move(sq as u8);
+            u8::from(self.box_open(m.box_index()))
+        }));
+        assert_eq!(v.len(), BOARD_WIDTH * BOARD_HEIGHT * 3);
+        StateVector { data: v }
     }
 
-    fn is_terminal(&self) -> bool {
-        todo!()
+    fn index_move(m: Self::Move) -> usize {
+        m.0 as usize
     }
 
-    fn evaluate(&self) -> i8 {
-        todo!()
+    fn action_space() -> usize {
+        BOARD_WIDTH * BOARD_HEIGHT
+    }
+
+    fn state_vector_dimensions() -> Vec<usize> {
+        vec![3, BOARD_HEIGHT, BOARD_WIDTH]
     }
+}
 
-    fn push(&mut self, _m: Self::Move) {
-        todo!()
+impl MCTSExt for UltimateTicTacToe {
+    fn typical_game_length() -> usize {
+        50
     }
 
-    fn push_random(&mut self, _rng: &mut fastrand::Rng) {
-        todo!()
+    /// Most moves send the opponent to a small board with a handful of open cells.
+    fn branching_factor(_ply: usize) -> usize {
+        9
+    }
+    fn network_evaluations(states: &[Self], network: &Network) -> Result<Vec<Evaluation>, String> {
+        network.evaluate_all(states)
     }
 }
 
-impl MCTSExt for UltimateTicTacToe {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::perft;
+
+    #[test]
+    fn shallow_perft() {
+        let mut board = UltimateTicTacToe::new();
+        assert_eq!(perft(&mut board, 1), 81);
+        // a move in the centre cell of the centre board sends the opponent back to
+        // that board, which has only eight open cells left.
+        assert_eq!(perft(&mut board, 2), 72 * 9 + 9 * 8);
+    }
+
+    #[test]
+    fn decided_boards_give_a_free_choice() {
+        let mut board = UltimateTicTacToe::new();
+        // X wins the top-left board along its top row.
+        for (box_index, cell) in [(0, 1), (1, 0), (0, 0), (0, 4), (4, 3), (3, 0), (0, 2)] {
+            board.push(Move::from_box_and_cell(box_index, cell));
+        }
+        assert_eq!(board.boxes[0], X);
+        // O is sent to board 2, which is empty.
+        let mut moves = <UltimateTicTacToe as Game>::Buffer::default();
+        board.generate_moves(&mut moves);
+        assert_eq!(moves.len(), 9);
+        assert!(moves.iter().all(|m| m.box_index() == 2));
+        // playing in the cell matching board 0 gives X a free choice of open boards.
+        board.push(Move::from_box_and_cell(2, 0));
+        moves.clear();
+        board.generate_moves(&mut moves);
+        assert!(moves.iter().all(|m| m.box_index() != 0));
+        assert_eq!(moves.len(), 81 - 9 - 4);
+    }
+
+    #[test]
+    fn pop_undoes_push() {
+        let rng = fastrand::Rng::with_seed(7);
+        let start = UltimateTicTacToe::new();
+        let mut board = start.clone();
+        let mut played = Vec::new();
+        while !board.is_terminal() {
+            let mut moves = <UltimateTicTacToe as Game>::Buffer::default();
+            board.generate_moves(&mut moves);
+            let m = moves[rng.usize(..moves.len())];
+            board.push(m);
+            played.push(m);
+        }
+        assert_eq!(board.vectorise_state().data.len(), 3 * 81);
+        for m in played.into_iter().rev() {
+            board.pop(m);
+        }
+        assert_eq!(board, start);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    game::Game,
+    games::{
+        antichess::Antichess, chess::Chess, connectfour::Connect4, gomoku::Gomoku,
+        tictactoe::TicTacToe, ultimatetictactoe::UltimateTicTacToe,
+    },
+};
+
+/// The width and height, in pixels, of one square of a PPM heatmap.
+const CELL_PIXELS: usize = 16;
+
+/// A game whose moves can be drawn on the squares of a board.
+pub trait BoardGame: Game {
+    /// The width and height of the board, or `None` if moves are not played on squares.
+    const BOARD_SHAPE: Option<(usize, usize)> = None;
+
+    /// The row-major index of the square `m` is played on, if it is played on one.
+    fn move_square(m: Self::Move) -> Option<usize> {
+        Some(Self::move_index(m))
+    }
+}
+
+impl BoardGame for Antichess {}
+impl BoardGame for Chess {}
+impl BoardGame for Connect4 {}
+impl BoardGame for TicTacToe {
+    const BOARD_SHAPE: Option<(usize, usize)> = Some((3, 3));
+}
+impl<const N: usize> BoardGame for Gomoku<N> {
+    const BOARD_SHAPE: Option<(usize, usize)> = Some((N, N));
+}
+impl BoardGame for UltimateTicTacToe {
+    const BOARD_SHAPE: Option<(usize, usize)> = Some((9, 9));
+}
+
+/// What the search found for the move on one square.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Square {
+    visits: u64,
+    /// The mean value of the move for the player making it, if it was visited.
+    q: Option<f64>,
+}
+
+/// The visits and values of the root moves of a search, laid out on the board.
+/// Squares without a legal move are empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    width: usize,
+    height: usize,
+    squares: Vec<Option<Square>>,
+}
+
+impl Heatmap {
+    /// Builds the heatmap of `edges`, the (move, visits, value) of every root move,
+    /// or returns `None` if the game is not played on squares.
+    pub fn new<G: BoardGame>(edges: &[(G::Move, u64, Option<f64>)]) -> Option<Self> {
+        let (width, height) = G::BOARD_SHAPE?;
+        let mut squares = vec![None; width * height];
+        for &(m, visits, q) in edges {
+            if let Some(square) = G::move_square(m) {
+                squares[square] = Some(Square { visits, q });
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            squares,
+        })
+    }
+
+    fn rows(&self) -> std::slice::Chunks<'_, Option<Square>> {
+        self.squares.chunks(self.width)
+    }
+
+    /// Writes the heatmap as a JSON object, with `visits` and `q` as arrays of rows,
+    /// and `null` for squares without a legal move (and for `q` of unvisited moves).
+    pub fn write_json<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let grid = |w: &mut W, cell: &dyn Fn(&Option<Square>) -> String| -> io::Result<()> {
+            write!(w, "[")?;
+            for (i, row) in self.rows().enumerate() {
+                let row = row.iter().map(cell).collect::<Vec<_>>().join(", ");
+                let sep = if i + 1 == self.height { "" } else { "," };
+                write!(w, "\n    [{row}]{sep}")?;
+            }
+            write!(w, "\n  ]")
+        };
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"width\": {},", self.width)?;
+        writeln!(w, "  \"height\": {},", self.height)?;
+        write!(w, "  \"visits\": ")?;
+        grid(&mut w, &|square| {
+            square.map_or_else(|| "null".to_string(), |s| s.visits.to_string())
+        })?;
+        writeln!(w, ",")?;
+        write!(w, "  \"q\": ")?;
+        grid(&mut w, &|square| {
+            square
+                .and_then(|s| s.q)
+                .map_or_else(|| "null".to_string(), |q| format!("{q:.4}"))
+        })?;
+        writeln!(w)?;
+        writeln!(w, "}}")
+    }
+
+    /// Writes the heatmap as a plain PPM image, shading each square from blue (no visits)
+    /// to red (the most visits). Squares without a legal move are grey.
+    pub fn write_ppm<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let max_visits = self
+            .squares
+            .iter()
+            .flatten()
+            .map(|s| s.visits)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        writeln!(w, "P3")?;
+        writeln!(
+            w,
+            "{} {}",
+            self.width * CELL_PIXELS,
+            self.height * CELL_PIXELS
+        )?;
+        writeln!(w, "255")?;
+        for row in self.rows() {
+            let colours = row
+                .iter()
+                .map(|square| {
+                    square.map_or((96, 96, 96), |s| {
+                        #[allow(
+                            clippy::cast_possible_truncation,
+                            clippy::cast_precision_loss,
+                            clippy::cast_sign_loss
+                        )]
+                        let heat = (s.visits as f64 / max_visits as f64 * 255.0).round() as u8;
+                        (heat, 0, 255 - heat)
+                    })
+                })
+                .collect::<Vec<_>>();
+            for _ in 0..CELL_PIXELS {
+                let line = colours
+                    .iter()
+                    .flat_map(|&colour| std::iter::repeat_n(colour, CELL_PIXELS))
+                    .map(|(r, g, b)| format!("{r} {g} {b}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                writeln!(w, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the heatmap to `path`, as JSON or PPM according to its extension.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let extension = path.extension().and_then(|e| e.to_str());
+        if !matches!(extension, Some("json" | "ppm")) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: heatmaps are written as .json or .ppm", path.display()),
+            ));
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        if extension == Some("json") {
+            self.write_json(&mut writer)?;
+        } else {
+            self.write_ppm(&mut writer)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::tictactoe::TicTacToeMove;
+
+    fn example() -> Heatmap {
+        // the centre is taken, and one corner has not been visited.
+        let edges = [0u32, 1, 2, 3, 5, 6, 7, 8].map(|square| {
+            let visits = if square == 8 {
+                0
+            } else {
+                10 * u64::from(square)
+            };
+            let q = (visits > 0).then_some(0.5);
+            (TicTacToeMove::new(square as usize), visits, q)
+        });
+        Heatmap::new::<TicTacToe>(&edges).unwrap()
+    }
+
+    #[test]
+    fn heatmaps_are_laid_out_by_square() {
+        let mut json = Vec::new();
+        example().write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("[30, null, 50]"), "{json}");
+        assert!(json.contains("[60, 70, 0]"), "{json}");
+        assert!(json.contains("[0.5000, 0.5000, null]"), "{json}");
+        assert!(Heatmap::new::<Chess>(&[]).is_none());
+    }
+
+    #[test]
+    fn ppm_images_have_a_block_per_square() {
+        let mut ppm = Vec::new();
+        example().write_ppm(&mut ppm).unwrap();
+        let ppm = String::from_utf8(ppm).unwrap();
+        let mut lines = ppm.lines();
+        assert_eq!(lines.next(), Some("P3"));
+        assert_eq!(lines.next(), Some("48 48"));
+        assert_eq!(lines.next(), Some("255"));
+        let rows = lines.collect::<Vec<_>>();
+        assert_eq!(rows.len(), 48);
+        // the middle row: 30 visits, the taken centre, and the most-visited square but one.
+        let middle = rows[CELL_PIXELS].split(' ').collect::<Vec<_>>();
+        assert_eq!(middle.len(), 48 * 3);
+        assert_eq!(
+            middle[CELL_PIXELS * 3..CELL_PIXELS * 3 + 3],
+            ["96", "96", "96"]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+/// The temperature of the Gibbs distribution that rollout moves are sampled from.
+/// Lower values follow the history table more greedily.
+const TEMPERATURE: f32 = 0.25;
+/// The value assumed for moves that have not been played in any rollout yet.
+const UNPLAYED_VALUE: f32 = 0.5;
+
+/// A table of how well each move has done in the rollouts of the current search,
+/// for each player, used to bias rollout move selection towards moves that tend to win
+/// (the Move-Average Sampling Technique).
+///
+/// Moves are keyed by [`Game::move_index`](crate::game::Game::move_index).
+#[derive(Clone, Debug, Default)]
+pub struct HistoryTable {
+    /// The total result and the number of plays for every (player, move) pair.
+    entries: Vec<(f32, u32)>,
+    /// The slots of the moves played in the current rollout.
+    played: Vec<usize>,
+}
+
+impl HistoryTable {
+    pub fn new(move_space: usize) -> Self {
+        Self {
+            entries: vec![(0.0, 0); move_space * 2],
+            played: Vec::new(),
+        }
+    }
+
+    /// Forgets everything, ready for a new search.
+    pub fn clear(&mut self) {
+        self.entries.fill((0.0, 0));
+        self.played.clear();
+    }
+
+    const fn slot(turn: i8, index: usize) -> usize {
+        index * 2 + (turn < 0) as usize
+    }
+
+    /// The mean result, in [0, 1], of the move with `index` when played by `turn`.
+    pub fn value(&self, turn: i8, index: usize) -> f32 {
+        let (total, plays) = self.entries[Self::slot(turn, index)];
+        #[allow(clippy::cast_precision_loss)]
+        if plays == 0 {
+            UNPLAYED_VALUE
+        } else {
+            total / plays as f32
+        }
+    }
+
+    fn weight(&self, turn: i8, index: usize) -> f32 {
+        (self.value(turn, index) / TEMPERATURE).exp()
+    }
+
+    /// Samples one of the moves with the given `indices` for `turn` to play,
+    /// with probability proportional to `exp(value / TEMPERATURE)`.
+    /// Returns the position of the chosen move in `indices`.
+    pub fn choose<I>(&self, turn: i8, indices: I, rng: &fastrand::Rng) -> usize
+    where
+        I: Iterator<Item = usize> + Clone,
+    {
+        let total = indices.clone().map(|i| self.weight(turn, i)).sum::<f32>();
+        let mut target = rng.f32() * total;
+        let mut last = 0;
+        for (n, i) in indices.enumerate() {
+            target -= self.weight(turn, i);
+            if target <= 0.0 {
+                return n;
+            }
+            last = n;
+        }
+        // only reachable through rounding error.
+        last
+    }
+
+    /// Records that `turn` played the move with `index` in the current rollout.
+    pub fn note(&mut self, turn: i8, index: usize) {
+        self.played.push(Self::slot(turn, index));
+    }
+
+    /// Credits every move noted since the last call with the rollout's `result`,
+    /// where 1 is a win for the first player and -1 a win for the second.
+    pub fn finish(&mut self, result: f32) {
+        for &slot in &self.played {
+            let perspective = if slot % 2 == 0 { result } else { -result };
+            let entry = &mut self.entries[slot];
+            entry.0 += perspective.midpoint(1.0);
+            entry.1 += 1;
+        }
+        self.played.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_credited_per_player() {
+        let mut table = HistoryTable::new(3);
+        table.note(1, 0);
+        table.note(-1, 2);
+        table.finish(1.0);
+        table.note(1, 0);
+        table.finish(0.0);
+        assert!((table.value(1, 0) - 0.75).abs() < 1e-6);
+        assert!((table.value(-1, 2) - 0.0).abs() < 1e-6);
+        assert!((table.value(1, 1) - UNPLAYED_VALUE).abs() < 1e-6);
+        table.clear();
+        assert!((table.value(1, 0) - UNPLAYED_VALUE).abs() < 1e-6);
+    }
+
+    #[test]
+    fn winning_moves_are_preferred() {
+        let mut table = HistoryTable::new(2);
+        for _ in 0..10 {
+            table.note(1, 1);
+            table.finish(1.0);
+            table.note(1, 0);
+            table.finish(-1.0);
+        }
+        let rng = fastrand::Rng::with_seed(0);
+        let picks = (0..1000)
+            .filter(|_| table.choose(1, 0..2, &rng) == 1)
+            .count();
+        // exp(4) : exp(0) is about 55 : 1.
+        assert!(picks > 950, "picked the winning move {picks} times");
+    }
+}
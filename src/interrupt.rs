@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the first Ctrl+C, and never cleared.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl+C has been pressed since [`install_handler`] was called.
+/// Searches stop at the next check, and commands stop starting new games or positions.
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Makes Ctrl+C ask the running command to stop gracefully, so that it can report what
+/// it has found and finish writing its files, rather than killing the process.
+/// A second Ctrl+C exits at once.
+#[cfg(unix)]
+pub fn install_handler() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: _exit is async-signal-safe, unlike the rest of process shutdown.
+            unsafe { libc::_exit(130) };
+        }
+    }
+    let handler: extern "C" fn(libc::c_int) = on_sigint;
+    // SAFETY: the handler only touches an atomic, and exits with an async-signal-safe call.
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Ctrl+C keeps its default behaviour on platforms without signals.
+#[cfg(not(unix))]
+pub const fn install_handler() {}
@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub trait BitIterable {
     fn iter_bits(self) -> IterBits;
 }
@@ -10,6 +11,7 @@ impl BitIterable for u8 {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IterBits {
     bitboard: u64,
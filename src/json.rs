@@ -0,0 +1,244 @@
+use std::fmt::{Display, Write};
+
+/// `s` as a JSON string literal, quoted and escaped.
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                write!(quoted, "\\u{:04x}", u32::from(c)).expect("writing to a String cannot fail");
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A JSON array of the textual forms of `items`.
+pub fn strings<T: Display>(items: impl IntoIterator<Item = T>) -> String {
+    let items = items
+        .into_iter()
+        .map(|item| string(&item.to_string()))
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+/// A parsed JSON value. Objects keep their keys in the order they were written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Self>),
+    Object(Vec<(String, Self)>),
+}
+
+impl Value {
+    /// The value of `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub const fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `text`, which must hold exactly one JSON value.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(format!(
+            "unexpected text after the JSON value at {}",
+            parser.pos
+        ));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next, returning whether it did.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected {token} at {}", self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ => Err(format!("expected a JSON value at {}", self.pos)),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        if self.eat("}") {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+            if self.eat("}") {
+                return Ok(Value::Object(fields));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect("[")?;
+        let mut items = Vec::new();
+        if self.eat("]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat("]") {
+                return Ok(Value::Array(items));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let len = self
+            .rest()
+            .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .unwrap_or_else(|| self.rest().len());
+        let text = &self.rest()[..len];
+        let x = text
+            .parse()
+            .map_err(|_| format!("bad number at {}: {text}", self.pos))?;
+        self.pos += len;
+        Ok(Value::Number(x))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        if !self.rest().starts_with('"') {
+            return Err(format!("expected a string at {start}"));
+        }
+        let mut chars = self.rest().char_indices().skip(1);
+        let mut s = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(s);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let hex = (0..4)
+                            .filter_map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<String>();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| {
+                                format!("bad escape in the string at {start}: \\u{hex}")
+                            })?;
+                        s.push(c);
+                    }
+                    _ => return Err(format!("bad escape in the string at {start}")),
+                },
+                c => s.push(c),
+            }
+        }
+        Err(format!("unterminated string at {start}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_parsed() {
+        let value = parse(r#" {"cmd": "search", "moves": ["e2e4", "e7e5"], "rollouts": 1e3, "ok": true, "q": null} "#)
+            .unwrap();
+        assert_eq!(value.get("cmd").and_then(Value::as_str), Some("search"));
+        assert_eq!(value.get("rollouts").and_then(Value::as_f64), Some(1000.0));
+        assert_eq!(
+            value.get("moves").and_then(Value::as_array).map(<[_]>::len),
+            Some(2)
+        );
+        assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("q"), Some(&Value::Null));
+        let text = "a \"quoted\"\n\\ line \u{1}";
+        assert_eq!(parse(&string(text)), Ok(Value::String(text.to_string())));
+        assert!(parse("{\"a\": 1,}").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert!(parse("\"open").is_err());
+    }
+}
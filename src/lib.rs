@@ -0,0 +1,68 @@
+//! The engine behind the `iridium-oxide` binary, for programs that search, play, or
+//! generate training data with it directly, such as through [`Behaviour::builder`] and
+//! [`generate`].
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+// these document items for callers outside of the crate, which was a binary until the library
+// was split out of it, and whose documentation says what errors and panics mean where it matters.
+#![allow(
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::return_self_not_must_use,
+    clippy::too_long_first_doc_paragraph
+)]
+
+mod agent;
+mod atomics;
+mod budget;
+mod cache;
+pub mod calibration;
+mod checksum;
+pub mod config;
+pub mod constants;
+pub mod datageneration;
+pub mod dataset;
+pub mod elo;
+mod fusion;
+pub mod game;
+pub mod gamerunner;
+pub mod games;
+pub mod heatmap;
+mod history;
+pub mod interrupt;
+mod iterbits;
+pub mod json;
+pub mod mcts;
+mod nneval;
+mod priors;
+mod profile;
+pub mod progress;
+pub mod rating;
+mod rave;
+mod record;
+pub mod resign;
+mod score;
+mod searchtree;
+pub mod selfcheck;
+pub mod selfplay;
+pub mod serve;
+pub mod snapshot;
+pub mod strength;
+pub mod sweep;
+mod symmetry;
+pub mod tournament;
+mod treenode;
+mod ucb;
+pub mod ugi;
+pub mod verify;
+
+pub use mcts::{Behaviour, BehaviourBuilder};
+pub use selfplay::generate;
+
+/// The name of the engine.
+pub static NAME: &str = "Iridium";
+/// The version of the engine.
+pub static VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The games that can be named on the command line.
+pub const AVAILABLE_GAMES: &str =
+    "connect4, tictactoe, gomoku{9,13,15,19}, reversi, uttt, chess, antichess";
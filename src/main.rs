@@ -1,235 +1,1213 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 
-use std::time::Instant;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use iridium_oxide::{
+    config::Config,
+    gamerunner::{GameRunner, Handicap, MatchOptions, Player},
+    rating::{Ratings, REFERENCE_RATING},
+    sweep::Grid,
+    games::{
+        connectfour::Connect4, gomoku::Gomoku, tictactoe::TicTacToe,
+        ultimatetictactoe::UltimateTicTacToe,
+    },
+    mcts::{Autosave, Behaviour, Limit, MCTS},
+    progress::{per_second, Progress},
+    selfplay::Starts,
+};
 
-use crate::{
-    gamerunner::{GameRunner, Player},
-    games::{connectfour::Connect4, gomoku::Gomoku, tictactoe::TicTacToe},
-    mcts::{Behaviour, Limit, RolloutPolicy, MCTS},
+use iridium_oxide::{
+    calibration, constants, datageneration, dataset, elo, game, games, heatmap, interrupt, json,
+    mcts, rating, resign, selfcheck, selfplay, serve, snapshot, strength, sweep, tournament, ugi,
+    verify, AVAILABLE_GAMES, NAME,
 };
 
-mod agent;
-mod constants;
-mod datageneration;
-mod elo;
-mod game;
-mod gamerunner;
-mod games;
-mod iterbits;
-mod mcts;
-mod searchtree;
-mod treenode;
-mod ucb;
-mod ugi;
-mod record;
-
-use datageneration::VectoriseState;
+use constants::{
+    DEFAULT_AUTOSAVE_MINUTES, DEFAULT_HASH_MB, DEFAULT_RESIGN_MAX_WRONG, DEFAULT_SERVE_PORT,
+    DETERMINISTIC_SEED, MIN_HASH_MB,
+};
+use datageneration::{GameData, VectoriseState};
 use game::Game;
-use games::chess::Chess;
+use heatmap::{BoardGame, Heatmap};
+use snapshot::{Comparison, Snapshot};
+use games::{antichess::Antichess, chess::Chess};
 use mcts::MCTSExt;
 use Player::{Computer, Human};
 
-/// The name of the engine.
-pub static NAME: &str = "Iridium";
-/// The version of the engine.
-pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[allow(clippy::too_many_lines)]
+/// Every game that is implemented, which `speedtest` benchmarks and `selfcheck` checks.
+const IMPLEMENTED_GAMES: [&str; 9] = [
+    "tictactoe", "connect4", "uttt", "gomoku9", "gomoku13", "gomoku15", "gomoku19", "chess",
+    "antichess",
+];
+
+/// Calls the generic function `$f` with the game type named by `$game`,
+/// printing the available games if the name is not recognised.
+macro_rules! with_game {
+    ($game:expr, $f:ident($($arg:expr),*)) => {
+        match $game {
+            Some("connect4") => $f::<Connect4>($($arg),*),
+            Some("tictactoe") => $f::<TicTacToe>($($arg),*),
+            Some("gomoku9") => $f::<Gomoku<9>>($($arg),*),
+            Some("gomoku13") => $f::<Gomoku<13>>($($arg),*),
+            Some("gomoku15") => $f::<Gomoku<15>>($($arg),*),
+            Some("gomoku19") => $f::<Gomoku<19>>($($arg),*),
+            Some("chess") => $f::<Chess>($($arg),*),
+            Some("antichess") => $f::<Antichess>($($arg),*),
+            Some("uttt") => $f::<UltimateTicTacToe>($($arg),*),
+            Some("reversi") => todo!(),
+            Some(unknown) => {
+                if unknown != "help" {
+                    eprintln!("Unknown game: {unknown}");
+                }
+                println!("Available games: {AVAILABLE_GAMES}");
+            }
+            None => println!("Available games: {AVAILABLE_GAMES}"),
+        }
+    };
+}
+
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut config = Config::from_args(&mut args).unwrap_or_else(|e| {
+        eprintln!("failed to read config: {e}");
+        std::process::exit(1);
+    });
+    // a game name can carry a rule variant, as in connect4-misere, but a flag is not a game.
+    let split = args.get(2).filter(|it| !it.starts_with('-'));
+    let (game, variant) = match split.and_then(|it| it.split_once('-')) {
+        Some((game, variant)) => (Some(game), Some(variant)),
+        None => (args.get(2).map(String::as_str), None),
+    };
+    if let Some(variant) = variant {
+        if config.variant.as_deref().is_some_and(|it| it != variant) {
+            eprintln!("the game name and --variant give different variants");
+            std::process::exit(1);
+        }
+        config.variant = Some(variant.to_string());
+    }
+    let config = &config;
 
+    // these commands stop gracefully on Ctrl+C, reporting and saving what they have done.
+    if matches!(args.get(1).map(String::as_str), Some("play" | "generate" | "match" | "tournament" | "sweep" | "analyse")) {
+        interrupt::install_handler();
+    }
     match args.get(1).map(String::as_str) {
         Some("play") => {
-            let game = args.get(2);
             let player = args.get(3).map(String::as_str);
-            match game.map(String::as_str) {
-                Some("connect4") => play::<Connect4>(player),
-                Some("tictactoe") => play::<TicTacToe>(player),
-                Some("gomoku9") => play::<Gomoku<9>>(player),
-                Some("gomoku13") => play::<Gomoku<13>>(player),
-                Some("gomoku15") => play::<Gomoku<15>>(player),
-                Some("gomoku19") => play::<Gomoku<19>>(player),
-                Some("chess") => play::<Chess>(player),
-                Some("reversi" | "uttt") => todo!(),
-                Some(unknown) => {
-                    if unknown != "help" {
-                        eprintln!("Unknown game: {unknown}");
-                    }
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,15,19}}, reversi, uttt, chess"
-                    );
-                }
-                None => {
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,15,19}}, reversi, uttt, chess"
-                    );
-                }
-            }
+            let priors = args.get(4).map(String::as_str);
+            with_game!(game, play(config, player, priors));
         }
         Some("generate") => {
-            let game = args.get(2);
+            let mut args = args.clone();
+            let force = take_flag(&mut args, "--force");
+            let out = take_option(&mut args, "--out").map(PathBuf::from);
+            let records = take_option(&mut args, "--records").map(PathBuf::from);
+            let openings = take_option(&mut args, "--openings").map(PathBuf::from);
+            let random_plies = take_option(&mut args, "--random-plies")
+                .map_or(0, |it| it.parse().expect("generate: --random-plies must be a number"));
+            assert!(
+                openings.is_none() || random_plies == 0,
+                "generate: --openings and --random-plies cannot be combined"
+            );
+            let eval_every = take_option(&mut args, "--eval-every")
+                .map(|it| it.parse().expect("generate: --eval-every must be a number"))
+                .filter(|&n: &usize| n > 0);
             let games = args[3].parse().unwrap();
-            let fname = args[4].as_str();
-            println!("{games} games will be played");
-            let start = Instant::now();
-            match game.map(String::as_str) {
-                Some("connect4") => generate_data::<Connect4>(games, fname),
-                Some("tictactoe") => generate_data::<TicTacToe>(games, fname),
-                Some(g @ ("gomoku9" | "gomoku13" | "gomoku15" | "gomoku19")) => {
-                    match g {
-                        "gomoku9" => generate_data::<Gomoku<9>>(games, fname),
-                        "gomoku13" => generate_data::<Gomoku<13>>(games, fname),
-                        "gomoku15" => generate_data::<Gomoku<15>>(games, fname),
-                        "gomoku19" => generate_data::<Gomoku<19>>(games, fname),
-                        _ => unreachable!(),
-                    }
-                }
-                Some("reversi" | "uttt") => todo!(),
-                Some("chess") => generate_data::<Chess>(games, fname),
-                Some(unknown) => {
-                    if unknown != "help" {
-                        eprintln!("Unknown game: {unknown}");
-                    }
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,19}}, reversi, uttt, chess"
-                    );
-                    return;
-                }
-                None => {
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,19}}, reversi, uttt, chess"
-                    );
-                    return;
-                }
-            }
-            let secs = start.elapsed().as_secs_f64();
-            println!("Generating data took {secs:.2} seconds");
-        }
-        Some("match") => {
-            // run match between two configurations
-            let game = args.get(2).map(String::as_str);
-            let rounds = args.get(3).map_or(1, |it| it.parse().unwrap());
-            let config1 = args.get(4).map(String::as_str);
-            let config2 = args.get(5).map(String::as_str);
-            match game {
-                Some("connect4") => run_test::<Connect4>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("tictactoe") => run_test::<TicTacToe>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("gomoku9") => run_test::<Gomoku<9>>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("gomoku13") => run_test::<Gomoku<13>>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("gomoku15") => run_test::<Gomoku<15>>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("gomoku19") => run_test::<Gomoku<19>>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("chess") => run_test::<Chess>(
-                    rounds,
-                    config1.expect("no config"),
-                    config2.expect("no config"),
-                ),
-                Some("reversi" | "uttt") => todo!(),
-                Some(unknown) => {
-                    if unknown != "help" {
-                        eprintln!("Unknown game: {unknown}");
-                    }
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,15,19}}, reversi, uttt, chess"
-                    );
-                }
-                None => {
-                    println!(
-                        "Available games: connect4, tictactoe, gomoku{{9,13,15,19}}, reversi, uttt, chess"
-                    );
-                }
-            }
+            let prefix = datageneration::output_prefix(
+                out.as_deref(),
+                config.data_dir(),
+                args.get(4).map(String::as_str),
+            )
+            .unwrap_or_else(|e| panic!("generate: {e}"));
+            let starts = Starts {
+                openings: openings.as_deref(),
+                random_plies,
+                eval_every,
+            };
+            with_game!(
+                game,
+                generate_data(config, games, &prefix, records.as_deref(), force, &starts)
+            );
+        }
+        Some("match") => run_match(config, game, &args),
+        Some("tournament") => run_tournament(config, game, &args),
+        Some("analyse") => run_analyse(config, game, &args),
+        Some("eval") => run_eval(config, game, &args),
+        Some("speedtest") => {
+            let rollouts = args.get(2).map_or(20_000, |it| it.parse().unwrap());
+            speedtest_all(config, rollouts);
+        }
+        Some("selfcheck") => run_selfcheck(config),
+        Some("profile") => {
+            let rollouts = args.get(3).map_or(100_000, |it| it.parse().unwrap());
+            with_game!(game, profile(config, rollouts));
         }
-        Some("uci") => ugi::main(),
-        None => {
-            println!("Available commands:");
-            println!("1. Play against a the computer ({NAME} play <game> <1|2>)");
-            println!("2. Generate data for a game ({NAME} generate <game> <count> <fname>)");
-            println!("3. Run a match between two configurations ({NAME} match <game> <rounds> <config1> <config2>)");
+        Some("verify") => {
+            let games = args.get(3).map_or(1, |it| it.parse().unwrap());
+            let depth = args.get(4).map_or(6, |it| it.parse().unwrap());
+            with_game!(game, verify(config, games, depth));
         }
+        Some("strength") => run_strength(config, game, &args),
+        Some("calibrate") => run_calibrate(config, &args),
+        Some("resign") => run_resign(config, &args),
+        Some("ratings") => run_ratings(config, game, &args),
+        Some("sweep") => run_sweep(config, game, &args),
+        Some("diff") => diff(args.get(2), args.get(3)),
+        Some("dataset") => dataset(
+            args.get(2).map(String::as_str),
+            args.get(3..).unwrap_or_default(),
+        ),
+        Some("defaults") => with_game!(game, print_defaults()),
+        Some("uci") => ugi::main(config),
+        Some("serve") => run_serve(config, &args),
+        None => print_help(),
         Some(unknown) => {
             if unknown != "help" {
                 eprintln!("Unknown command: {unknown}");
             }
-            println!("Available commands:");
-            println!("1. Play against a the computer ({NAME} play <game> <1|2>)");
-            println!("2. Generate data for a game ({NAME} generate <game> <count> <fname>)");
-            println!("3. Run a match between two configurations ({NAME} match <game> <rounds> <config1> <config2>)");
+            print_help();
+        }
+    }
+}
+
+/// Serves the engine over TCP until killed, see [`serve::main`].
+fn run_serve(config: &Config, args: &[String]) {
+    let mut args = args.to_vec();
+    let port = take_option(&mut args, "--port")
+        .map_or(DEFAULT_SERVE_PORT, |it| it.parse().expect("serve: --port must be a port number"));
+    serve::main(config, port).unwrap_or_else(|e| panic!("serve: {e}"));
+}
+
+/// Parses the arguments of `match`, and runs the match.
+fn run_match(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let log = take_option(&mut args, "--log").map(PathBuf::from);
+    let ratings = take_option(&mut args, "--ratings").map_or_else(
+        || config.data_dir().join(rating::DEFAULT_RATINGS_FILE),
+        PathBuf::from,
+    );
+    let options = match_options(&mut args);
+    let rounds = args.get(3).map_or(1, |it| it.parse().unwrap());
+    let config1 = args.get(4).map(String::as_str).expect("no config");
+    let config2 = args.get(5).map(String::as_str).expect("no config");
+    // ratings are kept apart for each rule variant.
+    let name = config.variant.as_ref().map_or_else(
+        || game.unwrap_or_default().to_string(),
+        |variant| format!("{}-{variant}", game.unwrap_or_default()),
+    );
+    with_game!(
+        game,
+        run_test(config, &name, rounds, [config1, config2], log.as_deref(), &ratings, &options)
+    );
+}
+
+/// Parses the arguments of `tournament`, and runs the tournament.
+fn run_tournament(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let concurrency = take_option(&mut args, "--concurrency")
+        .map_or(1, |it| it.parse().expect("tournament: --concurrency must be a number"));
+    let openings = take_option(&mut args, "--openings").map(PathBuf::from);
+    // without a seed, one is chosen, and printed so that the tournament can be replayed.
+    let seed = take_option(&mut args, "--seed")
+        .map_or_else(|| fastrand::u64(..), |it| it.parse().expect("tournament: --seed must be a number"));
+    let adjudicate = take_option(&mut args, "--adjudicate")
+        .map(|it| it.parse().expect("tournament: --adjudicate must be a number of plies"));
+    let games = args
+        .get(3)
+        .map(|it| it.parse().expect("tournament: the number of games per pairing must be a number"))
+        .expect("tournament: no number of games per pairing");
+    let players = args.get(4..).unwrap_or_default();
+    let options = TournamentOptions {
+        games,
+        concurrency,
+        openings: openings.as_deref(),
+        seed,
+        adjudicate,
+    };
+    with_game!(game, tournament(config, players, &options));
+}
+
+/// How a round-robin tournament is played, see [`tournament::Schedule`].
+struct TournamentOptions<'a> {
+    games: usize,
+    /// The number of games played at once, each on its own thread.
+    concurrency: usize,
+    openings: Option<&'a Path>,
+    seed: u64,
+    adjudicate: Option<usize>,
+}
+
+/// Plays a round-robin tournament between the configs of `players`, and prints the standings.
+fn tournament<G: Game + MCTSExt>(config: &Config, players: &[String], options: &TournamentOptions) {
+    let start = start_position::<G>(config, "tournament");
+    let openings = options
+        .openings
+        .map(|path| datageneration::read_openings(&start, path))
+        .transpose()
+        .unwrap_or_else(|e| panic!("tournament: failed to read openings: {e}"))
+        .unwrap_or_default();
+    let schedule = tournament::Schedule::round_robin(
+        players.len(),
+        options.games,
+        openings.len(),
+        options.seed,
+    )
+    .unwrap_or_else(|e| panic!("tournament: {e}"));
+    // like the sessions of serve, the engines playing at once share the hash between them.
+    let engines = 2 * options.concurrency.max(1);
+    let hash_mb = config.hash_mb.unwrap_or(DEFAULT_HASH_MB) / engines;
+    let behaviours = players
+        .iter()
+        .map(|player| {
+            let mut behaviour: Behaviour = rating::resolve(player)
+                .parse()
+                .unwrap_or_else(|e| panic!("tournament: {e}"));
+            config.apply_resources(&mut behaviour);
+            behaviour.hash_mb = G::hash_mb().min(hash_mb).max(MIN_HASH_MB);
+            behaviour.readout = false;
+            behaviour
+        })
+        .collect::<Vec<_>>();
+    println!(
+        "Running a {}-game round-robin of {} players with seed {}...",
+        schedule.fixtures().len(),
+        players.len(),
+        options.seed
+    );
+    let standings = tournament::run(
+        &schedule,
+        &behaviours,
+        &start,
+        &openings,
+        options.concurrency,
+        options.adjudicate,
+    );
+    print!(
+        "{}",
+        tournament::Table {
+            standings: &standings,
+            names: players,
+        }
+    );
+}
+
+/// Stores a match of `game` in the rating database at `ratings`, and reports the rating
+/// of each player that can be rated, fitted to every match stored there.
+fn record_match(
+    game: &str,
+    configs: [&str; 2],
+    ratings: &Path,
+    options: &MatchOptions,
+    elo: &elo::Difference,
+) {
+    if options.handicaps != [Handicap::default(); 2] {
+        println!("Not stored in the rating database: the players had handicaps.");
+        return;
+    }
+    if elo.results.iter().sum::<i32>() == 0 {
+        return;
+    }
+    let record = rating::Match::new(game, configs, elo, rating::now());
+    rating::record(ratings, &record)
+        .unwrap_or_else(|e| panic!("match: failed to write to {}: {e}", ratings.display()));
+    let matches = rating::read(ratings).unwrap_or_else(|e| panic!("match: {e}"));
+    let fitted = Ratings::fit(&matches, game, elo.confidence);
+    for config in configs.into_iter().filter(|&config| config != rating::REFERENCE) {
+        if let Some(rated) = fitted.of(config) {
+            println!(
+                "Rating of {config}: {:.1}, error: ±{:.1}, with the reference opponent at {REFERENCE_RATING}",
+                rated.elo, rated.error
+            );
+        }
+    }
+}
+
+/// Lists the configs in the rating database by rating, for one game or all of them.
+fn run_ratings(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let path = take_option(&mut args, "--ratings").map_or_else(
+        || config.data_dir().join(rating::DEFAULT_RATINGS_FILE),
+        PathBuf::from,
+    );
+    let matches = match rating::read(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("No matches have been stored in {}.", path.display());
+            return;
+        }
+        result => result.unwrap_or_else(|e| panic!("ratings: {e}")),
+    };
+    let games = rating::games(&matches);
+    let game = game.filter(|it| !it.starts_with('-'));
+    // a game given with a rule variant is stored under the full name.
+    let name = config.variant.as_ref().map_or_else(
+        || game.map(str::to_string),
+        |variant| Some(format!("{}-{variant}", game.unwrap_or_default())),
+    );
+    let games = match &name {
+        Some(name) if !games.contains(&name.as_str()) => {
+            println!("No matches of {name} have been stored in {}.", path.display());
+            return;
+        }
+        Some(name) => vec![name.as_str()],
+        None => games,
+    };
+    for (i, game) in games.into_iter().enumerate() {
+        if i > 0 {
+            println!();
         }
+        println!("{}", Ratings::fit(&matches, game, elo::DEFAULT_CONFIDENCE));
+    }
+}
+
+/// Parses the arguments of `sweep`, and runs the sweep.
+fn run_sweep(config: &Config, game: Option<&str>, args: &[String]) {
+    fn values<T>(
+        args: &mut Vec<String>,
+        flag: &str,
+        parse: fn(&str) -> Result<Vec<T>, String>,
+    ) -> Vec<T> {
+        take_option(args, flag)
+            .map(|it| parse(&it).unwrap_or_else(|e| panic!("sweep: {flag}: {e}")))
+            .unwrap_or_default()
+    }
+    let mut args = args.to_vec();
+    let grid = Grid {
+        cpuct_factors: values(&mut args, "--cpuct", Grid::parse_cpuct_factors),
+        rollout_policies: values(&mut args, "--policy", Grid::parse_rollout_policies),
+        expansion_thresholds: values(&mut args, "--expansion", Grid::parse_expansion_thresholds),
+    };
+    let out = take_option(&mut args, "--out").map(PathBuf::from);
+    let games = args
+        .get(3)
+        .map(|it| it.parse().expect("sweep: the number of games per cell must be a number"))
+        .filter(|&n: &usize| n > 0 && n.is_multiple_of(2))
+        .expect("sweep: the number of games per cell must be positive and even");
+    let baseline = args.get(4).map(String::as_str).expect("sweep: no baseline config");
+    with_game!(game, sweep(config, games, baseline, &grid, out.as_deref()));
+}
+
+/// Plays every cell of `grid` against `baseline` for `games` games, printing a table of
+/// the cells from best to worst, and writing the results to `out` as JSON.
+fn sweep<G: Game + MCTSExt>(
+    config: &Config,
+    games: usize,
+    baseline: &str,
+    grid: &Grid,
+    out: Option<&Path>,
+) {
+    let mut behaviour: Behaviour = rating::resolve(baseline)
+        .parse()
+        .unwrap_or_else(|e| panic!("sweep: {e}"));
+    behaviour.hash_mb = G::hash_mb();
+    config.apply_resources(&mut behaviour);
+    behaviour.readout = false;
+    let start = start_position::<G>(config, "sweep");
+    let cells = grid.cells(&behaviour).len();
+    println!("Sweeping {cells} cells of {games} games each against {baseline}...");
+    let results = sweep::run(&behaviour, grid, games, &start, elo::DEFAULT_CONFIDENCE);
+    print!("{}", sweep::Table(&results));
+    if let Some(out) = out {
+        std::fs::write(out, sweep::to_json(&results))
+            .unwrap_or_else(|e| panic!("sweep: failed to write {}: {e}", out.display()));
+        println!("wrote the results to {}", out.display());
+    }
+}
+
+/// Removes `flag` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+/// Removes `flag` and the value following it from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    let value = args
+        .get(i + 1)
+        .cloned()
+        .unwrap_or_else(|| panic!("no value given for {flag}"));
+    args.drain(i..=i + 1);
+    Some(value)
+}
+
+/// Removes the flags that control the handicaps, reporting, and early stopping of a match
+/// from `args`.
+fn match_options(args: &mut Vec<String>) -> MatchOptions {
+    let confidence = take_option(args, "--confidence")
+        .map_or(elo::DEFAULT_CONFIDENCE, |it| it.parse().unwrap());
+    assert!(
+        0.0 < confidence && confidence < 1.0,
+        "match: confidence must be between 0 and 1"
+    );
+    let report_every = take_option(args, "--report-every")
+        .map(|it| it.parse().expect("match: --report-every must be a number"))
+        .filter(|&k: &usize| k > 0);
+    let handicap = |args: &mut Vec<String>, flag| {
+        take_option(args, flag).map_or_else(Handicap::default, |it| {
+            it.parse().unwrap_or_else(|e| panic!("match: {e}"))
+        })
+    };
+    MatchOptions {
+        confidence,
+        report_every,
+        stop_when_significant: take_flag(args, "--stop-early"),
+        handicaps: [handicap(args, "--handicap1"), handicap(args, "--handicap2")],
+        adjudicate: take_option(args, "--adjudicate")
+            .map(|it| it.parse().expect("match: --adjudicate must be a number of plies")),
     }
 }
 
-fn play<G: Game + MCTSExt>(player: Option<&str>) {
+fn print_help() {
+    println!("Available commands:");
+    println!("1. Play against a the computer ({NAME} play <game> <1|2> [priors])");
+    println!("2. Generate data for a game ({NAME} generate <game> <count> [fname] [--out <dir-or-path>] [--records <file>] [--force] [--openings <file> | --random-plies <k>] [--eval-every <n>])");
+    println!("3. Run a match between two configurations ({NAME} match <game> <rounds> <config1> <config2> [--log <file>] [--ratings <file>] [--confidence <p>] [--report-every <k>] [--stop-early] [--handicap1 <h>] [--handicap2 <h>] [--adjudicate <plies>])");
+    println!("   where a config is limit=<limit>,rollout_policy=<policy>[,priors=<path>][,cache=<path>][,model=<path>][,schedule=<move>@<limit>/...][,noise=<sigma>][,anneal_noise][,minimax=<depth>][,fpu=<reduction>][,rave=<bias>][,selection=<ucb1|ucb1_tuned|puct>][,exp_factor=<c>][,cpuct_base=<b>][,cpuct_factor=<c>][,expansion_threshold=<n>][,merge=<sum|vote|average>][,lcb=<z>][,resign=<win rate>][,draw=<margin>][,threads=<n>][,trees=<n>][,batch=<n>][,hash=<mb>][,cp_scale=<scale>][,quality_scaling=<exp:<rate>|linear:<rate>|none>][,root_symmetry][,root_pruning][,tactical_expansion][,transpositions][,training][,deterministic]");
+    println!("   where each option can also be named by its setting, as root_parallelism_count for trees, and each flag can be given as <flag>=<true|false>");
+    println!("   or `reference`, a fixed opponent rated {REFERENCE_RATING}, which anchors the ratings of the rating database that every match is stored in");
+    println!("   and batch descends to that many leaves at once, each leaving a virtual loss on its line, and then values them together");
+    println!("   and model values positions with an ONNX policy-value network of dense layers, rather than rollouts, and takes the priors of moves from it");
+    println!("   and schedule searches to each phase's limit from its move on, as in 30@rollouts:2000/60@rollouts:500 for fewer rollouts later in the game");
+    println!("   and resign and draw make an engine resign below that win rate, and offer and accept draws within that margin of 0.5");
+    println!("   and trees searches that many trees at once, independently, and merges their root visits to choose a move");
+    println!("   and transpositions makes the nodes of the same position, reached by different orders of moves, share their statistics");
+    println!("   and selection scores children by UCB1 (with exp_factor), UCB1-Tuned, or PUCT (with cpuct_base and cpuct_factor, and the priors of moves)");
+    println!("   and rave blends the value of each move with its value in every line that made it later (all moves as first), less so the higher the bias");
+    println!("   and cache keeps the root visits of every search in a file of that game, which later searches take their root priors from");
+    println!("   where a handicap is any of nodes=<ratio>, time=<ratio>, and odds (an extra stone, or knight odds in chess)");
+    println!("   and --adjudicate ends games once a search of that many plies proves the result (in games that can unmake moves)");
+    println!("   or a round-robin tournament between many configurations, reproducible given its seed ({NAME} tournament <game> <games-per-pairing> <config>... [--concurrency <games at once>] [--openings <file>] [--seed <n>] [--adjudicate <plies>])");
+    println!("   and list the configs of the rating database by their rating ({NAME} ratings [game] [--ratings <file>])");
+    println!("   or sweep a grid of parameters, playing each combination against a baseline config ({NAME} sweep <game> <games-per-cell> <baseline> [--cpuct <values>] [--policy <policies>] [--expansion <values>] [--out <file.json>])");
+    println!("   where values are a list a,b,c or a range low:high:steps, and policies a list");
+    println!("4. Profile the phases of a search ({NAME} profile <game> [rollouts])");
+    println!("   or benchmark a short search of every game ({NAME} speedtest [rollouts])");
+    println!("   or check that every game works on this platform: move generation, search, self-play, and vectorisation ({NAME} selfcheck)");
+    println!("5. Speak UCI, or UGI for other games ({NAME} uci)");
+    println!("   or serve sessions of any game to many clients at once, as JSON lines over TCP ({NAME} serve [--port <n>]), the trees of the sessions sharing the memory of --hash");
+    println!("6. Compare predicted win rates in generated data with game results ({NAME} calibrate <dataset> [bins])");
+    println!("   or find how low a win rate is safe to resign at, from the games of generated data ({NAME} resign <dataset>... [--max-wrong <fraction>])");
+    println!("7. Check search moves against an alpha-beta search in self-play ({NAME} verify <game> [games] [depth])");
+    println!("   or measure how often searches of each budget find a perfect move, in positions alpha-beta solves ({NAME} strength <game> [positions] [rollouts...] [--depth <plies>])");
+    println!("8. Show the default search settings for a game ({NAME} defaults <game>)");
+    println!("9. Search a position and show where the search went ({NAME} analyse <game> [moves...] [--heatmap <file.json|file.ppm>] [--snapshot <file>] [--cache <file>])");
+    println!("   with --autosave <file>, the tree is saved every few minutes (--autosave-minutes <n>) and searches resume from it");
+    println!("   or many positions, one line of moves each ({NAME} analyse <game> --file <positions> [--rollouts <n>] [--output <file.jsonl>])");
+    println!("   or evaluate one position quickly, showing the best move, its win probability, and the {EVAL_MOVES} most searched moves ({NAME} eval <game> [moves...] [--rollouts <n>] [--json])");
+    println!("10. Compare two search snapshots of the same position, saved by analyse ({NAME} diff <snapshot> <snapshot>)");
+    println!("11. Summarise, check, or update to the current format a file of binary game records, as written by generate --records ({NAME} dataset stats <file> | dataset verify <file> | dataset migrate <file> <output>)");
+    println!();
+    println!("Options (override ~/.config/iridium/config.toml):");
+    println!("  --config <path>    read a different config file");
+    println!("  --hash <mb>        memory for the search tree, in megabytes");
+    println!("  --threads <n>      number of threads searching the tree together");
+    println!("  --data-dir <dir>   directory that generated data is written to");
+    println!("  --deterministic    search on one thread with a fixed seed, for reproducible runs");
+    println!("  --json-info        write search output as JSON lines, for GUIs and scripts");
+    println!("  --variant <name>   play a rule variant: misere (tictactoe, connect4) or stalemate-loss (chess),");
+    println!("                     which can also be given with the game, as in connect4-misere");
+    println!("Ctrl+C stops play, generate, match, tournament, and analyse after the current search, keeping what is done; press it again to exit at once.");
+}
+
+fn play<G: Game + MCTSExt>(config: &Config, player: Option<&str>, priors: Option<&str>) {
     println!("iridium-oxide operating at full capacity!");
-    let config = &Behaviour::for_game::<G>();
+    let mut behaviour = Behaviour {
+        root_priors: priors.map(str::to_string),
+        ..Behaviour::for_game::<G>()
+    };
+    config.apply(&mut behaviour);
     let player = player
         .unwrap_or_else(|| panic!("No side provided."))
         .parse()
         .unwrap();
-    match player {
-        1 => GameRunner::<G>::new(Human, Computer(MCTS::new(config))).run(),
-        2 => GameRunner::<G>::new(Computer(MCTS::new(config)), Human).run(),
+    let start = start_position::<G>(config, "play");
+    let mut runner = match player {
+        1 => GameRunner::<G>::new(Human, Computer(MCTS::new(&behaviour))),
+        2 => GameRunner::<G>::new(Computer(MCTS::new(&behaviour)), Human),
         _ => panic!("fastplay: player must be 1 (you play first) or 2 (i play first)"),
-    }
+    };
+    runner.start_from(start);
+    runner.run();
 }
 
-fn generate_data<G: VectoriseState + MCTSExt>(games: u32, id: &str) {
-    let limit = Limit::Rollouts(8_000);
-    let config = Behaviour {
-        debug: false,
-        readout: false,
-        log: false,
-        limit,
-        root_parallelism_count: 1,
-        rollout_policy: RolloutPolicy::Random,
-        exp_factor: 5.0,
-        training: true,
-    };
-
-    let episode_data = (0..games)
-        .map(|_it| {
-            // print progress bar
-            // print!("{:.2}%     \r", f64::from(it) * 100.0 / f64::from(games));
-            // std::io::stdout().flush().unwrap();
-            GameRunner::<G>::play_training_game(&config)
-        })
-        .reduce(|a, b| a + b)
-        .expect("failed to generate training data");
-    // println!("100%     ");
+/// The starting position of the configured variant of `G`, which `command` starts from.
+fn start_position<G: Game>(config: &Config, command: &str) -> G {
+    config
+        .start()
+        .unwrap_or_else(|e| panic!("{command}: {e}"))
+}
+
+/// Plays `games` games of self-play, and writes them to the dataset at `prefix`, and as
+/// binary game records to `records`, if it is given.
+fn generate_data<G: VectoriseState + MCTSExt>(
+    config: &Config,
+    games: u32,
+    prefix: &Path,
+    records: Option<&Path>,
+    force: bool,
+    starts: &Starts,
+) {
+    // fail before playing any games, rather than after.
+    datageneration::check_overwrite(prefix, force).unwrap_or_else(|e| panic!("generate: {e}"));
+    if let Some(records) = records.filter(|records| !force && records.exists()) {
+        panic!("generate: {} already exists, pass --force to overwrite it", records.display());
+    }
+    println!("{games} games will be played");
+    let start = Instant::now();
+    let mut progress = Progress::new(u64::from(games), "games");
+    let mut episode_data: Option<GameData> = None;
+    selfplay::generate::<G>(
+        config,
+        games as usize,
+        starts,
+        |data| {
+            episode_data = Some(match episode_data.take() {
+                Some(earlier) => earlier + data,
+                None => data,
+            });
+        },
+        |stats| {
+            progress.set_message(format!(
+                "{} positions, {:.1} positions/s",
+                stats.total_positions,
+                stats.positions_per_second()
+            ));
+            progress.inc();
+        },
+    )
+    .unwrap_or_else(|e| panic!("generate: {e}"));
+    progress.finish();
+    // a game cut short by Ctrl+C is dropped, and the finished games are saved.
+    let Some(episode_data) = episode_data else {
+        println!("interrupted before any games were finished, so no data was written");
+        return;
+    };
     episode_data
-        .save::<G>(id)
+        .save::<G>(prefix, force)
         .expect("failed to write file");
+    if let Some(records) = records {
+        let written = episode_data
+            .save_records(records, force)
+            .unwrap_or_else(|e| panic!("generate: failed to write {}: {e}", records.display()));
+        println!("wrote {written} game records to {}", records.display());
+    }
     episode_data.summary();
+    let secs = start.elapsed().as_secs_f64();
+    println!("Generating data took {secs:.2} seconds");
 }
 
-fn run_test<G: Game + MCTSExt>(rounds: usize, config1: &str, config2: &str) {
-    let behaviour_1: Behaviour = config1.parse().unwrap();
-    let behaviour_2: Behaviour = config2.parse().unwrap();
+fn run_test<G: Game + MCTSExt>(
+    config: &Config,
+    game: &str,
+    rounds: usize,
+    [config1, config2]: [&str; 2],
+    log: Option<&Path>,
+    ratings: &Path,
+    options: &MatchOptions,
+) {
+    // the reference opponent keeps the resources of its own config, so that its strength,
+    // and the ratings anchored to it, do not change with the machine running the match.
+    let behaviour = |name: &str| {
+        let mut behaviour: Behaviour = rating::resolve(name).parse().unwrap();
+        if name != rating::REFERENCE {
+            behaviour.hash_mb = G::hash_mb();
+            config.apply_resources(&mut behaviour);
+        }
+        behaviour
+    };
+    let mut behaviour_1 = behaviour(config1);
+    let mut behaviour_2 = behaviour(config2);
+    for (behaviour, handicap) in [&mut behaviour_1, &mut behaviour_2]
+        .into_iter()
+        .zip(&options.handicaps)
+    {
+        handicap
+            .apply(behaviour)
+            .unwrap_or_else(|e| panic!("match: {e}"));
+    }
     let player_1 = Computer(MCTS::<G>::new(&behaviour_1));
     let player_2 = Computer(MCTS::<G>::new(&behaviour_2));
     let mut runner = GameRunner::<G>::new(player_1, player_2);
-    runner.play_match(rounds * 2);
+    if config.variant.is_some() {
+        runner.start_from(start_position(config, "match"));
+    }
+    if let Some(log) = log {
+        let file = File::create(log)
+            .unwrap_or_else(|e| panic!("match: failed to create {}: {e}", log.display()));
+        runner.log_games_to(
+            Box::new(BufWriter::new(file)),
+            [config1.to_string(), config2.to_string()],
+        );
+    }
+    let elo = runner.play_match(rounds * 2, options);
+    record_match(game, [config1, config2], ratings, options, &elo);
+}
+
+/// Parses the arguments of `analyse`, and analyses one position or a file of them.
+fn run_analyse(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let heatmap = take_option(&mut args, "--heatmap").map(PathBuf::from);
+    let snapshot = take_option(&mut args, "--snapshot").map(PathBuf::from);
+    let cache = take_option(&mut args, "--cache");
+    let file = take_option(&mut args, "--file").map(PathBuf::from);
+    let output = take_option(&mut args, "--output").map(PathBuf::from);
+    let rollouts = take_option(&mut args, "--rollouts")
+        .map(|it| it.parse().expect("analyse: --rollouts must be a number"));
+    let autosave_minutes = take_option(&mut args, "--autosave-minutes")
+        .map_or(DEFAULT_AUTOSAVE_MINUTES, |it| {
+            it.parse().expect("analyse: --autosave-minutes must be a number")
+        });
+    let autosave = take_option(&mut args, "--autosave").map(|path| Autosave {
+        path: PathBuf::from(path),
+        interval: Duration::from_mins(autosave_minutes),
+    });
+    if let Some(file) = file {
+        let batch = Batch {
+            positions: &file,
+            output: output.as_deref(),
+            rollouts,
+        };
+        with_game!(game, analyse_batch(config, &batch));
+    } else {
+        let moves = args.get(3..).unwrap_or_default().join(" ");
+        let outputs = Outputs {
+            heatmap: heatmap.as_deref(),
+            snapshot: snapshot.as_deref(),
+            autosave,
+            cache: cache.as_deref(),
+        };
+        with_game!(game, analyse(config, &moves, &outputs));
+    }
+}
+
+/// The most searched moves that `eval` reports.
+const EVAL_MOVES: usize = 5;
+
+fn run_eval(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let json = take_flag(&mut args, "--json");
+    let rollouts = take_option(&mut args, "--rollouts")
+        .map(|it| it.parse().expect("eval: --rollouts must be a number"));
+    let moves = args.get(3..).unwrap_or_default().join(" ");
+    with_game!(game, evaluate(config, &moves, rollouts, json));
+}
+
+/// Searches the position reached by playing `moves` from the start, and prints the best
+/// move, the win probability of the side to move, and the share of the search given to
+/// each of the most searched moves, as text or as one JSON object. A position that cannot
+/// be searched is an error, and the process exits with a failure.
+fn evaluate<G: Game + MCTSExt>(config: &Config, moves: &str, rollouts: Option<u64>, json: bool) {
+    let fail = |e: &str| -> ! {
+        eprintln!("eval: {e}");
+        std::process::exit(1);
+    };
+    let mut state = start_position::<G>(config, "eval");
+    let moves = datageneration::parse_opening(&state, moves).unwrap_or_else(|e| fail(&e));
+    for m in moves {
+        state.push(m);
+    }
+    if state.is_terminal() {
+        fail("the game is over.");
+    }
+    let mut behaviour = Behaviour::for_game::<G>();
+    config.apply(&mut behaviour);
+    behaviour.readout = false;
+    if let Some(rollouts) = rollouts {
+        behaviour.limit = Limit::Rollouts(rollouts);
+    }
+    let mut engine = MCTS::<G>::new(&behaviour);
+    let results = engine.search(&state);
+    let mut edges = engine.root_edges();
+    edges.sort_by_key(|&(_, visits, _)| std::cmp::Reverse(visits));
+    edges.truncate(EVAL_MOVES);
+    #[allow(clippy::cast_precision_loss)]
+    let share = |visits: u64| visits as f64 / results.rollouts.max(1) as f64;
+    if json {
+        let policy = edges
+            .iter()
+            .map(|&(m, visits, _)| {
+                format!("{}: {:.4}", json::string(&m.to_string()), share(visits))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{{\"best_move\": {}, \"win_probability\": {:.4}, \"rollouts\": {}, \"policy\": {{{policy}}}}}",
+            json::string(&results.best_move.to_string()),
+            results.win_rate,
+            results.rollouts,
+        );
+    } else {
+        println!(
+            "best move {}, win probability {:.3} after {} rollouts",
+            results.best_move, results.win_rate, results.rollouts
+        );
+        for &(m, visits, _) in &edges {
+            println!("{m:>6} {:>5.1}%", 100.0 * share(visits));
+        }
+    }
+}
+
+/// Runs a `dataset` subcommand on a file of binary game records.
+fn dataset(command: Option<&str>, paths: &[String]) {
+    // a file that cannot be read is the user's mistake, not a bug, so it is reported plainly.
+    let fail = |e: &std::io::Error| -> ! {
+        eprintln!("dataset: {e}");
+        std::process::exit(1);
+    };
+    let path = Path::new(paths.first().expect("dataset: no file given"));
+    match command {
+        Some("stats") => {
+            let reader = dataset::Reader::open(path).unwrap_or_else(|e| fail(&e));
+            println!("{}", dataset::Stats::of(&reader));
+        }
+        Some("migrate") => {
+            let output = Path::new(paths.get(1).expect("dataset: no output file given"));
+            let games = dataset::migrate(path, output).unwrap_or_else(|e| fail(&e));
+            println!("migrated {games} games to {}", output.display());
+        }
+        Some("verify") => {
+            let verification = dataset::verify(path).unwrap_or_else(|e| fail(&e));
+            println!("{verification}");
+            if !verification.is_ok() {
+                std::process::exit(1);
+            }
+        }
+        _ => eprintln!("dataset: expected a subcommand, one of: stats, migrate, verify"),
+    }
+}
+
+/// Prints the differences between two search snapshots of the same position.
+fn diff(a: Option<&String>, b: Option<&String>) {
+    let load = |path: Option<&String>| {
+        let path = Path::new(path.expect("diff: two snapshots are needed"));
+        Snapshot::load(path).unwrap_or_else(|e| panic!("diff: {e}"))
+    };
+    let comparison = Comparison::new(&load(a), &load(b)).unwrap_or_else(|e| panic!("diff: {e}"));
+    print!("{comparison}");
+}
+
+/// The files that an analysis is written to, as well as stdout.
+struct Outputs<'a> {
+    heatmap: Option<&'a Path>,
+    snapshot: Option<&'a Path>,
+    /// Where the search tree is saved while searching, and resumed from.
+    autosave: Option<Autosave>,
+    /// The analysis cache that the search takes its root priors from, and is added to.
+    cache: Option<&'a str>,
+}
+
+/// Searches the position reached by playing `moves` from the start, and reports the
+/// visits and value of every move, optionally writing them to a heatmap file and
+/// saving a snapshot of the search to compare with others.
+/// With an autosave file, the search picks up from the tree saved there, if it is of
+/// the same position, which lets an infinite search survive being restarted.
+fn analyse<G: BoardGame + MCTSExt>(config: &Config, moves: &str, outputs: &Outputs) {
+    if outputs.heatmap.is_some() && G::BOARD_SHAPE.is_none() {
+        eprintln!("analyse: heatmaps are only available for games played on the squares of a board.");
+        return;
+    }
+    let mut state = start_position::<G>(config, "analyse");
+    for text in moves.split_whitespace() {
+        let m = state
+            .parse_move(text)
+            .unwrap_or_else(|e| panic!("analyse: {e}"));
+        state.push(m);
+    }
+    if state.is_terminal() {
+        eprintln!("analyse: the game is over.");
+        return;
+    }
+    let mut behaviour = Behaviour {
+        analysis_cache: outputs.cache.map(str::to_string),
+        ..Behaviour::for_game::<G>()
+    };
+    config.apply(&mut behaviour);
+    let mut engine = MCTS::<G>::new(&behaviour);
+    engine.set_autosave(outputs.autosave.clone());
+    // with JSON output, the search reports its own result, and stdout is kept to JSON lines.
+    let json_info = behaviour.json_info;
+    let note = |note: String| {
+        if json_info {
+            eprintln!("{note}");
+        } else {
+            println!("{note}");
+        }
+    };
+    if !json_info {
+        println!("{state}");
+    }
+    let results = engine.search(&state);
+    if let Some(path) = outputs.snapshot {
+        Snapshot::of(&engine, &results, &state, moves)
+            .save(path)
+            .unwrap_or_else(|e| panic!("analyse: failed to write {}: {e}", path.display()));
+        note(format!("snapshot written to {}", path.display()));
+    }
+    let mut edges = engine.root_edges();
+    if !json_info {
+        println!(
+            "best move {}, win rate {:.3} after {} rollouts",
+            results.best_move, results.win_rate, results.rollouts
+        );
+        edges.sort_by_key(|&(_, visits, _)| std::cmp::Reverse(visits));
+        for (m, visits, q) in &edges {
+            let q = q.map_or_else(|| "-".to_string(), |q| format!("{q:.3}"));
+            println!("{m:>6} {visits:>9} visits  q {q}");
+        }
+    }
+    if let Some(path) = outputs.heatmap {
+        let map = Heatmap::new::<G>(&edges).expect("the game is played on a board");
+        map.save(path)
+            .unwrap_or_else(|e| panic!("analyse: failed to write {}: {e}", path.display()));
+        note(format!("heatmap written to {}", path.display()));
+    }
+}
+
+/// The inputs and outputs of a batch analysis.
+struct Batch<'a> {
+    /// A file of positions, each a line of moves from the start.
+    positions: &'a Path,
+    /// The file the results are written to, or stdout if not given.
+    output: Option<&'a Path>,
+    /// The number of rollouts to search each position for, overriding the configured limit.
+    rollouts: Option<u64>,
+}
+
+/// Searches every position in a file, writing one JSON object per position with the
+/// best move, the value for the side to move, and the policy given by the root visits.
+/// Positions that cannot be searched get an object with an `error` instead.
+fn analyse_batch<G: Game + MCTSExt>(config: &Config, batch: &Batch) {
+    use std::io::Write;
+    let text = std::fs::read_to_string(batch.positions).unwrap_or_else(|e| {
+        panic!("analyse: failed to read {}: {e}", batch.positions.display())
+    });
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    let mut out: Box<dyn Write> = match batch.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).unwrap_or_else(|e| {
+            panic!("analyse: failed to create {}: {e}", path.display())
+        }))),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut behaviour = Behaviour::for_game::<G>();
+    config.apply(&mut behaviour);
+    behaviour.readout = false;
+    if let Some(rollouts) = batch.rollouts {
+        behaviour.limit = Limit::Rollouts(rollouts);
+    }
+    let mut engine = MCTS::<G>::new(&behaviour);
+    let start = start_position::<G>(config, "analyse");
+    let mut progress = Progress::new(lines.len() as u64, "positions");
+    for line in lines {
+        let position = json::string(line);
+        let record = match datageneration::parse_opening(&start, line) {
+            Err(e) => format!("{{\"position\": {position}, \"error\": {}}}", json::string(&e)),
+            Ok(moves) => {
+                let mut state = start.clone();
+                for m in moves {
+                    state.push(m);
+                }
+                engine.new_game();
+                let results = engine.search(&state);
+                let policy = engine
+                    .root_edges()
+                    .iter()
+                    .map(|&(m, visits, _)| {
+                        #[allow(clippy::cast_precision_loss)]
+                        let p = visits as f64 / results.rollouts.max(1) as f64;
+                        format!("{}: {p:.4}", json::string(&m.to_string()))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "{{\"position\": {position}, \"best_move\": {}, \"value\": {:.4}, \"rollouts\": {}, \"policy\": {{{policy}}}}}",
+                    json::string(&results.best_move.to_string()),
+                    results.win_rate,
+                    results.rollouts,
+                )
+            }
+        };
+        writeln!(out, "{record}").expect("analyse: failed to write results");
+        progress.inc();
+        if interrupt::requested() {
+            progress.println("interrupted: the last position was not searched in full");
+            break;
+        }
+    }
+    out.flush().expect("analyse: failed to write results");
+    progress.finish();
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn profile<G: Game + MCTSExt>(config: &Config, rollouts: u32) {
+    let mut behaviour = Behaviour::for_game::<G>();
+    config.apply(&mut behaviour);
+    let config = Behaviour {
+        readout: false,
+        limit: Limit::Rollouts(u64::from(rollouts)),
+        ..behaviour
+    };
+    let mut engine = MCTS::<G>::new(&config);
+    engine.enable_profiling();
+    let start = Instant::now();
+    engine.search(&G::default());
+    let elapsed = start.elapsed();
+    println!(
+        "{rollouts} rollouts in {}ms ({:.0} rollouts per second).",
+        elapsed.as_millis(),
+        f64::from(rollouts) / elapsed.as_secs_f64()
+    );
+    let phase_times = engine.phase_times().expect("profiling was enabled");
+    print!("{}", phase_times.report(elapsed).unwrap());
+    println!(
+        "{} state copies ({:.2} per rollout).",
+        phase_times.copies(),
+        phase_times.copies() as f64 / f64::from(rollouts)
+    );
+}
+
+/// Benchmarks a search of `rollouts` rollouts in every game, printing a table with a row per game.
+fn speedtest_all(config: &Config, rollouts: u32) {
+    println!(
+        "{:<10} {:>9} {:>9} {:>11} {:>14} {:>10}",
+        "game", "rollouts", "time ms", "nodes/s", "rollout plies", "expansions"
+    );
+    for game in IMPLEMENTED_GAMES {
+        with_game!(Some(game), speedtest(config, game, rollouts));
+    }
+}
+
+/// Runs every [`selfcheck::Check`] on every game, printing a table with a row per game and
+/// what went wrong below it, and exits with an error if any check failed.
+fn run_selfcheck(config: &Config) {
+    print!("{:<10}", "game");
+    for check in selfcheck::Check::ALL {
+        print!(" {:>10}", check.name());
+    }
+    println!();
+    let mut failures = Vec::new();
+    for game in IMPLEMENTED_GAMES {
+        with_game!(Some(game), selfcheck_game(config, game, &mut failures));
+    }
+    for failure in &failures {
+        println!();
+        println!("{failure}");
+    }
+    if !failures.is_empty() {
+        eprintln!("selfcheck: {} checks failed", failures.len());
+        std::process::exit(1);
+    }
+}
+
+/// Runs every check on `G`, printing one row of the `selfcheck` table, and adds a
+/// description of each failure to `failures`.
+fn selfcheck_game<G: VectoriseState + MCTSExt>(
+    config: &Config,
+    name: &str,
+    failures: &mut Vec<String>,
+) {
+    print!("{name:<10}");
+    for (check, status) in selfcheck::run::<G>(config) {
+        print!(" {:>10}", status.to_string());
+        if let selfcheck::Status::Failed(error) = status {
+            failures.push(format!("{name} {}: {error}", check.name()));
+        }
+    }
+    println!();
+}
+
+/// Runs a search of `rollouts` rollouts from the start of the game, and prints one row
+/// of the `speedtest` table: its speed and the average work done per rollout.
+#[allow(clippy::cast_precision_loss)]
+fn speedtest<G: Game + MCTSExt>(config: &Config, name: &str, rollouts: u32) {
+    // positions near the start have more moves than typical, so leave room for twice as many.
+    let mut behaviour = Behaviour {
+        hash_mb: G::hash_mb_for(rollouts as usize * 2),
+        ..Behaviour::for_game::<G>()
+    };
+    config.apply(&mut behaviour);
+    let behaviour = Behaviour {
+        readout: false,
+        limit: Limit::Rollouts(u64::from(rollouts)),
+        ..behaviour
+    };
+    let mut engine = MCTS::<G>::new(&behaviour);
+    engine.enable_profiling();
+    let start = Instant::now();
+    engine.search(&G::default());
+    let elapsed = start.elapsed();
+    let stats = engine.phase_times().expect("profiling was enabled");
+    println!(
+        "{name:<10} {rollouts:>9} {:>9} {:>11.0} {:>14.2} {:>10}",
+        elapsed.as_millis(),
+        per_second(f64::from(rollouts), elapsed),
+        stats.mean_rollout_length(),
+        stats.expansions()
+    );
+}
+
+/// Plays `games` games of self-play, checking every move the engine chooses against an
+/// alpha-beta search of `depth` plies, and reports the positions where alpha-beta
+/// proves that the chosen move was a mistake.
+fn verify<G: Game + MCTSExt>(config: &Config, games: usize, depth: usize) {
+    if !G::CAN_POP {
+        eprintln!("verify: this game does not support unmaking moves, which alpha-beta needs.");
+        return;
+    }
+    let mut behaviour = Behaviour::for_game::<G>();
+    config.apply(&mut behaviour);
+    behaviour.readout = false;
+    let mut engine = MCTS::<G>::new(&behaviour);
+    let start = start_position::<G>(config, "verify");
+    let mut positions = 0;
+    let mut disagreements = 0;
+    for game in 1..=games {
+        let mut state = start.clone();
+        let mut ply = 0;
+        while !state.is_terminal() {
+            let best_move = engine.search(&state).best_move;
+            positions += 1;
+            if let Some(d) = verify::check(&state, best_move, depth) {
+                disagreements += 1;
+                println!("game {game}, ply {ply}:");
+                println!("{state}");
+                println!("  search played {} ({})", d.chosen.0, d.chosen.1);
+                println!("  alpha-beta prefers {} ({})", d.best.0, d.best.1);
+            }
+            state.push(best_move);
+            ply += 1;
+        }
+    }
+    println!("{positions} positions checked to depth {depth}, {disagreements} disagreements.");
+}
+
+/// Parses the arguments of `strength`, and measures the accuracy of the search.
+fn run_strength(config: &Config, game: Option<&str>, args: &[String]) {
+    let mut args = args.to_vec();
+    let depth = take_option(&mut args, "--depth")
+        .map_or(12, |it| it.parse().expect("strength: --depth must be a number"));
+    let positions = args
+        .get(3)
+        .map_or(200, |it| it.parse().expect("strength: positions must be a number"));
+    let budgets = args.get(4..).filter(|budgets| !budgets.is_empty()).map_or_else(
+        || vec![16, 64, 256, 1024, 4096],
+        |budgets| {
+            budgets
+                .iter()
+                .map(|it| it.parse().expect("strength: rollouts must be numbers"))
+                .collect()
+        },
+    );
+    with_game!(game, strength(config, positions, &budgets, depth));
+}
+
+/// Prints the accuracy of searches of each of `budgets` rollouts against perfect play,
+/// in `positions` positions sampled from random games and solved by alpha-beta to `depth` plies.
+fn strength<G: Game + MCTSExt>(config: &Config, positions: usize, budgets: &[u64], depth: usize) {
+    #![allow(clippy::cast_precision_loss)]
+    if !G::CAN_POP {
+        eprintln!("strength: this game does not support unmaking moves, which alpha-beta needs.");
+        return;
+    }
+    let start = start_position::<G>(config, "strength");
+    // the same positions every time, so that runs can be compared.
+    let rng = fastrand::Rng::with_seed(DETERMINISTIC_SEED);
+    let positions = strength::sample_positions(&start, positions, depth, &rng);
+    if positions.is_empty() {
+        eprintln!("strength: no positions could be solved to depth {depth}.");
+        return;
+    }
+    println!(
+        "{} positions solved to depth {depth}, in which some moves are worse than others.",
+        positions.len()
+    );
+    let mut behaviour = Behaviour::for_game::<G>();
+    config.apply(&mut behaviour);
+    behaviour.readout = false;
+    let mut engine = MCTS::<G>::new(&behaviour);
+    println!("{:>9}  accuracy", "rollouts");
+    for &rollouts in budgets {
+        let correct = strength::correct_moves(&mut engine, &positions, rollouts);
+        println!("{rollouts:>9}  {:>7.1}%", 100.0 * correct as f64 / positions.len() as f64);
+    }
+}
+
+/// The files of `dataset`, a dataset prefix, or the name of a dataset in the data directory.
+fn dataset_files(config: &Config, dataset: &str) -> [PathBuf; 4] {
+    let files = datageneration::dataset_paths(Path::new(dataset));
+    if files[2].exists() {
+        files
+    } else {
+        datageneration::dataset_paths(&config.data_dir().join(dataset))
+    }
+}
+
+fn run_calibrate(config: &Config, args: &[String]) {
+    let dataset = args.get(2).expect("calibrate: no dataset given");
+    let bins = args.get(3).map_or(10, |it| it.parse().unwrap());
+    calibrate(config, dataset, bins);
+}
+
+/// Reports how well the win rates predicted during data generation match the results
+/// of the games. `dataset` is a dataset prefix, or the name of a dataset in the data directory.
+fn calibrate(config: &Config, dataset: &str, bins: usize) {
+    let [_, _, values, _] = dataset_files(config, dataset);
+    let values = calibration::read_values(&values)
+        .unwrap_or_else(|e| panic!("calibrate: failed to read {}: {e}", values.display()));
+    let mut calibration = calibration::Calibration::new(bins);
+    for (predicted, actual) in values {
+        calibration.add(predicted, actual);
+    }
+    println!("{calibration}");
+}
+
+fn run_resign(config: &Config, args: &[String]) {
+    let mut args = args.to_vec();
+    let max_wrong = take_option(&mut args, "--max-wrong").map_or(DEFAULT_RESIGN_MAX_WRONG, |it| {
+        it.parse().expect("resign: --max-wrong must be a number")
+    });
+    let datasets = args.get(2..).unwrap_or_default();
+    assert!(!datasets.is_empty(), "resign: no dataset given");
+    for dataset in datasets {
+        resign(config, dataset, max_wrong);
+    }
+}
+
+/// Reports how often resigning below each of a range of win rates would have given up a game
+/// that was not lost, in the games of `dataset`, and recommends the highest threshold at which
+/// at most `max_wrong` of resignations would have been wrong.
+fn resign(config: &Config, dataset: &str, max_wrong: f64) {
+    let [_, _, values, games] = dataset_files(config, dataset);
+    let traces = resign::read_traces(&values, &games)
+        .unwrap_or_else(|e| panic!("resign: failed to read {dataset}: {e}"));
+    println!("{dataset}:");
+    println!("{}", resign::Report::new(&traces, max_wrong));
+}
+
+/// Prints the built-in search defaults for `G`, in the format of the config file.
+fn print_defaults<G: Game + MCTSExt>() {
+    let behaviour = Behaviour::for_game::<G>();
+    println!("limit = \"{}\"", behaviour.limit);
+    println!("rollout_policy = \"{}\"", behaviour.rollout_policy);
+    println!("quality_scaling = \"{}\"", behaviour.quality_scaling);
+    println!("selection = \"{}\"", behaviour.selection);
+    println!("exp_factor = {}", behaviour.exp_factor);
+    println!("cpuct_base = {}", behaviour.cpuct_base);
+    println!("cpuct_factor = {}", behaviour.cpuct_factor);
+    println!("expansion_threshold = {}", behaviour.expansion_threshold);
+    println!("hash = {}", behaviour.hash_mb);
+    println!("# rollout cutoff length: {}", G::rollout_cutoff_length());
+    if !G::VARIANTS.is_empty() {
+        println!("# variants: {}", G::VARIANTS.join(", "));
+    }
 }
@@ -1,36 +1,159 @@
 #![allow(clippy::cast_precision_loss)]
 
-use rand::Rng;
-
 use std::{
+    collections::VecDeque,
     fmt::Display,
-    io::Write,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
     str::FromStr,
-    sync::{mpsc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex, RwLock,
+    },
+    thread::ScopedJoinHandle,
     time::{Duration, Instant},
 };
 
 use crate::{
-    constants::{DEFAULT_EXP_FACTOR, MAX_NODEPOOL_MEM, N_INF, ROOT_IDX},
+    cache::AnalysisCache,
+    config,
+    constants::{
+        DEFAULT_CPUCT_BASE, DEFAULT_CPUCT_FACTOR, DEFAULT_EXP_FACTOR, DEFAULT_HASH_MB, DEFAULT_MAX_ROLLOUT_PLIES, DEFAULT_QUALITY_DECAY, DETERMINISTIC_SEED,
+        HASH_SIZING_ROLLOUTS, MAX_THREADS, MIN_HASH_MB, PARALLEL_POLL_INTERVAL, READOUT_CANDIDATES, ROOT_IDX, SEARCH_CHECK_INTERVAL, TRAINING_TEMPERATURE,
+    },
+    fusion::{self, Agreement, Merge},
     game::{Game, MoveBuffer},
+    history::HistoryTable,
+    interrupt, json,
+    nneval::{Evaluation, Network},
+    priors::PriorTable,
+    profile::{Phase, PhaseTimes},
+    progress::per_second,
+    rave::Amaf,
+    score::{self, DEFAULT_CP_SCALE},
     searchtree::SearchTree,
-    treenode::Node,
-    ucb,
+    symmetry,
+    treenode::{Node, Proof},
+    ucb::{self, SelectionPolicy},
 };
 
 /// Determines whether we limit the search by time or by number of nodes.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum Limit {
     Time(Duration),
-    Rollouts(u32),
+    Rollouts(u64),
+    /// Search until told to stop by a `stop` command, or until the tree is full.
+    Infinite,
+}
+
+impl FromStr for Limit {
+    type Err = String;
+
+    /// Parses a limit of the form "rollouts:<n>", "time:<ms>", or "infinite".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "infinite" {
+            return Ok(Self::Infinite);
+        }
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid limit, no colon separator: {s}"))?;
+        match kind {
+            "rollouts" => {
+                let rollouts = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid limit, could not parse rollouts: {s}"))?;
+                Ok(Self::Rollouts(rollouts))
+            }
+            "time" => {
+                let time = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid limit, could not parse time: {s}"))?;
+                Ok(Self::Time(Duration::from_millis(time)))
+            }
+            _ => Err(format!("Invalid limit, invalid limit type: {s}")),
+        }
+    }
+}
+
+impl Display for Limit {
+    /// Writes the limit in the form accepted by [`Limit::from_str`].
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Time(time) => write!(f, "time:{}", time.as_millis()),
+            Self::Rollouts(rollouts) => write!(f, "rollouts:{rollouts}"),
+            Self::Infinite => write!(f, "infinite"),
+        }
+    }
+}
+
+/// Search limits that change with the phase of a game, such as more rollouts in the opening
+/// and fewer in the endgame. Each phase starts at a move number, counted from one as in
+/// chess, and lasts until the next; before the first, searches keep their usual limit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    /// The move each phase starts at, in increasing order, with the limit of the phase.
+    phases: Vec<(u32, Limit)>,
+}
+
+impl Schedule {
+    /// The limit of a search at `move_number`, where `limit` is the one before the first phase.
+    pub fn limit_at(&self, move_number: u32, limit: Limit) -> Limit {
+        self.phases
+            .iter()
+            .take_while(|&&(start, _)| start <= move_number)
+            .last()
+            .map_or(limit, |&(_, limit)| limit)
+    }
+
+    /// The limits of the phases, to be changed in place, as a handicap does.
+    pub fn limits_mut(&mut self) -> impl Iterator<Item = &mut Limit> {
+        self.phases.iter_mut().map(|(_, limit)| limit)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    /// Parses phases of the form "<move>@<limit>", separated by slashes, as in
+    /// "20@rollouts:2000/40@rollouts:500", whose moves must be increasing.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut phases = Vec::<(u32, Limit)>::new();
+        for phase in s.split('/') {
+            let (start, limit) = phase
+                .split_once('@')
+                .ok_or_else(|| format!("Invalid schedule, no @ separator: {phase}"))?;
+            let start = start
+                .parse::<u32>()
+                .ok()
+                .filter(|&start| start > 0)
+                .ok_or_else(|| format!("Invalid schedule, moves are counted from one: {phase}"))?;
+            if phases.last().is_some_and(|&(last, _)| last >= start) {
+                return Err(format!("Invalid schedule, phases out of order: {s}"));
+            }
+            phases.push((start, limit.parse()?));
+        }
+        Ok(Self { phases })
+    }
+}
+
+impl Display for Schedule {
+    /// Writes the schedule in the form accepted by [`Schedule::from_str`].
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, (start, limit)) in self.phases.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{start}@{limit}")?;
+        }
+        Ok(())
+    }
 }
 
 /// The policy to use when selecting moves during rollouts.
 /// `Random` will select a random move from the available moves.
 /// `Decisive` will try to choose an immediate win (if one exists), otherwise it will select a random move.
 #[derive(Clone, Debug, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum RolloutPolicy {
     Random,
     Decisive,
@@ -38,6 +161,14 @@ pub enum RolloutPolicy {
     DecisiveQualityScaled,
     RandomCutoff { moves: usize },
     DecisiveCutoff { moves: usize },
+    /// Samples moves by how well they have done in earlier rollouts of the same search.
+    History,
+    /// Plays the move with the highest [`MCTSExt::rollout_priority`], except for a uniformly
+    /// random move `random_percent`% of the time.
+    Greedy { random_percent: usize },
+    /// Takes the value of the position from [`Game::heuristic_eval`] instead of playing it
+    /// out, in games that have an evaluation, and plays a random rollout in those that do not.
+    Heuristic,
     MetaAggregated { policy: Box<Self>, rollouts: usize },
 }
 
@@ -50,6 +181,8 @@ impl FromStr for RolloutPolicy {
             "decisive" => Ok(Self::Decisive),
             "random_quality_scaled" => Ok(Self::RandomQualityScaled),
             "decisive_quality_scaled" => Ok(Self::DecisiveQualityScaled),
+            "history" => Ok(Self::History),
+            "heuristic" => Ok(Self::Heuristic),
             s if s.starts_with("random_cutoff") => {
                 let rest = s
                     .split_once('.')
@@ -80,6 +213,22 @@ impl FromStr for RolloutPolicy {
                 })?;
                 Ok(Self::DecisiveCutoff { moves })
             }
+            s if s.starts_with("greedy") => {
+                let rest = s
+                    .split_once('.')
+                    .ok_or_else(|| {
+                        format!("Invalid rollout policy, no dot separator after greedy: {s}")
+                    })?
+                    .1;
+                let random_percent = rest
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&percent| percent <= 100)
+                    .ok_or_else(|| {
+                        format!("Invalid rollout policy, could not parse percentage after greedy: {s}")
+                    })?;
+                Ok(Self::Greedy { random_percent })
+            }
             s if s.starts_with("meta_aggregated") => {
                 let rest = s
                     .split_once('.')
@@ -112,17 +261,197 @@ impl FromStr for RolloutPolicy {
     }
 }
 
+impl Display for RolloutPolicy {
+    /// Writes the policy in the form accepted by [`RolloutPolicy::from_str`].
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Random => write!(f, "random"),
+            Self::Decisive => write!(f, "decisive"),
+            Self::RandomQualityScaled => write!(f, "random_quality_scaled"),
+            Self::DecisiveQualityScaled => write!(f, "decisive_quality_scaled"),
+            Self::RandomCutoff { moves } => write!(f, "random_cutoff.{moves}"),
+            Self::DecisiveCutoff { moves } => write!(f, "decisive_cutoff.{moves}"),
+            Self::History => write!(f, "history"),
+            Self::Heuristic => write!(f, "heuristic"),
+            Self::Greedy { random_percent } => write!(f, "greedy.{random_percent}"),
+            Self::MetaAggregated { policy, rollouts } => {
+                write!(f, "meta_aggregated.{policy}.{rollouts}")
+            }
+        }
+    }
+}
+
+/// How the quality-scaled rollout policies shrink the result of a rollout with the number
+/// of moves it played, so that quick wins count for more than slow ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QualityScaling {
+    /// Multiplies the result by `exp(-rate * moves)`.
+    Exponential { rate: f32 },
+    /// Multiplies the result by `1 - rate * moves`, down to zero.
+    Linear { rate: f32 },
+    /// Leaves the result as it is.
+    None,
+}
+
+impl Default for QualityScaling {
+    fn default() -> Self {
+        Self::Exponential {
+            rate: DEFAULT_QUALITY_DECAY,
+        }
+    }
+}
+
+impl QualityScaling {
+    /// The result `q` of a rollout that played `moves` moves, scaled by the curve.
+    pub fn scale(self, q: f32, moves: f32) -> f32 {
+        match self {
+            Self::Exponential { rate } => q * (-rate * moves).exp(),
+            Self::Linear { rate } => q * rate.mul_add(-moves, 1.0).max(0.0),
+            Self::None => q,
+        }
+    }
+}
+
+impl FromStr for QualityScaling {
+    type Err = String;
+
+    /// Parses `exp:<rate>`, `linear:<rate>`, or `none`, where the rate is not negative.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(Self::None);
+        }
+        let (curve, rate) = s.split_once(':').ok_or_else(|| {
+            format!("Invalid quality scaling, expected exp:<rate>, linear:<rate>, or none: {s}")
+        })?;
+        let rate = rate
+            .parse::<f32>()
+            .ok()
+            .filter(|&rate| rate.is_finite() && rate >= 0.0)
+            .ok_or_else(|| {
+                format!("Invalid quality scaling, the rate must be a non-negative number: {s}")
+            })?;
+        match curve {
+            "exp" => Ok(Self::Exponential { rate }),
+            "linear" => Ok(Self::Linear { rate }),
+            _ => Err(format!(
+                "Invalid quality scaling, unknown curve {curve}: {s}"
+            )),
+        }
+    }
+}
+
+impl Display for QualityScaling {
+    /// Writes the curve in the form accepted by [`QualityScaling::from_str`].
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Exponential { rate } => write!(f, "exp:{rate}"),
+            Self::Linear { rate } => write!(f, "linear:{rate}"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
 /// A struct containing all configuration parameters for the MCTS algorithm.
 #[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Behaviour {
     pub debug: bool,
     pub readout: bool,
+    /// Whether the readout is written as JSON lines, for GUIs and scripts, rather than as text.
+    pub json_info: bool,
     pub log: bool,
     pub limit: Limit,
+    /// Limits that replace `limit` as a game goes on, keyed on the move being searched.
+    pub schedule: Option<Schedule>,
+    /// The threads that search the tree together. With more than one, the tree is shared:
+    /// the threads select and backpropagate at once, updating the visits and values of its
+    /// nodes atomically, and an expansion holds the tree for its thread alone.
+    pub threads: usize,
+    /// The trees searched at once from the root, independently and each on threads of its
+    /// own, whose root visits are fused to choose a move. The hash and a rollout limit are
+    /// shared out between them.
     pub root_parallelism_count: usize,
+    /// How the trees of root parallelism are combined to choose a move.
+    pub root_merge: Merge,
+    /// The iterations of a search on one thread that descend to their leaves together, with
+    /// virtual losses, before the leaves are valued, all in one run of the network if there is
+    /// one. One runs every iteration alone.
+    pub batch_size: usize,
     pub rollout_policy: RolloutPolicy,
+    /// How the quality-scaled rollout policies weight results by the length of the rollout.
+    pub quality_scaling: QualityScaling,
+    /// The formula that selection scores children by.
+    pub selection: SelectionPolicy,
+    /// The exploration constant of UCB1, see [`SelectionPolicy::Ucb1`].
     pub exp_factor: f32,
+    /// The parent visit count at which PUCT's exploration constant starts to grow
+    /// logarithmically: smaller values make it grow sooner. See [`ucb::Cpuct`].
+    pub cpuct_base: f32,
+    /// The scale of PUCT's exploration constant. See [`ucb::Cpuct`].
+    pub cpuct_factor: f32,
+    /// The number of visits a leaf needs before it is expanded. The root is always expanded.
+    pub expansion_threshold: u32,
+    /// Whether to search only one move of each class of symmetric moves at the root,
+    /// sharing its visits out between the class when reporting the root distribution.
+    pub root_symmetry: bool,
+    /// Whether to stop searching root moves that can no longer become the most-visited
+    /// within the remaining rollout budget. Only applies under a rollout limit.
+    pub root_pruning: bool,
+    /// Whether expansion looks one move ahead: a child that wins on the spot is proven at
+    /// once, and a line of forced moves, each the only legal move, is expanded in one go.
+    pub tactical_expansion: bool,
+    /// Whether nodes of the same position, reached by different orders of moves, share
+    /// their statistics, so that what is learnt of a position down one line is used down
+    /// the others. See [`SearchTree::with_transpositions`].
+    pub transpositions: bool,
     pub training: bool,
+    /// Whether to search with a fixed random seed, reset every game, so that
+    /// the same sequence of searches always builds the same trees.
+    pub deterministic: bool,
+    /// The memory reserved for the search tree, in megabytes.
+    pub hash_mb: usize,
+    /// The scale used to display win rates as centipawn scores, see [`score::q_to_cp`].
+    pub cp_scale: f64,
+    /// Path to a [`PriorTable`] file, used to supply priors for root moves.
+    pub root_priors: Option<String>,
+    /// Path to an [`AnalysisCache`] file, which supplies priors for root moves the table
+    /// of `root_priors` does not, and which every search is added to.
+    pub analysis_cache: Option<String>,
+    /// Path to an ONNX policy-value [`Network`], which values leaves in place of rollouts
+    /// and gives the children of each expanded node their priors.
+    pub model: Option<String>,
+    /// The standard deviation of Gaussian noise added to rollout results before they are
+    /// backpropagated, for exploration experiments. Zero disables noise.
+    pub value_noise: f32,
+    /// Whether the noise shrinks as a leaf is revisited, by a factor of `1 / sqrt(1 + visits)`.
+    pub anneal_noise: bool,
+    /// The number of levels of the tree, starting at the root, whose nodes are valued by
+    /// minimax over their children's values rather than by the mean of their rollouts.
+    /// Zero disables minimax backup.
+    pub minimax_depth: usize,
+    /// The most plies a rollout may play, after which it is scored as a draw, so that
+    /// games with reversible moves cannot make a rollout run on and on.
+    pub max_rollout_plies: usize,
+    /// First-play urgency: if set, unvisited children are valued at their parent's value
+    /// less this reduction, scaled by the square root of the policy mass of the visited
+    /// children, rather than being tried before any child is revisited.
+    pub fpu_reduction: Option<f32>,
+    /// With RAVE, the bias of the all-moves-as-first values of children, which selection
+    /// blends with their own values by [`rave::beta`], trusting the AMAF values for longer
+    /// the smaller the bias. `None` disables RAVE.
+    ///
+    /// [`rave::beta`]: crate::rave::beta
+    pub rave_bias: Option<f32>,
+    /// If set, the move played is the one with the highest lower confidence bound on
+    /// its win rate, this many standard errors below the mean, rather than the most
+    /// visited. See [`SearchTree::best_child_by_lcb`].
+    pub lcb: Option<f32>,
+    /// If set, the engine resigns a match game, after it moves, when its search gives
+    /// it a win rate below this.
+    pub resign_below: Option<f32>,
+    /// If set, the engine offers a draw in a match game, and accepts one offered to it,
+    /// when its search gives it a win rate within this of one half.
+    pub draw_margin: Option<f32>,
 }
 
 impl Default for Behaviour {
@@ -130,12 +459,41 @@ impl Default for Behaviour {
         Self {
             debug: false,
             readout: true,
+            json_info: false,
             log: false,
-            limit: Limit::Time(Duration::from_millis(15_000)),
+            limit: Limit::Time(Duration::from_secs(15)),
+            schedule: None,
+            threads: 1,
             root_parallelism_count: 1,
+            root_merge: Merge::SumVisits,
+            batch_size: 1,
             rollout_policy: RolloutPolicy::Random,
+            quality_scaling: QualityScaling::default(),
+            selection: SelectionPolicy::default(),
             exp_factor: DEFAULT_EXP_FACTOR,
+            cpuct_base: DEFAULT_CPUCT_BASE,
+            cpuct_factor: DEFAULT_CPUCT_FACTOR,
+            expansion_threshold: 0,
+            root_symmetry: false,
+            root_pruning: false,
+            tactical_expansion: false,
+            transpositions: false,
             training: false,
+            deterministic: false,
+            hash_mb: DEFAULT_HASH_MB,
+            cp_scale: DEFAULT_CP_SCALE,
+            root_priors: None,
+            analysis_cache: None,
+            model: None,
+            value_noise: 0.0,
+            anneal_noise: false,
+            minimax_depth: 0,
+            max_rollout_plies: DEFAULT_MAX_ROLLOUT_PLIES,
+            fpu_reduction: None,
+            rave_bias: None,
+            lcb: None,
+            resign_below: None,
+            draw_margin: None,
         }
     }
 }
@@ -144,22 +502,23 @@ impl FromStr for Behaviour {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // debug, readout, rpc, and training are not configurable
+        // debug, readout, log, and json_info are not configurable, being of the output
+        // rather than of the search.
         let mut behaviour = Self {
-            debug: false,
             readout: false,
-            log: false,
-            limit: Limit::Rollouts(1),
-            root_parallelism_count: 1,
-            rollout_policy: RolloutPolicy::Random,
-            exp_factor: DEFAULT_EXP_FACTOR,
-            training: false,
+            ..Self::default()
         };
         // format is "limit=rollouts:50,rollout_policy=random_cutoff.10"
         // or        "limit=time:1000,rollout_policy=meta_aggregated.decisive.10"
-        let (limit, rollout_policy) = s
+        // optionally followed by any of the options of `set_option`, such as ",trees=<n>"
+        // or ",training".
+        let (limit, rest) = s
             .split_once(',')
             .ok_or_else(|| format!("Invalid behaviour string, no comma separator: {s}"))?;
+        let (rollout_policy, options) = match rest.split_once(',') {
+            Some((rollout_policy, options)) => (rollout_policy, Some(options)),
+            None => (rest, None),
+        };
         let limit = limit.split_once('=').ok_or_else(|| {
             format!("Invalid behaviour string, no equals separator in limit: {s}")
         })?;
@@ -176,91 +535,515 @@ impl FromStr for Behaviour {
         }
         let limit = limit
             .1
-            .split_once(':')
-            .ok_or_else(|| format!("Invalid behaviour string, no colon separator in limit: {s}"))?;
+            .parse::<Limit>()
+            .map_err(|err| format!("Invalid behaviour string, could not parse limit: {err}"))?;
         let rollout_policy = rollout_policy.1;
-        let limit = match limit.0 {
-            "rollouts" => {
-                let rollouts = limit.1.parse::<u32>().map_err(|_| {
-                    format!("Invalid behaviour string, could not parse rollouts: {s}")
-                })?;
-                Limit::Rollouts(rollouts)
-            }
-            "time" => {
-                let time = limit
-                    .1
-                    .parse::<u64>()
-                    .map_err(|_| format!("Invalid behaviour string, could not parse time: {s}"))?;
-                Limit::Time(Duration::from_millis(time))
-            }
-            _ => return Err(format!("Invalid behaviour string, invalid limit type: {s}")),
-        };
         let rollout_policy = rollout_policy.parse::<RolloutPolicy>().map_err(|err| {
             format!("Invalid behaviour string, could not parse rollout policy: {err}")
         })?;
+        for option in options.into_iter().flat_map(|options| options.split(',')) {
+            behaviour
+                .set_option(option)
+                .map_err(|err| format!("Invalid behaviour string, {err}: {s}"))?;
+        }
         behaviour.limit = limit;
         behaviour.rollout_policy = rollout_policy;
+        behaviour
+            .validate()
+            .map_err(|err| format!("Invalid behaviour string, {err}: {s}"))?;
         Ok(behaviour)
     }
 }
 
+impl Behaviour {
+    /// Sets the setting named by `option`, one option of a behaviour string, which is
+    /// `key=<value>`, or `key` alone for a flag, the same as `key=true`. Every setting of the
+    /// search has a key, the name of its field, and many have a shorter one. The options
+    /// are `priors=<path>`, `cache=<path>`, `model=<path>`, `schedule=<phases>` (see
+    /// [`Schedule::from_str`]), `noise=<sigma>`, `minimax=<depth>`, `max_rollout_plies=<n>`,
+    /// `selection=<ucb1|ucb1_tuned|puct>`, `exp_factor=<c>`, `cpuct_base=<b>`, `cpuct_factor=<c>`, `expansion_threshold=<n>`,
+    /// `merge=<sum|vote|average>`, `fpu=<reduction>`, `lcb=<z>`, `rave=<bias>`,
+    /// `resign=<win rate>`, `draw=<margin>`, `threads=<n>`, `trees=<n>`, `batch=<n>`,
+    /// `hash=<mb>`, `cp_scale=<scale>`, `quality_scaling=<curve>`, and the flags
+    /// `anneal_noise`, `root_symmetry`, `root_pruning`, `tactical_expansion`,
+    /// `transpositions`, `training`, and `deterministic`.
+    fn set_option(&mut self, option: &str) -> Result<(), String> {
+        let (key, value) = option
+            .split_once('=')
+            .map_or((option, None), |(key, value)| (key, Some(value)));
+        if let Some(flag) = self.flag(key) {
+            *flag = value.map_or(Ok(true), |value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("{key} must be true or false: {value}"))
+            })?;
+            return Ok(());
+        }
+        let value = value.ok_or_else(|| format!("unknown option {option}"))?;
+        match key {
+            "priors" | "root_priors" => self.root_priors = Some(value.to_string()),
+            "cache" | "analysis_cache" => self.analysis_cache = Some(value.to_string()),
+            "model" => self.model = Some(value.to_string()),
+            "schedule" => self.schedule = Some(value.parse()?),
+            "noise" | "value_noise" => self.value_noise = parse_non_negative(key, value)?,
+            "minimax" | "minimax_depth" => self.minimax_depth = parse_whole(key, value)?,
+            "max_rollout_plies" => self.max_rollout_plies = parse_positive(key, value)?,
+            "selection" => self.selection = value.parse()?,
+            "exp_factor" => self.exp_factor = parse_non_negative(key, value)?,
+            "cpuct_base" => self.cpuct_base = parse_cpuct_base(value)?,
+            "cpuct_factor" => self.cpuct_factor = parse_cpuct_factor(value)?,
+            "expansion_threshold" => self.expansion_threshold = parse_whole(key, value)?,
+            "merge" | "root_merge" => self.root_merge = value.parse()?,
+            "fpu" | "fpu_reduction" => self.fpu_reduction = Some(parse_fpu_reduction(value)?),
+            "lcb" => self.lcb = Some(parse_lcb(value)?),
+            "rave" | "rave_bias" => self.rave_bias = Some(parse_rave_bias(value)?),
+            "resign" | "resign_below" => self.resign_below = Some(parse_resign(value)?),
+            "draw" | "draw_margin" => self.draw_margin = Some(parse_draw(value)?),
+            "threads" => self.threads = parse_threads(value)?,
+            "trees" | "root_parallelism_count" => self.root_parallelism_count = parse_trees(value)?,
+            "batch" | "batch_size" => self.batch_size = parse_batch_size(value)?,
+            "hash" | "hash_mb" => self.hash_mb = parse_positive(key, value)?,
+            "cp_scale" => self.cp_scale = config::parse_cp_scale(value)?,
+            "quality_scaling" => self.quality_scaling = value.parse()?,
+            _ => return Err(format!("unknown option {option}")),
+        }
+        Ok(())
+    }
+
+    /// The flag named by `key`, if it names one.
+    fn flag(&mut self, key: &str) -> Option<&mut bool> {
+        match key {
+            "anneal_noise" => Some(&mut self.anneal_noise),
+            "root_symmetry" => Some(&mut self.root_symmetry),
+            "root_pruning" => Some(&mut self.root_pruning),
+            "tactical_expansion" => Some(&mut self.tactical_expansion),
+            "transpositions" => Some(&mut self.transpositions),
+            "training" => Some(&mut self.training),
+            "deterministic" => Some(&mut self.deterministic),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the option `key` as a whole number.
+fn parse_whole<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{key} must be a whole number: {value}"))
+}
+
+/// Parses the option `key` as a whole number that is at least one.
+fn parse_positive(key: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("{key} must be a positive whole number: {value}"))
+}
+
+/// Parses the option `key` as a number that is finite and not negative.
+fn parse_non_negative(key: &str, value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&x| x.is_finite() && x >= 0.0)
+        .ok_or_else(|| format!("{key} must be a non-negative number: {value}"))
+}
+
+/// Parses a number of [`Behaviour::threads`], which must be at least one.
+fn parse_threads(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&threads| (1..=MAX_THREADS).contains(&threads))
+        .ok_or_else(|| format!("threads must be a whole number from 1 to {MAX_THREADS}: {value}"))
+}
+
+/// Parses a [`Behaviour::root_parallelism_count`], which must be at least one.
+fn parse_trees(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&trees| (1..=MAX_THREADS).contains(&trees))
+        .ok_or_else(|| format!("trees must be a whole number from 1 to {MAX_THREADS}: {value}"))
+}
+
+/// Parses a [`Behaviour::batch_size`], which must be at least one.
+fn parse_batch_size(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|&batch| batch > 0)
+        .ok_or_else(|| format!("batch must be a positive whole number: {value}"))
+}
+
+/// Parses a [`Behaviour::rave_bias`], which must be finite and not negative.
+fn parse_rave_bias(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&bias| bias.is_finite() && bias >= 0.0)
+        .ok_or_else(|| format!("rave must be a non-negative number: {value}"))
+}
+
+/// Parses a [`Behaviour::fpu_reduction`], which must be finite and not negative.
+pub fn parse_fpu_reduction(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&reduction| reduction.is_finite() && reduction >= 0.0)
+        .ok_or_else(|| format!("fpu must be a non-negative number: {value}"))
+}
+
+/// The `count` most visited of `edges`, as given by [`MCTS::root_edges`], with their share
+/// of the visits and the win chance of the player moving, as in `5 (48.0%, 72% win)`.
+fn format_candidates<M: Display>(edges: &[(M, u64, Option<f64>)], count: usize) -> String {
+    let total = edges.iter().map(|&(_, visits, _)| visits).sum::<u64>();
+    let total = total.max(1) as f64;
+    let mut ranked = edges.iter().collect::<Vec<_>>();
+    ranked.sort_by_key(|&&(_, visits, _)| std::cmp::Reverse(visits));
+    ranked
+        .iter()
+        .take(count)
+        .map(|(m, visits, q)| {
+            let share = 100.0 * *visits as f64 / total;
+            q.map_or_else(
+                || format!("{m} ({share:.1}%)"),
+                |q| format!("{m} ({share:.1}%, {:.0}% win)", 100.0 * q),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a [`Behaviour::resign_below`] threshold, which must be a win rate.
+fn parse_resign(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| format!("resign must be a win rate from 0 to 1: {value}"))
+}
+
+/// Parses a [`Behaviour::draw_margin`], which must be from 0 to one half.
+fn parse_draw(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|margin| (0.0..=0.5).contains(margin))
+        .ok_or_else(|| format!("draw must be a margin from 0 to 0.5: {value}"))
+}
+
+/// Parses a [`Behaviour::lcb`] multiplier, which must be finite and not negative.
+fn parse_lcb(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&z| z.is_finite() && z >= 0.0)
+        .ok_or_else(|| format!("lcb must be a non-negative number: {value}"))
+}
+
+/// Parses a [`Behaviour::cpuct_base`], which must be positive and finite.
+pub fn parse_cpuct_base(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&base| base.is_finite() && base > 0.0)
+        .ok_or_else(|| format!("cpuct_base must be a positive number: {value}"))
+}
+
+/// Parses a [`Behaviour::cpuct_factor`], which must be finite and not negative.
+pub fn parse_cpuct_factor(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|&factor| factor.is_finite() && factor >= 0.0)
+        .ok_or_else(|| format!("cpuct_factor must be a non-negative number: {value}"))
+}
+
 impl Display for Behaviour {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Behaviour {{ debug: {}, readout: {}, limit: {:?}, root_parallelism_count: {}, rollout_policy: {:?} }}",
+            "Behaviour {{ debug: {}, readout: {}, limit: {:?}, threads: {}, root_parallelism_count: {}, root_merge: {}, rollout_policy: {:?} }}",
             self.debug,
             self.readout,
             self.limit,
+            self.threads,
             self.root_parallelism_count,
+            self.root_merge,
             self.rollout_policy
         )
     }
 }
 
 impl Behaviour {
+    /// The defaults for searching `G`, as declared by its [`MCTSExt`] implementation.
     pub fn for_game<G: Game + MCTSExt>() -> Self {
         Self {
+            limit: G::limit(),
             rollout_policy: G::rollout_policy(),
+            quality_scaling: G::quality_scaling(),
+            exp_factor: G::exp_factor(),
+            expansion_threshold: G::expansion_threshold(),
+            hash_mb: G::hash_mb(),
+            max_rollout_plies: G::rollout_cutoff_length(),
             ..Self::default()
         }
     }
+
+    /// A builder of settings, starting from the [`Default`] ones.
+    ///
+    /// ```
+    /// use iridium_oxide::mcts::{Behaviour, Limit, RolloutPolicy};
+    ///
+    /// let behaviour = Behaviour::builder()
+    ///     .limit(Limit::Rollouts(10_000))
+    ///     .rollout_policy(RolloutPolicy::Decisive)
+    ///     .threads(4)
+    ///     .build()?;
+    /// assert_eq!(behaviour.threads, 4);
+    /// assert!(Behaviour::builder().root_parallelism_count(0).build().is_err());
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn builder() -> BehaviourBuilder {
+        BehaviourBuilder {
+            behaviour: Self::default(),
+        }
+    }
+
+    /// A builder of settings, starting from these ones.
+    pub const fn into_builder(self) -> BehaviourBuilder {
+        BehaviourBuilder { behaviour: self }
+    }
+
+    /// Checks that every setting is within the range that the option parsers accept.
+    pub fn validate(&self) -> Result<(), String> {
+        let require =
+            |valid: bool, problem: &str| valid.then_some(()).ok_or_else(|| problem.to_string());
+        let non_negative = |value: f32| value.is_finite() && value >= 0.0;
+        let threads = 1..=MAX_THREADS;
+        require(
+            threads.contains(&self.threads),
+            &format!("threads must be from 1 to {MAX_THREADS}"),
+        )?;
+        require(
+            !self.deterministic || self.threads == 1,
+            "a deterministic search must have one thread",
+        )?;
+        require(
+            threads.contains(&self.root_parallelism_count),
+            &format!("root_parallelism_count must be from 1 to {MAX_THREADS}"),
+        )?;
+        require(self.batch_size > 0, "batch_size must be positive")?;
+        require(self.hash_mb > 0, "hash_mb must be positive")?;
+        require(
+            self.max_rollout_plies > 0,
+            "max_rollout_plies must be positive",
+        )?;
+        require(
+            self.cpuct_base.is_finite() && self.cpuct_base > 0.0,
+            "cpuct_base must be positive",
+        )?;
+        require(
+            non_negative(self.cpuct_factor),
+            "cpuct_factor must not be negative",
+        )?;
+        require(
+            non_negative(self.exp_factor),
+            "exp_factor must not be negative",
+        )?;
+        require(
+            non_negative(self.value_noise),
+            "value_noise must not be negative",
+        )?;
+        require(
+            self.cp_scale.is_finite() && self.cp_scale > 0.0,
+            "cp_scale must be positive",
+        )?;
+        require(
+            self.fpu_reduction.is_none_or(non_negative),
+            "fpu_reduction must not be negative",
+        )?;
+        require(
+            self.rave_bias.is_none_or(non_negative),
+            "rave_bias must not be negative",
+        )?;
+        require(
+            self.lcb.is_none_or(non_negative),
+            "lcb must not be negative",
+        )?;
+        require(
+            self.resign_below
+                .is_none_or(|rate| (0.0..=1.0).contains(&rate)),
+            "resign_below must be a win rate from 0 to 1",
+        )?;
+        require(
+            self.draw_margin
+                .is_none_or(|margin| (0.0..=0.5).contains(&margin)),
+            "draw_margin must be from 0 to 0.5",
+        )
+    }
+}
+
+/// Builds a [`Behaviour`] one setting at a time, so that code that sets up a search
+/// names only the settings it changes, and goes on compiling as settings are added.
+/// [`build`](Self::build) checks the settings with [`Behaviour::validate`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct BehaviourBuilder {
+    behaviour: Behaviour,
+}
+
+/// Defines a setter on [`BehaviourBuilder`] for each of the given fields of [`Behaviour`].
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets [`Behaviour::", stringify!($field), "`].")]
+            #[allow(clippy::missing_const_for_fn)]
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.behaviour.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl BehaviourBuilder {
+    setters! {
+        debug: bool,
+        readout: bool,
+        json_info: bool,
+        log: bool,
+        limit: Limit,
+        schedule: Option<Schedule>,
+        threads: usize,
+        root_parallelism_count: usize,
+        root_merge: Merge,
+        batch_size: usize,
+        rollout_policy: RolloutPolicy,
+        quality_scaling: QualityScaling,
+        selection: SelectionPolicy,
+        exp_factor: f32,
+        cpuct_base: f32,
+        cpuct_factor: f32,
+        expansion_threshold: u32,
+        root_symmetry: bool,
+        root_pruning: bool,
+        tactical_expansion: bool,
+        transpositions: bool,
+        training: bool,
+        deterministic: bool,
+        hash_mb: usize,
+        cp_scale: f64,
+        root_priors: Option<String>,
+        analysis_cache: Option<String>,
+        model: Option<String>,
+        value_noise: f32,
+        anneal_noise: bool,
+        minimax_depth: usize,
+        max_rollout_plies: usize,
+        fpu_reduction: Option<f32>,
+        rave_bias: Option<f32>,
+        lcb: Option<f32>,
+        resign_below: Option<f32>,
+        draw_margin: Option<f32>,
+    }
+
+    /// The settings, if they are valid.
+    pub fn build(self) -> Result<Behaviour, String> {
+        self.behaviour.validate()?;
+        Ok(self.behaviour)
+    }
 }
 
 /// A struct containing the results of an MCTS search.
 #[derive(Clone, Debug)]
 pub struct SearchResults<G: Game> {
-    pub rollout_distribution: Vec<u32>,
+    pub rollout_distribution: Vec<u64>,
     pub new_node: G,
     pub new_node_idx: usize,
-    pub rollouts: u32,
+    /// The move that leads from the searched position to `new_node`.
+    pub best_move: G::Move,
+    pub rollouts: u64,
+    /// The mean value of the chosen child over its visits, in [0, 1], from the perspective
+    /// of the player to move in the searched position. Proven children are valued exactly.
     pub win_rate: f64,
+    /// How far the search ran past its limit.
+    pub overshoot: Overshoot,
+    /// The expected number of plies from each root move to the end of the game, in the
+    /// order of `rollout_distribution`, or `None` for moves that were never visited.
+    pub game_lengths: Vec<Option<f32>>,
+    /// The expansions skipped because the node pool was full, each of which left the
+    /// search shallower than its limit would otherwise have allowed.
+    pub skipped_expansions: u32,
+}
+
+/// How far a search ran past its limit. The limit is only checked between iterations, so
+/// a time-limited search finishes the iteration it is in when time runs out, and searches
+/// on several threads can each finish one more rollout after the rollout limit is reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Overshoot {
+    /// The time spent past a time limit.
+    pub time: Duration,
+    /// The rollouts done past a rollout limit.
+    pub rollouts: u64,
+}
+
+impl Overshoot {
+    pub const fn is_zero(&self) -> bool {
+        self.time.is_zero() && self.rollouts == 0
+    }
+}
+
+impl Display for Overshoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}ms and {} rollouts past the limit",
+            self.time.as_millis(),
+            self.rollouts
+        )
+    }
+}
+
+/// Where and how often a search saves its tree, so that a long analysis can be resumed
+/// after a crash by searching the same position with the same autosave file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Autosave {
+    pub path: PathBuf,
+    pub interval: Duration,
 }
 
 /// Information for the MCTS search, including both static config and particular search state.
 #[derive(Clone, Debug)]
 pub struct SearchInfo<'a> {
-    pub quit: bool,
+    /// Commands that were sent before the search but are still to be run, which it reads
+    /// before those on stdin.
+    pub unread: VecDeque<String>,
+    /// The commands that the search read and did not handle, in the order they were sent,
+    /// for the driver to run once it has printed the result of the search.
+    pub pending: Vec<String>,
     pub flags: Behaviour,
-    pub side: i8,
     pub start_time: Option<Instant>,
     /// A handle to a receiver for stdin.
     pub stdin_rx: Option<&'a Mutex<mpsc::Receiver<String>>>,
+    /// Set when the search is stopped, for the other trees of a root-parallel search.
+    pub stop: Option<Arc<AtomicBool>>,
 }
 
 impl<'a> SearchInfo<'a> {
-    #[allow(dead_code)]
     pub fn new(stdin_rx: &'a Mutex<mpsc::Receiver<String>>) -> Self {
         Self {
-            quit: false,
+            unread: VecDeque::new(),
+            pending: Vec::new(),
             flags: Behaviour::default(),
-            side: 1,
             start_time: None,
             stdin_rx: Some(stdin_rx),
+            stop: None,
         }
     }
     /// Returns true if the search should be terminated.
-    fn limit_reached(&self, rollouts: u32) -> bool {
+    fn limit_reached(&self, rollouts: u64) -> bool {
         match self.flags.limit {
             Limit::Time(max_duration) => {
                 let now = Instant::now();
@@ -270,20 +1053,61 @@ impl<'a> SearchInfo<'a> {
                 elapsed >= max_duration
             }
             Limit::Rollouts(max_rollouts) => rollouts >= max_rollouts,
+            Limit::Infinite => rollouts == u64::MAX,
+        }
+    }
+    /// How far a search that has done `rollouts` rollouts, of which `resumed` were
+    /// loaded from an autosave, has run past its limit.
+    fn overshoot(&self, rollouts: u64, resumed: u64) -> Overshoot {
+        match self.flags.limit {
+            Limit::Time(max_duration) => Overshoot {
+                time: self
+                    .start_time
+                    .unwrap()
+                    .elapsed()
+                    .saturating_sub(max_duration),
+                rollouts: 0,
+            },
+            Limit::Rollouts(max_rollouts) => Overshoot {
+                time: Duration::ZERO,
+                rollouts: rollouts.saturating_sub(max_rollouts.max(resumed)),
+            },
+            Limit::Infinite => Overshoot::default(),
         }
     }
-    /// Check if we've run out of time or received a signal from stdin.
-    #[allow(dead_code)]
-    fn check_up(&self) -> bool {
-        if let Some(rx) = self.stdin_rx {
-            let rx = rx.lock().unwrap();
-            if let Ok(msg) = rx.try_recv() {
-                if msg == "stop" {
-                    return true;
+    /// Handles the commands that arrived during the search, returning true if one of
+    /// them was `stop`, or `quit` during an infinite search, or if the search was stopped
+    /// through [`stop`](Self::stop). A search with a limit runs to it even when `quit` arrives,
+    /// so that it still gives a move. Everything but `stop` and `isready` is kept in
+    /// [`pending`](Self::pending), and reading stops at `quit`, as nothing after it is run.
+    fn check_stdin(&mut self) -> bool {
+        if self
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+        {
+            return true;
+        }
+        let Some(rx) = self.stdin_rx else {
+            return false;
+        };
+        if self.pending.last().is_some_and(|command| command == "quit") {
+            return false;
+        }
+        let rx = rx.lock().unwrap();
+        while let Some(msg) = self.unread.pop_front().or_else(|| rx.try_recv().ok()) {
+            match msg.as_str() {
+                "stop" => return true,
+                "isready" => println!("readyok"),
+                "quit" => {
+                    self.pending.push(msg);
+                    return self.flags.limit == Limit::Infinite;
                 }
+                // nothing else can be done in the middle of a search.
+                _ => self.pending.push(msg),
             }
         }
-        self.limit_reached(0)
+        false
     }
 }
 
@@ -294,437 +1118,3036 @@ impl<'a> SearchInfo<'a> {
 #[allow(clippy::upper_case_acronyms)]
 pub struct MCTS<'a, G: Game> {
     search_info: SearchInfo<'a>,
-    tree: SearchTree<G>,
+    /// The limit of searches, which the [`Behaviour::schedule`], if there is one, replaces
+    /// in its phases.
+    limit: Limit,
+    /// The move of the game that the next search is for, counted from one.
+    move_number: u32,
+    /// The tree, boxed so that the workers of a parallel search can swap it in and out
+    /// of the pool they share cheaply.
+    tree: Box<SearchTree<G>>,
     rng: fastrand::Rng,
+    root_priors: Option<PriorTable>,
+    analysis_cache: Option<AnalysisCache>,
+    /// The network of [`Behaviour::model`], shared by the workers of a parallel search.
+    network: Option<Arc<Network>>,
+    /// The nodes expanded by the current batch of iterations, with their states, whose
+    /// priors are given when the batch is evaluated, or `None` outside of a batch.
+    deferred_expansions: Option<Vec<(usize, G)>>,
+    /// The priors for the children of the current root, if the table has them.
+    root_policy: Option<Vec<f32>>,
+    /// The symmetry class of each child of the current root, if root symmetry folding
+    /// found any symmetric moves, as given by [`symmetry::classes`].
+    root_classes: Option<Vec<usize>>,
+    /// The children of the current root that selection must not choose.
+    root_excluded: Option<Vec<bool>>,
+    /// Time spent in each phase of the search, if profiling is enabled.
+    phase_times: Option<PhaseTimes>,
+    /// The plies played by the current rollout, or rollouts with [`RolloutPolicy::MetaAggregated`].
+    rollout_plies: u32,
+    /// The storage that iterations and searches reuse, rather than allocate anew.
+    context: SearchContext<G>,
+    /// Per-move rollout statistics for the history rollout policy, reset every search.
+    history: HistoryTable,
+    /// The moves of the current iteration, for the AMAF statistics of RAVE.
+    amaf: Amaf,
+    autosave: Option<Autosave>,
+    /// When the tree was last saved by the current search.
+    last_save: Option<Instant>,
+    /// The win rate of the move chosen by the last search, for the side that played it.
+    last_win_rate: Option<f64>,
+    /// The expansions skipped by the current search because the node pool was full.
+    skipped_expansions: u32,
+}
+
+/// The storage of an engine that its searches reuse, so that after the first search of a
+/// match or of self-play, the searches allocate little.
+#[derive(Default)]
+struct SearchContext<G: Game> {
+    /// The state used to walk down the tree, kept between iterations so its storage is reused.
+    scratch: Option<G>,
+    /// A move buffer reused by every rollout step that needs to generate moves.
+    move_buffer: G::Buffer,
+    /// The policy over the children of the node being selected from, see [`ucb::best`].
+    policies: Vec<f32>,
+    /// The visits of the root moves, as written to the log.
+    distribution: Vec<u64>,
+    /// The leaves of the current batch of iterations, see [`MCTS::batched_iteration`].
+    leaves: Vec<Leaf<G>>,
+    /// The log of the root policy, `log.txt`, opened by the first search that writes to it
+    /// and kept open for the rest.
+    log: Option<BufWriter<File>>,
+}
+
+// a file cannot be cloned, so a clone opens a log of its own if it needs one.
+impl<G: Game> Clone for SearchContext<G> {
+    fn clone(&self) -> Self {
+        Self {
+            scratch: self.scratch.clone(),
+            move_buffer: self.move_buffer.clone(),
+            policies: self.policies.clone(),
+            distribution: self.distribution.clone(),
+            leaves: self.leaves.clone(),
+            log: None,
+        }
+    }
 }
 
+/// A leaf reached by one iteration of a batch.
+#[derive(Clone)]
+struct Leaf<G: Game> {
+    idx: usize,
+    depth: usize,
+    state: G,
+    /// The result of the iteration and the length of its rollout, once it is known.
+    value: Option<(f32, f32)>,
+}
+
+/// Per-game search defaults, used by [`Behaviour::for_game`].
+/// Games override these to record the settings that tuning found best for them.
 pub trait MCTSExt: Game {
+    /// The number of moves the cutoff rollout policies play before stopping,
+    /// and the default cap on the length of the other rollouts.
     fn rollout_cutoff_length() -> usize {
-        100_000
+        DEFAULT_MAX_ROLLOUT_PLIES
     }
     fn rollout_policy() -> RolloutPolicy {
         RolloutPolicy::Random
     }
+    fn quality_scaling() -> QualityScaling {
+        QualityScaling::default()
+    }
+    fn exp_factor() -> f32 {
+        DEFAULT_EXP_FACTOR
+    }
+    fn expansion_threshold() -> u32 {
+        0
+    }
+    fn limit() -> Limit {
+        Limit::Time(Duration::from_secs(15))
+    }
+    /// The number of moves in a typical game.
+    fn typical_game_length() -> usize {
+        60
+    }
+    /// The number of legal moves in a typical position `ply` moves into the game. By default,
+    /// this assumes that every move fills one square of the action space, as in most board games.
+    fn branching_factor(ply: usize) -> usize {
+        Self::MOVE_SPACE.saturating_sub(ply).max(1)
+    }
+    /// The memory to reserve for the search tree, in megabytes: enough for
+    /// [`HASH_SIZING_ROLLOUTS`] rollouts, as given by [`hash_mb_for`](Self::hash_mb_for).
+    fn hash_mb() -> usize {
+        Self::hash_mb_for(HASH_SIZING_ROLLOUTS)
+    }
+    /// The memory needed for a search of `rollouts` rollouts, in megabytes, but no more
+    /// than [`DEFAULT_HASH_MB`].
+    ///
+    /// A search expands about one node per rollout, adding a child for every legal move,
+    /// so this is enough for `rollouts` rollouts, unless the whole game tree is smaller.
+    fn hash_mb_for(rollouts: usize) -> usize {
+        let mut game_tree = 1usize;
+        let mut level = 1usize;
+        for ply in 0..Self::typical_game_length() {
+            level = level.saturating_mul(Self::branching_factor(ply));
+            game_tree = game_tree.saturating_add(level);
+        }
+        // the first expansions, near the root, have the most children.
+        let nodes = game_tree.min(rollouts.saturating_mul(Self::branching_factor(0)));
+        let bytes = nodes.saturating_mul(std::mem::size_of::<Node<Self>>());
+        bytes
+            .div_ceil(1024 * 1024)
+            .clamp(MIN_HASH_MB, DEFAULT_HASH_MB)
+    }
+    /// How strongly the greedy rollout policy prefers playing `m` in this position.
+    /// All moves are alike by default, which makes greedy rollouts random.
+    fn rollout_priority(&self, _m: Self::Move) -> i32 {
+        0
+    }
+    /// What `network` makes of each of `states`. Only games with a [`VectoriseState`] layout
+    /// for the input of the network can be evaluated, all at once by [`Network::evaluate_all`].
+    ///
+    /// [`VectoriseState`]: crate::datageneration::VectoriseState
+    fn network_evaluations(
+        _states: &[Self],
+        _network: &Network,
+    ) -> Result<Vec<Evaluation>, String> {
+        Err("this game cannot be evaluated by a network".to_string())
+    }
 }
 
 impl<'a, G: Game + MCTSExt> MCTS<'a, G> {
-    const NODEPOOL_SIZE: usize = MAX_NODEPOOL_MEM / std::mem::size_of::<Node<G>>();
-
     pub fn new(flags: &Behaviour) -> Self {
         Self {
             search_info: SearchInfo {
-                quit: false,
+                unread: VecDeque::new(),
+                pending: Vec::new(),
                 flags: flags.clone(),
-                side: 1,
                 start_time: None,
                 stdin_rx: None,
+                stop: None,
             },
-            tree: SearchTree::with_capacity(Self::NODEPOOL_SIZE),
-            rng: fastrand::Rng::new(),
+            limit: flags.limit,
+            move_number: 1,
+            // the hash is shared out between the trees of root parallelism.
+            tree: Box::new(
+                SearchTree::with_capacity(
+                    flags.hash_mb * 1024 * 1024
+                        / SearchTree::<G>::node_bytes(flags.transpositions)
+                        / flags.root_parallelism_count.max(1),
+                )
+                .with_transpositions(flags.transpositions),
+            ),
+            rng: Self::new_rng(flags),
+            root_priors: flags.root_priors.as_deref().map(|path| {
+                PriorTable::load(path)
+                    .unwrap_or_else(|e| panic!("failed to load root priors from {path}: {e}"))
+            }),
+            // a cache that cannot be read is left alone, rather than overwritten.
+            analysis_cache: flags.analysis_cache.as_deref().and_then(|path| {
+                AnalysisCache::open(path)
+                    .inspect_err(|e| eprintln!("not using the analysis cache {path}: {e}"))
+                    .ok()
+            }),
+            network: flags.model.as_deref().map(|path| {
+                let network = Network::load(path)
+                    .unwrap_or_else(|e| panic!("failed to load the model {path}: {e}"));
+                if let Err(e) = G::network_evaluations(&[G::default()], &network) {
+                    panic!("cannot use the model {path}: {e}");
+                }
+                Arc::new(network)
+            }),
+            deferred_expansions: None,
+            root_policy: None,
+            root_classes: None,
+            root_excluded: None,
+            phase_times: None,
+            rollout_plies: 0,
+            context: SearchContext::default(),
+            history: HistoryTable::new(G::MOVE_SPACE),
+            amaf: Amaf::new(G::MOVE_SPACE),
+            autosave: None,
+            last_save: None,
+            last_win_rate: None,
+            skipped_expansions: 0,
         }
     }
 
-    fn limit_reached(search_info: &SearchInfo, rollouts: u32) -> bool {
-        search_info.limit_reached(rollouts)
+    /// Lets later searches be stopped by a `stop` command on `stdin`, or by `quit` if they are
+    /// infinite, keeping the other commands sent during them for
+    /// [`take_pending_commands`](Self::take_pending_commands).
+    pub const fn set_stdin(&mut self, stdin: &'a Mutex<mpsc::Receiver<String>>) {
+        self.search_info.stdin_rx = Some(stdin);
     }
 
-    pub fn search(&mut self, board: &G) -> SearchResults<G> {
-        self.search_info.start_time = Some(Instant::now());
+    /// Gives the next search `commands` to read before stdin, which are still to be run.
+    pub fn set_unread_commands(&mut self, commands: VecDeque<String>) {
+        self.search_info.unread = commands;
+    }
 
-        self.tree.setup(board.clone());
-        self.do_treesearch(board);
+    /// Takes the commands that the last search left to be run, in the order they were sent.
+    pub fn take_pending_commands(&mut self) -> VecDeque<String> {
+        let info = &mut self.search_info;
+        let mut commands = std::mem::take(&mut info.pending)
+            .into_iter()
+            .collect::<VecDeque<_>>();
+        commands.append(&mut info.unread);
+        commands
+    }
 
-        let rollout_distribution = self.tree.root_rollout_distribution();
+    /// Whether the last search gave the side that moved a win rate below
+    /// [`Behaviour::resign_below`].
+    pub fn wants_to_resign(&self) -> bool {
+        let threshold = self.search_info.flags.resign_below;
+        threshold
+            .zip(self.last_win_rate)
+            .is_some_and(|(threshold, win_rate)| win_rate < f64::from(threshold))
+    }
 
-        let avg_win_rate = self.tree.root().win_rate();
+    /// Whether the last search gave the side that moved a win rate within
+    /// [`Behaviour::draw_margin`] of one half.
+    pub fn wants_a_draw(&self) -> bool {
+        let margin = self.search_info.flags.draw_margin;
+        margin
+            .zip(self.last_win_rate)
+            .is_some_and(|(margin, win_rate)| (win_rate - 0.5).abs() <= f64::from(margin))
+    }
 
-        let total_rollouts = self.tree.rollouts();
-        if let Limit::Rollouts(x) = self.search_info.flags.limit {
-            #[allow(clippy::cast_possible_truncation)]
-            let expected_rollouts = x * self.search_info.flags.root_parallelism_count as u32;
-            assert_eq!(total_rollouts, expected_rollouts);
-        }
+    pub const fn set_limit(&mut self, limit: Limit) {
+        self.limit = limit;
+        self.search_info.flags.limit = limit;
+    }
 
-        let move_chosen = if self.search_info.flags.training {
-            sample_move_index_from_rollouts(&rollout_distribution)
-        } else {
-            let best = rollout_distribution
-                .iter()
-                .enumerate()
-                .max_by_key(|(_, &count)| count)
-                .unwrap()
-                .0;
-            best
-        };
+    /// Sets the move of the game that later searches are for, counted from one as in
+    /// chess, by which the [`Behaviour::schedule`] picks their limit.
+    pub const fn set_move_number(&mut self, move_number: u32) {
+        self.move_number = move_number;
+    }
 
-        let root_children = self.tree.root().children();
-        let new_node_idx = move_chosen + root_children.start;
-        let chosen_move = self.tree[new_node_idx].inbound_edge();
-        let mut new_node = board.clone();
-        new_node.push(chosen_move);
+    /// Saves the tree of later searches periodically and when they finish,
+    /// and resumes them from the saved tree if it is of the position being searched.
+    pub fn set_autosave(&mut self, autosave: Option<Autosave>) {
+        self.autosave = autosave;
+    }
 
-        SearchResults {
-            rollout_distribution,
-            new_node,
-            new_node_idx,
-            rollouts: total_rollouts,
-            win_rate: avg_win_rate,
-        }
+    /// Discards everything carried over from earlier searches: the tree, the root
+    /// move information, the history table, the search clock, and the move number.
+    /// In deterministic mode, the random seed is reset too.
+    /// Called between games, so that a game is not affected by the ones before it.
+    pub fn new_game(&mut self) {
+        self.tree.reset();
+        self.history.clear();
+        self.root_policy = None;
+        self.root_classes = None;
+        self.root_excluded = None;
+        self.context.scratch = None;
+        self.search_info.start_time = None;
+        self.last_win_rate = None;
+        self.move_number = 1;
+        self.rng = Self::new_rng(&self.search_info.flags);
     }
 
-    pub fn best_next_board(&mut self, board: &G) -> G {
-        let SearchResults {
-            rollout_distribution,
-            new_node,
-            new_node_idx,
-            rollouts,
-            win_rate,
-        } = self.search(board);
+    /// Seeds the random numbers of later searches, until the next [`new_game`](Self::new_game),
+    /// so that a game can be replayed exactly.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
 
-        if self.search_info.flags.readout {
-            println!(
-                "{} nodes processed in {}ms at {:.2} nodes per second.",
-                rollouts,
-                self.search_info.start_time.unwrap().elapsed().as_millis(),
-                f64::from(rollouts) / self.search_info.start_time.unwrap().elapsed().as_secs_f64()
-            );
-            let p1_wr = (win_rate * 100.0).clamp(0.0, 100.0);
-            println!(
-                "predicted outcome: {:.2}% chance of win.",
-                if self.search_info.side == -1 {
-                    p1_wr
-                } else {
-                    100.0 - p1_wr
-                }
-            );
-        }
-        if self.search_info.flags.debug {
-            println!("{rollout_distribution:?}");
-            println!("{new_node_idx:?}");
+    fn new_rng(flags: &Behaviour) -> fastrand::Rng {
+        if flags.deterministic {
+            fastrand::Rng::with_seed(DETERMINISTIC_SEED)
+        } else {
+            fastrand::Rng::new()
         }
+    }
 
-        new_node
+    /// Starts recording the time spent in each phase of subsequent searches.
+    pub fn enable_profiling(&mut self) {
+        self.phase_times = Some(PhaseTimes::default());
     }
 
-    fn do_treesearch(&mut self, root: &G) {
-        #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let log_file = std::fs::File::create("log.txt").unwrap();
-        let mut log_file = std::io::BufWriter::new(log_file);
-        while !Self::limit_reached(&self.search_info, self.tree.rollouts()) {
-            if self.search_info.flags.debug && self.tree.rollouts().is_power_of_two() {
-                print!("{}", self.tree.show_root_distribution(root).unwrap());
-                println!(" rollouts: {}", self.tree.rollouts());
-                std::io::stdout().flush().unwrap();
-            }
-            if self.search_info.flags.readout && self.tree.rollouts().is_power_of_two() {
-                assert!(
-                    self.tree.average_depth() >= 0.0,
-                    "It's impossible to have searched any lines to a negative depth."
-                );
-                #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-                let avg_depth = self.tree.average_depth().round() as u64;
-                let q = if self.tree.root().to_move() == -1 {
-                    f64::from(self.tree.root().wins()) / f64::from(self.tree.rollouts())
-                } else {
-                    1.0 - f64::from(self.tree.root().wins()) / f64::from(self.tree.rollouts())
-                };
-                print!(
-                    "info depth {avg_depth} seldepth {} score wdl {:.3} nodes {} nps {} pv {}\r",
-                    self.tree.pv_depth(),
-                    q,
-                    self.tree.rollouts(),
-                    (f64::from(self.tree.rollouts())
-                        / self.search_info.start_time.unwrap().elapsed().as_secs_f64())
-                        as u64,
-                    self.tree.pv_string()
-                );
-                std::io::stdout().flush().unwrap();
-            } else if self.search_info.flags.log && self.tree.rollouts() % 512 == 0 {
-                // print policy as an array
-                let rdist = self.tree.root_rollout_distribution();
-                let sum = rdist.iter().copied().map(u64::from).sum::<u64>();
-                let policy = rdist
-                    .iter()
-                    .copied()
-                    .map(u64::from)
-                    .map(|x| x as f64 / sum as f64);
-                for p in policy {
-                    write!(log_file, "{p:.3}, ").unwrap();
-                }
-                writeln!(log_file).unwrap();
-            }
-            self.select_expand_simulate_backpropagate(root);
-            self.tree.inc_rollouts();
+    /// The time spent in each phase since profiling was enabled.
+    pub const fn phase_times(&self) -> Option<&PhaseTimes> {
+        self.phase_times.as_ref()
+    }
+
+    /// If profiling is enabled, charges the time since `lap` to `phase` and starts the next lap.
+    fn lap(&mut self, lap: Option<Instant>, phase: Phase) -> Option<Instant> {
+        let times = self.phase_times.as_mut()?;
+        let now = Instant::now();
+        times.add(phase, now - lap?);
+        Some(now)
+    }
+
+    /// Records that a game state was copied, if profiling is enabled.
+    const fn note_copy(&mut self) {
+        if let Some(times) = &mut self.phase_times {
+            times.add_copy();
         }
-        if self.search_info.flags.readout {
-            println!();
+    }
+
+    /// Records that a move was played during a rollout, for the game lengths of the nodes
+    /// and, if profiling is enabled, the profile.
+    fn note_ply(&mut self, board: &G) {
+        self.note_move(-board.turn(), board.last_move());
+    }
+
+    /// Counts a ply of a rollout, in which `turn` made the move `m`, if the game says
+    /// which move it was, which RAVE credits with the result of the rollout.
+    fn note_move(&mut self, turn: i8, m: Option<G::Move>) {
+        self.rollout_plies += 1;
+        if let Some(times) = &mut self.phase_times {
+            times.add_rollout_ply();
+        }
+        if let (Some(_), Some(m)) = (self.search_info.flags.rave_bias, m) {
+            self.amaf.note(turn, G::move_index(m));
         }
     }
 
-    /// The main search loop of the MCTS algorithm.
-    ///
-    /// This function has four stages:
-    /// 1. Select a node to expand, based on the UCT formula.
-    /// 2. Expand the selected node.
-    /// 3. Simulate the game from the expanded node.
-    /// 4. Backpropagate the result of the simulation up the tree.
-    fn select_expand_simulate_backpropagate(&mut self, root: &G) {
-        // Each time we perform SESB, we have to walk a position down the tree, making moves as we go.
-        let mut traversing_state = root.clone();
+    /// Returns the evaluation of `board` after `m` is played, leaving `board` unchanged.
+    /// Uses make/unmake where the game supports it, and a copy otherwise.
+    fn evaluate_after(phase_times: &mut Option<PhaseTimes>, board: &mut G, m: G::Move) -> i8 {
+        if G::CAN_POP {
+            board.push(m);
+            let evaluation = board.evaluate();
+            board.pop(m);
+            evaluation
+        } else {
+            if let Some(times) = phase_times {
+                times.add_copy();
+            }
+            let mut board_copy = board.clone();
+            board_copy.push(m);
+            board_copy.evaluate()
+        }
+    }
 
-        let promising_node_idx = Self::select(
-            ROOT_IDX,
-            &self.tree,
-            &self.search_info,
-            &mut traversing_state,
-        );
+    fn limit_reached(search_info: &SearchInfo, rollouts: u64) -> bool {
+        search_info.limit_reached(rollouts)
+    }
 
-        if !traversing_state.is_terminal() {
-            self.tree.expand(promising_node_idx, &traversing_state);
+    pub fn search(&mut self, board: &G) -> SearchResults<G> {
+        if let Some(schedule) = &self.search_info.flags.schedule {
+            self.search_info.flags.limit = schedule.limit_at(self.move_number, self.limit);
         }
+        self.search_info.start_time = Some(Instant::now());
+        self.search_info.pending.clear();
+        self.last_save = self.search_info.start_time;
+        self.skipped_expansions = 0;
 
-        let promising_node = self.tree.get(promising_node_idx).unwrap();
-        let node_to_explore = if promising_node.has_children() {
-            promising_node.random_child(&self.rng)
+        if !self.resume(board) {
+            self.tree.setup(board.clone());
+        }
+        let resumed_rollouts = self.tree.rollouts();
+        self.history.clear();
+        self.root_policy = self
+            .root_priors
+            .as_ref()
+            .and_then(|table| table.root_priors(board))
+            .or_else(|| self.cached_priors(board));
+        self.fold_root_symmetries(board);
+        let (stopped, others) = if self.search_info.flags.root_parallelism_count > 1 {
+            self.root_parallel_treesearch(board)
         } else {
-            promising_node_idx
+            (self.do_treesearch(board), Vec::new())
         };
 
-        let q = self.simulate(node_to_explore, &mut traversing_state);
+        let distributions = std::iter::once(&*self)
+            .chain(&others)
+            .map(Self::root_visits)
+            .collect::<Vec<_>>();
+        let rollout_distribution = fusion::sum_visits(&distributions);
 
-        Self::backprop(node_to_explore, q, &mut self.tree);
-    }
+        let total_rollouts = self.tree.rollouts()
+            + others
+                .iter()
+                .map(|engine| engine.tree.rollouts())
+                .sum::<u64>();
+        // a search can run past its limit, but only one that was stopped can fall short of it.
+        if let (false, Limit::Rollouts(limit)) = (stopped, self.search_info.flags.limit) {
+            debug_assert!(
+                total_rollouts >= limit,
+                "the search finished after {total_rollouts} of {limit} rollouts"
+            );
+        }
+        let overshoot = self
+            .search_info
+            .overshoot(total_rollouts, resumed_rollouts);
 
-    /// BACKPROPAGATE: Given a node and a Q-value, backpropagate the Q-value up the tree.
-    #[inline(never)]
-    fn backprop(node_idx: usize, q: f32, tree: &mut SearchTree<G>) {
-        let mut node = tree.get_mut(node_idx).expect("called backprop on root");
-        loop {
-            node.update(q);
-            if let Some(parent_idx) = node.parent() {
-                node = tree.get_mut(parent_idx).unwrap();
-            } else {
-                break;
+        let best_child = if others.is_empty() {
+            self.search_info.flags.lcb.map_or_else(
+                || self.tree.best_child_by_visits(ROOT_IDX),
+                |z| self.tree.best_child_by_lcb(ROOT_IDX, f64::from(z)),
+            )
+        } else {
+            let chosen = self.fused_choice(&others, &distributions);
+            if self.search_info.flags.readout && !self.search_info.flags.json_info {
+                println!("info string {}", Agreement::new(&distributions, chosen));
             }
+            self.tree.nth_child(ROOT_IDX, chosen)
+        };
+        let new_node_idx =
+            if self.search_info.flags.training && self.tree[best_child].proof() != Proof::Win {
+                let sampled = sample_move_index_from_rollouts(&rollout_distribution, &self.rng);
+                self.tree.nth_child(ROOT_IDX, sampled)
+            } else {
+                best_child
+            };
+        let chosen_move = self.tree[new_node_idx].inbound_edge();
+        let mut new_node = board.clone();
+        new_node.push(chosen_move);
+
+        // a child chosen by sampling may never have been visited,
+        // so fall back on the root's value, which is from the other player's perspective.
+        let win_rate = if others.is_empty() {
+            self.tree[new_node_idx].known_value()
+        } else {
+            let index = self
+                .tree
+                .children_of(ROOT_IDX)
+                .position(|idx| idx == new_node_idx);
+            self.fused_win_rate(&others, index.unwrap())
         }
-    }
+        .unwrap_or_else(|| 1.0 - self.tree.root().win_rate());
 
-    /// SIMULATE: Given a node, simulate the game from that node, and return the resulting Q-value.
-    #[inline(never)]
-    fn simulate(&mut self, node_idx: usize, rollout_board: &mut G) -> f32 {
-        use RolloutPolicy::{
-            Decisive, DecisiveCutoff, DecisiveQualityScaled, MetaAggregated, Random, RandomCutoff,
-            RandomQualityScaled,
+        let results = SearchResults {
+            rollout_distribution,
+            new_node,
+            new_node_idx,
+            best_move: chosen_move,
+            rollouts: total_rollouts,
+            win_rate,
+            overshoot,
+            game_lengths: self.root_game_lengths(),
+            skipped_expansions: self.skipped_expansions,
         };
-        let node = &self.tree[node_idx];
+        self.last_win_rate = Some(win_rate);
+        self.add_to_cache(board, &results);
+        if self.search_info.flags.readout && self.search_info.flags.json_info {
+            println!("{}", self.json_result(&results));
+        }
+        results
+    }
 
-        // test for immediate loss
-        let status = rollout_board.evaluate();
-        if status == -self.search_info.side {
-            let parent_idx = node
-                .parent()
-                .expect("PANICKING: Immediate loss found in root node.");
-            self.tree[parent_idx].set_win_score(N_INF as f32);
-            return f32::from(status);
+    /// The priors that the analysis cache has for the root moves of `board`, if any,
+    /// noting in the readout how much earlier searches looked at it.
+    fn cached_priors(&self, board: &G) -> Option<Vec<f32>> {
+        let cache = self.analysis_cache.as_ref()?;
+        let (visits, value) = cache.lookup(board)?;
+        if self.search_info.flags.readout && !self.search_info.flags.json_info {
+            println!("info string cache has {visits} visits of this position, win rate {value:.3}");
         }
+        cache.root_priors(board)
+    }
 
-        // playout
-        match &self.search_info.flags.rollout_policy {
-            Random => self.random_rollout(rollout_board),
-            Decisive => self.decisive_rollout(rollout_board),
-            RandomQualityScaled => self.random_rollout_qs(rollout_board),
-            DecisiveQualityScaled => self.decisive_rollout_qs(rollout_board),
-            RandomCutoff { moves } => self.random_rollout_cutoff(rollout_board, *moves),
-            DecisiveCutoff { moves } => Self::decisive_rollout_cutoff(rollout_board, *moves),
-            MetaAggregated { policy, rollouts } => {
-                let rollouts = *rollouts;
-                let f = match policy.as_ref() {
-                    RolloutPolicy::Random => Self::random_rollout,
-                    RolloutPolicy::Decisive => Self::decisive_rollout,
-                    RolloutPolicy::RandomQualityScaled => Self::random_rollout_qs,
-                    RolloutPolicy::DecisiveQualityScaled => Self::decisive_rollout_qs,
-                    RolloutPolicy::RandomCutoff { .. } => {
-                        panic!("MetaAggregated policy cannot be RandomCutoff")
-                    }
-                    RolloutPolicy::DecisiveCutoff { .. } => {
-                        panic!("MetaAggregated policy cannot be DecisiveCutoff")
-                    }
-                    RolloutPolicy::MetaAggregated { .. } => {
-                        panic!("MetaAggregated policy must be a RolloutPolicy")
-                    }
-                };
-                let mut sum = 0.0;
-                for _ in 0..rollouts {
-                    let mut roller = rollout_board.clone();
-                    sum += f(self, &mut roller);
-                }
-                sum / (rollouts as f32)
+    /// Adds the search of `board` to the analysis cache, if there is one, and saves it.
+    fn add_to_cache(&mut self, board: &G, results: &SearchResults<G>) {
+        let Some(cache) = &mut self.analysis_cache else {
+            return;
+        };
+        cache.record(board, &results.rollout_distribution, results.win_rate);
+        if let Err(e) = cache.save() {
+            eprintln!("failed to save the analysis cache to {}: {e}", cache.path().display());
+        }
+    }
+
+    /// Loads the autosaved tree, if there is one of `board`, returning whether it was loaded.
+    fn resume(&mut self, board: &G) -> bool {
+        let Some(autosave) = &self.autosave else {
+            return false;
+        };
+        match self.tree.load(&autosave.path, board.clone()) {
+            Ok(()) => {
+                eprintln!(
+                    "resuming the search from {} rollouts saved in {}",
+                    self.tree.rollouts(),
+                    autosave.path.display()
+                );
+                true
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => {
+                eprintln!("not resuming from {}: {e}", autosave.path.display());
+                false
             }
         }
     }
 
-    /// SELECT: we traverse the on-policy (in-memory) part of the tree, at each node we select the child
-    /// with the highest UCB1 value. As we do not store states in the tree, we have to push
-    /// moves as we go.
-    #[inline(never)]
-    fn select(
-        root_idx: usize,
-        tree: &SearchTree<G>,
-        search_info: &SearchInfo,
-        state: &mut G,
-    ) -> usize {
-        let mut idx = root_idx;
-        let mut node = &tree[idx];
-        while node.has_children() {
-            let children = node.children();
-            idx = ucb::best(
-                state,
-                &tree.nodes[children.clone()],
-                node.visits(),
-                search_info.flags.exp_factor,
-            ) + children.start;
-            node = &tree[idx];
-            state.push(node.inbound_edge());
+    /// Saves the tree if an autosave is configured and, unless `now`, the interval has passed.
+    fn autosave(&mut self, now: bool) {
+        let Some(autosave) = &self.autosave else {
+            return;
+        };
+        let due = self
+            .last_save
+            .is_none_or(|last| last.elapsed() >= autosave.interval);
+        if !now && !due {
+            return;
+        }
+        if let Err(e) = self.tree.save(&autosave.path) {
+            eprintln!("failed to autosave to {}: {e}", autosave.path.display());
         }
-        idx
+        self.last_save = Some(Instant::now());
     }
 
-    /// The random rollout policy.
-    /// Simply plays random moves until the game ends,
-    /// then returns the result as 1.0 / 0.0 / -1.0.
-    fn random_rollout(&mut self, playout_board: &mut G) -> f32 {
-        while !playout_board.is_terminal() {
-            playout_board.push_random(&mut self.rng);
+    /// The principal variation of the last search.
+    pub fn principal_variation(&self) -> Vec<G::Move> {
+        self.tree.pv()
+    }
+
+    /// The move, visit count, and mean value (for the player making the move, if visited)
+    /// of every root move of the last search, in move generation order.
+    /// Moves folded into a symmetric representative report its visits and value.
+    pub fn root_edges(&self) -> Vec<(G::Move, u64, Option<f64>)> {
+        let visits = self.root_visits();
+        self.tree
+            .children_of(ROOT_IDX)
+            .enumerate()
+            .map(|(i, idx)| {
+                let source = self
+                    .root_classes
+                    .as_ref()
+                    .map_or(idx, |classes| self.tree.nth_child(ROOT_IDX, classes[i]));
+                let source = &self.tree[source];
+                let q = (source.visits() > 0).then(|| source.win_rate());
+                (self.tree[idx].inbound_edge(), visits[i], q)
+            })
+            .collect()
+    }
+
+    /// The visits of every root move of the last search, in move generation order. Moves
+    /// folded into a symmetric representative report its visits.
+    fn root_visits(&self) -> Vec<u64> {
+        let mut visits = self.tree.root_rollout_distribution();
+        if let Some(classes) = &self.root_classes {
+            symmetry::spread_visits(&mut visits, classes);
         }
-        f32::from(playout_board.evaluate())
+        visits
     }
 
-    /// A scaling function that allows for rollout results to be weighted by the quality of the
-    /// rollout, where rollouts that end more quickly are considered better, as they should be
-    /// more representative of the quality of the position they arose from.
-    fn scale(q: f32, moves: f32) -> f32 {
-        q * (-0.04 * moves).exp()
+    /// The index of the root move to play by a root-parallel search, of the trees of this
+    /// engine and of `others`, whose root visits are `distributions`. A move that any tree
+    /// proved to win is played, and otherwise the visits are fused by [`Behaviour::root_merge`].
+    fn fused_choice(&self, others: &[Self], distributions: &[Vec<u64>]) -> usize {
+        std::iter::once(self)
+            .chain(others)
+            .find_map(|engine| {
+                let tree = &engine.tree;
+                tree.children_of(ROOT_IDX)
+                    .position(|idx| tree[idx].proof() == Proof::Win)
+            })
+            .unwrap_or_else(|| self.search_info.flags.root_merge.choose(distributions))
     }
 
-    /// A quality-scaled version of [`random_rollout`](Self::random_rollout).
-    fn random_rollout_qs(&mut self, playout_board: &mut G) -> f32 {
-        let mut moves = 1;
-        while !playout_board.is_terminal() {
-            playout_board.push_random(&mut self.rng);
-            moves += 1;
+    /// The value of the root move at `index` for the player making it, over the trees of
+    /// this engine and of `others`: exact if any tree proved it, and otherwise the mean of
+    /// the trees' values weighted by their visits, or `None` if no tree visited it.
+    fn fused_win_rate(&self, others: &[Self], index: usize) -> Option<f64> {
+        let nodes = std::iter::once(self)
+            .chain(others)
+            .map(|engine| &engine.tree[engine.tree.nth_child(ROOT_IDX, index)]);
+        if let Some(proven) = nodes.clone().find(|node| node.proof() != Proof::Unknown) {
+            return proven.known_value();
         }
-        let q = f32::from(playout_board.evaluate());
-        Self::scale(q, moves as f32)
+        let visits = nodes.clone().map(|node| node.visits() as f64).sum::<f64>();
+        (visits > 0.0).then(|| {
+            nodes
+                .map(|node| node.win_rate() * node.visits() as f64)
+                .sum::<f64>()
+                / visits
+        })
     }
 
-    /// The decisive rollout policy.
-    /// In each position, if there is a move that wins on the spot, we play that move.
-    /// Otherwise, we play a random move.
-    fn decisive_rollout(&mut self, playout_board: &mut G) -> f32 {
-        while !playout_board.is_terminal() {
-            let mut buffer = G::Buffer::default();
-            playout_board.generate_moves(&mut buffer);
-            for &m in buffer.iter() {
-                let mut board_copy = playout_board.clone();
-                board_copy.push(m);
-                let evaluation = playout_board.evaluate();
-                if evaluation != 0 {
-                    return f32::from(evaluation);
-                }
+    /// The expected number of plies from every root move of the last search to the end of
+    /// the game, if the move was visited, in move generation order, as for [`root_edges`].
+    ///
+    /// [`root_edges`]: Self::root_edges
+    pub fn root_game_lengths(&self) -> Vec<Option<f32>> {
+        self.tree
+            .children_of(ROOT_IDX)
+            .enumerate()
+            .map(|(i, idx)| {
+                let source = self
+                    .root_classes
+                    .as_ref()
+                    .map_or(idx, |classes| self.tree.nth_child(ROOT_IDX, classes[i]));
+                let source = &self.tree[source];
+                (source.visits() > 0).then(|| source.game_length() + 1.0)
+            })
+            .collect()
+    }
+
+    /// If root symmetry folding is enabled and `board` has symmetric moves, expands the root
+    /// and excludes every child but one from each class of equivalent moves.
+    fn fold_root_symmetries(&mut self, board: &G) {
+        self.root_classes = None;
+        self.root_excluded = None;
+        if !self.search_info.flags.root_symmetry || G::SYMMETRIES == 1 || board.is_terminal() {
+            return;
+        }
+        // a resumed tree has its root expanded already.
+        if !self.tree.root().has_children() {
+            self.expand(ROOT_IDX, &mut board.clone());
+        }
+        let moves = self
+            .tree
+            .child_nodes(ROOT_IDX)
+            .iter()
+            .map(Node::inbound_edge)
+            .collect::<Vec<_>>();
+        let classes = symmetry::classes(board, &moves);
+        if classes.iter().enumerate().all(|(i, &class)| i == class) {
+            return;
+        }
+        self.root_excluded = Some(
+            classes
+                .iter()
+                .enumerate()
+                .map(|(i, &class)| i != class)
+                .collect(),
+        );
+        self.root_classes = Some(classes);
+    }
+
+    pub fn best_next_board(&mut self, board: &G) -> G {
+        let SearchResults {
+            rollout_distribution,
+            new_node,
+            new_node_idx,
+            best_move: _,
+            rollouts,
+            win_rate,
+            overshoot,
+            game_lengths: _,
+            skipped_expansions: _,
+        } = self.search(board);
+
+        if self.search_info.flags.readout && !self.search_info.flags.json_info {
+            let elapsed = self.search_info.start_time.unwrap().elapsed();
+            println!(
+                "{} nodes processed in {}ms at {:.0} nodes per second.",
+                rollouts,
+                elapsed.as_millis(),
+                per_second(rollouts as f64, elapsed)
+            );
+            println!(
+                "average depth {:.1}, maximum depth {}.",
+                self.tree.average_depth(),
+                self.tree.max_depth()
+            );
+            println!(
+                "predicted outcome: {:.2}% chance of win.",
+                (win_rate * 100.0).clamp(0.0, 100.0)
+            );
+            println!(
+                "candidates: {}",
+                format_candidates(&self.root_edges(), READOUT_CANDIDATES)
+            );
+            if !overshoot.is_zero() {
+                println!("the search ran {overshoot}.");
             }
-            let idx = self.rng.usize(..buffer.len());
-            playout_board.push(buffer[idx]);
         }
-        f32::from(playout_board.evaluate())
+        if self.search_info.flags.debug {
+            println!("{rollout_distribution:?}");
+            println!("{new_node_idx:?}");
+        }
+
+        new_node
     }
 
-    /// A quality-scaled version of [`decisive_rollout`](Self::decisive_rollout).
-    fn decisive_rollout_qs(&mut self, playout_board: &mut G) -> f32 {
-        let mut moves = 1;
-        while !playout_board.is_terminal() {
-            let mut buffer = G::Buffer::default();
-            playout_board.generate_moves(&mut buffer);
-            for &m in buffer.iter() {
-                let mut board_copy = playout_board.clone();
-                board_copy.push(m);
-                let evaluation = playout_board.evaluate();
-                if evaluation != 0 {
-                    return f32::from(evaluation) / (moves as f32 + 10.0) * 10.0;
-                }
+    /// Searches until the limit is reached, returning true if the search was stopped early
+    /// by a command on stdin or by Ctrl+C.
+    fn do_treesearch(&mut self, root: &G) -> bool {
+        let mut log = self.context.log.take();
+        if self.search_info.flags.log && log.is_none() {
+            log = Some(BufWriter::new(File::create("log.txt").unwrap()));
+        }
+        let (stopped, full) = match &mut log {
+            Some(log_file) => {
+                let searched = self.treesearch(root, log_file);
+                log_file.flush().unwrap();
+                searched
+            }
+            None => self.treesearch(root, &mut io::sink()),
+        };
+        self.context.log = log;
+        self.autosave(true);
+        if self.search_info.flags.readout && !self.search_info.flags.json_info {
+            println!();
+            if full {
+                println!("info string hash full, so the infinite search stopped; a larger hash would let it go on");
+            } else if self.skipped_expansions > 0 {
+                println!(
+                    "info string hash full, so {} expansions were skipped and the search was shallower than its limit allows; a larger hash would help",
+                    self.skipped_expansions
+                );
             }
-            let idx = self.rng.usize(..buffer.len());
-            playout_board.push(buffer[idx]);
-            moves += 1;
         }
-        let q = f32::from(playout_board.evaluate());
-        Self::scale(q, moves as f32)
+        stopped
     }
 
-    /// A cutoff version of [`random_rollout`](Self::random_rollout).
-    /// This policy will stop rollouts after a fixed number of moves,
-    /// returning a Q-value of 0.0.
-    fn random_rollout_cutoff(&mut self, playout_board: &mut G, moves: usize) -> f32 {
-        let mut counter = 1;
-        while !playout_board.is_terminal() {
-            if counter > moves {
-                return 0.0;
+    /// Searches on this thread, or on [`Behaviour::threads`] threads. Returns whether the
+    /// search was stopped, and whether it ran out of room for nodes under an infinite limit.
+    fn treesearch(&mut self, root: &G, log_file: &mut impl Write) -> (bool, bool) {
+        if self.search_info.flags.threads > 1 {
+            self.parallel_treesearch(root, log_file)
+        } else {
+            self.serial_treesearch(root, log_file)
+        }
+    }
+
+    /// Searches [`Behaviour::root_parallelism_count`] trees of `root` at once, each on
+    /// threads of its own. This engine's tree is searched on this thread as it would be
+    /// alone, with the readout, commands, and autosaves, and the others by engines that are
+    /// returned for their root visits to be fused. A rollout limit is shared out between
+    /// the trees, and stopping this search stops them all. Returns whether it was stopped.
+    fn root_parallel_treesearch(&mut self, root: &G) -> (bool, Vec<Self>) {
+        let limit = self.search_info.flags.limit;
+        let mut trees = self.search_info.flags.root_parallelism_count;
+        if let Limit::Rollouts(rollouts) = limit {
+            // every tree does at least one rollout, so that it has root visits to fuse.
+            trees = trees
+                .min(usize::try_from(rollouts).unwrap_or(usize::MAX))
+                .max(1);
+        }
+        let share = |tree: usize| match limit {
+            Limit::Rollouts(rollouts) => {
+                let (tree, trees) = (tree as u64, trees as u64);
+                Limit::Rollouts(rollouts / trees + u64::from(tree < rollouts % trees))
+            }
+            limit => limit,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut others = (1..trees)
+            .map(|tree| {
+                let mut engine = self.worker();
+                engine.tree = Box::new(
+                    SearchTree::with_capacity(self.tree.capacity())
+                        .with_transpositions(self.search_info.flags.transpositions),
+                );
+                engine.tree.setup(root.clone());
+                let info = &mut engine.search_info;
+                info.flags.limit = share(tree);
+                (info.flags.debug, info.flags.readout, info.flags.log) = (false, false, false);
+                info.stop = Some(Arc::clone(&stop));
+                engine
+            })
+            .collect::<Vec<_>>();
+        self.search_info.flags.limit = share(0);
+        let stopped = std::thread::scope(|s| {
+            for engine in &mut others {
+                s.spawn(|| engine.treesearch(root, &mut io::sink()));
+            }
+            let stopped = self.do_treesearch(root);
+            if stopped {
+                stop.store(true, Ordering::Relaxed);
+            }
+            stopped
+        });
+        self.search_info.flags.limit = limit;
+        for engine in &others {
+            self.skipped_expansions += engine.skipped_expansions;
+            if let (Some(times), Some(theirs)) = (&mut self.phase_times, &engine.phase_times) {
+                times.merge(theirs);
             }
-            playout_board.push_random(&mut self.rng);
-            counter += 1;
         }
-        f32::from(playout_board.evaluate())
+        // a tree stopped before its first rollout has nothing to fuse.
+        others.retain(|engine| engine.tree.root().has_children());
+        (stopped, others)
     }
 
-    /// A cutoff version of [`decisive_rollout`](Self::decisive_rollout).
-    /// This policy will stop rollouts after a fixed number of moves,
-    /// returning a Q-value of 0.0.
-    fn decisive_rollout_cutoff(playout_board: &mut G, moves: usize) -> f32 {
-        let mut counter = 1;
-        while !playout_board.is_terminal() {
-            if counter > moves {
-                return 0.0;
+    /// Writes the policy at the root, the share of the root's visits of each child, to `log_file`.
+    fn log_policy(&mut self, log_file: &mut impl Write) {
+        let rdist = &mut self.context.distribution;
+        rdist.clear();
+        rdist.extend(self.tree.child_nodes(ROOT_IDX).iter().map(Node::visits));
+        let sum = rdist.iter().sum::<u64>();
+        let policy = rdist.iter().map(|&x| x as f64 / sum as f64);
+        for p in policy {
+            write!(log_file, "{p:.3}, ").unwrap();
+        }
+        writeln!(log_file).unwrap();
+    }
+
+    /// Prints the visits of each root move, for debugging.
+    fn print_root_distribution(&self, root: &G) {
+        print!("{}", self.tree.show_root_distribution(root).unwrap());
+        println!(" rollouts: {}", self.tree.rollouts());
+        std::io::stdout().flush().unwrap();
+    }
+
+    /// Runs the iterations of a search on this thread alone. Returns whether the search was
+    /// stopped, and whether it ran out of room for nodes under an infinite limit.
+    fn serial_treesearch(&mut self, root: &G, log_file: &mut impl Write) -> (bool, bool) {
+        let mut stopped = false;
+        let mut full = false;
+        while !Self::limit_reached(&self.search_info, self.tree.rollouts()) {
+            let batch = self.next_batch();
+            if self.search_info.flags.limit == Limit::Infinite
+                && !self.tree.has_room_for(G::MOVE_SPACE * batch)
+            {
+                full = true;
+                break;
+            }
+            if self.search_info.flags.debug && self.tree.rollouts().is_power_of_two() {
+                self.print_root_distribution(root);
+            }
+            if self.search_info.flags.readout && self.tree.rollouts().is_power_of_two() {
+                self.print_info();
+            } else if self.search_info.flags.log && self.tree.rollouts().is_multiple_of(512) {
+                self.log_policy(log_file);
+            }
+            if self.search_info.flags.root_pruning {
+                let limit = self.search_info.flags.limit;
+                Self::prune_root(&self.tree, limit, &mut self.root_excluded);
+            }
+            let before = self.tree.rollouts();
+            if batch == 1 {
+                self.select_expand_simulate_backpropagate(root);
+                self.tree.inc_rollouts();
+            } else {
+                self.batched_iteration(root, batch);
             }
-            let mut buffer = G::Buffer::default();
-            playout_board.generate_moves(&mut buffer);
-            for &m in buffer.iter() {
-                let mut board_copy = playout_board.clone();
-                board_copy.push(m);
-                let evaluation = playout_board.evaluate();
-                if evaluation != 0 {
-                    return f32::from(evaluation);
+            // a batch may step over the multiples of the interval.
+            if self.tree.rollouts() / SEARCH_CHECK_INTERVAL != before / SEARCH_CHECK_INTERVAL {
+                if interrupt::requested() || self.search_info.check_stdin() {
+                    stopped = true;
+                    break;
                 }
+                self.autosave(false);
             }
-            let mut rng = rand::thread_rng();
-            let idx = rng.gen_range(0..buffer.len());
-            playout_board.push(buffer[idx]);
-            counter += 1;
         }
-        f32::from(playout_board.evaluate())
+        (stopped, full)
     }
-}
 
-fn sample_move_index_from_rollouts(fused_distribution: &[u32]) -> usize {
-    return fused_distribution.iter().enumerate().max_by_key(|(i, v)| **v).unwrap().0;
-    let total_rollouts = fused_distribution.iter().sum::<u32>();
-    let prob_vector = fused_distribution
-        .iter()
-        .map(|&count| {
-            let raw_policy = f64::from(count) / f64::from(total_rollouts);
-            // adjust to flatten the distribution
-            #[allow(clippy::cast_precision_loss)]
-            let uniform_val = 1.0 / fused_distribution.len() as f64;
-            raw_policy.mul_add(0.7, uniform_val * 0.3)
-        })
-        .collect::<Vec<_>>();
-    let rfloat = rand::thread_rng().gen_range(0.0..1.0);
-    let mut cumulative_probability = 0.0;
-    let mut choice = 0;
-    for (i, &prob) in prob_vector.iter().enumerate() {
-        cumulative_probability += prob;
-        if rfloat <= cumulative_probability {
-            choice = i;
-            break;
+    /// Runs the iterations of a search on [`Behaviour::threads`] worker threads, which share
+    /// the tree, while this thread checks for commands, shows the progress of the search,
+    /// and autosaves. Returns what [`serial_treesearch`](Self::serial_treesearch) does.
+    fn parallel_treesearch(&mut self, root: &G, log_file: &mut impl Write) -> (bool, bool) {
+        let started = AtomicU64::new(self.tree.rollouts());
+        let pool = RwLock::new(std::mem::replace(
+            &mut self.tree,
+            Box::new(SearchTree::with_capacity(0)),
+        ));
+        let stop = AtomicBool::new(false);
+        let mut workers = (0..self.search_info.flags.threads)
+            .map(|_| self.worker())
+            .collect::<Vec<_>>();
+        let mut stopped = false;
+        let mut full = false;
+        std::thread::scope(|s| {
+            let (pool, started, stop) = (&pool, &started, &stop);
+            let main = std::thread::current();
+            let handles = workers
+                .iter_mut()
+                .map(|worker| {
+                    let main = main.clone();
+                    s.spawn(move || {
+                        worker.work(pool, root, started, stop);
+                        main.unpark();
+                    })
+                })
+                .collect::<Vec<_>>();
+            // each is done as often as a search on one thread does it: the readout each
+            // time the rollouts pass a power of two, and the rest at their intervals.
+            let mut shown = None;
+            let mut logged = 0;
+            let mut checked = 0;
+            while !handles.iter().all(ScopedJoinHandle::is_finished) {
+                std::thread::park_timeout(PARALLEL_POLL_INTERVAL);
+                self.with_tree(pool, |this| {
+                    let rollouts = this.tree.rollouts();
+                    if rollouts / SEARCH_CHECK_INTERVAL > checked {
+                        checked = rollouts / SEARCH_CHECK_INTERVAL;
+                        if interrupt::requested() || this.search_info.check_stdin() {
+                            stopped = true;
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        this.autosave(false);
+                    }
+                    let flags = &this.search_info.flags;
+                    if flags.limit == Limit::Infinite && !this.tree.has_room_for(G::MOVE_SPACE) {
+                        full = true;
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    if rollouts.checked_ilog2() > shown {
+                        shown = rollouts.checked_ilog2();
+                        if flags.debug {
+                            this.print_root_distribution(root);
+                        }
+                        if flags.readout {
+                            this.print_info();
+                        }
+                    }
+                    if flags.log && rollouts / 512 > logged {
+                        logged = rollouts / 512;
+                        this.log_policy(log_file);
+                    }
+                });
+            }
+        });
+        self.tree = pool.into_inner().unwrap();
+        for worker in workers {
+            self.skipped_expansions += worker.skipped_expansions;
+            if let (Some(times), Some(theirs)) = (&mut self.phase_times, &worker.phase_times) {
+                times.merge(theirs);
+            }
+        }
+        (stopped, full)
+    }
+
+    /// A worker for a parallel search of the current root, with the settings and root move
+    /// information of this engine, but random numbers of its own and an empty tree, into
+    /// which it swaps the shared tree when it needs to change its structure.
+    fn worker(&self) -> Self {
+        Self {
+            search_info: SearchInfo {
+                stdin_rx: None,
+                ..self.search_info.clone()
+            },
+            limit: self.limit,
+            move_number: self.move_number,
+            tree: Box::new(SearchTree::with_capacity(0)),
+            rng: fastrand::Rng::with_seed(self.rng.u64(..)),
+            root_priors: None,
+            analysis_cache: None,
+            network: self.network.clone(),
+            deferred_expansions: None,
+            root_policy: self.root_policy.clone(),
+            root_classes: self.root_classes.clone(),
+            root_excluded: self.root_excluded.clone(),
+            phase_times: self.phase_times.as_ref().map(|_| PhaseTimes::default()),
+            rollout_plies: 0,
+            context: SearchContext::default(),
+            history: HistoryTable::new(G::MOVE_SPACE),
+            amaf: Amaf::new(G::MOVE_SPACE),
+            autosave: None,
+            last_save: None,
+            last_win_rate: None,
+            skipped_expansions: 0,
         }
     }
-    choice
+
+    /// Runs iterations of a parallel search on the tree shared in `pool`, until the limit
+    /// of the search is reached, counting each iteration in `started` as it starts so that
+    /// the workers do no more rollouts between them than a rollout limit allows, or until
+    /// `stop` is set.
+    fn work(
+        &mut self,
+        pool: &RwLock<Box<SearchTree<G>>>,
+        root: &G,
+        started: &AtomicU64,
+        stop: &AtomicBool,
+    ) {
+        let mut state = root.clone();
+        while !stop.load(Ordering::Relaxed)
+            && !Self::limit_reached(&self.search_info, started.fetch_add(1, Ordering::Relaxed))
+        {
+            self.parallel_iteration(pool, root, &mut state);
+        }
+    }
+
+    /// Prints the depth, score, speed, and principal variation of the search so far,
+    /// as a line of text that the next overwrites, or as a JSON line.
+    fn print_info(&self) {
+        #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        assert!(
+            self.tree.average_depth() >= 0.0,
+            "It's impossible to have searched any lines to a negative depth."
+        );
+        let avg_depth = self.tree.average_depth().round() as u64;
+        // the root's value is from the perspective of the player who is not to move.
+        let q = 1.0 - self.tree.root().win_rate();
+        let cp = score::q_to_cp(q, self.search_info.flags.cp_scale);
+        let nodes = self.tree.rollouts();
+        let nps = per_second(nodes as f64, self.search_info.start_time.unwrap().elapsed()) as u64;
+        if self.search_info.flags.json_info {
+            println!(
+                "{{\"type\": \"info\", \"depth\": {avg_depth}, \"seldepth\": {}, \"score_cp\": {cp}, \"nodes\": {nodes}, \"nps\": {nps}, \"pv\": {}}}",
+                self.tree.max_depth(),
+                json::strings(self.tree.pv()),
+            );
+        } else {
+            print!(
+                "info depth {avg_depth} seldepth {} score cp {cp} nodes {nodes} nps {nps} pv {}\r",
+                self.tree.max_depth(),
+                self.tree.pv_string()
+            );
+        }
+        std::io::stdout().flush().unwrap();
+    }
+
+    /// The outcome of the last search, `results`, as a JSON object: the move chosen,
+    /// its value, the work done, and the visits and value of every root move.
+    pub fn json_result(&self, results: &SearchResults<G>) -> String {
+        let elapsed = self.search_info.start_time.unwrap().elapsed();
+        let moves = self
+            .root_edges()
+            .into_iter()
+            .zip(&results.game_lengths)
+            .map(|((m, visits, q), plies)| {
+                let q = q.map_or_else(|| "null".to_string(), |q| format!("{q:.4}"));
+                let plies = plies.map_or_else(|| "null".to_string(), |p| format!("{p:.1}"));
+                format!(
+                    "{{\"move\": {}, \"visits\": {visits}, \"q\": {q}, \"plies\": {plies}}}",
+                    json::string(&m.to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{{\"type\": \"result\", \"best_move\": {}, \"win_rate\": {:.4}, \"rollouts\": {}, \"time_ms\": {}, \"overshoot_ms\": {}, \"overshoot_rollouts\": {}, \"skipped_expansions\": {}, \"pv\": {}, \"moves\": [{moves}]}}",
+            json::string(&results.best_move.to_string()),
+            results.win_rate,
+            results.rollouts,
+            elapsed.as_millis(),
+            results.overshoot.time.as_millis(),
+            results.overshoot.rollouts,
+            results.skipped_expansions,
+            json::strings(self.tree.pv()),
+        )
+    }
+
+    /// Excludes from `root_excluded` the root moves of `tree` that could not become the
+    /// most-visited even if every remaining rollout under `limit` went to them, so that
+    /// the rest of a fixed rollout budget goes to the contenders.
+    fn prune_root(tree: &SearchTree<G>, limit: Limit, root_excluded: &mut Option<Vec<bool>>) {
+        let Limit::Rollouts(max_rollouts) = limit else {
+            return;
+        };
+        let rollouts = tree.rollouts();
+        let remaining = max_rollouts.saturating_sub(rollouts);
+        // no move has more visits than there have been rollouts.
+        if remaining >= rollouts || !tree.root().has_children() {
+            return;
+        }
+        let children = tree.child_nodes(ROOT_IDX);
+        let best = children.iter().map(Node::visits).max().unwrap_or(0);
+        let excluded = root_excluded.get_or_insert_with(|| vec![false; children.len()]);
+        for (excluded, child) in excluded.iter_mut().zip(children) {
+            if child.visits() + remaining < best {
+                *excluded = true;
+            }
+        }
+    }
+
+    /// The main search loop of the MCTS algorithm.
+    ///
+    /// This function has four stages:
+    /// 1. Select a node to expand, based on the UCT formula.
+    /// 2. Expand the selected node.
+    /// 3. Simulate the game from the expanded node.
+    /// 4. Backpropagate the result of the simulation up the tree.
+    fn select_expand_simulate_backpropagate(&mut self, root: &G) {
+        let lap = self.phase_times.is_some().then(Instant::now);
+
+        // Each time we perform SESB, we have to walk a position down the tree, making moves as we go.
+        let mut traversing_state = self.context.scratch.take().unwrap_or_default();
+        let (node_to_explore, depth, lap) = self.descend(root, &mut traversing_state, lap);
+
+        let (q, plies) = self.simulate(node_to_explore, &mut traversing_state);
+        let lap = self.lap(lap, Phase::Simulate);
+
+        let minimax_depth = self.search_info.flags.minimax_depth;
+        Self::backprop(node_to_explore, depth, q, plies, minimax_depth, &self.tree);
+        if self.search_info.flags.rave_bias.is_some() {
+            self.amaf.backprop(&mut self.tree, node_to_explore, q);
+        }
+        self.lap(lap, Phase::Backprop);
+
+        self.context.scratch = Some(traversing_state);
+    }
+
+    /// Runs `batch` iterations together, for [`Behaviour::batch_size`]. Each descends to its
+    /// leaf as [`select_expand_simulate_backpropagate`](Self::select_expand_simulate_backpropagate)
+    /// does, and leaves a virtual loss on the line to it, which steers the later descents
+    /// of the batch onto other lines. With a network, the leaves are then valued, and the
+    /// nodes expanded on the way given their priors, by one run of the network on all of
+    /// them. Otherwise, each leaf is valued by its rollouts. Last, the result of each leaf
+    /// is backpropagated, in the order the leaves were reached.
+    fn batched_iteration(&mut self, root: &G, batch: usize) {
+        let mut lap = self.phase_times.is_some().then(Instant::now);
+        self.deferred_expansions = self.network.is_some().then(Vec::new);
+        // the leaves of the last batch lend their states to this one.
+        let mut leaves = std::mem::take(&mut self.context.leaves);
+        leaves.truncate(batch);
+        for i in 0..batch {
+            if i == leaves.len() {
+                leaves.push(Leaf {
+                    idx: ROOT_IDX,
+                    depth: 0,
+                    state: root.clone(),
+                    value: None,
+                });
+            }
+            let leaf = &mut leaves[i];
+            let (idx, depth, next_lap) = self.descend(root, &mut leaf.state, lap);
+            self.tree.add_virtual_loss(idx);
+            (leaf.idx, leaf.depth) = (idx, depth);
+            leaf.value = self.terminal_value(idx, &leaf.state);
+            lap = next_lap;
+        }
+
+        if let (Some(network), Some(deferred)) =
+            (self.network.clone(), self.deferred_expansions.take())
+        {
+            let states = deferred
+                .iter()
+                .map(|(_, state)| state.clone())
+                .chain(
+                    leaves
+                        .iter()
+                        .filter(|leaf| leaf.value.is_none())
+                        .map(|leaf| leaf.state.clone()),
+                )
+                .collect::<Vec<_>>();
+            let evaluations = Self::network_evaluations(&network, &states);
+            let (priors, values) = evaluations.split_at(deferred.len());
+            for ((idx, _), evaluation) in deferred.iter().zip(priors) {
+                Self::set_priors(&mut self.tree, *idx, evaluation);
+            }
+            let unknown = leaves.iter_mut().filter(|leaf| leaf.value.is_none());
+            for (leaf, evaluation) in unknown.zip(values) {
+                let q = evaluation.value * f32::from(leaf.state.turn());
+                leaf.value = Some((self.add_noise(q, self.tree[leaf.idx].visits()), 0.0));
+            }
+            lap = self.lap(lap, Phase::Simulate);
+        }
+
+        let minimax_depth = self.search_info.flags.minimax_depth;
+        for leaf in &mut leaves {
+            let visits = self.tree[leaf.idx].visits();
+            let (q, plies) = leaf
+                .value
+                .unwrap_or_else(|| self.playout(&mut leaf.state, visits));
+            lap = self.lap(lap, Phase::Simulate);
+            self.tree.remove_virtual_loss(leaf.idx);
+            Self::backprop(
+                leaf.idx,
+                leaf.depth,
+                q,
+                plies,
+                minimax_depth,
+                &self.tree,
+            );
+            // the moves of a rollout are credited before the next rollout is played.
+            if self.search_info.flags.rave_bias.is_some() {
+                self.amaf.backprop(&mut self.tree, leaf.idx, q);
+            }
+            self.tree.inc_rollouts();
+            lap = self.lap(lap, Phase::Backprop);
+        }
+        self.context.leaves = leaves;
+    }
+
+    /// The number of iterations to run together in the next batch of a search on one thread,
+    /// which is [`Behaviour::batch_size`], unless fewer rollouts are left under the limit.
+    fn next_batch(&self) -> usize {
+        let batch = self.search_info.flags.batch_size;
+        match self.search_info.flags.limit {
+            Limit::Rollouts(max_rollouts) => {
+                let left = max_rollouts.saturating_sub(self.tree.rollouts());
+                batch.min(usize::try_from(left).unwrap_or(usize::MAX))
+            }
+            Limit::Time(_) | Limit::Infinite => batch,
+        }
+    }
+
+    /// The first half of an iteration, selection and expansion: walks `traversing_state`,
+    /// made a copy of `root`, down the tree to the node that the rollout of the iteration
+    /// starts from, expanding the leaf it selects. Returns the node, its depth below the
+    /// root, and the start of the next lap of the profile.
+    fn descend(
+        &mut self,
+        root: &G,
+        traversing_state: &mut G,
+        lap: Option<Instant>,
+    ) -> (usize, usize, Option<Instant>) {
+        traversing_state.clone_from(root);
+        self.note_copy();
+
+        let (promising_node_idx, depth) = Self::select(
+            ROOT_IDX,
+            &self.tree,
+            &self.search_info,
+            self.root_policy.as_deref(),
+            self.root_excluded.as_deref(),
+            traversing_state,
+            &mut self.context.policies,
+        );
+        let lap = self.lap(lap, Phase::Select);
+
+        let (node_to_explore, depth) = self.grow(promising_node_idx, depth, traversing_state);
+        (node_to_explore, depth, self.lap(lap, Phase::Expand))
+    }
+
+    /// Whether [`grow`](Self::grow) would change the tree at the leaf `idx`, whose state is
+    /// `state`: by expanding it, by proving it terminal, or by following forced moves from it.
+    fn can_grow(&self, tree: &SearchTree<G>, idx: usize, state: &G) -> bool {
+        let flags = &self.search_info.flags;
+        let ready_to_expand = idx == ROOT_IDX
+            || tree[idx].visits() >= u64::from(flags.expansion_threshold);
+        tree[idx].terminal_outcome().is_none()
+            && (ready_to_expand || flags.tactical_expansion || state.is_terminal())
+    }
+
+    /// Expands the leaf `idx` that selection reached at `depth`, if it is ready to be, and
+    /// steps `state`, its state, on to a random child of it. Returns the node that the
+    /// rollout of the iteration starts from, and its depth.
+    fn grow(&mut self, idx: usize, depth: usize, state: &mut G) -> (usize, usize) {
+        let mut depth = depth;
+        let ready_to_expand = idx == ROOT_IDX
+            || self.tree[idx].visits() >= u64::from(self.search_info.flags.expansion_threshold);
+        // a node whose state was found to be terminal is not looked at again.
+        let known_terminal = self.tree[idx].terminal_outcome().is_some();
+        // in a parallel search, another thread may have expanded the leaf since it was selected.
+        let leaf = !self.tree[idx].has_children();
+        if ready_to_expand && !known_terminal && leaf && !state.is_terminal() {
+            // a full pool leaves the node a leaf, to be valued by rollouts alone.
+            self.expand(idx, state);
+        }
+
+        let promising_node = self.tree.get(idx).unwrap();
+        let mut node_to_explore = if promising_node.has_children() {
+            let child_idx = promising_node.random_child(&self.rng);
+            state.push(self.tree[child_idx].inbound_edge());
+            depth += 1;
+            child_idx
+        } else {
+            idx
+        };
+        if self.search_info.flags.tactical_expansion {
+            (node_to_explore, depth) = self.follow_forced_moves(node_to_explore, depth, state);
+        }
+        self.tree.record_depth(depth);
+        (node_to_explore, depth)
+    }
+
+    /// One iteration of a parallel search, by a worker whose own tree is empty, on the tree
+    /// shared in `pool`. Selection and backpropagation run on the shared tree alongside those
+    /// of the other workers, updating the statistics of its nodes atomically, and the rollout
+    /// in between does not touch the tree. Only a change to the structure of the tree, as an
+    /// expansion, takes it for this worker alone. Until the result is backpropagated, the line
+    /// of the iteration carries a virtual loss, which steers the other workers onto other lines.
+    fn parallel_iteration(&mut self, pool: &RwLock<Box<SearchTree<G>>>, root: &G, state: &mut G) {
+        let lap = self.phase_times.is_some().then(Instant::now);
+        let tree = pool.read().unwrap();
+        let flags = &self.search_info.flags;
+        if flags.root_pruning {
+            Self::prune_root(&tree, flags.limit, &mut self.root_excluded);
+        }
+        state.clone_from(root);
+        self.note_copy();
+        let (leaf, depth) = Self::select(
+            ROOT_IDX,
+            &tree,
+            &self.search_info,
+            self.root_policy.as_deref(),
+            self.root_excluded.as_deref(),
+            state,
+            &mut self.context.policies,
+        );
+        let lap = self.lap(lap, Phase::Select);
+        // the tree is only taken for this worker alone if the iteration changes it.
+        let grows = self.can_grow(&tree, leaf, state);
+        if !grows {
+            tree.add_virtual_loss(leaf);
+            tree.record_depth(depth);
+        }
+        drop(tree);
+        let (leaf, depth, known, visits) = if grows {
+            self.with_tree(pool, |this| {
+                let (leaf, depth) = this.grow(leaf, depth, state);
+                this.tree.add_virtual_loss(leaf);
+                let known = this.terminal_value(leaf, state);
+                (leaf, depth, known, this.tree[leaf].visits())
+            })
+        } else {
+            let tree = pool.read().unwrap();
+            let known = tree[leaf].terminal_outcome().map(|outcome| (f32::from(outcome), 0.0));
+            (leaf, depth, known, tree[leaf].visits())
+        };
+        let lap = self.lap(lap, Phase::Expand);
+
+        let (q, plies) = known.unwrap_or_else(|| self.playout(state, visits));
+        let lap = self.lap(lap, Phase::Simulate);
+
+        let minimax_depth = self.search_info.flags.minimax_depth;
+        let tree = pool.read().unwrap();
+        tree.remove_virtual_loss(leaf);
+        Self::backprop(leaf, depth, q, plies, minimax_depth, &tree);
+        drop(tree);
+        if self.search_info.flags.rave_bias.is_some() {
+            self.with_tree(pool, |this| this.amaf.backprop(&mut this.tree, leaf, q));
+        }
+        pool.read().unwrap().inc_rollouts();
+        self.lap(lap, Phase::Backprop);
+    }
+
+    /// Runs `f` with the tree shared in `pool` swapped in for this engine's own,
+    /// holding the pool for itself until it returns.
+    fn with_tree<T>(
+        &mut self,
+        pool: &RwLock<Box<SearchTree<G>>>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let mut tree = pool.write().unwrap();
+        std::mem::swap(&mut self.tree, &mut tree);
+        let result = f(self);
+        std::mem::swap(&mut self.tree, &mut tree);
+        result
+    }
+
+    /// EXPAND: Given a node and its state, adds a child for every legal move. With
+    /// [`Behaviour::tactical_expansion`], a child that wins on the spot for the player to move
+    /// is proven at once, which proves the node lost for whoever chose it, so that selection
+    /// takes the win without spending rollouts to find it.
+    /// Returns false, leaving the node a leaf, if the node pool is full.
+    fn expand(&mut self, idx: usize, board: &mut G) -> bool {
+        if !self.tree.expand(idx, board) {
+            self.skipped_expansions += 1;
+            return false;
+        }
+        if let Some(times) = &mut self.phase_times {
+            times.add_expansion();
+        }
+        if let Some(network) = &self.network {
+            // the nodes expanded by a batch take their priors when the batch is evaluated.
+            if let Some(deferred) = &mut self.deferred_expansions {
+                deferred.push((idx, board.clone()));
+            } else {
+                let evaluations = Self::network_evaluations(network, std::slice::from_ref(board));
+                Self::set_priors(&mut self.tree, idx, &evaluations[0]);
+            }
+        }
+        if !self.search_info.flags.tactical_expansion {
+            return true;
+        }
+        let mover = board.turn();
+        for child in self.tree[idx].children() {
+            let m = self.tree[child].inbound_edge();
+            // in misère games, ending the game can lose, and such moves are not wins.
+            if Self::evaluate_after(&mut self.phase_times, board, m) == mover {
+                // one win is enough to decide the node.
+                self.tree.prove_terminal(child, mover);
+                break;
+            }
+        }
+        true
+    }
+
+    /// What `network` makes of each of `states`.
+    fn network_evaluations(network: &Network, states: &[G]) -> Vec<Evaluation> {
+        G::network_evaluations(states, network)
+            .expect("the model was checked when the engine was made")
+    }
+
+    /// Gives the children of the expanded node `idx` their priors from `evaluation`,
+    /// which is of its state.
+    fn set_priors(tree: &mut SearchTree<G>, idx: usize, evaluation: &Evaluation) {
+        for (child, &prior) in tree[idx].children().zip(&evaluation.priors) {
+            tree[child].set_prior(prior);
+        }
+    }
+
+    /// While the state of the unexpanded node `idx`, at `depth` below the root, has exactly
+    /// one legal move, expands it and plays the move, so that a forced line collapses into
+    /// the rollout that reached it rather than taking one rollout per move. `state` is
+    /// left at the end of the line. Returns the node at the end of the line, and its depth.
+    fn follow_forced_moves(&mut self, idx: usize, depth: usize, state: &mut G) -> (usize, usize) {
+        let mut idx = idx;
+        let mut depth = depth;
+        while !self.tree[idx].has_children()
+            && self.tree[idx].terminal_outcome().is_none()
+            && !state.is_terminal()
+        {
+            self.context.move_buffer.clear();
+            state.legal_moves(&mut self.context.move_buffer);
+            if self.context.move_buffer.len() != 1 || !self.expand(idx, state) {
+                break;
+            }
+            idx = self.tree.nth_child(idx, 0);
+            state.push(self.tree[idx].inbound_edge());
+            depth += 1;
+        }
+        (idx, depth)
+    }
+
+    /// BACKPROPAGATE: Given a node at `depth` below the root, a Q-value, and the length of
+    /// the rollout from the node, backpropagate both up the tree. Nodes less than `minimax_depth`
+    /// below the root take the value of their best child instead of the mean of the values
+    /// backpropagated through them. With transpositions, a node's result goes to the node it
+    /// shares its statistics with, and the node takes them back.
+    #[inline(never)]
+    fn backprop(
+        node_idx: usize,
+        depth: usize,
+        q: f32,
+        plies: f32,
+        minimax_depth: usize,
+        tree: &SearchTree<G>,
+    ) {
+        let mut idx = node_idx;
+        let mut depth = depth;
+        let mut plies = plies;
+        loop {
+            let representative = tree.representative(idx);
+            let node = tree
+                .get(representative)
+                .expect("called backprop on a missing node");
+            node.update(q);
+            node.record_length(plies);
+            if representative != idx {
+                tree[idx].share_stats(node);
+            }
+            plies += 1.0;
+            if depth < minimax_depth {
+                // children are chosen by the other player, so the best child is worst for us.
+                if let Some(best) = tree.best_child_value(idx) {
+                    tree[idx].set_minimax_value(1.0 - best);
+                }
+            }
+            let Some(parent_idx) = tree[idx].parent() else {
+                break;
+            };
+            idx = parent_idx;
+            depth = depth.saturating_sub(1);
+        }
+    }
+
+    /// SIMULATE: Given a node, simulate the game from that node, and return the resulting Q-value
+    /// and the number of plies the rollout took, averaged over the rollouts of [`MetaAggregated`].
+    ///
+    /// [`MetaAggregated`]: RolloutPolicy::MetaAggregated
+    #[inline(never)]
+    fn simulate(&mut self, node_idx: usize, rollout_board: &mut G) -> (f32, f32) {
+        if let Some(value) = self.terminal_value(node_idx, rollout_board) {
+            return value;
+        }
+        let visits = self.tree[node_idx].visits();
+        self.playout(rollout_board, visits)
+    }
+
+    /// If the state `board` of the node `node_idx` is terminal, proves the node and returns
+    /// its value, as [`simulate`](Self::simulate) does, without a rollout.
+    fn terminal_value(&mut self, node_idx: usize, board: &G) -> Option<(f32, f32)> {
+        // terminal states have a known value, which the solver can propagate up the tree.
+        if let Some(outcome) = self.tree[node_idx].terminal_outcome() {
+            return Some((f32::from(outcome), 0.0));
+        }
+        if board.is_terminal() {
+            let status = board.evaluate();
+            self.tree.prove_terminal(node_idx, status);
+            return Some((f32::from(status), 0.0));
+        }
+        None
+    }
+
+    /// Plays the rollouts of [`simulate`](Self::simulate) from `rollout_board`, the state of
+    /// a node with `visits` visits, which does not touch the tree.
+    fn playout(&mut self, rollout_board: &mut G, visits: u64) -> (f32, f32) {
+        use RolloutPolicy::{
+            Decisive, DecisiveCutoff, DecisiveQualityScaled, Greedy, Heuristic, History,
+            MetaAggregated, Random, RandomCutoff, RandomQualityScaled,
+        };
+        self.rollout_plies = 0;
+        if let Some(times) = &mut self.phase_times {
+            times.add_rollout();
+        }
+        // a network values the leaf for the side to move, in place of a rollout.
+        if let Some(network) = &self.network {
+            let evaluations =
+                Self::network_evaluations(network, std::slice::from_ref(rollout_board));
+            let q = evaluations[0].value * f32::from(rollout_board.turn());
+            return (self.add_noise(q, visits), 0.0);
+        }
+
+        // playout
+        let q = match &self.search_info.flags.rollout_policy {
+            Random => self.random_rollout(rollout_board),
+            Decisive => self.decisive_rollout(rollout_board),
+            RandomQualityScaled => self.random_rollout_qs(rollout_board),
+            DecisiveQualityScaled => self.decisive_rollout_qs(rollout_board),
+            RandomCutoff { moves } => self.random_rollout_cutoff(rollout_board, *moves),
+            DecisiveCutoff { moves } => self.decisive_rollout_cutoff(rollout_board, *moves),
+            History => self.history_rollout(rollout_board),
+            Greedy { random_percent } => self.greedy_rollout(rollout_board, *random_percent),
+            Heuristic => self.heuristic_rollout(rollout_board),
+            MetaAggregated { policy, rollouts } => {
+                let rollouts = *rollouts;
+                let f = match policy.as_ref() {
+                    RolloutPolicy::Random => Self::random_rollout,
+                    RolloutPolicy::Decisive => Self::decisive_rollout,
+                    RolloutPolicy::RandomQualityScaled => Self::random_rollout_qs,
+                    RolloutPolicy::DecisiveQualityScaled => Self::decisive_rollout_qs,
+                    RolloutPolicy::History => Self::history_rollout,
+                    RolloutPolicy::RandomCutoff { .. } => {
+                        panic!("MetaAggregated policy cannot be RandomCutoff")
+                    }
+                    RolloutPolicy::DecisiveCutoff { .. } => {
+                        panic!("MetaAggregated policy cannot be DecisiveCutoff")
+                    }
+                    RolloutPolicy::Greedy { .. } => {
+                        panic!("MetaAggregated policy cannot be Greedy")
+                    }
+                    RolloutPolicy::Heuristic => {
+                        panic!("MetaAggregated policy cannot be Heuristic")
+                    }
+                    RolloutPolicy::MetaAggregated { .. } => {
+                        panic!("MetaAggregated policy must be a RolloutPolicy")
+                    }
+                };
+                let mut sum = 0.0;
+                let mut roller = rollout_board.clone();
+                for _ in 0..rollouts {
+                    roller.clone_from(rollout_board);
+                    self.note_copy();
+                    sum += f(self, &mut roller);
+                }
+                sum / (rollouts as f32)
+            }
+        };
+        let rollouts = match &self.search_info.flags.rollout_policy {
+            MetaAggregated { rollouts, .. } => (*rollouts).max(1),
+            _ => 1,
+        };
+        let plies = self.rollout_plies as f32 / rollouts as f32;
+        (self.add_noise(q, visits), plies)
+    }
+
+    /// Perturbs the rollout result `q`, from a node with `visits` visits,
+    /// with [`Behaviour::value_noise`], keeping it in [-1, 1].
+    fn add_noise(&self, q: f32, visits: u64) -> f32 {
+        let flags = &self.search_info.flags;
+        if flags.value_noise == 0.0 {
+            return q;
+        }
+        let mut sigma = flags.value_noise;
+        if flags.anneal_noise {
+            sigma /= (1.0 + visits as f32).sqrt();
+        }
+        // Box-Muller, taking care that the logarithm is of a positive number.
+        let u1 = 1.0 - self.rng.f32();
+        let u2 = self.rng.f32();
+        let normal = (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+        sigma.mul_add(normal, q).clamp(-1.0, 1.0)
+    }
+
+    /// SELECT: we traverse the on-policy (in-memory) part of the tree, at each node we select the child
+    /// with the highest UCB1 value. As we do not store states in the tree, we have to push
+    /// moves as we go. `root_policy`, if present, replaces the game's policy at the root,
+    /// and `root_excluded`, if present, marks root children that must not be selected.
+    /// With transpositions, the children of each node are brought up to date before one is
+    /// chosen. Returns the index of the selected node, and its depth below `root_idx`.
+    #[inline(never)]
+    fn select(
+        root_idx: usize,
+        tree: &SearchTree<G>,
+        search_info: &SearchInfo,
+        root_policy: Option<&[f32]>,
+        root_excluded: Option<&[bool]>,
+        state: &mut G,
+        policies: &mut Vec<f32>,
+    ) -> (usize, usize) {
+        let mut idx = root_idx;
+        let mut depth = 0;
+        while tree[idx].has_children() {
+            tree.share_child_stats(idx);
+            let node = &tree[idx];
+            let (priors, excluded) = if idx == root_idx {
+                (root_policy, root_excluded)
+            } else {
+                (None, None)
+            };
+            // the parent's value is from the perspective of the player who chose it,
+            // so the side choosing a child values it at one minus that.
+            let fpu = search_info.flags.fpu_reduction.map(|reduction| ucb::Fpu {
+                parent_value: 1.0 - node.q(),
+                reduction,
+            });
+            let scoring = ucb::Scoring {
+                selection: search_info.flags.selection,
+                exp_factor: search_info.flags.exp_factor,
+                cpuct: ucb::Cpuct {
+                    base: search_info.flags.cpuct_base,
+                    factor: search_info.flags.cpuct_factor,
+                },
+                fpu,
+                rave_bias: search_info.flags.rave_bias,
+            };
+            idx = tree.select_child(idx, |children| {
+                ucb::best(
+                    state,
+                    children,
+                    node.selection_stats().0,
+                    priors,
+                    excluded,
+                    scoring,
+                    policies,
+                )
+            });
+            state.push(tree[idx].inbound_edge());
+            depth += 1;
+        }
+        (idx, depth)
+    }
+
+    /// The random rollout policy.
+    /// Simply plays random moves until the game ends,
+    /// then returns the result as 1.0 / 0.0 / -1.0.
+    fn random_rollout(&mut self, playout_board: &mut G) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut plies = 0;
+        while !playout_board.is_terminal() && plies < cap {
+            playout_board.push_random_or_pass(&mut self.rng);
+            self.note_ply(playout_board);
+            plies += 1;
+        }
+        Self::rollout_result(playout_board)
+    }
+
+    /// The result of a finished rollout, where one that was cut off by
+    /// [`Behaviour::max_rollout_plies`] before the game ended counts as a draw.
+    fn rollout_result(playout_board: &G) -> f32 {
+        if playout_board.is_terminal() {
+            f32::from(playout_board.evaluate())
+        } else {
+            0.0
+        }
+    }
+
+    /// The history rollout policy.
+    /// Moves are sampled with a preference for those that have done well for the player
+    /// making them in earlier rollouts of this search.
+    fn history_rollout(&mut self, playout_board: &mut G) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut plies = 0;
+        while !playout_board.is_terminal() && plies < cap {
+            self.context.move_buffer.clear();
+            playout_board.legal_moves(&mut self.context.move_buffer);
+            let turn = playout_board.turn();
+            let moves = self.context.move_buffer.iter().map(|&m| G::move_index(m));
+            let choice = self.history.choose(turn, moves, &self.rng);
+            let m = self.context.move_buffer[choice];
+            self.history.note(turn, G::move_index(m));
+            playout_board.push(m);
+            self.note_ply(playout_board);
+            plies += 1;
+        }
+        let result = Self::rollout_result(playout_board);
+        self.history.finish(result);
+        result
+    }
+
+    /// The heuristic rollout policy.
+    /// The value of the position is the game's static evaluation, clamped to [-1, 1], and the
+    /// game is only played out if it has none.
+    fn heuristic_rollout(&mut self, playout_board: &mut G) -> f32 {
+        playout_board.heuristic_eval().map_or_else(
+            || self.random_rollout(playout_board),
+            |value| value.clamp(-1.0, 1.0),
+        )
+    }
+
+    /// The greedy rollout policy.
+    /// Plays the move with the highest [`MCTSExt::rollout_priority`], breaking ties at random,
+    /// except that a uniformly random move is played `random_percent`% of the time.
+    fn greedy_rollout(&mut self, playout_board: &mut G, random_percent: usize) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut plies = 0;
+        while !playout_board.is_terminal() && plies < cap {
+            plies += 1;
+            if self.rng.usize(..100) < random_percent {
+                playout_board.push_random_or_pass(&mut self.rng);
+                self.note_ply(playout_board);
+                continue;
+            }
+            self.context.move_buffer.clear();
+            playout_board.legal_moves(&mut self.context.move_buffer);
+            let mut best_priority = i32::MIN;
+            let mut chosen = 0;
+            let mut ties = 0;
+            for (i, &m) in self.context.move_buffer.iter().enumerate() {
+                let priority = playout_board.rollout_priority(m);
+                if priority > best_priority {
+                    best_priority = priority;
+                    chosen = i;
+                    ties = 1;
+                } else if priority == best_priority {
+                    // reservoir sampling, so that every tied move is equally likely.
+                    ties += 1;
+                    if self.rng.usize(..ties) == 0 {
+                        chosen = i;
+                    }
+                }
+            }
+            playout_board.push(self.context.move_buffer[chosen]);
+            self.note_ply(playout_board);
+        }
+        Self::rollout_result(playout_board)
+    }
+
+    /// Weights a rollout result by the quality of the rollout, by [`Behaviour::quality_scaling`],
+    /// where rollouts that end more quickly are considered better, as they should be
+    /// more representative of the quality of the position they arose from.
+    fn scale(&self, q: f32, moves: f32) -> f32 {
+        self.search_info.flags.quality_scaling.scale(q, moves)
+    }
+
+    /// A quality-scaled version of [`random_rollout`](Self::random_rollout).
+    fn random_rollout_qs(&mut self, playout_board: &mut G) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut moves = 1;
+        while !playout_board.is_terminal() && moves <= cap {
+            playout_board.push_random_or_pass(&mut self.rng);
+            self.note_ply(playout_board);
+            moves += 1;
+        }
+        let q = Self::rollout_result(playout_board);
+        self.scale(q, moves as f32)
+    }
+
+    /// A single step of a decisive rollout.
+    /// If there is a move that wins on the spot, returns the resulting evaluation.
+    /// Otherwise, plays a random move and returns `None`.
+    fn decisive_step(&mut self, playout_board: &mut G) -> Option<i8> {
+        self.context.move_buffer.clear();
+        playout_board.legal_moves(&mut self.context.move_buffer);
+        let mover = playout_board.turn();
+        for &m in self.context.move_buffer.iter() {
+            // in misère games, ending the game can lose, and such moves are no shortcut.
+            let evaluation = Self::evaluate_after(&mut self.phase_times, playout_board, m);
+            if evaluation == mover {
+                self.note_move(mover, Some(m));
+                return Some(evaluation);
+            }
+        }
+        let idx = self.rng.usize(..self.context.move_buffer.len());
+        playout_board.push(self.context.move_buffer[idx]);
+        self.note_ply(playout_board);
+        None
+    }
+
+    /// The decisive rollout policy.
+    /// In each position, if there is a move that wins on the spot, we play that move.
+    /// Otherwise, we play a random move.
+    fn decisive_rollout(&mut self, playout_board: &mut G) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut plies = 0;
+        while !playout_board.is_terminal() && plies < cap {
+            if let Some(evaluation) = self.decisive_step(playout_board) {
+                return f32::from(evaluation);
+            }
+            plies += 1;
+        }
+        Self::rollout_result(playout_board)
+    }
+
+    /// A quality-scaled version of [`decisive_rollout`](Self::decisive_rollout).
+    fn decisive_rollout_qs(&mut self, playout_board: &mut G) -> f32 {
+        let cap = self.search_info.flags.max_rollout_plies;
+        let mut moves = 1;
+        while !playout_board.is_terminal() && moves <= cap {
+            if let Some(evaluation) = self.decisive_step(playout_board) {
+                return f32::from(evaluation) / (moves as f32 + 10.0) * 10.0;
+            }
+            moves += 1;
+        }
+        let q = Self::rollout_result(playout_board);
+        self.scale(q, moves as f32)
+    }
+
+    /// A cutoff version of [`random_rollout`](Self::random_rollout).
+    /// This policy will stop rollouts after a fixed number of moves,
+    /// returning a Q-value of 0.0.
+    fn random_rollout_cutoff(&mut self, playout_board: &mut G, moves: usize) -> f32 {
+        let mut counter = 1;
+        while !playout_board.is_terminal() {
+            if counter > moves {
+                return 0.0;
+            }
+            playout_board.push_random_or_pass(&mut self.rng);
+            self.note_ply(playout_board);
+            counter += 1;
+        }
+        f32::from(playout_board.evaluate())
+    }
+
+    /// A cutoff version of [`decisive_rollout`](Self::decisive_rollout).
+    /// This policy will stop rollouts after a fixed number of moves,
+    /// returning a Q-value of 0.0.
+    fn decisive_rollout_cutoff(&mut self, playout_board: &mut G, moves: usize) -> f32 {
+        let mut counter = 1;
+        while !playout_board.is_terminal() {
+            if counter > moves {
+                return 0.0;
+            }
+            if let Some(evaluation) = self.decisive_step(playout_board) {
+                return f32::from(evaluation);
+            }
+            counter += 1;
+        }
+        f32::from(playout_board.evaluate())
+    }
+}
+
+/// Samples the index of a root move from `distribution`, the rollouts of each root move,
+/// with probability in proportion to its rollouts raised to the power of one over
+/// [`TRAINING_TEMPERATURE`].
+#[allow(clippy::cast_precision_loss)]
+fn sample_move_index_from_rollouts(distribution: &[u64], rng: &fastrand::Rng) -> usize {
+    let weights = distribution
+        .iter()
+        .map(|&count| (count as f64).powf(TRAINING_TEMPERATURE.recip()))
+        .collect::<Vec<_>>();
+    let mut remaining = rng.f64() * weights.iter().sum::<f64>();
+    for (i, &weight) in weights.iter().enumerate() {
+        if remaining < weight {
+            return i;
+        }
+        remaining -= weight;
+    }
+    // rounding can leave a sliver past the last weight.
+    weights.iter().rposition(|&weight| weight > 0.0).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ArrayBuffer;
+
+    /// A race to three points, where the second player has no move on their first turn.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    struct PassRace {
+        points: [u8; 2],
+        ply: u8,
+    }
+
+    /// Scores `.0` points, or passes if zero.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Points(u8);
+
+    impl Display for Points {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            if self.0 == 0 {
+                write!(f, "pass")
+            } else {
+                write!(f, "{}", self.0)
+            }
+        }
+    }
+
+    impl Display for PassRace {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}-{}", self.points[0], self.points[1])
+        }
+    }
+
+    impl Game for PassRace {
+        type Move = Points;
+        type Buffer = ArrayBuffer<Points, 2>;
+
+        const MOVE_SPACE: usize = 3;
+
+        fn turn(&self) -> i8 {
+            if self.ply.is_multiple_of(2) {
+                1
+            } else {
+                -1
+            }
+        }
+
+        fn generate_moves(&self, moves: &mut Self::Buffer) {
+            assert!(!self.must_pass(), "moves generated for a player who must pass");
+            moves.push(Points(1));
+            moves.push(Points(2));
+        }
+
+        fn is_terminal(&self) -> bool {
+            self.points.iter().any(|&p| p >= 3)
+        }
+
+        fn evaluate(&self) -> i8 {
+            match self.points {
+                [p, _] if p >= 3 => 1,
+                [_, p] if p >= 3 => -1,
+                _ => 0,
+            }
+        }
+
+        fn push(&mut self, m: Self::Move) {
+            self.points[usize::from(self.ply % 2)] += m.0;
+            self.ply += 1;
+        }
+
+        fn push_random(&mut self, rng: &mut fastrand::Rng) {
+            self.push(Points(rng.u8(1..=2)));
+        }
+
+        fn hash(&self) -> u64 {
+            u64::from(self.ply) << 16 | u64::from(self.points[0]) << 8 | u64::from(self.points[1])
+        }
+
+        fn move_index(m: Self::Move) -> usize {
+            usize::from(m.0)
+        }
+
+        fn must_pass(&self) -> bool {
+            self.ply == 1
+        }
+
+        fn pass_move() -> Self::Move {
+            Points(0)
+        }
+    }
+
+    impl MCTSExt for PassRace {}
+
+    /// Subtraction Nim: each player takes one to three stones from a pile, and whoever takes
+    /// the last stone wins, so a player to move wins exactly when the pile is not a multiple of 4.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Nim {
+        stones: u8,
+        ply: u8,
+    }
+
+    impl Default for Nim {
+        fn default() -> Self {
+            Self { stones: 10, ply: 0 }
+        }
+    }
+
+    /// Takes `.0` stones.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Take(u8);
+
+    impl Display for Take {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl Display for Nim {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{} stones", self.stones)
+        }
+    }
+
+    impl Game for Nim {
+        type Move = Take;
+        type Buffer = ArrayBuffer<Take, 3>;
+
+        const MOVE_SPACE: usize = 4;
+
+        fn turn(&self) -> i8 {
+            if self.ply.is_multiple_of(2) {
+                1
+            } else {
+                -1
+            }
+        }
+
+        fn generate_moves(&self, moves: &mut Self::Buffer) {
+            for stones in 1..=self.stones.min(3) {
+                moves.push(Take(stones));
+            }
+        }
+
+        fn is_terminal(&self) -> bool {
+            self.stones == 0
+        }
+
+        fn evaluate(&self) -> i8 {
+            // the player who took the last stone is the one not to move.
+            if self.is_terminal() {
+                -self.turn()
+            } else {
+                0
+            }
+        }
+
+        fn push(&mut self, m: Self::Move) {
+            self.stones -= m.0;
+            self.ply += 1;
+        }
+
+        fn push_random(&mut self, rng: &mut fastrand::Rng) {
+            self.push(Take(rng.u8(1..=self.stones.min(3))));
+        }
+
+        fn hash(&self) -> u64 {
+            u64::from(self.ply) << 8 | u64::from(self.stones)
+        }
+
+        fn move_index(m: Self::Move) -> usize {
+            usize::from(m.0)
+        }
+    }
+
+    impl MCTSExt for Nim {}
+
+    /// Checks the structure of `tree` after a search of `rollouts` rollouts from its root:
+    /// every child range lies after its parent and within the arena, every child points back
+    /// at its parent, no node has fewer visits than its children together, and the root has
+    /// been visited once per rollout.
+    fn assert_tree_invariants<G: Game>(tree: &SearchTree<G>, rollouts: u64) {
+        assert_eq!(tree.rollouts(), rollouts);
+        assert_eq!(tree.root().visits(), rollouts);
+        assert_eq!(tree.root().parent(), None);
+        let mut has_parent = vec![false; tree.nodes.len()];
+        for (idx, node) in tree.nodes.iter().enumerate() {
+            assert!(
+                (0.0..=1.0).contains(&node.win_rate()),
+                "node {idx} has value {}",
+                node.win_rate()
+            );
+            if !node.has_children() {
+                continue;
+            }
+            let children = node.children();
+            assert!(children.start > idx, "node {idx} has children {children:?} before it");
+            assert!(
+                children.end <= tree.nodes.len(),
+                "node {idx} has children {children:?} past the end"
+            );
+            let mut child_visits = 0;
+            for child in children {
+                assert_eq!(tree[child].parent(), Some(idx), "child {child} of node {idx}");
+                assert_eq!(tree[child].to_move(), -node.to_move(), "child {child} of node {idx}");
+                assert!(!has_parent[child], "node {child} is the child of two nodes");
+                has_parent[child] = true;
+                child_visits += tree[child].visits();
+            }
+            assert!(
+                node.visits() >= child_visits,
+                "node {idx} has {} visits, and its children {child_visits}",
+                node.visits()
+            );
+        }
+        // every node but the root hangs from the tree.
+        assert!(has_parent.iter().skip(1).all(|&it| it));
+    }
+
+    #[test]
+    fn trees_keep_their_invariants() {
+        use crate::games::{connectfour::Connect4, tictactoe::TicTacToe};
+        let behaviour = Behaviour {
+            readout: false,
+            limit: Limit::Rollouts(3000),
+            hash_mb: 16,
+            deterministic: true,
+            ..Behaviour::default()
+        };
+        let variations = [
+            behaviour.clone(),
+            Behaviour {
+                minimax_depth: 2,
+                fpu_reduction: Some(0.2),
+                ..behaviour.clone()
+            },
+            Behaviour {
+                expansion_threshold: 3,
+                root_pruning: true,
+                rollout_policy: RolloutPolicy::Decisive,
+                ..behaviour.clone()
+            },
+            Behaviour {
+                root_symmetry: true,
+                rollout_policy: RolloutPolicy::MetaAggregated {
+                    policy: Box::new(RolloutPolicy::Random),
+                    rollouts: 3,
+                },
+                ..behaviour
+            },
+        ];
+        for behaviour in &variations {
+            let mut engine = MCTS::<Nim>::new(behaviour);
+            let results = engine.search(&Nim::default());
+            assert_tree_invariants(&engine.tree, 3000);
+            // the tree is rebuilt for the next position.
+            engine.search(&results.new_node);
+            assert_tree_invariants(&engine.tree, 3000);
+
+            let mut engine = MCTS::<TicTacToe>::new(behaviour);
+            engine.search(&TicTacToe::new());
+            assert_tree_invariants(&engine.tree, 3000);
+
+            let mut engine = MCTS::<Connect4>::new(behaviour);
+            engine.search(&Connect4::new());
+            assert_tree_invariants(&engine.tree, 3000);
+        }
+    }
+
+    #[test]
+    fn nim_is_solved() {
+        let behaviour = Behaviour {
+            readout: false,
+            limit: Limit::Rollouts(5000),
+            hash_mb: 16,
+            deterministic: true,
+            ..Behaviour::default()
+        };
+        let mut engine = MCTS::<Nim>::new(&behaviour);
+        for stones in 1..=10 {
+            let results = engine.search(&Nim { stones, ply: 0 });
+            let winning = stones % 4 != 0;
+            if winning {
+                // taking the pile down to a multiple of 4 is the only winning move.
+                let winning_child = engine.tree.child_by_move(ROOT_IDX, Take(stones % 4));
+                assert_eq!(winning_child, Some(results.new_node_idx), "{stones} stones");
+                assert_eq!(engine.tree[results.new_node_idx].proof(), Proof::Win);
+            }
+            // the root is chosen by the player not to move.
+            let expected = if winning { Proof::Loss } else { Proof::Win };
+            assert_eq!(engine.tree.root().proof(), expected, "{stones} stones");
+        }
+    }
+
+    #[test]
+    fn threads_share_one_tree() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = Behaviour {
+            readout: false,
+            limit: Limit::Rollouts(3000),
+            hash_mb: 16,
+            threads: 4,
+            ..Behaviour::default()
+        };
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let results = engine.search(&Connect4::new());
+        // the workers stop at the limit between them, and leave no virtual losses behind.
+        assert_eq!(results.rollouts, 3000);
+        assert_tree_invariants(&engine.tree, 3000);
+        assert!(engine
+            .tree
+            .nodes
+            .iter()
+            .all(|node| node.selection_stats() == (node.visits(), node.q())));
+        assert!(engine.root_edges().iter().all(|&(_, visits, _)| visits > 0));
+
+        let parsed = "limit=rollouts:3000,rollout_policy=random,threads=4"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(parsed.threads, 4);
+        assert!("limit=rollouts:1,rollout_policy=random,threads=0"
+            .parse::<Behaviour>()
+            .is_err());
+        let mut engine = MCTS::<Nim>::new(&Behaviour {
+            hash_mb: 16,
+            ..parsed
+        });
+        let results = engine.search(&Nim { stones: 7, ply: 0 });
+        assert_eq!(results.best_move, Take(3));
+        assert_eq!(engine.tree.root().proof(), Proof::Loss);
+    }
+
+    #[test]
+    fn root_parallel_trees_are_fused() {
+        use crate::games::connectfour::Connect4;
+        let mut behaviour = "limit=rollouts:1001,rollout_policy=random,trees=4,merge=vote"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.root_parallelism_count, 4);
+        assert!("limit=rollouts:1,rollout_policy=random,trees=0"
+            .parse::<Behaviour>()
+            .is_err());
+        behaviour.hash_mb = 16;
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let nodes = 16 * 1024 * 1024 / std::mem::size_of::<Node<Connect4>>();
+        assert_eq!(engine.tree.capacity(), nodes / 4);
+        let results = engine.search(&Connect4::new());
+        // the trees share out the rollouts, and this engine's has the one left over.
+        assert_eq!(results.rollouts, 1001);
+        assert_eq!(results.rollout_distribution.iter().sum::<u64>(), 1001);
+        assert_tree_invariants(&engine.tree, 251);
+        assert_eq!(
+            engine.tree[results.new_node_idx].inbound_edge(),
+            results.best_move
+        );
+        assert!((0.0..=1.0).contains(&results.win_rate));
+
+        // a win proven by any of the trees is played.
+        let mut engine = MCTS::<Nim>::new(&behaviour);
+        let results = engine.search(&Nim { stones: 7, ply: 0 });
+        assert_eq!(results.best_move, Take(3));
+        assert!((results.win_rate - 1.0).abs() < f64::EPSILON);
+
+        // a time limit stops every tree, and a rollout limit smaller than the trees
+        // leaves the trees that would have none out.
+        behaviour.limit = Limit::Time(Duration::from_millis(50));
+        let results = MCTS::<Connect4>::new(&behaviour).search(&Connect4::new());
+        assert_eq!(
+            results.rollout_distribution.iter().sum::<u64>(),
+            results.rollouts
+        );
+        behaviour.limit = Limit::Rollouts(2);
+        let results = MCTS::<Connect4>::new(&behaviour).search(&Connect4::new());
+        assert_eq!(results.rollouts, 2);
+
+        // a stop command stops the trees that cannot see it, too.
+        let (tx, rx) = mpsc::channel();
+        let rx = Mutex::new(rx);
+        tx.send("stop".to_string()).unwrap();
+        behaviour.limit = Limit::Infinite;
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        engine.set_stdin(&rx);
+        let results = engine.search(&Connect4::new());
+        assert_eq!(
+            results.rollout_distribution.iter().sum::<u64>(),
+            results.rollouts
+        );
+    }
+
+    #[test]
+    fn forced_passes_are_searched() {
+        let behaviour = Behaviour {
+            readout: false,
+            limit: Limit::Rollouts(200),
+            hash_mb: 1,
+            ..Behaviour::default()
+        };
+        for policy in [RolloutPolicy::Random, RolloutPolicy::Decisive, RolloutPolicy::History] {
+            let mut engine = MCTS::<PassRace>::new(&Behaviour {
+                rollout_policy: policy,
+                ..behaviour.clone()
+            });
+            let results = engine.search(&PassRace::default());
+            assert_eq!(results.rollout_distribution.iter().sum::<u64>(), 200);
+            let after = results.new_node;
+            assert!(after.must_pass());
+            let pass = engine.search(&after);
+            assert_eq!(pass.best_move, Points(0));
+            assert_eq!(pass.rollout_distribution, [200]);
+        }
+    }
+
+    #[test]
+    fn new_game_forgets_the_last_search() {
+        let behaviour = Behaviour {
+            limit: Limit::Rollouts(100),
+            root_symmetry: true,
+            ..Behaviour::default()
+        };
+        let mut engine = MCTS::<crate::games::tictactoe::TicTacToe>::new(&behaviour);
+        engine.search(&crate::games::tictactoe::TicTacToe::new());
+        assert!(engine.root_classes.is_some());
+        engine.new_game();
+        assert!(engine.tree.nodes.is_empty() && engine.tree.root.is_none());
+        assert_eq!(engine.tree.rollouts(), 0);
+        assert!(engine.root_classes.is_none() && engine.search_info.start_time.is_none());
+    }
+
+    #[test]
+    fn value_noise_is_configurable() {
+        use crate::games::tictactoe::TicTacToe;
+        let behaviour = "limit=rollouts:10,rollout_policy=random,noise=0.3,anneal_noise"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert!((behaviour.value_noise - 0.3).abs() < 1e-6 && behaviour.anneal_noise);
+        assert!("limit=rollouts:10,rollout_policy=random,noise=-1"
+            .parse::<Behaviour>()
+            .is_err());
+        assert!("limit=rollouts:10,rollout_policy=random,colour=blue"
+            .parse::<Behaviour>()
+            .is_err());
+
+        let mut engine = MCTS::<TicTacToe>::new(&behaviour);
+        engine.search(&TicTacToe::new());
+        let noisy = (0..1000)
+            .map(|_| engine.add_noise(0.0, engine.tree.root().visits()))
+            .collect::<Vec<_>>();
+        assert!(noisy.iter().all(|q| (-1.0..=1.0).contains(q)));
+        assert!(noisy.iter().any(|&q| q != 0.0));
+        // the root has been visited ten times, so the annealed deviation is 0.3 / sqrt(11).
+        let variance = noisy.iter().map(|q| q * q).sum::<f32>() / 1000.0;
+        assert!(variance < 0.3 * 0.3 / 5.0, "{variance}");
+    }
+
+    #[test]
+    fn overshoot_is_measured_against_the_limit() {
+        use crate::games::connectfour::Connect4;
+        let mut behaviour = "limit=rollouts:300,rollout_policy=random"
+            .parse::<Behaviour>()
+            .unwrap();
+        // search threads do not change how many rollouts one search does.
+        behaviour.root_parallelism_count = 4;
+        let results = MCTS::<Connect4>::new(&behaviour).search(&Connect4::new());
+        assert_eq!(results.rollouts, 300);
+        assert!(results.overshoot.is_zero());
+
+        behaviour.limit = Limit::Time(Duration::from_millis(50));
+        let results = MCTS::<Connect4>::new(&behaviour).search(&Connect4::new());
+        assert_eq!(results.overshoot.rollouts, 0);
+        assert!(results.overshoot.time < Duration::from_secs(1), "{}", results.overshoot);
+    }
+
+    #[test]
+    fn rollouts_stop_at_the_ply_limit() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = "limit=rollouts:10,rollout_policy=random,max_rollout_plies=1"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.max_rollout_plies, 1);
+        assert!("limit=rollouts:10,rollout_policy=random,max_rollout_plies=0"
+            .parse::<Behaviour>()
+            .is_err());
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        // one ply from the start cannot finish a game, so it is scored as a draw.
+        for _ in 0..20 {
+            let mut board = Connect4::new();
+            assert!(engine.random_rollout(&mut board).abs() < f32::EPSILON);
+            assert_eq!(board.turn(), -Connect4::new().turn());
+        }
+    }
+
+    #[test]
+    fn heuristic_rollouts_take_the_static_evaluation() {
+        use crate::games::{chess::Chess, connectfour::Connect4};
+        let behaviour = "limit=rollouts:400,rollout_policy=heuristic"
+            .parse::<Behaviour>()
+            .unwrap();
+        let behaviour = Behaviour {
+            readout: false,
+            hash_mb: 1,
+            ..behaviour
+        };
+        // the queens face each other, and whoever moves first takes the other's.
+        let board = Chess::from_fen("4k3/8/8/3q4/8/8/8/3QK3 w - - 0 1", false).unwrap();
+        let mut engine = MCTS::<Chess>::new(&behaviour);
+        let mut rollout_board = board.clone();
+        assert_eq!(engine.playout(&mut rollout_board, 0), (0.0, 0.0));
+        assert_eq!(rollout_board, board);
+        assert_eq!(engine.search(&board).best_move.to_string(), "d1d5");
+        // games without an evaluation are played out.
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let mut rollout_board = Connect4::new();
+        let (_, plies) = engine.playout(&mut rollout_board, 0);
+        assert!(plies >= 7.0 && rollout_board.is_terminal());
+    }
+
+    #[test]
+    fn networks_value_leaves_and_give_priors() {
+        use crate::games::tictactoe::TicTacToe;
+        let path = std::env::temp_dir().join(format!("iridium-model-{}.onnx", std::process::id()));
+        // the network thinks every position even, and the centre the best move.
+        let mut centre = [0.0; 9];
+        centre[4] = 20.0f32.ln();
+        std::fs::write(&path, crate::nneval::tests::tictactoe_model(centre, 0.0)).unwrap();
+        let behaviour = format!(
+            "limit=rollouts:100,rollout_policy=random,fpu=0,model={}",
+            path.display()
+        )
+        .parse::<Behaviour>()
+        .unwrap();
+        let behaviour = Behaviour {
+            readout: false,
+            ..behaviour
+        };
+        let mut engine = MCTS::<TicTacToe>::new(&behaviour);
+        let board = TicTacToe::new();
+        assert_eq!(engine.playout(&mut board.clone(), 0), (0.0, 0.0));
+        let results = engine.search(&board);
+        assert_eq!(results.best_move.to_string(), "5");
+        let priors = engine
+            .tree
+            .children_of(ROOT_IDX)
+            .map(|child| engine.tree[child].prior())
+            .collect::<Vec<_>>();
+        assert!((priors[4] - 20.0 / 28.0).abs() < 1e-6);
+        assert!((priors[0] - 1.0 / 28.0).abs() < 1e-6);
+        // the nodes expanded by a batch take their priors from the run on the batch.
+        let mut batched = MCTS::<TicTacToe>::new(&Behaviour {
+            batch_size: 8,
+            ..behaviour
+        });
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(batched.search(&board).best_move.to_string(), "5");
+        let child = batched.tree.nth_child(ROOT_IDX, 4);
+        let grandchildren = batched.tree.children_of(child).collect::<Vec<_>>();
+        assert!(!grandchildren.is_empty());
+        for grandchild in grandchildren {
+            assert!((batched.tree[grandchild].prior() - 0.125).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn searches_reuse_their_storage() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = "limit=rollouts:200,rollout_policy=random,batch=8"
+            .parse::<Behaviour>()
+            .unwrap();
+        let behaviour = Behaviour {
+            readout: false,
+            ..behaviour
+        };
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let board = Connect4::new();
+        engine.search(&board);
+        let leaves = engine.context.leaves.as_ptr();
+        let policies = engine.context.policies.as_ptr();
+        assert_eq!(engine.context.leaves.len(), 8);
+        engine.search(&board);
+        assert_eq!(engine.context.leaves.as_ptr(), leaves);
+        assert_eq!(engine.context.policies.as_ptr(), policies);
+        // nothing is logged, so no log is opened.
+        assert!(engine.context.log.is_none());
+    }
+
+    #[test]
+    fn batched_searches_do_every_rollout() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = "limit=rollouts:1000,rollout_policy=random,batch=48,rave=0.5"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.batch_size, 48);
+        assert!("limit=rollouts:10,rollout_policy=random,batch=0"
+            .parse::<Behaviour>()
+            .is_err());
+        let behaviour = Behaviour {
+            readout: false,
+            ..behaviour
+        };
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let results = engine.search(&Connect4::new());
+        assert_eq!(results.rollouts, 1000);
+        assert!(results.overshoot.is_zero());
+        assert_eq!(engine.tree.root().visits(), 1000);
+        // every virtual loss of a batch is taken back.
+        for node in &engine.tree.nodes {
+            assert_eq!(node.selection_stats().0, node.visits());
+        }
+    }
+
+    #[test]
+    fn fpu_reduction_leaves_unpromising_moves_unvisited() {
+        use crate::games::gomoku::Gomoku;
+        let unvisited = |config: &str| {
+            let behaviour = config.parse::<Behaviour>().unwrap();
+            let mut engine = MCTS::<Gomoku<9>>::new(&behaviour);
+            engine.search(&Gomoku::default());
+            engine.root_edges().iter().filter(|&&(_, visits, _)| visits == 0).count()
+        };
+        // without first-play urgency, every move is tried before any is revisited.
+        assert_eq!(unvisited("limit=rollouts:200,rollout_policy=random"), 0);
+        assert!(unvisited("limit=rollouts:200,rollout_policy=random,fpu=0.5") > 0);
+        assert!("limit=rollouts:200,rollout_policy=random,fpu=-1"
+            .parse::<Behaviour>()
+            .is_err());
+    }
+
+    #[test]
+    fn a_larger_cpuct_spreads_visits_more() {
+        use crate::games::connectfour::Connect4;
+        let most_visits = |cpuct: &str| {
+            let config = format!("limit=rollouts:2000,rollout_policy=random,{cpuct}");
+            let mut engine = MCTS::<Connect4>::new(&config.parse().unwrap());
+            engine.search(&Connect4::new());
+            engine.root_edges().iter().map(|&(_, visits, _)| visits).max().unwrap()
+        };
+        assert!(most_visits("cpuct_factor=0.1") > most_visits("cpuct_factor=10,cpuct_base=1"));
+        assert!("limit=rollouts:1,rollout_policy=random,cpuct_base=0"
+            .parse::<Behaviour>()
+            .is_err());
+    }
+
+    #[test]
+    fn every_selection_policy_searches() {
+        use crate::games::connectfour::Connect4;
+        let search = |state: &Connect4, options: &str| {
+            let config = format!("limit=rollouts:2000,rollout_policy=random,{options}");
+            let mut engine = MCTS::<Connect4>::new(&config.parse().unwrap());
+            let best_move = engine.search(state).best_move;
+            let most_visits = engine
+                .root_edges()
+                .iter()
+                .map(|&(_, visits, _)| visits)
+                .max();
+            (best_move, most_visits.unwrap())
+        };
+        let mut threat = Connect4::new();
+        for text in ["4", "5", "4", "5", "4", "5"] {
+            threat.push(threat.parse_move(text).unwrap());
+        }
+        for selection in ["ucb1", "ucb1_tuned", "puct"] {
+            assert_eq!(
+                selection.parse::<SelectionPolicy>().unwrap().to_string(),
+                selection
+            );
+            let (best_move, _) = search(&threat, &format!("selection={selection}"));
+            assert_eq!(best_move.to_string(), "4", "{selection} missed the win");
+        }
+        let start = Connect4::new();
+        assert!(
+            search(&start, "selection=ucb1,exp_factor=0.1").1
+                > search(&start, "selection=ucb1,exp_factor=10").1
+        );
+        assert!("ucb2".parse::<SelectionPolicy>().is_err());
+    }
+
+    #[test]
+    fn minimax_backup_values_nodes_by_their_best_child() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = "limit=rollouts:2000,rollout_policy=random,minimax=2"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.minimax_depth, 2);
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        engine.search(&Connect4::new());
+        let tree = &engine.tree;
+        let best = tree.best_child_value(ROOT_IDX).unwrap();
+        assert!((tree.root().win_rate() - (1.0 - best)).abs() < 1e-12);
+        // the children of the root are backed up too, but their children are averaged.
+        let child = tree.best_child_by_visits(ROOT_IDX);
+        let best = tree.best_child_value(child).unwrap();
+        assert!((tree[child].win_rate() - (1.0 - best)).abs() < 1e-12);
+        let grandchild = tree.best_child_by_visits(child);
+        let best = tree.best_child_value(grandchild).unwrap();
+        assert!((tree[grandchild].win_rate() - (1.0 - best)).abs() > 1e-12);
+    }
+
+    #[test]
+    fn hopeless_root_moves_are_pruned() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = "limit=rollouts:3000,rollout_policy=random,root_pruning"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert!(behaviour.root_pruning);
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        let results = engine.search(&Connect4::new());
+        let excluded = engine.root_excluded.clone().expect("some moves were pruned");
+        let best = *results.rollout_distribution.iter().max().unwrap();
+        let best_child = engine.tree.best_child_by_visits(ROOT_IDX);
+        let chosen = engine.tree.children_of(ROOT_IDX).position(|i| i == best_child).unwrap();
+        assert!(!excluded[chosen]);
+        for (visits, excluded) in results.rollout_distribution.iter().zip(excluded) {
+            // a pruned move had fallen too far behind to catch up.
+            if excluded {
+                assert!(*visits < best);
+            }
+        }
+    }
+
+    #[test]
+    fn autosaved_searches_resume() {
+        use crate::games::connectfour::Connect4;
+        let path = std::env::temp_dir()
+            .join(format!("iridium-autosave-{}.tree", std::process::id()));
+        let autosave = Autosave {
+            path: path.clone(),
+            interval: Duration::from_mins(1),
+        };
+        let behaviour = "limit=rollouts:500,rollout_policy=random"
+            .parse::<Behaviour>()
+            .unwrap();
+        let mut engine = MCTS::<Connect4>::new(&behaviour);
+        engine.set_autosave(Some(autosave.clone()));
+        let state = Connect4::new();
+        engine.search(&state);
+        let saved = engine.tree.nodes.clone();
+
+        let mut resumed = MCTS::<Connect4>::new(&behaviour);
+        resumed.tree.load(&path, state).unwrap();
+        assert_eq!(resumed.tree.nodes, saved);
+        assert_eq!(resumed.tree.rollouts(), 500);
+        resumed.set_autosave(Some(autosave));
+        resumed.set_limit(Limit::Rollouts(800));
+        let results = resumed.search(&state);
+        assert_eq!(results.rollouts, 800);
+        assert_eq!(resumed.tree.root().visits(), 800);
+
+        // a tree of another position is not resumed from.
+        let mut elsewhere = state;
+        elsewhere.push(elsewhere.parse_move("4").unwrap());
+        assert!(resumed.tree.load(&path, elsewhere).is_err());
+        assert_eq!(resumed.search(&elsewhere).rollouts, 800);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn deterministic_searches_repeat() {
+        use crate::games::connectfour::Connect4;
+        let behaviour = Behaviour {
+            limit: Limit::Rollouts(500),
+            deterministic: true,
+            ..Behaviour::default()
+        };
+        let mut first = MCTS::<Connect4>::new(&behaviour);
+        let mut second = MCTS::<Connect4>::new(&behaviour);
+        let a = first.search(&Connect4::new());
+        let b = second.search(&Connect4::new());
+        assert_eq!(a.rollout_distribution, b.rollout_distribution);
+        assert_eq!(a.best_move, b.best_move);
+        // searching again continues the sequence, until a new game starts it afresh.
+        second.search(&a.new_node);
+        second.new_game();
+        let c = second.search(&Connect4::new());
+        assert_eq!(a.rollout_distribution, c.rollout_distribution);
+    }
+
+    #[test]
+    fn selective_depth_is_the_deepest_iteration() {
+        use crate::games::tictactoe::TicTacToe;
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            limit: Limit::Rollouts(2000),
+            ..Behaviour::default()
+        });
+        engine.search(&TicTacToe::new());
+        let (average, max) = (engine.tree.average_depth(), engine.tree.max_depth());
+        #[allow(clippy::cast_precision_loss)]
+        let max_f = max as f64;
+        assert!(average >= 1.0 && max_f > average, "average {average}, max {max}");
+        // no game of tic-tac-toe lasts longer than nine moves.
+        assert!(max <= 9);
+    }
+
+    #[test]
+    fn win_rate_is_the_movers() {
+        use crate::games::tictactoe::{TicTacToe, TicTacToeMove};
+        let behaviour = Behaviour {
+            limit: Limit::Rollouts(500),
+            ..Behaviour::default()
+        };
+        let mut engine = MCTS::<TicTacToe>::new(&behaviour);
+        let position = |squares: &[usize]| {
+            let mut state = TicTacToe::new();
+            for &square in squares {
+                state.push(TicTacToeMove::new(square));
+            }
+            state
+        };
+        // X to move wins on 2, and O to move wins on 5.
+        for squares in [&[0, 3, 1, 4][..], &[0, 3, 2, 4, 7]] {
+            let results = engine.search(&position(squares));
+            assert!((results.win_rate - 1.0).abs() < f64::EPSILON, "{squares:?}");
+        }
+        // the empty board is too deep to prove, so the chosen child's mean is used.
+        let results = engine.search(&TicTacToe::new());
+        let chosen = &engine.tree[results.new_node_idx];
+        assert_eq!(chosen.proof(), Proof::Unknown);
+        assert!((results.win_rate - chosen.win_rate()).abs() < f64::EPSILON);
+        assert!(results.win_rate > 0.5, "X should be favoured, got {}", results.win_rate);
+    }
+
+    #[test]
+    fn tactical_expansion_proves_wins_and_follows_forced_moves() {
+        use crate::games::tictactoe::{TicTacToe, TicTacToeMove};
+        let position = |squares: &[usize]| {
+            let mut state = TicTacToe::new();
+            for &square in squares {
+                state.push(TicTacToeMove::new(square));
+            }
+            state
+        };
+        let search = |tactical_expansion, squares: &[usize]| {
+            let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+                limit: Limit::Rollouts(1),
+                root_symmetry: false,
+                tactical_expansion,
+                ..Behaviour::default()
+            });
+            engine.search(&position(squares));
+            engine
+        };
+        // X to move wins on 2, which proves the root lost for O on the first expansion.
+        let engine = search(true, &[0, 3, 1, 4]);
+        assert_eq!(engine.tree.root().proof(), Proof::Loss);
+        let win = engine.tree.child_by_move(ROOT_IDX, TicTacToeMove::new(2)).unwrap();
+        assert_eq!(engine.tree[win].terminal_outcome(), Some(1));
+        // O has two moves, and either leaves X only one, which is played in the same rollout.
+        let forced = [0, 1, 2, 3, 5, 4, 7];
+        assert_eq!(search(true, &forced).tree.max_depth(), 2);
+        assert_eq!(search(false, &forced).tree.max_depth(), 1);
+        assert!("limit=rollouts:1,rollout_policy=random,tactical_expansion"
+            .parse::<Behaviour>()
+            .unwrap()
+            .tactical_expansion);
+    }
+
+    #[test]
+    fn transposed_positions_share_their_statistics() {
+        use crate::games::tictactoe::TicTacToe;
+        let behaviour = "limit=rollouts:2000,rollout_policy=random,transpositions"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert!(behaviour.transpositions);
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            root_symmetry: false,
+            ..behaviour
+        });
+        let results = engine.search(&TicTacToe::new());
+        assert_eq!(results.rollouts, 2000);
+        // lines that play the same moves in another order reach the same positions.
+        let transposed = (0..engine.tree.nodes.len())
+            .filter(|&idx| engine.tree.representative(idx) != idx)
+            .collect::<Vec<_>>();
+        assert!(!transposed.is_empty());
+        for idx in transposed {
+            let representative = engine.tree.representative(idx);
+            assert_eq!(engine.tree.representative(representative), representative);
+            assert!(engine.tree[idx].visits() <= engine.tree[representative].visits());
+        }
+    }
+
+    #[test]
+    fn rave_credits_moves_made_later_in_the_iteration() {
+        use crate::games::tictactoe::TicTacToe;
+        let behaviour = "limit=rollouts:2000,rollout_policy=random,rave=0.1"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.rave_bias, Some(0.1));
+        assert!("limit=rollouts:2000,rollout_policy=random,rave=-1"
+            .parse::<Behaviour>()
+            .is_err());
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            root_symmetry: false,
+            ..behaviour
+        });
+        let results = engine.search(&TicTacToe::new());
+        assert_eq!(results.rollouts, 2000);
+        // every visit of a child made its move, and most rollouts make the others later.
+        let children = engine.tree.child_nodes(ROOT_IDX);
+        for child in children {
+            assert!(child.amaf_stats().0 >= child.visits());
+        }
+        let amaf_visits = children.iter().map(|c| c.amaf_stats().0).sum::<u64>();
+        assert!(amaf_visits > 2 * results.rollouts);
+    }
+
+    #[test]
+    fn fast_wins_and_slow_losses_are_preferred() {
+        use crate::games::tictactoe::{TicTacToe, TicTacToeMove};
+        let behaviour = Behaviour {
+            limit: Limit::Rollouts(500),
+            ..Behaviour::default()
+        };
+        let mut engine = MCTS::<TicTacToe>::new(&behaviour);
+        // X to move wins on 8 at once.
+        let mut state = TicTacToe::new();
+        for square in [0, 1, 4, 2] {
+            state.push(TicTacToeMove::new(square));
+        }
+        let results = engine.search(&state);
+        assert_eq!(results.best_move, TicTacToeMove::new(8));
+        let i = engine
+            .root_edges()
+            .iter()
+            .position(|&(m, _, _)| m == TicTacToeMove::new(8))
+            .unwrap();
+        assert!((results.game_lengths[i].unwrap() - 1.0).abs() < f32::EPSILON);
+
+        // of several proven wins, the shortest is played, and of proven losses, the longest.
+        let tree = &mut engine.tree;
+        tree.setup(state);
+        tree.expand(ROOT_IDX, &state);
+        let children = tree.children_of(ROOT_IDX).collect::<Vec<_>>();
+        for proof in [Proof::Win, Proof::Loss] {
+            for (n, &i) in children.iter().enumerate() {
+                tree[i].set_proof(proof);
+                tree[i].update(0.0);
+                tree[i].record_length([5.0, 3.0, 7.0, 3.0, 4.0][n]);
+            }
+            let expected = if proof == Proof::Win { 1 } else { 2 };
+            assert_eq!(tree.best_child_by_visits(ROOT_IDX), children[expected]);
+        }
+    }
+
+    #[test]
+    fn lcb_trusts_well_searched_moves() {
+        use crate::games::tictactoe::TicTacToe;
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            hash_mb: 1,
+            ..Behaviour::default()
+        });
+        let tree = &mut engine.tree;
+        let state = TicTacToe::new();
+        tree.setup(state);
+        tree.expand(ROOT_IDX, &state);
+        let children = tree.children_of(ROOT_IDX).collect::<Vec<_>>();
+        // a well-searched move, a promising one with a fifth of the visits, and a lucky one.
+        for (&i, (visits, value)) in children.iter().zip([(100, 0.6), (20, 0.7), (5, 0.9)]) {
+            for _ in 0..visits {
+                tree[i].update(0.0);
+            }
+            tree[i].set_minimax_value(value);
+        }
+        assert_eq!(tree.best_child_by_visits(ROOT_IDX), children[0]);
+        assert_eq!(tree.best_child_by_lcb(ROOT_IDX, 2.0), children[0]);
+        assert_eq!(tree.best_child_by_lcb(ROOT_IDX, 0.5), children[1]);
+        // the lucky move has too few visits to be chosen at all.
+        assert_eq!(tree.best_child_by_lcb(ROOT_IDX, 0.0), children[1]);
+
+        let behaviour = "limit=rollouts:1,rollout_policy=random,lcb=1.5"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.lcb, Some(1.5));
+        assert!("limit=rollouts:1,rollout_policy=random,lcb=-1"
+            .parse::<Behaviour>()
+            .is_err());
+    }
+
+    #[test]
+    fn full_pools_stop_expanding() {
+        use crate::games::gomoku::Gomoku;
+        // a megabyte holds a few hundred expansions of the 81 moves of the empty board.
+        let behaviour = Behaviour {
+            readout: false,
+            hash_mb: 1,
+            limit: Limit::Rollouts(2000),
+            ..Behaviour::for_game::<Gomoku<9>>()
+        };
+        let mut engine = MCTS::<Gomoku<9>>::new(&behaviour);
+        let results = engine.search(&Gomoku::default());
+        assert_eq!(results.rollouts, 2000);
+        assert!(results.skipped_expansions > 0);
+        assert!(!engine.tree.has_room_for(Gomoku::<9>::MOVE_SPACE));
+        let json = json::parse(&engine.json_result(&results)).unwrap();
+        assert!(json.get("skipped_expansions").is_some());
+    }
+
+    #[test]
+    fn node_pools_fit_the_game() {
+        use crate::games::{chess::Chess, gomoku::Gomoku, tictactoe::TicTacToe};
+        let small = Behaviour::for_game::<TicTacToe>().hash_mb;
+        let large = Behaviour::for_game::<Gomoku<19>>().hash_mb;
+        // room for the 986,410 lines of up to nine moves, and no more.
+        assert!(small < 100, "tic-tac-toe reserves {small}MB");
+        assert!(large > small);
+        assert_eq!(Behaviour::for_game::<Chess>().hash_mb, DEFAULT_HASH_MB);
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            limit: Limit::Rollouts(20_000),
+            ..Behaviour::for_game::<TicTacToe>()
+        });
+        engine.search(&TicTacToe::new());
+    }
+
+    #[test]
+    fn settings_display_round_trips() {
+        for limit in ["time:15000", "rollouts:800", "rollouts:5000000000"] {
+            assert_eq!(limit.parse::<Limit>().unwrap().to_string(), limit);
+        }
+        for policy in [
+            "random",
+            "decisive_cutoff.50",
+            "history",
+            "greedy.10",
+            "heuristic",
+            "meta_aggregated.decisive.10",
+        ] {
+            assert_eq!(policy.parse::<RolloutPolicy>().unwrap().to_string(), policy);
+        }
+    }
+
+    #[test]
+    fn every_setting_of_the_search_is_parsed() {
+        let parse = |options: &str| {
+            format!("limit=rollouts:100,rollout_policy=random,{options}").parse::<Behaviour>()
+        };
+        let behaviour = parse(
+            "root_parallelism_count=3,training,deterministic=true,root_symmetry=false,\
+             exp_factor=2.5,expansion_threshold=4,hash_mb=8,cp_scale=300,value_noise=0.1",
+        )
+        .unwrap();
+        assert_eq!(behaviour.root_parallelism_count, 3);
+        assert!(behaviour.training && behaviour.deterministic && !behaviour.root_symmetry);
+        assert!((behaviour.exp_factor - 2.5).abs() < f32::EPSILON);
+        assert_eq!(behaviour.expansion_threshold, 4);
+        assert_eq!(behaviour.hash_mb, 8);
+        assert!((behaviour.cp_scale - 300.0).abs() < f64::EPSILON);
+        assert!((behaviour.value_noise - 0.1).abs() < f32::EPSILON);
+        assert_eq!(
+            parse("trees=3").unwrap(),
+            parse("root_parallelism_count=3").unwrap()
+        );
+        for bad in [
+            "trees=0",
+            "training=maybe",
+            "hash=0",
+            "exp_factor=-1",
+            "colour=red",
+            "debug",
+        ] {
+            assert!(parse(bad).is_err(), "{bad} was accepted");
+        }
+    }
+
+    #[test]
+    fn schedules_pick_the_limit_of_each_move() {
+        use crate::games::tictactoe::TicTacToe;
+
+        let text = "3@rollouts:300/5@rollouts:50";
+        let schedule = text.parse::<Schedule>().unwrap();
+        assert_eq!(schedule.to_string(), text);
+        for bad in [
+            "0@rollouts:10",
+            "3@rollouts:10/3@rollouts:20",
+            "3",
+            "3@soon",
+        ] {
+            assert!(bad.parse::<Schedule>().is_err(), "{bad} was accepted");
+        }
+        let behaviour = format!("limit=rollouts:1000,rollout_policy=random,schedule={text}")
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.schedule, Some(schedule));
+        let mut engine = MCTS::<TicTacToe>::new(&Behaviour {
+            deterministic: true,
+            ..behaviour
+        });
+        for (move_number, rollouts) in [(1, 1000), (4, 300), (9, 50)] {
+            engine.new_game();
+            engine.set_move_number(move_number);
+            assert_eq!(engine.search(&TicTacToe::new()).rollouts, rollouts);
+        }
+    }
+
+    #[test]
+    fn builder_sets_only_what_it_is_told() {
+        let behaviour = Behaviour::builder()
+            .limit(Limit::Rollouts(500))
+            .rollout_policy(RolloutPolicy::Decisive)
+            .threads(4)
+            .build()
+            .unwrap();
+        assert_eq!(
+            behaviour,
+            Behaviour {
+                limit: Limit::Rollouts(500),
+                rollout_policy: RolloutPolicy::Decisive,
+                threads: 4,
+                ..Behaviour::default()
+            }
+        );
+        let rebuilt = behaviour.clone().into_builder().build().unwrap();
+        assert_eq!(rebuilt, behaviour);
+        for invalid in [
+            Behaviour::builder().root_parallelism_count(0),
+            Behaviour::builder().threads(MAX_THREADS + 1),
+            Behaviour::builder().cpuct_base(0.0),
+            Behaviour::builder().rave_bias(Some(f32::NAN)),
+            Behaviour::builder().draw_margin(Some(0.6)),
+            Behaviour::builder().deterministic(true).threads(4),
+        ] {
+            assert!(invalid.build().is_err());
+        }
+    }
+
+    #[test]
+    fn rollout_counts_run_past_u32_max() {
+        let past = u64::from(u32::MAX) + 2;
+        let info = SearchInfo {
+            unread: VecDeque::new(),
+            pending: Vec::new(),
+            flags: Behaviour {
+                limit: Limit::Rollouts(past),
+                ..Behaviour::default()
+            },
+            start_time: None,
+            stdin_rx: None,
+            stop: None,
+        };
+        assert!(!info.limit_reached(u64::from(u32::MAX)));
+        assert!(info.limit_reached(past));
+        assert_eq!(info.overshoot(past + 3, 0).rollouts, 3);
+        let summed = fusion::sum_visits(&[vec![u64::from(u32::MAX), 1], vec![2, 0]]);
+        assert_eq!(summed, [past, 1]);
+    }
+
+    #[test]
+    fn quality_scaling_curves_decay() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        let exp = "exp:0.04".parse::<QualityScaling>().unwrap();
+        assert_eq!(exp, QualityScaling::default());
+        assert!(close(exp.scale(-1.0, 25.0), -(-1.0f32).exp()));
+        let linear = "linear:0.1".parse::<QualityScaling>().unwrap();
+        assert!(close(linear.scale(1.0, 5.0), 0.5));
+        assert!(close(linear.scale(1.0, 20.0), 0.0));
+        assert!(close(QualityScaling::None.scale(1.0, 80.0), 1.0));
+        for curve in ["exp:0.04", "linear:0.1", "none"] {
+            assert_eq!(curve.parse::<QualityScaling>().unwrap().to_string(), curve);
+        }
+        assert!("exp:-1".parse::<QualityScaling>().is_err());
+        assert!("cubic:1".parse::<QualityScaling>().is_err());
+        let behaviour = "limit=rollouts:1,rollout_policy=random,quality_scaling=none"
+            .parse::<Behaviour>()
+            .unwrap();
+        assert_eq!(behaviour.quality_scaling, QualityScaling::None);
+    }
+
+    #[test]
+    fn candidates_are_ranked_by_visits() {
+        let edges = [
+            (1, 10, Some(0.25)),
+            (2, 0, None),
+            (3, 30, Some(0.75)),
+            (4, 60, Some(0.5)),
+        ];
+        assert_eq!(
+            format_candidates(&edges, 3),
+            "4 (60.0%, 50% win), 3 (30.0%, 75% win), 1 (10.0%, 25% win)"
+        );
+        assert_eq!(
+            format_candidates(&edges[..2], 3),
+            "1 (100.0%, 25% win), 2 (0.0%)"
+        );
+    }
 }
@@ -0,0 +1,695 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{datageneration::VectoriseState, game::MoveBuffer};
+
+/// The ONNX data type of 32-bit floats, the only type of tensor that is read.
+const FLOAT: u64 = 1;
+
+/// What a network makes of one position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    /// The value of the position for the side to move, from -1 for a loss to 1 for a win.
+    pub value: f32,
+    /// The priors of the legal moves, in move generation order, which sum to one.
+    pub priors: Vec<f32>,
+}
+
+/// A policy-value network read from an ONNX model, and run by an interpreter of its own
+/// rather than an inference runtime. The interpreter knows the operators of fully-connected
+/// networks, `Gemm`, `MatMul`, `Add`, `Relu`, `Tanh`, `Sigmoid`, `Softmax` (over the last
+/// axis), `Flatten`, and `Identity`, on float tensors of at most two dimensions, after
+/// flattening.
+///
+/// The network is given a batch of the state vectors of [`VectoriseState`], each flattened
+/// into a row. Its first output is the policy, as logits over the action space of the game,
+/// indexed by [`VectoriseState::index_move`], and its second is the value of each position
+/// for the side to move, in [-1, 1].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    input: String,
+    policy: String,
+    value: String,
+    initializers: HashMap<String, Tensor>,
+    operations: Vec<Operation>,
+}
+
+impl Network {
+    /// Reads the ONNX model at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        read_model(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("invalid model, {e}")))
+    }
+
+    /// Evaluates every one of `states`, running the network once on all of them together.
+    pub fn evaluate_all<G: VectoriseState>(&self, states: &[G]) -> Result<Vec<Evaluation>, String> {
+        let width = G::state_vector_dimensions().iter().product::<usize>();
+        if width == 0 {
+            return Err("the positions of this game have no state vector".to_string());
+        }
+        if states.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut inputs = Vec::with_capacity(width * states.len());
+        for state in states {
+            inputs.extend(state.vectorise_state().data.into_iter().map(f32::from));
+        }
+        let (policy, value) = self.run(inputs, states.len())?;
+        let actions = G::action_space();
+        if policy.data.len() != actions * states.len() {
+            return Err(format!(
+                "the policy has {} logits for each position, but the game has {actions} actions",
+                policy.data.len() / states.len()
+            ));
+        }
+        if value.data.len() != states.len() {
+            return Err(format!(
+                "the value has {} numbers for each position, rather than one",
+                value.data.len() / states.len()
+            ));
+        }
+        let mut moves = G::Buffer::default();
+        let evaluations = states
+            .iter()
+            .zip(policy.data.chunks(actions))
+            .zip(&value.data)
+            .map(|((state, logits), &value)| {
+                moves.clear();
+                state.legal_moves(&mut moves);
+                let mut priors = moves
+                    .iter()
+                    .map(|&m| logits[G::index_move(m)])
+                    .collect::<Vec<_>>();
+                softmax(&mut priors);
+                Evaluation {
+                    value: value.clamp(-1.0, 1.0),
+                    priors,
+                }
+            })
+            .collect();
+        Ok(evaluations)
+    }
+
+    /// Runs the network on a batch of `batch` inputs, one after another in `inputs`,
+    /// returning its policy and value outputs.
+    fn run(&self, inputs: Vec<f32>, batch: usize) -> Result<(Tensor, Tensor), String> {
+        let mut values = HashMap::new();
+        let dims = vec![batch, inputs.len() / batch];
+        values.insert(self.input.as_str(), Tensor { dims, data: inputs });
+        for operation in &self.operations {
+            let output = {
+                let args = operation
+                    .inputs
+                    .iter()
+                    // an empty name stands for an optional input that is left out.
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        values
+                            .get(name.as_str())
+                            .or_else(|| self.initializers.get(name))
+                            .ok_or_else(|| format!("{name} is used before it is computed"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                apply(operation.op, &args)?
+            };
+            values.insert(operation.output.as_str(), output);
+        }
+        let mut output = |name: &str| {
+            values
+                .remove(name)
+                .ok_or_else(|| format!("the output {name} is never computed"))
+        };
+        Ok((output(&self.policy)?, output(&self.value)?))
+    }
+}
+
+/// A tensor of floats, in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+struct Tensor {
+    dims: Vec<usize>,
+    data: Vec<f32>,
+}
+
+impl Tensor {
+    /// The rows and columns of the tensor as a matrix, of which a vector is one row.
+    fn shape(&self) -> Result<(usize, usize), String> {
+        match self.dims[..] {
+            [] => Ok((1, 1)),
+            [cols] => Ok((1, cols)),
+            [rows, cols] => Ok((rows, cols)),
+            _ => Err(format!(
+                "tensors of {} dimensions are not supported",
+                self.dims.len()
+            )),
+        }
+    }
+
+    fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            dims: self.dims.clone(),
+            data: self.data.iter().map(|&x| f(x)).collect(),
+        }
+    }
+}
+
+/// The operators that a [`Network`] can run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Gemm {
+        alpha: f32,
+        beta: f32,
+        trans_a: bool,
+        trans_b: bool,
+    },
+    MatMul,
+    Add,
+    Relu,
+    Tanh,
+    Sigmoid,
+    Softmax,
+    Flatten {
+        axis: usize,
+    },
+    Identity,
+}
+
+/// One node of the graph of a [`Network`].
+#[derive(Clone, Debug, PartialEq)]
+struct Operation {
+    op: Op,
+    inputs: Vec<String>,
+    output: String,
+}
+
+fn apply(op: Op, args: &[&Tensor]) -> Result<Tensor, String> {
+    let arg = |i: usize| {
+        args.get(i)
+            .copied()
+            .ok_or_else(|| format!("{op:?} is missing an input"))
+    };
+    match op {
+        Op::Gemm {
+            alpha,
+            beta,
+            trans_a,
+            trans_b,
+        } => {
+            let product = matmul(arg(0)?, arg(1)?, trans_a, trans_b)?.map(|x| alpha * x);
+            args.get(2).map_or_else(
+                || Ok(product.clone()),
+                |c| add(&product, &c.map(|x| beta * x)),
+            )
+        }
+        Op::MatMul => matmul(arg(0)?, arg(1)?, false, false),
+        Op::Add => add(arg(0)?, arg(1)?),
+        Op::Relu => Ok(arg(0)?.map(|x| x.max(0.0))),
+        Op::Tanh => Ok(arg(0)?.map(f32::tanh)),
+        Op::Sigmoid => Ok(arg(0)?.map(|x| 1.0 / (1.0 + (-x).exp()))),
+        Op::Softmax => {
+            let mut out = arg(0)?.clone();
+            let (_, cols) = out.shape()?;
+            for row in out.data.chunks_mut(cols.max(1)) {
+                softmax(row);
+            }
+            Ok(out)
+        }
+        Op::Flatten { axis } => {
+            let x = arg(0)?;
+            if axis > x.dims.len() {
+                return Err(format!(
+                    "cannot flatten {} dimensions from axis {axis}",
+                    x.dims.len()
+                ));
+            }
+            Ok(Tensor {
+                dims: vec![
+                    x.dims[..axis].iter().product(),
+                    x.dims[axis..].iter().product(),
+                ],
+                data: x.data.clone(),
+            })
+        }
+        Op::Identity => Ok(arg(0)?.clone()),
+    }
+}
+
+fn matmul(a: &Tensor, b: &Tensor, trans_a: bool, trans_b: bool) -> Result<Tensor, String> {
+    let (a_rows, a_cols) = a.shape()?;
+    let (b_rows, b_cols) = b.shape()?;
+    let (rows, inner) = if trans_a {
+        (a_cols, a_rows)
+    } else {
+        (a_rows, a_cols)
+    };
+    let (b_inner, cols) = if trans_b {
+        (b_cols, b_rows)
+    } else {
+        (b_rows, b_cols)
+    };
+    if inner != b_inner {
+        return Err(format!(
+            "cannot multiply a {rows}x{inner} matrix by a {b_inner}x{cols} one"
+        ));
+    }
+    let a_at = |row: usize, col: usize| {
+        if trans_a {
+            a.data[col * a_cols + row]
+        } else {
+            a.data[row * a_cols + col]
+        }
+    };
+    let b_at = |row: usize, col: usize| {
+        if trans_b {
+            b.data[col * b_cols + row]
+        } else {
+            b.data[row * b_cols + col]
+        }
+    };
+    let mut data = vec![0.0; rows * cols];
+    for row in 0..rows {
+        for col in 0..cols {
+            data[row * cols + col] = (0..inner).map(|i| a_at(row, i) * b_at(i, col)).sum();
+        }
+    }
+    Ok(Tensor {
+        dims: vec![rows, cols],
+        data,
+    })
+}
+
+/// Adds two tensors of the same size, or broadcasts the smaller over the rows of the
+/// larger, if it is one row or one number.
+fn add(a: &Tensor, b: &Tensor) -> Result<Tensor, String> {
+    let (big, small) = if a.data.len() >= b.data.len() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    let (_, cols) = big.shape()?;
+    let (small_rows, small_cols) = small.shape()?;
+    let mut out = big.clone();
+    if small.data.len() == big.data.len() {
+        out.data
+            .iter_mut()
+            .zip(&small.data)
+            .for_each(|(x, y)| *x += y);
+    } else if small.data.len() == 1 {
+        out.data.iter_mut().for_each(|x| *x += small.data[0]);
+    } else if small_rows == 1 && small_cols == cols {
+        for row in out.data.chunks_mut(cols) {
+            row.iter_mut().zip(&small.data).for_each(|(x, y)| *x += y);
+        }
+    } else {
+        return Err(format!(
+            "cannot add tensors of dimensions {:?} and {:?}",
+            a.dims, b.dims
+        ));
+    }
+    Ok(out)
+}
+
+/// Turns `logits` into probabilities, in place.
+fn softmax(logits: &mut [f32]) {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for x in logits.iter_mut() {
+        *x = (*x - max).exp();
+        sum += *x;
+    }
+    for x in logits.iter_mut() {
+        *x /= sum;
+    }
+}
+
+/// A field of a protobuf message, by its wire type.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// The fields of a protobuf message, in the order they were written.
+struct Fields<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    const fn of(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first().ok_or("truncated varint")?;
+            self.bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is too long".to_string())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.bytes.len() {
+            return Err("truncated field".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u64, Value<'a>), String> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| "field is too long")?;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            wire_type => return Err(format!("unsupported wire type {wire_type}")),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        (!self.bytes.is_empty()).then(|| self.field())
+    }
+}
+
+fn string(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "a name is not UTF-8".to_string())
+}
+
+fn floats(bytes: &[u8]) -> Result<impl Iterator<Item = f32> + '_, String> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err("tensor data is not a whole number of floats".to_string());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())))
+}
+
+/// Reads a `ModelProto`, of which only the graph matters.
+fn read_model(bytes: &[u8]) -> Result<Network, String> {
+    let mut graph = None;
+    for field in Fields::of(bytes) {
+        if let (7, Value::Bytes(bytes)) = field? {
+            graph = Some(bytes);
+        }
+    }
+    read_graph(graph.ok_or("the model has no graph")?)
+}
+
+/// Reads a `GraphProto`.
+fn read_graph(bytes: &[u8]) -> Result<Network, String> {
+    let mut operations = Vec::new();
+    let mut initializers = HashMap::new();
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for field in Fields::of(bytes) {
+        match field? {
+            (1, Value::Bytes(node)) => operations.push(read_node(node)?),
+            (5, Value::Bytes(tensor)) => {
+                let (name, tensor) = read_tensor(tensor)?;
+                initializers.insert(name, tensor);
+            }
+            (11, Value::Bytes(info)) => inputs.push(read_name(info)?),
+            (12, Value::Bytes(info)) => outputs.push(read_name(info)?),
+            _ => {}
+        }
+    }
+    // older exporters list the initializers among the inputs.
+    let mut inputs = inputs
+        .into_iter()
+        .filter(|name| !initializers.contains_key(name));
+    let input = inputs.next().ok_or("the graph has no input")?;
+    if inputs.next().is_some() {
+        return Err("the graph has more than one input".to_string());
+    }
+    let [policy, value] = <[String; 2]>::try_from(outputs)
+        .map_err(|_| "the graph must have two outputs, the policy and the value")?;
+    Ok(Network {
+        input,
+        policy,
+        value,
+        initializers,
+        operations,
+    })
+}
+
+/// Reads the name of a `ValueInfoProto`.
+fn read_name(bytes: &[u8]) -> Result<String, String> {
+    for field in Fields::of(bytes) {
+        if let (1, Value::Bytes(name)) = field? {
+            return string(name);
+        }
+    }
+    Err("a graph input or output has no name".to_string())
+}
+
+/// The numeric values of an `AttributeProto`, of which operators only take numbers.
+#[derive(Clone, Copy, Debug, Default)]
+struct Attribute {
+    float: Option<f32>,
+    int: Option<i64>,
+}
+
+fn read_attribute(bytes: &[u8]) -> Result<(String, Attribute), String> {
+    let mut name = None;
+    let mut attribute = Attribute::default();
+    for field in Fields::of(bytes) {
+        match field? {
+            (1, Value::Bytes(bytes)) => name = Some(string(bytes)?),
+            (2, Value::Fixed32(bits)) => attribute.float = Some(f32::from_bits(bits)),
+            // negative integers are written in two's complement.
+            #[allow(clippy::cast_possible_wrap)]
+            (3, Value::Varint(int)) => attribute.int = Some(int as i64),
+            _ => {}
+        }
+    }
+    Ok((name.ok_or("an attribute has no name")?, attribute))
+}
+
+/// Reads a `NodeProto`.
+fn read_node(bytes: &[u8]) -> Result<Operation, String> {
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut op_type = String::new();
+    let mut attributes = HashMap::new();
+    for field in Fields::of(bytes) {
+        match field? {
+            (1, Value::Bytes(name)) => inputs.push(string(name)?),
+            (2, Value::Bytes(name)) => outputs.push(string(name)?),
+            (4, Value::Bytes(name)) => op_type = string(name)?,
+            (5, Value::Bytes(attribute)) => {
+                let (name, attribute) = read_attribute(attribute)?;
+                attributes.insert(name, attribute);
+            }
+            _ => {}
+        }
+    }
+    let float = |name: &str, default| {
+        attributes
+            .get(name)
+            .and_then(|a: &Attribute| a.float)
+            .unwrap_or(default)
+    };
+    let int = |name: &str, default| {
+        attributes
+            .get(name)
+            .and_then(|a: &Attribute| a.int)
+            .unwrap_or(default)
+    };
+    let op = match op_type.as_str() {
+        "Gemm" => Op::Gemm {
+            alpha: float("alpha", 1.0),
+            beta: float("beta", 1.0),
+            trans_a: int("transA", 0) != 0,
+            trans_b: int("transB", 0) != 0,
+        },
+        "MatMul" => Op::MatMul,
+        "Add" => Op::Add,
+        "Relu" => Op::Relu,
+        "Tanh" => Op::Tanh,
+        "Sigmoid" => Op::Sigmoid,
+        // the tensors are matrices, whose last axis is axis 1.
+        "Softmax" if matches!(int("axis", -1), -1 | 1) => Op::Softmax,
+        "Softmax" => return Err("Softmax is only supported over the last axis".to_string()),
+        "Flatten" => Op::Flatten {
+            axis: usize::try_from(int("axis", 1))
+                .map_err(|_| "Flatten is only supported from a positive axis")?,
+        },
+        "Identity" => Op::Identity,
+        _ => return Err(format!("unsupported operator {op_type}")),
+    };
+    let [output] = <[String; 1]>::try_from(outputs)
+        .map_err(|_| format!("{op_type} must have exactly one output"))?;
+    Ok(Operation { op, inputs, output })
+}
+
+/// Reads a `TensorProto` of floats, returning its name and the tensor.
+fn read_tensor(bytes: &[u8]) -> Result<(String, Tensor), String> {
+    let mut name = String::new();
+    let mut dims = Vec::new();
+    let mut data = Vec::new();
+    let mut data_type = FLOAT;
+    for field in Fields::of(bytes) {
+        match field? {
+            (1, Value::Varint(dim)) => dims.push(dim),
+            (1, Value::Bytes(packed)) => {
+                let mut packed = Fields::of(packed);
+                while !packed.bytes.is_empty() {
+                    dims.push(packed.varint()?);
+                }
+            }
+            (2, Value::Varint(t)) => data_type = t,
+            (4, Value::Fixed32(bits)) => data.push(f32::from_bits(bits)),
+            (4 | 9, Value::Bytes(packed)) => data.extend(floats(packed)?),
+            (8, Value::Bytes(bytes)) => name = string(bytes)?,
+            _ => {}
+        }
+    }
+    if data_type != FLOAT {
+        return Err(format!("the tensor {name} is not of 32-bit floats"));
+    }
+    let dims = dims
+        .into_iter()
+        .map(usize::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("the tensor {name} is too large"))?;
+    if dims.iter().product::<usize>() != data.len() {
+        return Err(format!(
+            "the tensor {name} has {} values, but its dimensions {dims:?} hold {}",
+            data.len(),
+            dims.iter().product::<usize>()
+        ));
+    }
+    Ok((name, Tensor { dims, data }))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        game::Game,
+        games::tictactoe::{TicTacToe, TicTacToeMove},
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(field << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn tensor(name: &str, dims: &[u64], data: &[f32]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &dim in dims {
+            varint(1 << 3, &mut out);
+            varint(dim, &mut out);
+        }
+        varint(2 << 3, &mut out);
+        varint(FLOAT, &mut out);
+        bytes_field(8, name.as_bytes(), &mut out);
+        let raw = data
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes_field(9, &raw, &mut out);
+        out
+    }
+
+    fn node(op_type: &str, inputs: &[&str], output: &str, trans_b: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        for input in inputs {
+            bytes_field(1, input.as_bytes(), &mut out);
+        }
+        bytes_field(2, output.as_bytes(), &mut out);
+        bytes_field(4, op_type.as_bytes(), &mut out);
+        if trans_b {
+            let mut attribute = Vec::new();
+            bytes_field(1, b"transB", &mut attribute);
+            varint(3 << 3, &mut attribute);
+            varint(1, &mut attribute);
+            bytes_field(5, &attribute, &mut out);
+        }
+        out
+    }
+
+    /// An ONNX model of a network for tic-tac-toe whose policy logits are the
+    /// `policy_bias`, whatever the position, and whose value is `tanh(value_bias)`.
+    pub fn tictactoe_model(policy_bias: [f32; 9], value_bias: f32) -> Vec<u8> {
+        let inputs = 18;
+        let mut graph = Vec::new();
+        bytes_field(
+            1,
+            &node("Gemm", &["x", "wp", "bp"], "policy", true),
+            &mut graph,
+        );
+        bytes_field(1, &node("MatMul", &["x", "wv"], "v0", false), &mut graph);
+        bytes_field(1, &node("Add", &["v0", "bv"], "v1", false), &mut graph);
+        bytes_field(1, &node("Tanh", &["v1"], "value", false), &mut graph);
+        let zeros = vec![0.0; 9 * inputs];
+        bytes_field(5, &tensor("wp", &[9, inputs as u64], &zeros), &mut graph);
+        bytes_field(5, &tensor("bp", &[9], &policy_bias), &mut graph);
+        bytes_field(
+            5,
+            &tensor("wv", &[inputs as u64, 1], &zeros[..inputs]),
+            &mut graph,
+        );
+        bytes_field(5, &tensor("bv", &[1], &[value_bias]), &mut graph);
+        for (field, name) in [(11, "x"), (11, "wp"), (12, "policy"), (12, "value")] {
+            let mut info = Vec::new();
+            bytes_field(1, name.as_bytes(), &mut info);
+            bytes_field(field, &info, &mut graph);
+        }
+        let mut model = Vec::new();
+        varint(1 << 3, &mut model);
+        varint(8, &mut model);
+        bytes_field(7, &graph, &mut model);
+        model
+    }
+
+    #[test]
+    fn networks_are_read_and_run() {
+        let model = tictactoe_model([0.0, 0.0, 0.0, 0.0, 2.0f32.ln(), 0.0, 0.0, 0.0, 0.0], 0.5);
+        let network = read_model(&model).unwrap();
+        let start = TicTacToe::new();
+        let evaluation = network.evaluate_all(&[start]).unwrap().remove(0);
+        assert!((evaluation.value - 0.5f32.tanh()).abs() < 1e-6);
+        // the centre has twice the weight of each other square.
+        let priors = evaluation.priors;
+        assert_eq!(priors.len(), 9);
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((priors[4] - 0.2).abs() < 1e-6 && (priors[0] - 0.1).abs() < 1e-6);
+        let mut after = start;
+        after.push(TicTacToeMove::new(4));
+        let both = network.evaluate_all(&[start, after]).unwrap();
+        assert_eq!(both[0].priors, priors);
+        assert_eq!(both[1].priors, [0.125; 8]);
+        assert!(read_model(&model[..model.len() - 3]).is_err());
+        assert!(network
+            .evaluate_all(&[crate::games::connectfour::Connect4::new()])
+            .is_err());
+    }
+}
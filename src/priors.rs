@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::game::{Game, MoveBuffer};
+
+/// A table of precomputed policies, keyed by position hash, used as priors at the root.
+///
+/// The file format is one position per line:
+/// `<hash in hex> <move>:<weight> <move>:<weight> ...`
+/// where moves are written the way the game displays them. Weights do not need to be
+/// normalised. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PriorTable {
+    entries: HashMap<u64, Vec<(String, f32)>>,
+}
+
+impl PriorTable {
+    /// Loads a prior table from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a prior table from the given `io::BufRead`.
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut entries = HashMap::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid prior table, {msg} on line {}: {line}", i + 1),
+                )
+            };
+            let mut words = line.split_whitespace();
+            let hash = words.next().ok_or_else(|| invalid("no hash"))?;
+            let hash = u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+                .map_err(|_| invalid("could not parse hash"))?;
+            let mut policy = Vec::new();
+            for word in words {
+                let (m, weight) = word
+                    .split_once(':')
+                    .ok_or_else(|| invalid("no colon separator in move weight"))?;
+                let weight = weight
+                    .parse::<f32>()
+                    .map_err(|_| invalid("could not parse weight"))?;
+                if !weight.is_finite() || weight < 0.0 {
+                    return Err(invalid("weight must be finite and non-negative"));
+                }
+                policy.push((m.to_string(), weight));
+            }
+            entries.insert(hash, policy);
+        }
+        Ok(Self { entries })
+    }
+
+    /// The number of positions in the table.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the priors over the legal moves of `state`, in move generation order,
+    /// normalised to sum to one. Legal moves missing from the table get zero weight.
+    /// Returns `None` if the position is not in the table, or none of its moves are.
+    pub fn root_priors<G: Game>(&self, state: &G) -> Option<Vec<f32>> {
+        let policy = self.entries.get(&state.hash())?;
+        over_legal_moves(
+            state,
+            policy.iter().map(|(name, weight)| (name.as_str(), *weight)),
+        )
+    }
+}
+
+/// Spreads the weights of `policy`, given by move name, over the legal moves of `state`,
+/// in move generation order, normalised to sum to one. Legal moves missing from the policy
+/// get zero weight, and names that are not legal moves are ignored. Returns `None` if no
+/// legal move has any weight.
+pub fn over_legal_moves<'a, G: Game>(
+    state: &G,
+    policy: impl IntoIterator<Item = (&'a str, f32)>,
+) -> Option<Vec<f32>> {
+    let mut buffer = G::Buffer::default();
+    state.legal_moves(&mut buffer);
+    let mut priors = vec![0.0; buffer.len()];
+    for (name, weight) in policy {
+        let Ok(m) = state.parse_move(name) else {
+            continue;
+        };
+        let index = buffer.iter().position(|&legal| legal == m).unwrap();
+        priors[index] = weight;
+    }
+    let sum = priors.iter().sum::<f32>();
+    if sum <= 0.0 {
+        return None;
+    }
+    for p in &mut priors {
+        *p /= sum;
+    }
+    Some(priors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::connectfour::Connect4;
+
+    #[test]
+    fn priors_follow_move_order() {
+        let state = Connect4::new();
+        let text = format!("# a comment\n\n{:x} 4:3.0 3:1.0 9:100\n", state.hash());
+        let table = PriorTable::read_from(text.as_bytes()).unwrap();
+        assert_eq!(table.len(), 1);
+        let priors = table.root_priors(&state).unwrap();
+        assert_eq!(priors, vec![0.0, 0.0, 0.25, 0.75, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_positions_have_no_priors() {
+        let table = PriorTable::read_from(&b"0 4:1.0\n"[..]).unwrap();
+        assert_eq!(table.root_priors(&Connect4::new()), None);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert!(PriorTable::read_from(&b"zz 4:1.0\n"[..]).is_err());
+        assert!(PriorTable::read_from(&b"ab 4=1.0\n"[..]).is_err());
+        assert!(PriorTable::read_from(&b"ab 4:-1.0\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fmt::{self, Write},
+    time::Duration,
+};
+
+/// The four phases of a single MCTS iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Select,
+    Expand,
+    Simulate,
+    Backprop,
+}
+
+impl Phase {
+    pub const ALL: [Self; 4] = [Self::Select, Self::Expand, Self::Simulate, Self::Backprop];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Select => "select",
+            Self::Expand => "expand",
+            Self::Simulate => "simulate",
+            Self::Backprop => "backprop",
+        }
+    }
+}
+
+/// Accumulated wall-clock time spent in each phase of the search, the number of times
+/// a game state had to be copied, and counts of the expansions and rollouts done.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimes {
+    times: [Duration; 4],
+    copies: u64,
+    expansions: u64,
+    rollouts: u64,
+    rollout_plies: u64,
+}
+
+impl PhaseTimes {
+    pub fn add(&mut self, phase: Phase, time: Duration) {
+        self.times[phase as usize] += time;
+    }
+
+    pub const fn add_copy(&mut self) {
+        self.copies += 1;
+    }
+
+    pub const fn copies(&self) -> u64 {
+        self.copies
+    }
+
+    pub const fn add_expansion(&mut self) {
+        self.expansions += 1;
+    }
+
+    pub const fn expansions(&self) -> u64 {
+        self.expansions
+    }
+
+    /// Records the start of a rollout from a position that is not terminal.
+    pub const fn add_rollout(&mut self) {
+        self.rollouts += 1;
+    }
+
+    /// Records a move played during a rollout.
+    pub const fn add_rollout_ply(&mut self) {
+        self.rollout_plies += 1;
+    }
+
+    /// The mean number of moves played per rollout, counting only rollouts
+    /// that started from a position that was not terminal.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean_rollout_length(&self) -> f64 {
+        self.rollout_plies as f64 / self.rollouts.max(1) as f64
+    }
+
+    /// Adds the times and counts of `other`, such as those of another thread of the same search.
+    pub fn merge(&mut self, other: &Self) {
+        for (time, other) in self.times.iter_mut().zip(other.times) {
+            *time += other;
+        }
+        self.copies += other.copies;
+        self.expansions += other.expansions;
+        self.rollouts += other.rollouts;
+        self.rollout_plies += other.rollout_plies;
+    }
+
+    pub const fn get(&self, phase: Phase) -> Duration {
+        self.times[phase as usize]
+    }
+
+    /// The total time spent inside the four phases.
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+
+    /// Formats a table of the time spent in each phase as a percentage of `elapsed`,
+    /// the wall-clock time of the whole search. Time not spent in any phase
+    /// (limit checks, readouts, logging) is reported as overhead.
+    pub fn report(&self, elapsed: Duration) -> Result<String, fmt::Error> {
+        let mut buf = String::new();
+        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let overhead = elapsed.saturating_sub(self.total());
+        for (name, time) in Phase::ALL
+            .iter()
+            .map(|&phase| (phase.name(), self.get(phase)))
+            .chain(std::iter::once(("overhead", overhead)))
+        {
+            writeln!(
+                buf,
+                "{name:>10}: {:>10.3}ms {:>6.2}%",
+                time.as_secs_f64() * 1000.0,
+                time.as_secs_f64() / elapsed_secs * 100.0
+            )?;
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_includes_overhead() {
+        let mut times = PhaseTimes::default();
+        times.add(Phase::Select, Duration::from_millis(10));
+        times.add(Phase::Simulate, Duration::from_millis(30));
+        times.add(Phase::Simulate, Duration::from_millis(40));
+        assert_eq!(times.get(Phase::Simulate), Duration::from_millis(70));
+        assert_eq!(times.total(), Duration::from_millis(80));
+        let report = times.report(Duration::from_millis(100)).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].contains("select") && lines[0].ends_with("10.00%"));
+        assert!(lines[2].contains("simulate") && lines[2].ends_with("70.00%"));
+        assert!(lines[4].contains("overhead") && lines[4].ends_with("20.00%"));
+
+        for _ in 0..2 {
+            times.add_rollout();
+        }
+        for _ in 0..7 {
+            times.add_rollout_ply();
+        }
+        assert!((times.mean_rollout_length() - 3.5).abs() < 1e-9);
+    }
+}
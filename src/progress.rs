@@ -0,0 +1,179 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// How often the bar is redrawn when stderr is a terminal.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+/// How often a progress line is written when stderr is redirected.
+const REDIRECTED_INTERVAL: Duration = Duration::from_secs(5);
+const BAR_WIDTH: usize = 24;
+
+/// A progress display for long-running batch jobs, like matches and data generation.
+///
+/// Progress is written to stderr, so that results on stdout can be redirected cleanly.
+/// On a terminal the bar is redrawn in place; otherwise a plain line is written
+/// every few seconds, so that logs stay readable.
+pub struct Progress {
+    unit: &'static str,
+    total: u64,
+    done: u64,
+    message: String,
+    start: Instant,
+    last_draw: Option<Instant>,
+    interactive: bool,
+}
+
+impl Progress {
+    /// Creates a progress display for `total` items, counted in `unit`s (e.g. "games").
+    pub fn new(total: u64, unit: &'static str) -> Self {
+        Self {
+            unit,
+            total,
+            done: 0,
+            message: String::new(),
+            start: Instant::now(),
+            last_draw: None,
+            interactive: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Sets the text shown after the rate and ETA, e.g. a running score.
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// Marks one more item as done, and redraws if enough time has passed.
+    pub fn inc(&mut self) {
+        self.done = (self.done + 1).min(self.total);
+        self.draw(false);
+    }
+
+    /// Prints `text` as a line of its own on stdout, above the bar.
+    pub fn println(&mut self, text: &str) {
+        if self.interactive {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\u{001b}[K");
+            let _ = stderr.flush();
+        }
+        println!("{text}");
+        if self.interactive {
+            self.last_draw = None;
+            self.draw(false);
+        }
+    }
+
+    /// Draws the final state of the bar and moves onto a new line.
+    pub fn finish(&mut self) {
+        self.draw(true);
+    }
+
+    fn draw(&mut self, force: bool) {
+        let now = Instant::now();
+        let interval = if self.interactive {
+            REDRAW_INTERVAL
+        } else {
+            REDIRECTED_INTERVAL
+        };
+        let due = self
+            .last_draw
+            .is_none_or(|last| now.duration_since(last) >= interval);
+        if !force && !due {
+            return;
+        }
+        self.last_draw = Some(now);
+        let line = self.line(now.duration_since(self.start));
+        let mut stderr = io::stderr().lock();
+        // progress is best-effort, so failing to write it is not an error.
+        let _ = if !self.interactive {
+            writeln!(stderr, "{line}")
+        } else if force {
+            writeln!(stderr, "\r{line}\u{001b}[K")
+        } else {
+            write!(stderr, "\r{line}\u{001b}[K")
+        };
+        let _ = stderr.flush();
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn line(&self, elapsed: Duration) -> String {
+        let fraction = if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        };
+        let filled = ((fraction * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+        let eta = if self.done == 0 {
+            "?".to_string()
+        } else {
+            let remaining = (self.total - self.done) as f64 / self.done as f64;
+            format_duration(elapsed.mul_f64(remaining))
+        };
+        let mut line = format!(
+            "[{}{}] {}/{} {:>3.0}% | {:.2} {}/s | ETA {eta}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            fraction * 100.0,
+            per_second(self.done as f64, elapsed),
+            self.unit,
+        );
+        if !self.message.is_empty() {
+            line.push_str(" | ");
+            line.push_str(&self.message);
+        }
+        line
+    }
+}
+
+/// The rate of `count` events over `elapsed`, which stays finite even if no time has passed.
+pub fn per_second(count: f64, elapsed: Duration) -> f64 {
+    count / elapsed.as_secs_f64().max(1e-6)
+}
+
+/// Formats a duration as `h:mm:ss`, or `m:ss` if it is under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{secs:02}")
+    } else {
+        format!("{minutes}:{secs:02}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_formatted() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0:00");
+        assert_eq!(format_duration(Duration::from_secs(75)), "1:15");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 62)),
+            "3:01:02"
+        );
+    }
+
+    #[test]
+    fn line_shows_rate_and_eta() {
+        let mut progress = Progress::new(4, "games");
+        progress.done = 1;
+        progress.set_message("W-D-L 1-0-0".to_string());
+        let line = progress.line(Duration::from_secs(10));
+        assert!(
+            line.starts_with("[######------------------] 1/4  25%"),
+            "{line}"
+        );
+        assert!(line.contains("0.10 games/s"), "{line}");
+        assert!(line.contains("ETA 0:30"), "{line}");
+        assert!(line.ends_with("| W-D-L 1-0-0"), "{line}");
+        assert!(per_second(5.0, Duration::ZERO).is_finite());
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    elo::{self, Difference},
+    json,
+};
+
+/// The name that stands for the reference opponent in place of a match config.
+pub const REFERENCE: &str = "reference";
+
+/// The config of the reference opponent: a short search with random rollouts, on one
+/// thread and a small hash whatever the match is given, which is a fixed strength in
+/// every game, and can be given a fixed rating.
+pub const REFERENCE_CONFIG: &str = "limit=rollouts:1000,rollout_policy=random,threads=1,hash=16";
+
+/// The rating of the reference opponent, to which all ratings are anchored.
+pub const REFERENCE_RATING: f64 = 1000.0;
+
+/// The file of the rating database, in the data directory unless another is given.
+pub const DEFAULT_RATINGS_FILE: &str = "ratings.jsonl";
+
+/// The match config that `config` stands for, which is itself unless it is [`REFERENCE`].
+pub fn resolve(config: &str) -> &str {
+    if config == REFERENCE {
+        REFERENCE_CONFIG
+    } else {
+        config
+    }
+}
+
+/// The seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The date (in UTC) of `time`, in seconds since the Unix epoch, as `YYYY-MM-DD`.
+fn date(time: u64) -> String {
+    // the Gregorian calendar repeats every 400 years, and is simplest from the 1st of March.
+    let z = time / 86_400 + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// A match stored in the rating database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// The game played, with its rule variant if it has one.
+    pub game: String,
+    /// The configs of the players, as given to `match`.
+    pub configs: [String; 2],
+    /// When the match finished, in seconds since the Unix epoch.
+    pub time: u64,
+    /// The wins, draws, and losses of the first player.
+    pub results: [i32; 3],
+    /// The Elo difference of the first player, and the half-width of its confidence interval.
+    pub difference: f64,
+    pub error: f64,
+}
+
+impl Match {
+    pub fn new(game: &str, configs: [&str; 2], elo: &Difference, time: u64) -> Self {
+        Self {
+            game: game.to_string(),
+            configs: configs.map(str::to_string),
+            time,
+            results: elo.results,
+            difference: elo.difference,
+            error: elo.error,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        // a match won or lost outright has no finite difference, which JSON cannot hold.
+        let number = |x: f64| {
+            if x.is_finite() {
+                format!("{x:.1}")
+            } else {
+                "null".to_string()
+            }
+        };
+        let [wins, draws, losses] = self.results;
+        format!(
+            "{{\"game\": {}, \"configs\": {}, \"time\": {}, \"results\": [{wins}, {draws}, {losses}], \"elo\": {}, \"error\": {}}}",
+            json::string(&self.game),
+            json::strings(&self.configs),
+            self.time,
+            number(self.difference),
+            number(self.error)
+        )
+    }
+
+    fn from_json(value: &json::Value) -> Option<Self> {
+        let number = |key| match value.get(key)? {
+            json::Value::Null => Some(f64::NAN),
+            value => value.as_f64(),
+        };
+        let configs = value.get("configs")?.as_array()?;
+        let results = value.get("results")?.as_array()?;
+        #[allow(clippy::cast_possible_truncation)]
+        let count = |i: usize| results.get(i)?.as_f64().map(|x| x as i32);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Some(Self {
+            game: value.get("game")?.as_str()?.to_string(),
+            configs: [
+                configs.first()?.as_str()?.to_string(),
+                configs.get(1)?.as_str()?.to_string(),
+            ],
+            time: value.get("time")?.as_f64()? as u64,
+            results: [count(0)?, count(1)?, count(2)?],
+            difference: number("elo")?,
+            error: number("error")?,
+        })
+    }
+}
+
+/// Appends `record` to the rating database at `path`, creating it if needed.
+pub fn record(path: &Path, record: &Match) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", record.to_json())
+}
+
+/// Reads every match in the rating database at `path`, oldest first.
+pub fn read(path: &Path) -> io::Result<Vec<Match>> {
+    let text = fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            json::parse(line)
+                .ok()
+                .as_ref()
+                .and_then(Match::from_json)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: not a match: {line}", path.display(), i + 1),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// The games in the rating database, in the order they were first played.
+pub fn games(matches: &[Match]) -> Vec<&str> {
+    let mut games = Vec::new();
+    for m in matches {
+        if !games.contains(&m.game.as_str()) {
+            games.push(m.game.as_str());
+        }
+    }
+    games
+}
+
+/// The rating of a config, fitted to all of its matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rating {
+    pub config: String,
+    pub elo: f64,
+    /// The half-width of the confidence interval around `elo`.
+    pub error: f64,
+    pub games: i32,
+    /// When the config last played, in seconds since the Unix epoch.
+    pub last_played: u64,
+}
+
+/// The ratings of the configs that have played a game.
+///
+/// The ratings are the Bradley-Terry model that best fits the results of every match, with
+/// the reference opponent at [`REFERENCE_RATING`] and a draw counted as half a win.
+/// Each pair of configs that played is also given one virtual draw, so that a config that
+/// won or lost every game still has a finite rating. Configs that are not connected to the
+/// reference opponent by a chain of matches cannot be rated, and are left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ratings {
+    pub game: String,
+    /// The configs that could be rated, best first.
+    pub ranked: Vec<Rating>,
+    /// The confidence level of the error margins, in (0, 1).
+    pub confidence: f64,
+    /// The number of configs that could not be rated.
+    pub unanchored: usize,
+}
+
+impl Ratings {
+    pub fn fit(matches: &[Match], game: &str, confidence: f64) -> Self {
+        let matches = matches
+            .iter()
+            .filter(|m| m.game == game)
+            .collect::<Vec<_>>();
+        let mut configs = vec![REFERENCE];
+        for m in &matches {
+            for config in &m.configs {
+                if !configs.contains(&config.as_str()) {
+                    configs.push(config);
+                }
+            }
+        }
+        let index = |config: &str| configs.iter().position(|&c| c == config).unwrap();
+        let n = configs.len();
+        // the games and score of each config against each other.
+        let mut games = vec![vec![0.0; n]; n];
+        let mut scores = vec![vec![0.0; n]; n];
+        let mut played = vec![0; n];
+        let mut last_played = vec![0; n];
+        for m in &matches {
+            let [a, b] = [index(&m.configs[0]), index(&m.configs[1])];
+            let [wins, draws, losses] = m.results.map(f64::from);
+            if a == b {
+                continue;
+            }
+            if games[a][b] == 0.0 {
+                for (i, j) in [(a, b), (b, a)] {
+                    games[i][j] += 1.0;
+                    scores[i][j] += 0.5;
+                }
+            }
+            games[a][b] += wins + draws + losses;
+            games[b][a] += wins + draws + losses;
+            scores[a][b] += draws.mul_add(0.5, wins);
+            scores[b][a] += draws.mul_add(0.5, losses);
+            for i in [a, b] {
+                played[i] += m.results.iter().sum::<i32>();
+                last_played[i] = last_played[i].max(m.time);
+            }
+        }
+        let mut anchored = BTreeSet::from([0]);
+        let mut frontier = vec![0];
+        while let Some(i) = frontier.pop() {
+            for (j, &played) in games[i].iter().enumerate() {
+                if played > 0.0 && anchored.insert(j) {
+                    frontier.push(j);
+                }
+            }
+        }
+        // the strength of each config, 10^(elo / 400), fitted by minorization-maximization.
+        let mut strength = vec![1.0f64; n];
+        for _ in 0..10_000 {
+            let mut change = 0.0f64;
+            for &i in anchored.iter().skip(1) {
+                let expected = anchored
+                    .iter()
+                    .map(|&j| games[i][j] / (strength[i] + strength[j]))
+                    .sum::<f64>();
+                let updated = scores[i].iter().sum::<f64>() / expected;
+                change = change.max((updated / strength[i]).ln().abs());
+                strength[i] = updated;
+            }
+            if change < 1e-9 {
+                break;
+            }
+        }
+        let z = elo::phi_inv(confidence.midpoint(1.0));
+        let mut ranked = anchored
+            .iter()
+            .filter(|&&i| played[i] > 0)
+            .map(|&i| {
+                // the standard error, from the curvature of the likelihood at the fit.
+                let information = anchored
+                    .iter()
+                    .map(|&j| {
+                        let p = strength[i] / (strength[i] + strength[j]);
+                        games[i][j] * p * (1.0 - p)
+                    })
+                    .sum::<f64>();
+                let error = if i == 0 {
+                    0.0
+                } else {
+                    z * 400.0 / std::f64::consts::LN_10 / information.sqrt()
+                };
+                Rating {
+                    config: configs[i].to_string(),
+                    elo: 400.0f64.mul_add(strength[i].log10(), REFERENCE_RATING),
+                    error,
+                    games: played[i],
+                    last_played: last_played[i],
+                }
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+        Self {
+            game: game.to_string(),
+            ranked,
+            confidence,
+            unanchored: n - anchored.len(),
+        }
+    }
+
+    /// The rating of `config`, if it could be rated.
+    pub fn of(&self, config: &str) -> Option<&Rating> {
+        self.ranked.iter().find(|rating| rating.config == config)
+    }
+}
+
+impl Display for Ratings {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.game)?;
+        writeln!(f, "rank |    elo |  error |  games | last played | config")?;
+        for (rank, rating) in self.ranked.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4} | {:>6.1} | {:>6.1} | {:>6} |  {} | {}",
+                rank + 1,
+                rating.elo,
+                rating.error,
+                rating.games,
+                date(rating.last_played),
+                rating.config
+            )?;
+        }
+        write!(
+            f,
+            "errors at {:.0}% confidence, with the reference opponent ({REFERENCE_CONFIG}) at {REFERENCE_RATING}",
+            self.confidence * 100.0
+        )?;
+        if self.unanchored > 0 {
+            write!(
+                f,
+                "\n{} configs are not rated, having played no chain of matches to the reference opponent",
+                self.unanchored
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_are_fitted_to_every_match() {
+        let played = |configs: [&str; 2], wins, draws, losses, time| {
+            let elo = elo::difference(wins, losses, draws, elo::DEFAULT_CONFIDENCE);
+            Match::new("connect4", configs, &elo, time)
+        };
+        let matches = [
+            played(["strong", REFERENCE], 70, 10, 20, 1_700_000_000),
+            played([REFERENCE, "weak"], 60, 20, 20, 1_700_086_400),
+            played(["strong", "weak"], 80, 10, 10, 1_700_172_800),
+            // two configs that never met the reference, or anyone who did.
+            played(["lonely", "lonelier"], 10, 0, 0, 1_700_172_800),
+        ];
+        let path =
+            std::env::temp_dir().join(format!("iridium-ratings-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        for m in &matches {
+            record(&path, m).unwrap();
+        }
+        let read = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), 4);
+        assert_eq!(read[2].configs, ["strong", "weak"].map(str::to_string));
+        assert_eq!(read[3].results, [10, 0, 0]);
+        assert!(read[3].difference.is_nan());
+        assert_eq!(games(&read), ["connect4"]);
+
+        let ratings = Ratings::fit(&read, "connect4", elo::DEFAULT_CONFIDENCE);
+        let order = ratings
+            .ranked
+            .iter()
+            .map(|r| r.config.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["strong", REFERENCE, "weak"]);
+        assert_eq!(ratings.unanchored, 2);
+        let reference = ratings.of(REFERENCE).unwrap();
+        assert!((reference.elo - REFERENCE_RATING).abs() < 1e-9);
+        assert_eq!(reference.games, 200);
+        let strong = ratings.of("strong").unwrap();
+        assert!(strong.error > 0.0);
+        assert_eq!(date(strong.last_played), "2023-11-16");
+        assert!(Ratings::fit(&read, "chess", elo::DEFAULT_CONFIDENCE)
+            .ranked
+            .is_empty());
+
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(4_107_542_399), "2100-02-28");
+        let reference = resolve(REFERENCE)
+            .parse::<crate::mcts::Behaviour>()
+            .unwrap();
+        assert_eq!((reference.threads, reference.hash_mb), (1, 16));
+    }
+}
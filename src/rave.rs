@@ -0,0 +1,107 @@
+use crate::{game::Game, searchtree::SearchTree};
+
+/// The weight that RAVE gives the all-moves-as-first value of a child over its own mean
+/// value, by the formula of Silver (2009): `ñ / (n + ñ + 4b²nñ)`, for a child with `n`
+/// visits and `ñ` AMAF visits, where `b` is the bias of the AMAF value. The weight starts
+/// at one, and falls away as the child's own visits come to outnumber its AMAF visits.
+pub fn beta(bias: f32, visits: u64, amaf_visits: u64) -> f32 {
+    #![allow(clippy::cast_precision_loss)]
+    let (n, amaf_n) = (visits as f32, amaf_visits as f32);
+    amaf_n / (4.0 * bias * bias).mul_add(n * amaf_n, n + amaf_n)
+}
+
+/// The value of a child with `visits` visits and mean value `q` blended with its AMAF
+/// value `amaf_q` from `amaf_visits` AMAF visits, or `None` if it has no AMAF visits.
+pub fn blend(bias: f32, visits: u64, q: f32, amaf_visits: u64, amaf_q: f32) -> Option<f32> {
+    if amaf_visits == 0 {
+        return None;
+    }
+    let beta = beta(bias, visits, amaf_visits);
+    Some(beta.mul_add(amaf_q - q, q))
+}
+
+/// The moves made in one iteration of a search, below each node of its line through the
+/// tree, for the all-moves-as-first statistics of RAVE. A child of a node on the line is
+/// credited with the result of the iteration if the player choosing between the children
+/// made the child's move anywhere later in the iteration, in the tree or in the rollout.
+///
+/// Moves are keyed by [`Game::move_index`], like those of the
+/// [`HistoryTable`](crate::history::HistoryTable).
+#[derive(Clone, Debug, Default)]
+pub struct Amaf {
+    /// Whether each (player, move) pair has been made later in the iteration.
+    played: Vec<bool>,
+    /// The slots of the moves made in the rollout of the iteration, and then of the
+    /// line above it, so that they can be cleared.
+    moves: Vec<usize>,
+}
+
+impl Amaf {
+    pub fn new(move_space: usize) -> Self {
+        Self {
+            played: vec![false; move_space * 2],
+            moves: Vec::new(),
+        }
+    }
+
+    const fn slot(turn: i8, index: usize) -> usize {
+        index * 2 + (turn < 0) as usize
+    }
+
+    /// Records that `turn` made the move with `index` in the rollout of the iteration.
+    pub fn note(&mut self, turn: i8, index: usize) {
+        self.moves.push(Self::slot(turn, index));
+    }
+
+    fn mark(&mut self, slot: usize) {
+        self.played[slot] = true;
+    }
+
+    /// Credits the result `q` of the iteration whose line through `tree` ended at `leaf`
+    /// to the AMAF statistics of the children along the line, as kept by
+    /// [`Node::update_amaf`](crate::treenode::Node::update_amaf), and forgets the moves
+    /// of the iteration.
+    pub fn backprop<G: Game>(&mut self, tree: &mut SearchTree<G>, leaf: usize, q: f32) {
+        for i in 0..self.moves.len() {
+            self.mark(self.moves[i]);
+        }
+        let mut idx = leaf;
+        loop {
+            let turn = tree[idx].to_move();
+            for child in tree.children_of(idx) {
+                let index = G::move_index(tree[child].inbound_edge());
+                if self.played[Self::slot(turn, index)] {
+                    tree[child].update_amaf(q);
+                }
+            }
+            let Some(parent) = tree[idx].parent() else {
+                break;
+            };
+            // the move into this node was made by the player to move in its parent.
+            let slot = Self::slot(-turn, G::move_index(tree[idx].inbound_edge()));
+            self.moves.push(slot);
+            self.mark(slot);
+            idx = parent;
+        }
+        for &slot in &self.moves {
+            self.played[slot] = false;
+        }
+        self.moves.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amaf_values_give_way_to_visits() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert!(close(beta(0.0, 10, 30), 0.75));
+        assert!(close(beta(0.5, 0, 30), 1.0));
+        assert!(beta(0.5, 100, 30) < beta(0.5, 10, 30));
+        assert!(beta(0.5, 10, 30) < beta(0.0, 10, 30));
+        assert_eq!(blend(0.5, 10, 0.2, 0, 0.9), None);
+        assert!(close(blend(0.0, 10, 0.2, 30, 0.6).unwrap(), 0.5));
+    }
+}
@@ -1,39 +1,120 @@
-use std::io;
+use std::io::{self, Read, Write};
+
+use crate::checksum::Hashing;
+
+/// The first bytes of every record since format version 1. Records written before then
+/// (version 0) start directly with the move count, which is never this large.
+pub const RECORD_MAGIC: [u8; 4] = *b"IRGR";
+/// The format that [`GameRecord::write_to`] writes.
+///
+/// Version 1 adds, after the magic and version bytes, the game identifier and engine version
+/// as u16-length-prefixed UTF-8, and an extension blob prefixed by its u32 length; the rest of
+/// the record is laid out as in version 0. New fields go in the extension blob, which readers
+/// skip if they do not understand it, so the version only changes if the layout does.
+/// Version 2 appends a CRC-32 of the rest of the record, from the magic onwards.
+/// Version 3 puts a u16 after the evaluation of each entry: [`DENSE_POLICY`] if the whole
+/// policy follows, as in earlier versions, or otherwise the number of (u16 index, u16 rollouts)
+/// pairs that follow, one for each move with any rollouts, in index order.
+pub const RECORD_VERSION: u8 = 3;
+/// The first format version whose records end with a checksum.
+pub const CHECKSUM_VERSION: u8 = 2;
+/// The first format version whose policies may be stored sparsely.
+pub const SPARSE_POLICY_VERSION: u8 = 3;
+/// Marks a policy that is stored in full, rather than as (index, rollouts) pairs.
+pub const DENSE_POLICY: u16 = u16::MAX;
 
 /// A data structure that records data from self-play.
 #[derive(Debug, PartialEq)]
-struct GameRecord {
+pub struct GameRecord {
     /// The header of the game record.
-    header: GameRecordHeader,
+    pub header: GameRecordHeader,
     /// The entries of the game record.
-    entries: Vec<GameRecordEntry>,
+    pub entries: Vec<GameRecordEntry>,
 }
 
 /// A header for a game record.
-#[derive(Debug, PartialEq)]
-struct GameRecordHeader {
+#[derive(Debug, PartialEq, Eq)]
+pub struct GameRecordHeader {
     /// The number of moves in the game.
-    move_count: u32,
+    pub move_count: u32,
     /// The outcome of the game.
-    outcome: i8,
+    pub outcome: i8,
     /// The dimensions of action space.
-    action_space_dimensions: Vec<usize>,
+    pub action_space_dimensions: Vec<usize>,
+    /// A name for the game that is unique within its dataset, or empty if it has none.
+    pub game_id: String,
+    /// The version of the engine that played the game, or empty if it is not known.
+    pub engine_version: String,
+    /// Fields added since this version of the format, for newer readers.
+    pub extension: Vec<u8>,
 }
 
 /// A single entry in a game record.
 #[derive(Debug, PartialEq)]
-struct GameRecordEntry {
+pub struct GameRecordEntry {
     /// The number of rollouts assigned to each move.
-    policy: Vec<u16>,
+    pub policy: Vec<u16>,
     /// The move played in the game.
-    chosen_move: u32,
+    pub chosen_move: u32,
     /// The evaluation of the state.
-    evaluation: f64,
+    pub evaluation: f64,
+}
+
+impl GameRecordEntry {
+    /// Writes the policy sparsely if that is smaller, else in full after [`DENSE_POLICY`].
+    fn write_policy<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        let visited = self.policy.iter().filter(|&&p| p != 0).count();
+        // each pair takes two u16s, so a sparse policy is only smaller if
+        // fewer than half of the moves have rollouts, and never looks like DENSE_POLICY.
+        match u16::try_from(visited) {
+            Ok(pairs) if 2 * visited < self.policy.len() && self.policy.len() <= 1 << 16 => {
+                writer.write_all(&pairs.to_le_bytes())?;
+                for (index, &p) in self.policy.iter().enumerate().filter(|(_, &p)| p != 0) {
+                    writer.write_all(&u16::try_from(index).unwrap().to_le_bytes())?;
+                    writer.write_all(&p.to_le_bytes())?;
+                }
+            }
+            _ => {
+                writer.write_all(&DENSE_POLICY.to_le_bytes())?;
+                for &p in &self.policy {
+                    writer.write_all(&p.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a policy written by [`write_policy`](Self::write_policy).
+    fn read_policy<R: io::Read>(reader: &mut R, policy_dim: usize) -> io::Result<Vec<u16>> {
+        let pairs = read_u16(reader)?;
+        if pairs == DENSE_POLICY {
+            return read_dense_policy(reader, policy_dim);
+        }
+        let mut policy = vec![0; policy_dim];
+        for _ in 0..pairs {
+            let index = usize::from(read_u16(reader)?);
+            let p = read_u16(reader)?;
+            *policy.get_mut(index).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "policy index out of range")
+            })? = p;
+        }
+        Ok(policy)
+    }
+}
+
+fn read_u16<R: io::Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_dense_policy<R: io::Read>(reader: &mut R, policy_dim: usize) -> io::Result<Vec<u16>> {
+    (0..policy_dim).map(|_| read_u16(reader)).collect()
 }
 
 impl GameRecord {
     /// Creates a new game record.
-    fn new(header: GameRecordHeader) -> Self {
+    pub const fn new(header: GameRecordHeader) -> Self {
         Self {
             header,
             entries: Vec::new(),
@@ -41,13 +122,27 @@ impl GameRecord {
     }
 
     /// Adds a new entry to the game record.
-    fn add_entry(&mut self, entry: GameRecordEntry) {
+    pub fn add_entry(&mut self, entry: GameRecordEntry) {
         self.entries.push(entry);
     }
 
-    /// Writes the game record as bytes into the given `io::Write`.
-    fn write_to<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
-        let policy_dim = self.header.action_space_dimensions.iter().product();
+    /// Writes the game record as bytes into the given `io::Write`, in the current format.
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.write_as(writer, RECORD_VERSION)
+    }
+
+    /// Writes the game record in format `version`, for readers that only know older formats.
+    /// Fields that the format does not have are left out.
+    pub fn write_as<W: io::Write>(&self, writer: W, version: u8) -> io::Result<()> {
+        assert!(
+            version <= RECORD_VERSION,
+            "unknown record version {version}"
+        );
+        let mut writer = Hashing::new(writer);
+        let policy_dim: usize = self.header.action_space_dimensions.iter().product();
+        if version > 0 {
+            self.write_prefix(&mut writer, version)?;
+        }
         writer.write_all(&self.header.move_count.to_le_bytes())?;
         writer.write_all(&self.header.outcome.to_le_bytes())?;
         writer.write_all(
@@ -71,18 +166,76 @@ impl GameRecord {
                     .to_le_bytes(),
             )?;
             writer.write_all(&entry.evaluation.to_le_bytes())?;
-            for &p in &entry.policy {
-                writer.write_all(&p.to_le_bytes())?;
+            if version >= SPARSE_POLICY_VERSION {
+                entry.write_policy(&mut writer)?;
+            } else {
+                for &p in &entry.policy {
+                    writer.write_all(&p.to_le_bytes())?;
+                }
             }
         }
+        if version >= CHECKSUM_VERSION {
+            let checksum = writer.crc.finish();
+            writer.inner.write_all(&checksum.to_le_bytes())?;
+        }
         Ok(())
     }
 
-    /// Reads a game record from the given `io::Read`.
-    fn read_from<R: io::Read>(mut reader: R) -> io::Result<Self> {
-        let mut move_count_bytes = [0u8; 4];
-        reader.read_exact(&mut move_count_bytes)?;
-        let move_count = u32::from_le_bytes(move_count_bytes);
+    /// Writes the fields that version 1 added before the move count.
+    fn write_prefix<W: io::Write>(&self, writer: &mut W, version: u8) -> io::Result<()> {
+        writer.write_all(&RECORD_MAGIC)?;
+        writer.write_all(&[version])?;
+        for text in [&self.header.game_id, &self.header.engine_version] {
+            let len = u16::try_from(text.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "record strings are limited to 65535 bytes",
+                )
+            })?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(text.as_bytes())?;
+        }
+        writer.write_all(
+            &TryInto::<u32>::try_into(self.header.extension.len())
+                .unwrap()
+                .to_le_bytes(),
+        )?;
+        writer.write_all(&self.header.extension)
+    }
+
+    /// Reads a game record from the given `io::Read`, in the current format or any earlier one.
+    pub fn read_from<R: io::Read>(reader: R) -> io::Result<Self> {
+        let mut reader = Hashing::new(reader);
+        let mut first_bytes = [0u8; 4];
+        reader.read_exact(&mut first_bytes)?;
+        let mut game_id = String::new();
+        let mut engine_version = String::new();
+        let mut extension = Vec::new();
+        let mut version = [0u8; 1];
+        if first_bytes == RECORD_MAGIC {
+            reader.read_exact(&mut version)?;
+            if !(1..=RECORD_VERSION).contains(&version[0]) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported record version {}", version[0]),
+                ));
+            }
+            for text in [&mut game_id, &mut engine_version] {
+                let mut len_bytes = [0u8; 2];
+                reader.read_exact(&mut len_bytes)?;
+                let mut bytes = vec![0; usize::from(u16::from_le_bytes(len_bytes))];
+                reader.read_exact(&mut bytes)?;
+                *text = String::from_utf8(bytes).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "record string is not UTF-8")
+                })?;
+            }
+            let mut len_bytes = [0u8; 4];
+            reader.read_exact(&mut len_bytes)?;
+            extension.resize(u32::from_le_bytes(len_bytes) as usize, 0);
+            reader.read_exact(&mut extension)?;
+            reader.read_exact(&mut first_bytes)?;
+        }
+        let move_count = u32::from_le_bytes(first_bytes);
 
         let mut outcome_bytes = [0u8; 1];
         reader.read_exact(&mut outcome_bytes)?;
@@ -98,7 +251,7 @@ impl GameRecord {
             reader.read_exact(&mut dim_bytes)?;
             action_space_dimensions.push(u32::from_le_bytes(dim_bytes));
         }
-        let policy_dim = action_space_dimensions.iter().product();
+        let policy_dim = action_space_dimensions.iter().product::<u32>() as usize;
 
         let mut entries_count_bytes = [0u8; 4];
         reader.read_exact(&mut entries_count_bytes)?;
@@ -113,12 +266,11 @@ impl GameRecord {
             reader.read_exact(&mut evaluation_bytes)?;
             let evaluation = f64::from_le_bytes(evaluation_bytes);
 
-            let mut policy = Vec::new();
-            for _ in 0..policy_dim {
-                let mut p_bytes = [0u8; 2];
-                reader.read_exact(&mut p_bytes)?;
-                policy.push(u16::from_le_bytes(p_bytes));
-            }
+            let policy = if version[0] >= SPARSE_POLICY_VERSION {
+                GameRecordEntry::read_policy(&mut reader, policy_dim)?
+            } else {
+                read_dense_policy(&mut reader, policy_dim)?
+            };
 
             entries.push(GameRecordEntry {
                 policy,
@@ -127,6 +279,18 @@ impl GameRecord {
             });
         }
 
+        if version[0] >= CHECKSUM_VERSION {
+            let checksum = reader.crc.finish();
+            let mut checksum_bytes = [0u8; 4];
+            reader.inner.read_exact(&mut checksum_bytes)?;
+            if u32::from_le_bytes(checksum_bytes) != checksum {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the record checksum does not match, so the record is corrupt",
+                ));
+            }
+        }
+
         Ok(Self {
             header: GameRecordHeader {
                 move_count,
@@ -135,6 +299,9 @@ impl GameRecord {
                     .into_iter()
                     .map(|d| d as usize)
                     .collect(),
+                game_id,
+                engine_version,
+                extension,
             },
             entries,
         })
@@ -151,6 +318,9 @@ mod tests {
             move_count: 3,
             outcome: 1,
             action_space_dimensions: vec![2, 3],
+            game_id: "selfplay-7".to_string(),
+            engine_version: "1.0.0".to_string(),
+            extension: vec![1, 2, 3],
         });
         record.add_entry(GameRecordEntry {
             policy: vec![1, 2, 3, 4, 5, 6],
@@ -177,6 +347,63 @@ mod tests {
             record2.header.action_space_dimensions
         );
         assert_eq!(record.entries, record2.entries);
+        assert_eq!(record.header, record2.header);
+
+        // a record from before versioning, without the magic, identifiers, and extension.
+        let mut legacy = Vec::new();
+        record.write_as(&mut legacy, 0).unwrap();
+        let record3 = GameRecord::read_from(&legacy[..]).unwrap();
+        assert_eq!(record3.entries, record.entries);
+        assert_eq!(record3.header.game_id, "");
+        assert!(record3.header.extension.is_empty());
+
+        // every older version can still be read.
+        for version in 1..RECORD_VERSION {
+            let mut old = Vec::new();
+            record.write_as(&mut old, version).unwrap();
+            assert_eq!(GameRecord::read_from(&old[..]).unwrap(), record);
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[30] ^= 1;
+        assert!(GameRecord::read_from(&corrupt[..]).is_err());
+        bytes[4] = RECORD_VERSION + 1;
+        assert!(GameRecord::read_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn mostly_empty_policies_are_stored_sparsely() {
+        let mut record = GameRecord::new(GameRecordHeader {
+            move_count: 2,
+            outcome: 0,
+            action_space_dimensions: vec![19, 19],
+            game_id: String::new(),
+            engine_version: String::new(),
+            extension: Vec::new(),
+        });
+        let mut policy = vec![0; 361];
+        policy[0] = 7;
+        policy[180] = 900;
+        policy[360] = 1;
+        record.add_entry(GameRecordEntry {
+            policy: policy.clone(),
+            chosen_move: 180,
+            evaluation: 0.5,
+        });
+        record.add_entry(GameRecordEntry {
+            policy: vec![1; 361],
+            chosen_move: 3,
+            evaluation: 0.25,
+        });
+        let mut sparse = Vec::new();
+        record.write_to(&mut sparse).unwrap();
+        let mut dense = Vec::new();
+        record
+            .write_as(&mut dense, SPARSE_POLICY_VERSION - 1)
+            .unwrap();
+        // the first policy takes 3 pairs rather than 361 u16s, and the second is dense.
+        assert_eq!(dense.len() + 2 * 2 + 3 * 4 - 361 * 2, sparse.len());
+        assert_eq!(GameRecord::read_from(&sparse[..]).unwrap(), record);
     }
 
     #[test]
@@ -188,11 +415,15 @@ mod tests {
                 move_count: rng.u32(..),
                 outcome: rng.i8(..),
                 action_space_dimensions: (0..rng.u32(1..=3)).map(|_| rng.usize(1..=3)).collect(),
+                game_id: (0..rng.usize(..4)).map(|_| rng.alphanumeric()).collect(),
+                engine_version: String::new(),
+                extension: (0..rng.usize(..4)).map(|_| rng.u8(..)).collect(),
             });
             for _ in 0..rng.u32(..5) {
                 record.add_entry(GameRecordEntry {
+                    // a mix of dense and sparse policies.
                     policy: (0..record.header.action_space_dimensions.iter().product())
-                        .map(|_| rng.u16(..))
+                        .map(|_| if rng.bool() { 0 } else { rng.u16(..) })
                         .collect(),
                     chosen_move: rng.u32(..),
                     evaluation: rng.f64(),
@@ -0,0 +1,194 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use crate::calibration;
+
+/// The thresholds that a [`Report`] tries, in win rate for the side to move.
+const THRESHOLDS: [f64; 20] = [
+    0.01, 0.02, 0.03, 0.04, 0.05, 0.06, 0.07, 0.08, 0.09, 0.10, 0.12, 0.14, 0.16, 0.18, 0.20, 0.22,
+    0.25, 0.30, 0.35, 0.40,
+];
+
+/// The positions of one game, in order: the win rate the search predicted for the side
+/// to move, and the score of the game for that side (1 for a win, 0.5 for a draw, 0 for a loss).
+pub type Trace = Vec<(f64, f64)>;
+
+/// Reads the games of a dataset written by data generation, from its value file and
+/// its game file, which gives the number of positions of each game.
+pub fn read_traces(values: &Path, games: &Path) -> io::Result<Vec<Trace>> {
+    let mut values = calibration::read_values(values)?.into_iter();
+    let text = fs::read_to_string(games)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut traces = Vec::new();
+    for (i, line) in text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+    {
+        let entries = line
+            .split(',')
+            .nth(1)
+            .and_then(|field| field.trim().parse::<usize>().ok())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "{}:{}: expected `outcome,entries,...`: {line}",
+                    games.display(),
+                    i + 1
+                ))
+            })?;
+        let trace = values.by_ref().take(entries).collect::<Trace>();
+        if trace.len() < entries {
+            return Err(invalid(format!(
+                "the value file has fewer positions than the games of {}",
+                games.display()
+            )));
+        }
+        traces.push(trace);
+    }
+    if values.next().is_some() {
+        return Err(invalid(format!(
+            "the value file has more positions than the games of {}",
+            games.display()
+        )));
+    }
+    Ok(traces)
+}
+
+/// What would have happened if players had resigned the first time the search rated
+/// their position below some threshold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Resignations {
+    pub games: usize,
+    /// The games in which a player would have resigned.
+    pub resigned: usize,
+    /// The resignations of games that the resigning player went on to draw or win.
+    pub wrong: usize,
+    pub positions: usize,
+    /// The positions after the resignations, which would not have been searched.
+    pub saved: usize,
+}
+
+impl Resignations {
+    pub fn at(traces: &[Trace], threshold: f64) -> Self {
+        let mut resignations = Self {
+            games: traces.len(),
+            positions: traces.iter().map(Vec::len).sum(),
+            ..Self::default()
+        };
+        for trace in traces {
+            if let Some(ply) = trace.iter().position(|&(win_rate, _)| win_rate < threshold) {
+                resignations.resigned += 1;
+                resignations.wrong += usize::from(trace[ply].1 > 0.0);
+                resignations.saved += trace.len() - ply - 1;
+            }
+        }
+        resignations
+    }
+
+    /// The fraction of resignations that were wrong, or 0 if nobody resigned.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn wrong_rate(&self) -> f64 {
+        self.wrong as f64 / self.resigned.max(1) as f64
+    }
+}
+
+/// The resignations at a range of thresholds, and the highest threshold at which at most
+/// `max_wrong` of resignations would have been wrong, which saves the most positions safely.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    rows: Vec<(f64, Resignations)>,
+    max_wrong: f64,
+}
+
+impl Report {
+    pub fn new(traces: &[Trace], max_wrong: f64) -> Self {
+        let rows = THRESHOLDS
+            .iter()
+            .map(|&threshold| (threshold, Resignations::at(traces, threshold)))
+            .collect();
+        Self { rows, max_wrong }
+    }
+
+    /// The recommended threshold, or `None` if no threshold is both used and safe enough.
+    pub fn recommended(&self) -> Option<f64> {
+        self.rows
+            .iter()
+            .filter(|(_, r)| r.resigned > 0 && r.wrong_rate() <= self.max_wrong)
+            .map(|&(threshold, _)| threshold)
+            .next_back()
+    }
+}
+
+impl Display for Report {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "threshold | resigned |  wrong | wrong rate | positions saved"
+        )?;
+        for (threshold, r) in &self.rows {
+            let resigned = 100.0 * r.resigned as f64 / r.games.max(1) as f64;
+            let saved = 100.0 * r.saved as f64 / r.positions.max(1) as f64;
+            writeln!(
+                f,
+                "{threshold:>9.2} | {resigned:>7.1}% | {:>6} | {:>9.1}% | {saved:>14.1}%",
+                r.wrong,
+                100.0 * r.wrong_rate()
+            )?;
+        }
+        let games = self.rows.first().map_or(0, |(_, r)| r.games);
+        writeln!(f, "games: {games}")?;
+        match self.recommended() {
+            Some(threshold) => write!(
+                f,
+                "recommended threshold: {threshold:.2} (at most {:.1}% of resignations wrong)",
+                100.0 * self.max_wrong
+            ),
+            None => write!(
+                f,
+                "no threshold keeps wrong resignations to {:.1}%",
+                100.0 * self.max_wrong
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_resignations_are_counted() {
+        let traces = vec![
+            // resigns at 0.1 with two positions to go, and loses.
+            vec![
+                (0.5, 0.0),
+                (0.92, 1.0),
+                (0.08, 0.0),
+                (0.95, 1.0),
+                (0.0, 0.0),
+            ],
+            // would resign at 0.05, but wins.
+            vec![(0.5, 0.0), (0.04, 1.0), (0.9, 0.0)],
+            // draws without falling below 0.2.
+            vec![(0.5, 0.5), (0.25, 0.5)],
+        ];
+        let resignations = |resigned, wrong, saved| Resignations {
+            games: 3,
+            resigned,
+            wrong,
+            positions: 10,
+            saved,
+        };
+        assert_eq!(Resignations::at(&traces, 0.05), resignations(2, 1, 1));
+        assert_eq!(Resignations::at(&traces, 0.1), resignations(2, 1, 3));
+        // at 0.3 the drawn game resigns too.
+        assert_eq!(Resignations::at(&traces, 0.3), resignations(3, 2, 3));
+        assert_eq!(Report::new(&traces, 0.5).recommended(), Some(0.25));
+        assert_eq!(Report::new(&traces, 0.1).recommended(), Some(0.04));
+        assert_eq!(Report::new(&traces[1..], 0.1).recommended(), None);
+    }
+}
@@ -0,0 +1,40 @@
+/// The default scale of the logistic curve relating win probability to centipawns,
+/// chosen so that a 10:1 win/loss ratio reads as 400cp, as in the Elo model.
+pub const DEFAULT_CP_SCALE: f64 = 400.0;
+
+/// The largest magnitude of score reported, so that near-certain results stay readable.
+const MAX_CP: f64 = 10_000.0;
+
+/// Converts a win probability `q` in [0, 1] into a centipawn-like score for display,
+/// inverting `q = 1 / (1 + 10^(-cp / scale))`.
+///
+/// This only changes what is shown to the user or GUI; the search itself works with `q`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn q_to_cp(q: f64, scale: f64) -> i32 {
+    if q.is_nan() {
+        return 0;
+    }
+    let q = q.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+    let cp = scale * (q / (1.0 - q)).log10();
+    cp.clamp(-MAX_CP, MAX_CP).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cp_follows_the_logistic_curve() {
+        assert_eq!(q_to_cp(0.5, DEFAULT_CP_SCALE), 0);
+        assert_eq!(q_to_cp(10.0 / 11.0, DEFAULT_CP_SCALE), 400);
+        assert_eq!(q_to_cp(1.0 / 11.0, DEFAULT_CP_SCALE), -400);
+        assert_eq!(q_to_cp(10.0 / 11.0, 100.0), 100);
+        assert!(q_to_cp(1.0, DEFAULT_CP_SCALE) > 5_000);
+        assert_eq!(
+            q_to_cp(0.0, DEFAULT_CP_SCALE),
+            -q_to_cp(1.0, DEFAULT_CP_SCALE)
+        );
+        assert_eq!(q_to_cp(1.0, 1000.0), 10_000);
+        assert_eq!(q_to_cp(f64::NAN, DEFAULT_CP_SCALE), 0);
+    }
+}
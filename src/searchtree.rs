@@ -1,16 +1,25 @@
 #![allow(clippy::cast_precision_loss)]
 
 use std::{
+    collections::HashMap,
     fmt::{self, Display, Write},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read},
     ops::{Index, IndexMut},
+    path::Path,
 };
 
 use crate::{
-    constants::{ROOT_IDX, TREE_PRINT_DEPTH},
+    atomics::Counter,
+    constants::{LCB_MIN_VISIT_FRACTION, ROOT_IDX, TREE_PRINT_DEPTH},
     game::{Game, MoveBuffer},
-    treenode::Node,
+    treenode::{Node, Proof},
 };
 
+/// The first bytes of a saved tree, followed by [`TREE_VERSION`].
+const TREE_MAGIC: &[u8; 8] = b"iridtree";
+const TREE_VERSION: u8 = 5;
+
 /// The structure of a `SearchTree` is as follows:
 /// │            None
 /// │              ▲
@@ -44,7 +53,18 @@ pub struct SearchTree<G: Game> {
     pub root: Option<G>,
     pub nodes: Vec<Node<G>>,
     capacity: usize,
-    rollouts: u32,
+    rollouts: Counter,
+    /// The sum of the depths reached by every iteration of the search.
+    depth_sum: Counter,
+    /// The greatest depth reached by any iteration of the search.
+    max_depth: Counter,
+    /// A move buffer reused by every expansion.
+    move_buffer: G::Buffer,
+    /// With transpositions, the first node found of each state, by the hash of the state.
+    transpositions: Option<HashMap<u64, u32>>,
+    /// With transpositions, the node whose statistics each node shares: the first node
+    /// of its state, which is the node itself if it was the first.
+    representatives: Vec<u32>,
 }
 
 impl<G: Game> SearchTree<G> {
@@ -53,23 +73,91 @@ impl<G: Game> SearchTree<G> {
             root: None,
             nodes: Vec::with_capacity(capacity),
             capacity,
-            rollouts: 0,
+            rollouts: Counter::new(0),
+            depth_sum: Counter::new(0),
+            max_depth: Counter::new(0),
+            move_buffer: G::Buffer::default(),
+            transpositions: None,
+            representatives: Vec::new(),
+        }
+    }
+
+    /// Makes the tree merge the statistics of nodes of the same state, reached by different
+    /// orders of moves, if `transpositions`, so that the search runs on a graph of states
+    /// rather than a tree of lines. Nodes are the same state if [`Game::hash`] says so.
+    pub fn with_transpositions(self, transpositions: bool) -> Self {
+        Self {
+            transpositions: transpositions.then(HashMap::new),
+            ..self
+        }
+    }
+
+    /// The memory that each node of a tree takes, with or without transpositions, for
+    /// which the table keeps room to spare.
+    pub const fn node_bytes(transpositions: bool) -> usize {
+        let node = std::mem::size_of::<Node<G>>();
+        if transpositions {
+            node + std::mem::size_of::<u32>() + 2 * std::mem::size_of::<(u64, u32)>()
+        } else {
+            node
         }
     }
 
+    /// The most nodes the tree can hold.
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     pub fn root(&self) -> &Node<G> {
         self.nodes.get(ROOT_IDX).expect("SearchTree is empty")
     }
 
-    pub fn inc_rollouts(&mut self) {
-        self.rollouts += 1;
+    pub fn inc_rollouts(&self) {
+        self.rollouts.add(1);
     }
 
-    pub const fn rollouts(&self) -> u32 {
-        self.rollouts
+    pub fn rollouts(&self) -> u64 {
+        self.rollouts.get()
     }
 
-    pub fn root_rollout_distribution(&self) -> Vec<u32> {
+    /// The indices of the children of `idx`, in move generation order.
+    pub fn children_of(
+        &self,
+        idx: usize,
+    ) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + Clone {
+        self.nodes[idx].children()
+    }
+
+    /// The children of `idx`, in move generation order.
+    pub fn child_nodes(&self, idx: usize) -> &[Node<G>] {
+        &self.nodes[self.nodes[idx].children()]
+    }
+
+    /// The index of the child of `idx` that is `n`th in move generation order.
+    pub fn nth_child(&self, idx: usize, n: usize) -> usize {
+        let children = self.nodes[idx].children();
+        assert!(
+            n < children.len(),
+            "node {idx} has no child {n}, only {}",
+            children.len()
+        );
+        children.start + n
+    }
+
+    /// The index of the child of `idx` reached by playing `m`, if it has been expanded.
+    #[allow(dead_code)]
+    pub fn child_by_move(&self, idx: usize, m: G::Move) -> Option<usize> {
+        self.children_of(idx)
+            .find(|&child| self.nodes[child].inbound_edge() == m)
+    }
+
+    /// The index of the child of `idx` that `f` picks, given the children in move generation
+    /// order and returning the position of its pick among them.
+    pub fn select_child(&self, idx: usize, f: impl FnOnce(&[Node<G>]) -> usize) -> usize {
+        self.nth_child(idx, f(self.child_nodes(idx)))
+    }
+
+    pub fn root_rollout_distribution(&self) -> Vec<u64> {
         self.root()
             .children()
             .map(|idx| self.nodes[idx].visits())
@@ -80,10 +168,12 @@ impl<G: Game> SearchTree<G> {
         let mut counts = self
             .root()
             .children()
-            .map(|idx| f64::from(self.nodes[idx].visits()))
+            .map(|idx| self.nodes[idx].visits() as f64)
             .collect::<Vec<_>>();
         let total = counts.iter().sum::<f64>();
-        counts.iter_mut().for_each(|count| *count /= total);
+        for count in &mut counts {
+            *count /= total;
+        }
         counts
     }
 
@@ -94,7 +184,7 @@ impl<G: Game> SearchTree<G> {
             return Ok("No moves yet searched.".to_string());
         }
         let mut buffer = G::Buffer::default();
-        root.generate_moves(&mut buffer);
+        root.legal_moves(&mut buffer);
         assert_eq!(buffer.len(), counts.len());
         write!(buf, "[")?;
         for (&m, &count) in buffer.iter().zip(counts.iter()) {
@@ -106,45 +196,225 @@ impl<G: Game> SearchTree<G> {
 
     pub fn clear(&mut self) {
         self.nodes.clear();
+        self.representatives.clear();
+        if let Some(table) = &mut self.transpositions {
+            table.clear();
+        }
+    }
+
+    /// The node whose statistics the node at `idx` shares, which is `idx` itself unless
+    /// another node of its state was found first, with transpositions.
+    pub fn representative(&self, idx: usize) -> usize {
+        self.representatives
+            .get(idx)
+            .map_or(idx, |&representative| representative as usize)
+    }
+
+    /// Brings the statistics of the children of `idx` up to date with those of the nodes
+    /// they share them with, which the iterations of other lines may have added to.
+    pub fn share_child_stats(&self, idx: usize) {
+        if self.transpositions.is_none() {
+            return;
+        }
+        for child in self.nodes[idx].children() {
+            let representative = self.representative(child);
+            if representative != child {
+                self.nodes[child].share_stats(&self.nodes[representative]);
+            }
+        }
+    }
+
+    /// Forgets the root and every statistic of the last search, keeping the node storage.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.root = None;
+        self.rollouts.set(0);
+        self.depth_sum.set(0);
+        self.max_depth.set(0);
     }
 
     pub fn setup(&mut self, root: G) {
         self.clear();
         self.nodes
             .push(Node::new(root.turn(), None, G::Move::default()));
+        if let Some(table) = &mut self.transpositions {
+            table.insert(root.hash(), 0);
+            self.representatives.push(0);
+        }
         self.root = Some(root);
-        self.rollouts = 0;
+        self.rollouts.set(0);
+        self.depth_sum.set(0);
+        self.max_depth.set(0);
     }
 
+    /// The child of `idx` to play: the proven win with the shortest expected
+    /// [`game_length`](Node::game_length) if there is one, otherwise the most-visited
+    /// child that is not a proven loss, or, if every child is, the one that loses slowest.
+    /// Ties go to the earliest child, so the choice only depends on the statistics of the children.
     pub fn best_child_by_visits(&self, idx: usize) -> usize {
         let children = self.nodes[idx].children();
         assert!(children.end <= self.nodes.len());
+        let length = |i: usize| self.nodes[i].game_length();
+        if let Some(win) = children
+            .clone()
+            .filter(|&i| self.nodes[i].proof() == Proof::Win)
+            .min_by(|&a, &b| length(a).total_cmp(&length(b)))
+        {
+            return win;
+        }
+        let avoid_losses = children
+            .clone()
+            .any(|i| self.nodes[i].proof() != Proof::Loss);
+        if !avoid_losses {
+            return children
+                .min_by(|&a, &b| length(b).total_cmp(&length(a)))
+                .expect("Node has no children");
+        }
         // SAFETY: we know that the children are valid indices
         // because children.end <= self.nodes.len()
         children
-            .max_by_key(|&i| self.nodes.get(i).unwrap().visits())
+            .filter(|&i| self.nodes[i].proof() != Proof::Loss)
+            .min_by_key(|&i| std::cmp::Reverse(self.nodes.get(i).unwrap().visits()))
             .expect("Node has no children")
     }
 
+    /// The child of `idx` to play by lower confidence bound (as in KataGo): proven wins and
+    /// proven losses are chosen as by [`best_child_by_visits`](Self::best_child_by_visits),
+    /// but otherwise, of the children that are not proven losses and have at least
+    /// [`LCB_MIN_VISIT_FRACTION`] of the visits of the most-visited child, the one whose
+    /// win rate is highest `z` standard errors below its mean. A move whose value rests on
+    /// few rollouts is then only played if it is clearly better than the well-searched ones.
+    /// Win rates are taken to vary as a Bernoulli trial, and proven draws not at all.
+    pub fn best_child_by_lcb(&self, idx: usize, z: f64) -> usize {
+        let by_visits = self.best_child_by_visits(idx);
+        let most_visits = self.nodes[by_visits].visits();
+        if matches!(self.nodes[by_visits].proof(), Proof::Win | Proof::Loss) || most_visits == 0 {
+            return by_visits;
+        }
+        let lcb = |node: &Node<G>| {
+            if node.proof() == Proof::Draw {
+                return 0.5;
+            }
+            let q = node.win_rate();
+            z.mul_add(-(q * (1.0 - q) / node.visits() as f64).sqrt(), q)
+        };
+        let mut best = by_visits;
+        let mut best_lcb = lcb(&self.nodes[by_visits]);
+        for i in self.children_of(idx) {
+            let node = &self.nodes[i];
+            let enough_visits = node.visits() as f64 >= LCB_MIN_VISIT_FRACTION * most_visits as f64;
+            if node.proof() != Proof::Loss && node.visits() > 0 && enough_visits {
+                let bound = lcb(node);
+                if bound > best_lcb {
+                    best = i;
+                    best_lcb = bound;
+                }
+            }
+        }
+        best
+    }
+
+    /// The highest [`known_value`](Node::known_value) of the children of `idx`,
+    /// or `None` if no child has been visited or proven.
+    pub fn best_child_value(&self, idx: usize) -> Option<f64> {
+        self.nodes[idx]
+            .children()
+            .filter_map(|i| self.nodes[i].known_value())
+            .reduce(f64::max)
+    }
+
+    /// Marks the node at `idx`, whose state is terminal with the given outcome, as proven,
+    /// and propagates the proof towards the root for as long as it settles the parent (MCTS-Solver).
+    pub fn prove_terminal(&mut self, idx: usize, outcome: i8) {
+        if self.nodes[idx].proof() != Proof::Unknown {
+            return;
+        }
+        self.nodes[idx].prove_terminal(outcome);
+        let mut idx = idx;
+        while let Some(parent_idx) = self.nodes[idx].parent() {
+            let proof = match self.nodes[idx].proof() {
+                Proof::Unknown => return,
+                // the player to move in the parent can win, so the parent is lost for whoever chose it.
+                Proof::Win => Proof::Loss,
+                // otherwise, the parent is settled only once all of its children are.
+                Proof::Loss | Proof::Draw => {
+                    let mut best = Proof::Loss;
+                    for child in self.nodes[parent_idx].children() {
+                        match self.nodes[child].proof() {
+                            // a winning sibling has already settled the parent.
+                            Proof::Unknown | Proof::Win => return,
+                            Proof::Draw => best = Proof::Draw,
+                            Proof::Loss => {}
+                        }
+                    }
+                    if best == Proof::Draw {
+                        Proof::Draw
+                    } else {
+                        Proof::Win
+                    }
+                }
+            };
+            if self.nodes[parent_idx].proof() == proof {
+                return;
+            }
+            self.nodes[parent_idx].set_proof(proof);
+            idx = parent_idx;
+        }
+    }
+
+    /// Adds a virtual loss to `idx` and each of its ancestors, for an iteration of a
+    /// parallel search that has reached `idx` and is yet to backpropagate its result.
+    pub fn add_virtual_loss(&self, idx: usize) {
+        let mut idx = Some(idx);
+        while let Some(i) = idx {
+            self.nodes[i].add_virtual_loss();
+            idx = self.nodes[i].parent();
+        }
+    }
+
+    /// Takes back the virtual losses added by [`add_virtual_loss`](Self::add_virtual_loss).
+    pub fn remove_virtual_loss(&self, idx: usize) {
+        let mut idx = Some(idx);
+        while let Some(i) = idx {
+            self.nodes[i].remove_virtual_loss();
+            idx = self.nodes[i].parent();
+        }
+    }
+
+    /// Adds a child of `idx` for every legal move of `movegen_board`, its state.
+    /// Returns false, leaving the node a leaf, if the pool has no room for the children.
     #[inline(never)]
-    pub fn expand(&mut self, idx: usize, movegen_board: &G) {
+    pub fn expand(&mut self, idx: usize, movegen_board: &G) -> bool {
         let start = self.nodes.len();
         let node = self.nodes.get_mut(idx).expect("Node does not exist");
         assert!(!node.has_children(), "Node already has children");
 
-        let mut move_buffer = G::Buffer::default();
-        movegen_board.generate_moves(&mut move_buffer);
-        for m in move_buffer.iter() {
-            if self.nodes.len() == self.capacity {
-                println!("{self}");
-                panic!("SearchTree full, aborting...");
-            }
+        self.move_buffer.clear();
+        movegen_board.legal_moves(&mut self.move_buffer);
+        if !self.has_room_for(self.move_buffer.len()) {
+            return false;
+        }
+        for m in self.move_buffer.iter() {
             self.nodes
                 .push(Node::new(-movegen_board.turn(), Some(idx), *m));
         }
-        
+        if let Some(table) = &mut self.transpositions {
+            let mut child = movegen_board.clone();
+            for (i, &m) in self.move_buffer.iter().enumerate() {
+                child.push(m);
+                let fresh = u32::try_from(start + i).unwrap();
+                self.representatives.push(*table.entry(child.hash()).or_insert(fresh));
+                if G::CAN_POP {
+                    child.pop(m);
+                } else {
+                    child.clone_from(movegen_board);
+                }
+            }
+        }
+
         let node = self.nodes.get_mut(idx).expect("Node does not exist");
-        node.add_children(start, move_buffer.len());
+        node.add_children(start, self.move_buffer.len());
+        true
     }
 
     fn write_tree(
@@ -172,10 +442,9 @@ impl<G: Game> SearchTree<G> {
         }
         writeln!(
             f,
-            "visits: {}, wins: {}, winrate: {:.2}, to_move: {}",
+            "visits: {}, q: {:.3}, to_move: {}",
             node.visits(),
-            node.wins(),
-            node.win_rate(),
+            node.q(),
             node.to_move()
         )?;
         for child in node.children() {
@@ -188,60 +457,183 @@ impl<G: Game> SearchTree<G> {
         self.nodes.get(idx)
     }
 
-    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Node<G>> {
-        self.nodes.get_mut(idx)
-    }
-
     pub fn pv_string(&self) -> String {
         let mut buf = String::new();
-        let mut idx = ROOT_IDX;
-        while let Some(node) = self.nodes.get(idx) {
-            if !node.has_children() {
-                break;
-            }
-            idx = self.best_child_by_visits(idx);
-            write!(buf, "{} ", self.nodes.get(idx).unwrap().inbound_edge()).unwrap();
+        for m in self.pv() {
+            write!(buf, "{m} ").unwrap();
         }
         buf
     }
 
-    pub fn pv_depth(&self) -> usize {
-        let mut depth = 0;
+    /// The principal variation: the line of most-visited children from the root.
+    pub fn pv(&self) -> Vec<G::Move> {
+        let mut pv = Vec::new();
         let mut idx = ROOT_IDX;
         while let Some(node) = self.nodes.get(idx) {
             if !node.has_children() {
                 break;
             }
             idx = self.best_child_by_visits(idx);
-            depth += 1;
+            pv.push(self.nodes[idx].inbound_edge());
         }
-        depth
+        pv
     }
 
-    fn average_depth_of(&self, node_idx: usize) -> f64 {
-        let node = self.nodes.get(node_idx).expect("Node does not exist");
-        if node.has_children() {
-            node.children()
-                .map(|i| self.average_depth_of(i) + 1.0)
-                .sum::<f64>()
-                / node.children().len() as f64
-        } else {
-            0.0
+    /// Whether `nodes` more nodes fit in the pool.
+    pub const fn has_room_for(&self, nodes: usize) -> bool {
+        self.nodes.len() + nodes <= self.capacity
+    }
+
+    /// Writes the tree, so that its search can be picked up again with
+    /// [`read_from`](Self::read_from).
+    ///
+    /// The format is [`TREE_MAGIC`], the version, the hash of the root position,
+    /// the rollout and depth statistics, the node count, and then every node in order.
+    /// Moves are not written, since they are regenerated from the root position.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let root = self.root.as_ref().expect("SearchTree has no root");
+        w.write_all(TREE_MAGIC)?;
+        w.write_all(&[TREE_VERSION])?;
+        w.write_all(&root.hash().to_le_bytes())?;
+        w.write_all(&self.rollouts.get().to_le_bytes())?;
+        w.write_all(&self.depth_sum.get().to_le_bytes())?;
+        w.write_all(&self.max_depth.get().to_le_bytes())?;
+        w.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        for node in &self.nodes {
+            node.write_to(w)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces this tree with one written by [`write_to`](Self::write_to) from a search of `root`.
+    /// Fails if the tree is of a different position or does not fit in the pool,
+    /// in which case this tree is left empty.
+    pub fn read_from<R: Read>(&mut self, r: &mut R, root: G) -> io::Result<()> {
+        let result = self.read_nodes(r, &root);
+        match result {
+            Ok(()) => self.root = Some(root),
+            Err(_) => self.reset(),
         }
+        result
     }
 
+    fn read_nodes<R: Read>(&mut self, r: &mut R, root: &G) -> io::Result<()> {
+        fn read<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+            let mut buf = [0; N];
+            r.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        let invalid = |msg: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("invalid tree, {msg}"))
+        };
+        if &read::<8>(r)? != TREE_MAGIC {
+            return Err(invalid("not a saved search tree".to_string()));
+        }
+        let [version] = read::<1>(r)?;
+        if version != TREE_VERSION {
+            return Err(invalid(format!("unsupported version {version}")));
+        }
+        if u64::from_le_bytes(read(r)?) != root.hash() {
+            return Err(invalid("it is of a different position".to_string()));
+        }
+        self.clear();
+        self.rollouts.set(u64::from_le_bytes(read(r)?));
+        self.depth_sum.set(u64::from_le_bytes(read(r)?));
+        self.max_depth.set(u64::from_le_bytes(read(r)?));
+        let len = usize::try_from(u64::from_le_bytes(read(r)?)).unwrap_or(usize::MAX);
+        if len == 0 || len > self.capacity {
+            return Err(invalid(format!(
+                "it has {len} nodes, and there is room for {}",
+                self.capacity
+            )));
+        }
+        for idx in 0..len {
+            let node = Node::read_from(r)?;
+            let children = node.children();
+            let parent_ok = node.parent().map_or(idx == ROOT_IDX, |parent| parent < idx);
+            let children_ok =
+                children.is_empty() || (children.start > idx && children.end <= len);
+            if !parent_ok || !children_ok {
+                return Err(invalid(format!("node {idx} links outside the tree")));
+            }
+            self.nodes.push(node);
+        }
+        // children are always created after their parents, so a walk down from the root
+        // can regenerate every inbound edge.
+        let mut stack = vec![(ROOT_IDX, root.clone())];
+        while let Some((idx, state)) = stack.pop() {
+            let children = self.nodes[idx].children();
+            if children.is_empty() {
+                continue;
+            }
+            self.move_buffer.clear();
+            state.legal_moves(&mut self.move_buffer);
+            if self.move_buffer.len() != children.len() {
+                return Err(invalid(format!(
+                    "node {idx} has {} children, but {} legal moves",
+                    children.len(),
+                    self.move_buffer.len()
+                )));
+            }
+            for (child, &m) in children.zip(self.move_buffer.iter()) {
+                self.nodes[child].set_inbound_edge(m);
+                if self.nodes[child].has_children() {
+                    let mut next = state.clone();
+                    next.push(m);
+                    stack.push((child, next));
+                }
+            }
+        }
+        // the nodes of a saved tree share nothing, but the ones expanded after it may.
+        if self.transpositions.is_some() {
+            self.representatives.extend(0..u32::try_from(len).unwrap());
+        }
+        Ok(())
+    }
+
+    /// Writes the tree to `path`, replacing the file only once the new tree is fully written,
+    /// so that a crash while saving leaves the last save intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        self.write_to(&mut writer)?;
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(partial, path)
+    }
+
+    /// Replaces this tree with the one saved at `path` from a search of `root`.
+    pub fn load(&mut self, path: &Path, root: G) -> io::Result<()> {
+        self.read_from(&mut BufReader::new(File::open(path)?), root)
+    }
+
+    /// Records the depth reached by a single iteration of the search.
+    pub fn record_depth(&self, depth: usize) {
+        self.depth_sum.add(depth as u64);
+        self.max_depth.raise(depth as u64);
+    }
+
+    /// The mean depth reached by the iterations of the search so far.
     pub fn average_depth(&self) -> f64 {
-        self.average_depth_of(ROOT_IDX)
+        let rollouts = self.rollouts();
+        if rollouts == 0 {
+            0.0
+        } else {
+            self.depth_sum.get() as f64 / rollouts as f64
+        }
+    }
+
+    /// The greatest depth reached by any iteration of the search so far (the selective depth).
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.get() as usize
     }
 
     #[allow(dead_code)]
     pub fn eval(&self) -> f64 {
         let root = self.get(ROOT_IDX).expect("Root node does not exist");
-        let q = root.wins();
-        let n = root.visits();
-        assert_eq!(n, self.rollouts);
+        assert_eq!(root.visits(), self.rollouts());
         // scale [0, 1] to [-1, 1]
-        let zero_to_one = f64::from(q) / f64::from(n);
+        let zero_to_one = f64::from(root.q());
         zero_to_one.mul_add(2.0, -1.0) * f64::from(-self.nodes.first().unwrap().to_move())
     }
 }
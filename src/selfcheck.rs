@@ -0,0 +1,357 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    config::Config,
+    constants::DETERMINISTIC_SEED,
+    datageneration::VectoriseState,
+    game::{Game, MoveBuffer},
+    gamerunner::{GameRunner, Player, Termination},
+    mcts::{Behaviour, Limit, MCTSExt, MCTS},
+    record::{GameRecord, GameRecordEntry, GameRecordHeader},
+};
+
+/// The random games whose every position the move generation check looks at.
+const RANDOM_GAMES: usize = 20;
+/// The plies after which a random game is taken never to end.
+const MAX_PLIES: usize = 10_000;
+/// The rollouts of the short search.
+const SEARCH_ROLLOUTS: u32 = 1_000;
+/// The rollouts of each move of the self-play game.
+const SELF_PLAY_ROLLOUTS: u32 = 64;
+
+/// The checks that `selfcheck` runs on every game, in the order they are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Legal moves are distinct, fit the move space, round-trip through their names, and
+    /// can be unmade, in every position of some random games, which all end.
+    Movegen,
+    /// A short search from the start finishes its rollouts and chooses a legal move.
+    Search,
+    /// A game of self-play is legal from start to end, and its result is the one the rules give.
+    SelfPlay,
+    /// States and policies vectorise to the sizes the game declares, and a binary record of a
+    /// random game reads back as it was written.
+    Vectorisation,
+}
+
+impl Check {
+    pub const ALL: [Self; 4] = [
+        Self::Movegen,
+        Self::Search,
+        Self::SelfPlay,
+        Self::Vectorisation,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Movegen => "movegen",
+            Self::Search => "search",
+            Self::SelfPlay => "self-play",
+            Self::Vectorisation => "vectorise",
+        }
+    }
+}
+
+/// The result of one check of one game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Passed,
+    /// The check does not apply to the game, such as vectorisation in a game that has no
+    /// state vector.
+    Skipped,
+    Failed(String),
+}
+
+impl Status {
+    fn from_result(result: Result<bool, String>) -> Self {
+        match result {
+            Ok(true) => Self::Passed,
+            Ok(false) => Self::Skipped,
+            Err(e) => Self::Failed(e),
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "ok"),
+            Self::Skipped => write!(f, "skipped"),
+            Self::Failed(_) => write!(f, "FAILED"),
+        }
+    }
+}
+
+/// Runs every [`Check`] on `G`, searching with the settings of `config`.
+pub fn run<G: VectoriseState + MCTSExt>(config: &Config) -> Vec<(Check, Status)> {
+    let mut behaviour = Behaviour {
+        readout: false,
+        ..Behaviour::for_game::<G>()
+    };
+    config.apply(&mut behaviour);
+    behaviour.readout = false;
+    Check::ALL
+        .into_iter()
+        .map(|check| {
+            let result = match check {
+                Check::Movegen => movegen::<G>().map(|()| true),
+                Check::Search => search::<G>(&behaviour).map(|()| true),
+                Check::SelfPlay => self_play::<G>(&behaviour).map(|()| true),
+                Check::Vectorisation => vectorisation::<G>(),
+            };
+            (check, Status::from_result(result))
+        })
+        .collect()
+}
+
+/// Plays random games from the start of `G`, calling `visit` on every position before the
+/// move played from it, with the move. Fails if a game runs past [`MAX_PLIES`].
+fn random_games<G: Game>(
+    games: usize,
+    mut visit: impl FnMut(&G, G::Move) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut rng = fastrand::Rng::with_seed(DETERMINISTIC_SEED);
+    for game in 0..games {
+        let mut state = G::default();
+        let mut ply = 0;
+        while !state.is_terminal() {
+            if ply == MAX_PLIES {
+                return Err(format!(
+                    "random game {game} has not ended after {ply} plies"
+                ));
+            }
+            let mut next = state.clone();
+            next.push_random_or_pass(&mut rng);
+            let mut moves = G::Buffer::default();
+            state.legal_moves(&mut moves);
+            // the move played is the legal move that leads to the same position.
+            let played = moves.iter().copied().find(|&m| {
+                let mut child = state.clone();
+                child.push(m);
+                child == next
+            });
+            let played = played.ok_or_else(|| {
+                format!("random game {game}, ply {ply}: the random move is not legal\n{state}")
+            })?;
+            visit(&state, played)
+                .map_err(|e| format!("random game {game}, ply {ply}: {e}\n{state}"))?;
+            if next.turn() != -state.turn() {
+                return Err(format!(
+                    "random game {game}, ply {ply}: the turn does not pass after {played}"
+                ));
+            }
+            state = next;
+            ply += 1;
+        }
+        if !(-1..=1).contains(&state.evaluate()) {
+            return Err(format!(
+                "random game {game} ended with the result {}, outside [-1, 1]",
+                state.evaluate()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn movegen<G: Game>() -> Result<(), String> {
+    random_games::<G>(RANDOM_GAMES, |state, _| {
+        let mut moves = G::Buffer::default();
+        state.legal_moves(&mut moves);
+        if moves.is_empty() {
+            return Err("the game is not over, but there are no legal moves".to_string());
+        }
+        let mut sorted = moves.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        if let Some(&[m, _]) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("{m} is generated twice"));
+        }
+        for &m in moves.iter() {
+            let index = G::move_index(m);
+            if index >= G::MOVE_SPACE {
+                return Err(format!(
+                    "{m} has the index {index}, outside the move space of {}",
+                    G::MOVE_SPACE
+                ));
+            }
+            if state.parse_move(&m.to_string()) != Ok(m) {
+                return Err(format!("{m} does not parse back from its name"));
+            }
+            if G::CAN_POP {
+                let mut child = state.clone();
+                child.push(m);
+                child.pop(m);
+                if child != *state || child.hash() != state.hash() {
+                    return Err(format!("unmaking {m} does not restore the position"));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn search<G: Game + MCTSExt>(behaviour: &Behaviour) -> Result<(), String> {
+    let start = G::default();
+    let mut engine = MCTS::<G>::new(&Behaviour {
+        limit: Limit::Rollouts(u64::from(SEARCH_ROLLOUTS)),
+        // positions near the start have more moves than typical, so leave room for twice as many.
+        hash_mb: G::hash_mb_for(SEARCH_ROLLOUTS as usize * 2),
+        ..behaviour.clone()
+    });
+    let results = engine.search(&start);
+    let mut moves = G::Buffer::default();
+    start.legal_moves(&mut moves);
+    if !moves.iter().any(|&m| m == results.best_move) {
+        return Err(format!(
+            "the search chose {}, which is not legal",
+            results.best_move
+        ));
+    }
+    if results.rollouts != u64::from(SEARCH_ROLLOUTS) {
+        return Err(format!(
+            "the search did {} rollouts of {SEARCH_ROLLOUTS}",
+            results.rollouts
+        ));
+    }
+    if results.rollout_distribution.len() != moves.len() {
+        return Err(format!(
+            "the search has visits for {} moves, but there are {} legal moves",
+            results.rollout_distribution.len(),
+            moves.len()
+        ));
+    }
+    if !(0.0..=1.0).contains(&results.win_rate) {
+        return Err(format!(
+            "the win rate {} is outside [0, 1]",
+            results.win_rate
+        ));
+    }
+    Ok(())
+}
+
+fn self_play<G: Game + MCTSExt>(behaviour: &Behaviour) -> Result<(), String> {
+    let behaviour = Behaviour {
+        limit: Limit::Rollouts(u64::from(SELF_PLAY_ROLLOUTS)),
+        hash_mb: G::hash_mb_for(SELF_PLAY_ROLLOUTS as usize * 2),
+        resign_below: None,
+        draw_margin: None,
+        ..behaviour.clone()
+    };
+    let mut runner = GameRunner::<G>::new(
+        Player::Computer(MCTS::new(&behaviour)),
+        Player::Computer(MCTS::new(&behaviour)),
+    );
+    let trace = runner
+        .play_game(G::default(), false, None)
+        .ok_or("the game was interrupted")?;
+    let mut state = G::default();
+    for (ply, m) in trace.moves.iter().enumerate() {
+        let mut moves = G::Buffer::default();
+        state.legal_moves(&mut moves);
+        if !moves.iter().any(|&legal| legal == m.played) {
+            return Err(format!("ply {ply}: {} is not legal\n{state}", m.played));
+        }
+        if let Some(stats) = m
+            .search
+            .filter(|stats| !(0.0..=1.0).contains(&stats.win_rate))
+        {
+            return Err(format!(
+                "ply {ply}: the win rate {} is outside [0, 1]",
+                stats.win_rate
+            ));
+        }
+        state.push(m.played);
+    }
+    if trace.termination != Termination::Rules || !state.is_terminal() {
+        return Err(format!("the game stopped before it was over\n{state}"));
+    }
+    if trace.result != state.evaluate() {
+        return Err(format!(
+            "the game was scored {}, but the rules give {}",
+            trace.result,
+            state.evaluate()
+        ));
+    }
+    Ok(())
+}
+
+/// Returns whether `G` is vectorised at all: games without a state vector are skipped.
+fn vectorisation<G: VectoriseState>() -> Result<bool, String> {
+    if G::default().vectorise_state().data.is_empty() {
+        return Ok(false);
+    }
+    let state_size = G::state_vector_dimensions().iter().product::<usize>();
+    let mut entries = Vec::new();
+    let mut final_state = G::default();
+    random_games::<G>(1, |state, played| {
+        let vector = state.vectorise_state();
+        if vector.data.len() != state_size {
+            return Err(format!(
+                "the state vector has {} values, but its dimensions {:?} hold {state_size}",
+                vector.data.len(),
+                G::state_vector_dimensions()
+            ));
+        }
+        let mut moves = G::Buffer::default();
+        state.legal_moves(&mut moves);
+        #[allow(clippy::cast_precision_loss)]
+        let uniform = vec![1.0 / moves.len() as f64; moves.len()];
+        let policy = state.policy_vector(&uniform).data;
+        if policy.len() != G::action_space() {
+            return Err(format!(
+                "the policy vector has {} values, but the action space has {}",
+                policy.len(),
+                G::action_space()
+            ));
+        }
+        // moves that share an index overwrite each other, and the policy no longer sums to one.
+        if (policy.iter().sum::<f64>() - 1.0).abs() > 1e-9 {
+            return Err("two legal moves share a policy index".to_string());
+        }
+        entries.push(GameRecordEntry {
+            policy: policy.iter().map(|&p| u16::from(p > 0.0)).collect(),
+            chosen_move: u32::try_from(G::index_move(played)).unwrap(),
+            evaluation: 0.5,
+        });
+        final_state = state.clone();
+        final_state.push(played);
+        Ok(())
+    })?;
+    let mut record = GameRecord::new(GameRecordHeader {
+        move_count: u32::try_from(entries.len()).unwrap(),
+        outcome: final_state.evaluate(),
+        action_space_dimensions: vec![G::action_space()],
+        game_id: "selfcheck".to_string(),
+        engine_version: crate::VERSION.to_string(),
+        extension: Vec::new(),
+    });
+    for entry in entries {
+        record.add_entry(entry);
+    }
+    let mut bytes = Vec::new();
+    record
+        .write_to(&mut bytes)
+        .map_err(|e| format!("failed to write a game record: {e}"))?;
+    let read = GameRecord::read_from(&bytes[..])
+        .map_err(|e| format!("failed to read back a game record: {e}"))?;
+    if read != record {
+        return Err("a game record does not read back as it was written".to_string());
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::{connectfour::Connect4, tictactoe::TicTacToe};
+
+    #[test]
+    fn small_games_pass_every_check() {
+        let config = "hash = 1\n".parse::<Config>().unwrap();
+        for (check, status) in run::<TicTacToe>(&config)
+            .into_iter()
+            .chain(run::<Connect4>(&config))
+        {
+            assert_eq!(status, Status::Passed, "{}", check.name());
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    constants::DETERMINISTIC_SEED,
+    datageneration::{self, GameData, VectoriseState},
+    gamerunner::GameRunner,
+    mcts::{Behaviour, Limit, MCTSExt, RolloutPolicy},
+    progress::per_second,
+};
+
+/// Where the games of a generation run start from, and how they are played.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Starts<'a> {
+    /// A file of openings, one line of moves each, which the games cycle through.
+    pub openings: Option<&'a Path>,
+    /// Otherwise, the number of random moves each game starts with.
+    pub random_plies: usize,
+    /// If set, every nth game is an evaluation game, which always plays the best move.
+    pub eval_every: Option<usize>,
+}
+
+/// The progress of a generation run, reported after each game it finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameStats {
+    /// The games finished so far, including this one.
+    pub games: usize,
+    /// The games the run was asked to play.
+    pub total_games: usize,
+    /// The positions recorded from this game.
+    pub positions: usize,
+    /// The outcome of this game: 1 if the first player won, -1 if the second did, and 0 for a draw.
+    pub outcome: i8,
+    /// Whether this game was an evaluation game.
+    pub evaluation: bool,
+    /// The positions recorded from every game so far.
+    pub total_positions: usize,
+    /// The time since the run started.
+    pub elapsed: Duration,
+}
+
+impl GameStats {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn positions_per_second(&self) -> f64 {
+        per_second(self.total_positions as f64, self.elapsed)
+    }
+}
+
+/// The search settings of self-play for `G`, with the resources of `config` applied.
+pub fn behaviour<G: MCTSExt>(config: &Config) -> Behaviour {
+    let mut behaviour = Behaviour::builder()
+        .readout(false)
+        .limit(Limit::Rollouts(8_000))
+        .rollout_policy(RolloutPolicy::Random)
+        .exp_factor(5.0)
+        .root_symmetry(true)
+        .training(true)
+        .hash_mb(G::hash_mb())
+        .max_rollout_plies(G::rollout_cutoff_length())
+        .build()
+        .expect("the self-play settings are valid");
+    config.apply_resources(&mut behaviour);
+    behaviour
+}
+
+/// Plays `games` games of self-play from the configured start position of `G`, as
+/// [`Starts`] says, with the settings of [`behaviour`]. The data of each game is passed to
+/// `sink` as soon as it is finished, and then its statistics to `progress`, so that a caller
+/// can store the games as it likes and show live throughput.
+///
+/// A game cut short by Ctrl+C is dropped, and the run stops. Returns the number of games
+/// finished.
+///
+/// ```
+/// use iridium_oxide::{config::Config, games::tictactoe::TicTacToe, selfplay::{self, Starts}};
+///
+/// let config = "hash = 16\n".parse::<Config>()?;
+/// let mut positions = 0;
+/// let finished = selfplay::generate::<TicTacToe>(
+///     &config,
+///     2,
+///     &Starts::default(),
+///     |data| positions += data.entries.len(),
+///     |stats| println!("{} of {} games", stats.games, stats.total_games),
+/// )?;
+/// assert_eq!(finished, 2);
+/// assert!(positions > 0);
+/// # Ok::<(), String>(())
+/// ```
+pub fn generate<G: VectoriseState + MCTSExt>(
+    config: &Config,
+    games: usize,
+    starts: &Starts,
+    mut sink: impl FnMut(GameData),
+    mut progress: impl FnMut(&GameStats),
+) -> Result<usize, String> {
+    let start_position = config.start::<G>()?;
+    let openings = starts
+        .openings
+        .map(|path| datageneration::read_openings(&start_position, path))
+        .transpose()
+        .map_err(|e| format!("failed to read openings: {e}"))?;
+    let behaviour = behaviour::<G>(config);
+    // evaluation games play the most-visited move, with no noise in the values of rollouts.
+    let greedy = Behaviour {
+        training: false,
+        value_noise: 0.0,
+        anneal_noise: false,
+        ..behaviour.clone()
+    };
+    let rng = if behaviour.deterministic {
+        fastrand::Rng::with_seed(DETERMINISTIC_SEED)
+    } else {
+        fastrand::Rng::new()
+    };
+    let start = Instant::now();
+    let mut total_positions = 0;
+    for i in 0..games {
+        let opening = openings.as_ref().map_or_else(
+            || datageneration::random_opening(&start_position, starts.random_plies, &rng),
+            |openings| openings[i % openings.len()].clone(),
+        );
+        let evaluation = starts.eval_every.is_some_and(|n| (i + 1).is_multiple_of(n));
+        let flags = if evaluation { &greedy } else { &behaviour };
+        let Some(data) = GameRunner::<G>::play_training_game(flags, &start_position, &opening)
+        else {
+            return Ok(i);
+        };
+        let positions = data.entries.len();
+        let outcome = data.games.first().map_or(0, |game| game.outcome);
+        total_positions += positions;
+        sink(data);
+        progress(&GameStats {
+            games: i + 1,
+            total_games: games,
+            positions,
+            outcome,
+            evaluation,
+            total_positions,
+            elapsed: start.elapsed(),
+        });
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::tictactoe::TicTacToe;
+
+    #[test]
+    fn every_game_is_reported() {
+        let config = "hash = 1\ndeterministic = true\n"
+            .parse::<Config>()
+            .unwrap();
+        let starts = Starts {
+            random_plies: 2,
+            eval_every: Some(2),
+            ..Starts::default()
+        };
+        let mut data = Vec::new();
+        let mut reports = Vec::new();
+        let games = generate::<TicTacToe>(
+            &config,
+            4,
+            &starts,
+            |d| data.push(d),
+            |stats| {
+                reports.push(*stats);
+            },
+        )
+        .unwrap();
+        assert_eq!(games, 4);
+        assert_eq!(data.len(), 4);
+        assert_eq!(reports.len(), 4);
+        for (i, (stats, data)) in reports.iter().zip(&data).enumerate() {
+            assert_eq!(stats.games, i + 1);
+            assert_eq!(stats.total_games, 4);
+            assert_eq!(stats.positions, data.entries.len());
+            assert_eq!(stats.evaluation, i % 2 == 1);
+        }
+        let positions = data.iter().map(|d| d.entries.len()).sum::<usize>();
+        assert_eq!(reports[3].total_positions, positions);
+    }
+
+    #[test]
+    fn evaluation_games_play_differently_from_training_games() {
+        let config = "hash = 1\ndeterministic = true\n"
+            .parse::<Config>()
+            .unwrap();
+        // a training game then an evaluation game, both from the empty board.
+        let starts = Starts {
+            eval_every: Some(2),
+            ..Starts::default()
+        };
+        let mut games = Vec::new();
+        generate::<TicTacToe>(&config, 2, &starts, |data| games.push(data), |_| {}).unwrap();
+        let moves = |data: &GameData| {
+            data.records[0]
+                .entries
+                .iter()
+                .map(|entry| entry.chosen_move)
+                .collect::<Vec<_>>()
+        };
+        // the moves played that had fewer rollouts than another.
+        let sampled = |data: &GameData| {
+            data.records[0]
+                .entries
+                .iter()
+                .filter(|entry| {
+                    entry.policy[entry.chosen_move as usize] < *entry.policy.iter().max().unwrap()
+                })
+                .count()
+        };
+        assert_ne!(moves(&games[0]), moves(&games[1]));
+        assert!(sampled(&games[0]) > 0);
+        assert_eq!(sampled(&games[1]), 0);
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    budget::{HashBudget, HashReservation},
+    config::Config,
+    constants::DEFAULT_HASH_MB,
+    game::Game,
+    games::{
+        antichess::Antichess, chess::Chess, connectfour::Connect4, gomoku::Gomoku,
+        tictactoe::TicTacToe, ultimatetictactoe::UltimateTicTacToe,
+    },
+    json::{self, Value},
+    mcts::{Behaviour, Limit, MCTSExt, MCTS},
+    AVAILABLE_GAMES,
+};
+
+/// A game being analysed for a client, with its own engine.
+trait Session: Send {
+    /// Plays `moves` from the starting position, returning whether the game is then over.
+    fn set_position(&mut self, moves: &[&str]) -> Result<bool, String>;
+
+    /// Searches the current position, returning the result as a JSON object.
+    fn search(&mut self, limit: Option<Limit>) -> Result<String, String>;
+
+    /// The result of the last search, if there has been one.
+    fn last_result(&self) -> Option<&str>;
+}
+
+struct EngineSession<G: Game> {
+    engine: MCTS<'static, G>,
+    /// The memory of the engine's tree, given back to the server when the session is closed.
+    _hash: HashReservation,
+    limit: Limit,
+    start: G,
+    position: G,
+    last_result: Option<String>,
+}
+
+impl<G: Game + MCTSExt> EngineSession<G> {
+    fn new(
+        config: &Config,
+        variant: Option<&str>,
+        budget: &Arc<HashBudget>,
+    ) -> Result<Self, String> {
+        let start = match variant {
+            Some(variant) => G::with_variant(variant)?,
+            None => config.start()?,
+        };
+        let mut behaviour = Behaviour::for_game::<G>();
+        config.apply(&mut behaviour);
+        behaviour.readout = false;
+        // the configured hash is the memory of every session together, and each session
+        // takes what its game needs of it.
+        let hash = budget.reserve(G::hash_mb()).ok_or_else(|| {
+            format!(
+                "not enough memory for another session ({}MB free), close one first",
+                budget.free_mb()
+            )
+        })?;
+        behaviour.hash_mb = hash.mb();
+        Ok(Self {
+            engine: MCTS::new(&behaviour),
+            _hash: hash,
+            limit: behaviour.limit,
+            position: start.clone(),
+            start,
+            last_result: None,
+        })
+    }
+}
+
+impl<G: Game + MCTSExt> Session for EngineSession<G>
+where
+    G::Buffer: Send,
+{
+    fn set_position(&mut self, moves: &[&str]) -> Result<bool, String> {
+        let mut position = self.start.clone();
+        for text in moves {
+            if position.is_terminal() {
+                return Err(format!("the game is over before {text}"));
+            }
+            let m = position.parse_move(text)?;
+            position.push(m);
+        }
+        self.position = position;
+        Ok(self.position.is_terminal())
+    }
+
+    fn search(&mut self, limit: Option<Limit>) -> Result<String, String> {
+        if self.position.is_terminal() {
+            return Err("cannot search a position where the game is over".to_string());
+        }
+        if limit == Some(Limit::Infinite) {
+            return Err("a search must have a rollout or time limit".to_string());
+        }
+        self.engine.set_limit(limit.unwrap_or(self.limit));
+        let results = self.engine.search(&self.position);
+        let result = self.engine.json_result(&results);
+        self.last_result = Some(result.clone());
+        Ok(result)
+    }
+
+    fn last_result(&self) -> Option<&str> {
+        self.last_result.as_deref()
+    }
+}
+
+/// A new session of the game named `game`.
+fn new_session(
+    config: &Config,
+    game: &str,
+    variant: Option<&str>,
+    budget: &Arc<HashBudget>,
+) -> Result<Box<dyn Session>, String> {
+    fn boxed<G: Game + MCTSExt + 'static>(
+        config: &Config,
+        variant: Option<&str>,
+        budget: &Arc<HashBudget>,
+    ) -> Result<Box<dyn Session>, String>
+    where
+        G::Buffer: Send,
+    {
+        Ok(Box::new(EngineSession::<G>::new(config, variant, budget)?))
+    }
+    match game {
+        "connect4" => boxed::<Connect4>(config, variant, budget),
+        "tictactoe" => boxed::<TicTacToe>(config, variant, budget),
+        "gomoku9" => boxed::<Gomoku<9>>(config, variant, budget),
+        "gomoku13" => boxed::<Gomoku<13>>(config, variant, budget),
+        "gomoku15" => boxed::<Gomoku<15>>(config, variant, budget),
+        "gomoku19" => boxed::<Gomoku<19>>(config, variant, budget),
+        "chess" => boxed::<Chess>(config, variant, budget),
+        "antichess" => boxed::<Antichess>(config, variant, budget),
+        "uttt" => boxed::<UltimateTicTacToe>(config, variant, budget),
+        _ => Err(format!(
+            "unknown game: {game} (available games: {AVAILABLE_GAMES})"
+        )),
+    }
+}
+
+/// A session, locked while it is in use.
+type Shared = Arc<Mutex<Box<dyn Session>>>;
+
+/// The sessions of every client, each behind its own lock, so that searches of
+/// different sessions run at the same time.
+struct Sessions {
+    next_id: AtomicU64,
+    by_id: Mutex<HashMap<u64, Shared>>,
+    /// The memory that the trees of the sessions share.
+    budget: Arc<HashBudget>,
+}
+
+impl Sessions {
+    fn new(hash_mb: usize) -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            by_id: Mutex::new(HashMap::new()),
+            budget: HashBudget::new(hash_mb),
+        }
+    }
+
+    fn get(&self, request: &Value) -> Result<Shared, String> {
+        let id = session_id(request)?;
+        self.by_id
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("no such session: {id}"))
+    }
+
+    /// Carries out one request, returning the fields of a successful response.
+    fn handle(&self, config: &Config, request: &Value) -> Result<String, String> {
+        let cmd = request
+            .get("cmd")
+            .and_then(Value::as_str)
+            .ok_or("no cmd given")?;
+        match cmd {
+            "new" => {
+                let game = request
+                    .get("game")
+                    .and_then(Value::as_str)
+                    .ok_or("no game given")?;
+                let variant = request.get("variant").and_then(Value::as_str);
+                let session = new_session(config, game, variant, &self.budget)?;
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+                self.by_id
+                    .lock()
+                    .unwrap()
+                    .insert(id, Arc::new(Mutex::new(session)));
+                Ok(format!("\"session\": {id}"))
+            }
+            "position" => {
+                let moves = match request.get("moves") {
+                    None => Vec::new(),
+                    Some(moves) => moves
+                        .as_array()
+                        .and_then(|moves| moves.iter().map(Value::as_str).collect())
+                        .ok_or("moves must be an array of strings")?,
+                };
+                let over = self.get(request)?.lock().unwrap().set_position(&moves)?;
+                Ok(format!("\"game_over\": {over}"))
+            }
+            "search" => {
+                let limit = search_limit(request)?;
+                let result = self.get(request)?.lock().unwrap().search(limit)?;
+                Ok(format!("\"result\": {result}"))
+            }
+            "result" => {
+                let result = self
+                    .get(request)?
+                    .lock()
+                    .unwrap()
+                    .last_result()
+                    .map(String::from);
+                Ok(format!(
+                    "\"result\": {}",
+                    result.ok_or("no search has been run")?
+                ))
+            }
+            "close" => {
+                let id = session_id(request)?;
+                self.by_id
+                    .lock()
+                    .unwrap()
+                    .remove(&id)
+                    .ok_or_else(|| format!("no such session: {id}"))?;
+                Ok(String::new())
+            }
+            _ => Err(format!("unknown cmd: {cmd}")),
+        }
+    }
+
+    /// The response to one line of input.
+    fn respond(&self, config: &Config, line: &str) -> String {
+        match json::parse(line).and_then(|request| self.handle(config, &request)) {
+            Ok(fields) if fields.is_empty() => "{\"ok\": true}".to_string(),
+            Ok(fields) => format!("{{\"ok\": true, {fields}}}"),
+            Err(e) => format!("{{\"ok\": false, \"error\": {}}}", json::string(&e)),
+        }
+    }
+}
+
+/// The session that `request` is for.
+fn session_id(request: &Value) -> Result<u64, String> {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    request
+        .get("session")
+        .and_then(Value::as_f64)
+        .filter(|&id| id >= 1.0 && id.fract() == 0.0)
+        .map(|id| id as u64)
+        .ok_or_else(|| "no session given".to_string())
+}
+
+/// The limit of a search request, given by `rollouts` or `time_ms`, if it has one.
+fn search_limit(request: &Value) -> Result<Option<Limit>, String> {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let positive = |key| {
+        request
+            .get(key)
+            .map(|value| {
+                value
+                    .as_f64()
+                    .filter(|&n| n >= 1.0)
+                    .ok_or_else(|| format!("{key} must be a positive number"))
+            })
+            .transpose()
+    };
+    match (positive("rollouts")?, positive("time_ms")?) {
+        (Some(_), Some(_)) => Err("give only one of rollouts and time_ms".to_string()),
+        (Some(rollouts), None) => Ok(Some(Limit::Rollouts(rollouts as u64))),
+        (None, Some(ms)) => Ok(Some(Limit::Time(Duration::from_millis(ms as u64)))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Answers the requests of one client, a line of JSON each, until it disconnects.
+fn client(sessions: &Sessions, config: &Config, stream: TcpStream) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(out, "{}", sessions.respond(config, &line))?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Serves the engine over TCP on `port`, speaking one JSON object per line each way.
+///
+/// A client creates a session of a game, with its own engine, with
+/// `{"cmd": "new", "game": "connect4"}` (and optionally a `"variant"`), which responds
+/// `{"ok": true, "session": 1}`. The session's position is set, from the starting position,
+/// with `{"cmd": "position", "session": 1, "moves": ["4", "4"]}`, and searched with
+/// `{"cmd": "search", "session": 1, "rollouts": 10000}` (or `"time_ms"`, or neither for the
+/// configured limit), which responds with the `"result"` of the search.
+/// `{"cmd": "result", "session": 1}` repeats the last result, and `{"cmd": "close", "session": 1}`
+/// ends the session. Failed requests respond `{"ok": false, "error": "..."}`.
+///
+/// Sessions are shared by every connection, and each client is served on its own thread,
+/// so searches of different sessions run concurrently; requests to a session that is
+/// searching wait for the search to finish.
+///
+/// The configured hash is shared by the trees of every session, rather than reserved by
+/// each: a new session takes the memory its game needs, or what is left if that is less,
+/// and one that would be left too little is refused until another is closed.
+pub fn main(config: &Config, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("serving on {}", listener.local_addr()?);
+    let sessions = Sessions::new(config.hash_mb.unwrap_or(DEFAULT_HASH_MB));
+    std::thread::scope(|s| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sessions = &sessions;
+                    s.spawn(move || {
+                        if let Err(e) = client(sessions, config, stream) {
+                            eprintln!("serve: client disconnected: {e}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("serve: failed to accept a connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_created_searched_and_closed() {
+        let config = Config::default();
+        let sessions = Sessions::new(DEFAULT_HASH_MB);
+        let respond = |line: &str| json::parse(&sessions.respond(&config, line)).unwrap();
+        let new = respond(r#"{"cmd": "new", "game": "tictactoe"}"#);
+        assert_eq!(new.get("session"), Some(&Value::Number(1.0)));
+        let other = respond(r#"{"cmd": "new", "game": "connect4", "variant": "misere"}"#);
+        assert_eq!(other.get("session"), Some(&Value::Number(2.0)));
+        let position = respond(r#"{"cmd": "position", "session": 1, "moves": ["5", "1"]}"#);
+        assert_eq!(position.get("game_over"), Some(&Value::Bool(false)));
+        let search = respond(r#"{"cmd": "search", "session": 1, "rollouts": 500}"#);
+        let result = search.get("result").unwrap();
+        assert_eq!(result.get("rollouts").and_then(Value::as_f64), Some(500.0));
+        let best_move = result.get("best_move").and_then(Value::as_str).unwrap();
+        assert!(!["5", "1"].contains(&best_move));
+        let again = respond(r#"{"cmd": "result", "session": 1}"#);
+        assert_eq!(again.get("result"), Some(result));
+        assert_eq!(
+            respond(r#"{"cmd": "close", "session": 1}"#).get("ok"),
+            Some(&Value::Bool(true))
+        );
+        for bad in [
+            r#"{"cmd": "result", "session": 1}"#,
+            r#"{"cmd": "result", "session": 2}"#,
+            r#"{"cmd": "new", "game": "go"}"#,
+            r#"{"cmd": "position", "session": 2, "moves": ["9"]}"#,
+            r#"{"cmd": "search", "session": 2, "rollouts": 10, "time_ms": 10}"#,
+            "not json",
+        ] {
+            let response = respond(bad);
+            assert_eq!(response.get("ok"), Some(&Value::Bool(false)), "{bad}");
+            assert!(response.get("error").and_then(Value::as_str).is_some());
+        }
+    }
+
+    #[test]
+    fn sessions_share_the_hash() {
+        let config = Config::default();
+        let hash_mb = TicTacToe::hash_mb();
+        let sessions = Sessions::new(2 * hash_mb);
+        let respond = |line: &str| json::parse(&sessions.respond(&config, line)).unwrap();
+        let new = r#"{"cmd": "new", "game": "tictactoe"}"#;
+        for _ in 0..2 {
+            assert_eq!(respond(new).get("ok"), Some(&Value::Bool(true)));
+        }
+        assert_eq!(sessions.budget.free_mb(), 0);
+        assert_eq!(respond(new).get("ok"), Some(&Value::Bool(false)));
+        respond(r#"{"cmd": "close", "session": 1}"#);
+        assert_eq!(sessions.budget.free_mb(), hash_mb);
+        assert_eq!(respond(new).get("session"), Some(&Value::Number(3.0)));
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    game::Game,
+    mcts::{MCTSExt, SearchResults, MCTS},
+};
+
+/// The statistics of one root move in a [`Snapshot`].
+#[derive(Clone, Debug, PartialEq)]
+struct RootMove {
+    name: String,
+    visits: u64,
+    /// The mean value of the move for the player making it, if it was visited.
+    q: Option<f64>,
+}
+
+/// A summary of a search of one position: the root policy, principal variation, and value.
+/// Snapshots are saved so that searches by different versions or settings of the engine
+/// can be compared with [`Comparison`].
+///
+/// The file format is one field per line:
+/// ```text
+/// position <moves from the start>
+/// hash <position hash in hex>
+/// rollouts <n>
+/// value <win rate of the best move for the side to move>
+/// pv <move> <move> ...
+/// move <name> <visits> <q, or - if unvisited>
+/// ```
+/// with a `move` line for every legal move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    position: String,
+    hash: u64,
+    rollouts: u64,
+    value: f64,
+    pv: Vec<String>,
+    moves: Vec<RootMove>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the last search of `engine`, of the position `state`,
+    /// reached by playing `position` from the start.
+    pub fn of<G: Game + MCTSExt>(
+        engine: &MCTS<G>,
+        results: &SearchResults<G>,
+        state: &G,
+        position: &str,
+    ) -> Self {
+        Self {
+            position: position.split_whitespace().collect::<Vec<_>>().join(" "),
+            hash: state.hash(),
+            rollouts: results.rollouts,
+            value: results.win_rate,
+            pv: engine
+                .principal_variation()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            moves: engine
+                .root_edges()
+                .into_iter()
+                .map(|(m, visits, q)| RootMove {
+                    name: m.to_string(),
+                    visits,
+                    q,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "position {}", self.position)?;
+        writeln!(writer, "hash {:x}", self.hash)?;
+        writeln!(writer, "rollouts {}", self.rollouts)?;
+        writeln!(writer, "value {}", self.value)?;
+        writeln!(writer, "pv {}", self.pv.join(" "))?;
+        for m in &self.moves {
+            let q = m.q.map_or_else(|| "-".to_string(), |q| q.to_string());
+            writeln!(writer, "move {} {} {q}", m.name, m.visits)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut snapshot = Self {
+            position: String::new(),
+            hash: 0,
+            rollouts: 0,
+            value: 0.0,
+            pv: Vec::new(),
+            moves: Vec::new(),
+        };
+        let mut seen_hash = false;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid snapshot, {msg} on line {}: {line}", i + 1),
+                )
+            };
+            let (key, rest) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            match key {
+                "" => {}
+                "position" => snapshot.position = rest.trim().to_string(),
+                "hash" => {
+                    snapshot.hash = u64::from_str_radix(rest.trim(), 16)
+                        .map_err(|_| invalid("could not parse hash"))?;
+                    seen_hash = true;
+                }
+                "rollouts" => {
+                    snapshot.rollouts = rest
+                        .trim()
+                        .parse()
+                        .map_err(|_| invalid("could not parse rollouts"))?;
+                }
+                "value" => {
+                    snapshot.value = rest
+                        .trim()
+                        .parse()
+                        .map_err(|_| invalid("could not parse value"))?;
+                }
+                "pv" => snapshot.pv = rest.split_whitespace().map(str::to_string).collect(),
+                "move" => {
+                    let mut words = rest.split_whitespace();
+                    let (Some(name), Some(visits), Some(q), None) =
+                        (words.next(), words.next(), words.next(), words.next())
+                    else {
+                        return Err(invalid("expected `move <name> <visits> <q>`"));
+                    };
+                    let visits = visits
+                        .parse()
+                        .map_err(|_| invalid("could not parse visits"))?;
+                    let q = match q {
+                        "-" => None,
+                        q => Some(q.parse().map_err(|_| invalid("could not parse q"))?),
+                    };
+                    snapshot.moves.push(RootMove {
+                        name: name.to_string(),
+                        visits,
+                        q,
+                    });
+                }
+                _ => return Err(invalid("unknown field")),
+            }
+        }
+        if !seen_hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid snapshot, no position hash",
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    /// The fraction of root visits that went to each move.
+    #[allow(clippy::cast_precision_loss)]
+    fn policy(&self) -> impl Iterator<Item = (&RootMove, f64)> {
+        let total = self.moves.iter().map(|m| m.visits).sum::<u64>().max(1);
+        self.moves
+            .iter()
+            .map(move |m| (m, m.visits as f64 / total as f64))
+    }
+}
+
+/// One root move, as seen by the two searches of a [`Comparison`].
+#[derive(Clone, Debug, PartialEq)]
+struct MoveDiff {
+    name: String,
+    share: [f64; 2],
+    q: [Option<f64>; 2],
+}
+
+impl MoveDiff {
+    fn share_change(&self) -> f64 {
+        self.share[1] - self.share[0]
+    }
+}
+
+/// The differences between two [`Snapshot`]s of the same position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    position: String,
+    rollouts: [u64; 2],
+    value: [f64; 2],
+    pv: [Vec<String>; 2],
+    moves: Vec<MoveDiff>,
+}
+
+impl Comparison {
+    /// Compares two snapshots, which must be of the same position.
+    pub fn new(a: &Snapshot, b: &Snapshot) -> Result<Self, String> {
+        if a.hash != b.hash {
+            return Err(format!(
+                "the snapshots are of different positions (hashes {:x} and {:x})",
+                a.hash, b.hash
+            ));
+        }
+        let mut moves = a
+            .policy()
+            .map(|(m, share)| MoveDiff {
+                name: m.name.clone(),
+                share: [share, 0.0],
+                q: [m.q, None],
+            })
+            .collect::<Vec<_>>();
+        for (m, share) in b.policy() {
+            if let Some(diff) = moves.iter_mut().find(|diff| diff.name == m.name) {
+                diff.share[1] = share;
+                diff.q[1] = m.q;
+            } else {
+                moves.push(MoveDiff {
+                    name: m.name.clone(),
+                    share: [0.0, share],
+                    q: [None, m.q],
+                });
+            }
+        }
+        moves.sort_by(|x, y| y.share_change().abs().total_cmp(&x.share_change().abs()));
+        Ok(Self {
+            position: a.position.clone(),
+            rollouts: [a.rollouts, b.rollouts],
+            value: [a.value, b.value],
+            pv: [a.pv.clone(), b.pv.clone()],
+            moves,
+        })
+    }
+
+    /// The number of leading moves that the two principal variations share.
+    fn pv_agreement(&self) -> usize {
+        self.pv[0]
+            .iter()
+            .zip(&self.pv[1])
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    /// The total variation distance between the two root policies: half the sum of the
+    /// absolute differences in visit share, from 0 (identical) to 1 (disjoint).
+    fn policy_distance(&self) -> f64 {
+        self.moves
+            .iter()
+            .map(|m| m.share_change().abs())
+            .sum::<f64>()
+            / 2.0
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let position = if self.position.is_empty() {
+            "the starting position"
+        } else {
+            &self.position
+        };
+        writeln!(f, "position: {position}")?;
+        writeln!(f, "rollouts: {} vs {}", self.rollouts[0], self.rollouts[1])?;
+        writeln!(
+            f,
+            "value: {:.3} vs {:.3} ({:+.3})",
+            self.value[0],
+            self.value[1],
+            self.value[1] - self.value[0]
+        )?;
+        writeln!(f, "pv a: {}", self.pv[0].join(" "))?;
+        writeln!(f, "pv b: {}", self.pv[1].join(" "))?;
+        let agreement = self.pv_agreement();
+        if agreement == self.pv[0].len() && agreement == self.pv[1].len() {
+            writeln!(f, "the principal variations are the same")?;
+        } else {
+            writeln!(
+                f,
+                "the principal variations diverge at ply {}",
+                agreement + 1
+            )?;
+        }
+        writeln!(f, "policy distance: {:.3}", self.policy_distance())?;
+        writeln!(
+            f,
+            "  move |  share a |  share b |  change |     q a |     q b"
+        )?;
+        let q = |q: Option<f64>| q.map_or_else(|| "-".to_string(), |q| format!("{q:.3}"));
+        for m in &self.moves {
+            writeln!(
+                f,
+                "{:>6} | {:>8.3} | {:>8.3} | {:>+7.3} | {:>7} | {:>7}",
+                m.name,
+                m.share[0],
+                m.share[1],
+                m.share_change(),
+                q(m.q[0]),
+                q(m.q[1])
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "position d4\nhash 1f\nrollouts 100\nvalue 0.5\npv c3 c4\nmove c3 60 0.55\nmove c4 40 0.45\nmove c5 0 -\n";
+    const B: &str = "position d4\nhash 1f\nrollouts 200\nvalue 0.6\npv c3 c5\nmove c3 100 0.6\nmove c4 20 0.4\nmove c5 80 0.5\n";
+
+    #[test]
+    fn snapshots_round_trip() {
+        let snapshot = Snapshot::read_from(A.as_bytes()).unwrap();
+        assert_eq!(snapshot.moves[2].q, None);
+        let mut written = Vec::new();
+        snapshot.write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), A);
+        assert!(Snapshot::read_from(&b"rollouts 1\n"[..]).is_err());
+        assert!(Snapshot::read_from(&b"hash 1f\nmove c3 x 0.5\n"[..]).is_err());
+    }
+
+    #[test]
+    fn comparisons_show_where_searches_differ() {
+        let a = Snapshot::read_from(A.as_bytes()).unwrap();
+        let b = Snapshot::read_from(B.as_bytes()).unwrap();
+        let comparison = Comparison::new(&a, &b).unwrap();
+        assert_eq!(comparison.pv_agreement(), 1);
+        // c5 gained 0.4 of the visits, c4 lost 0.3, and c3 lost 0.1.
+        assert!((comparison.policy_distance() - 0.4).abs() < 1e-9);
+        assert_eq!(comparison.moves[0].name, "c5");
+        let report = comparison.to_string();
+        assert!(report.contains("diverge at ply 2"), "{report}");
+        assert!(
+            report.contains("value: 0.500 vs 0.600 (+0.100)"),
+            "{report}"
+        );
+
+        let elsewhere = Snapshot { hash: 2, ..b };
+        assert!(Comparison::new(&a, &elsewhere).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    game::{Game, MoveBuffer},
+    mcts::{Limit, MCTSExt, MCTS},
+    verify,
+};
+
+/// A position whose moves have all been solved, and in which the choice of move matters.
+pub struct SolvedPosition<G: Game> {
+    pub state: G,
+    /// The moves with the best result with perfect play.
+    pub best_moves: Vec<G::Move>,
+}
+
+impl<G: Game> SolvedPosition<G> {
+    /// Solves `state` with an alpha-beta search of `depth` plies, if that proves the
+    /// result of every move and some moves are worse than others.
+    pub fn new(state: G, depth: usize) -> Option<Self> {
+        let results = verify::solve_moves(&state, depth)?;
+        let best = results.iter().map(|&(_, result)| result).max()?;
+        if results.iter().all(|&(_, result)| result == best) {
+            return None;
+        }
+        let best_moves = results
+            .into_iter()
+            .filter(|&(_, result)| result == best)
+            .map(|(m, _)| m)
+            .collect();
+        Some(Self { state, best_moves })
+    }
+}
+
+/// Samples up to `count` solved positions from the ends of uniformly random games from
+/// `start`, each a random number of plies (at most `depth`) before its game ended, so
+/// that a search of `depth` plies can usually solve it. Gives up after `100 * count`
+/// positions that could not be used. `start` must not be the end of the game, and the
+/// game must support [`pop`](Game::pop).
+pub fn sample_positions<G: Game>(
+    start: &G,
+    count: usize,
+    depth: usize,
+    rng: &fastrand::Rng,
+) -> Vec<SolvedPosition<G>> {
+    let mut buffer = G::Buffer::default();
+    let mut positions = Vec::with_capacity(count);
+    let mut failures = 0;
+    while positions.len() < count && failures < 100 * count {
+        let mut game = vec![start.clone()];
+        let mut state = start.clone();
+        while !state.is_terminal() {
+            buffer.clear();
+            state.legal_moves(&mut buffer);
+            state.push(buffer[rng.usize(..buffer.len())]);
+            game.push(state.clone());
+        }
+        // the last position is the one where the game ended.
+        let back = rng.usize(1..=depth.clamp(1, game.len() - 1));
+        let state = game.swap_remove(game.len() - 1 - back);
+        match SolvedPosition::new(state, depth) {
+            Some(position) => positions.push(position),
+            None => failures += 1,
+        }
+    }
+    positions
+}
+
+/// The number of `positions` in which a search of `rollouts` rollouts chooses a move
+/// with the best result.
+pub fn correct_moves<G: Game + MCTSExt>(
+    engine: &mut MCTS<G>,
+    positions: &[SolvedPosition<G>],
+    rollouts: u64,
+) -> usize {
+    engine.set_limit(Limit::Rollouts(rollouts));
+    positions
+        .iter()
+        .filter(|position| {
+            engine.new_game();
+            let best_move = engine.search(&position.state).best_move;
+            position.best_moves.contains(&best_move)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        games::tictactoe::{TicTacToe, TicTacToeMove},
+        mcts::Behaviour,
+    };
+
+    #[test]
+    fn accuracy_is_measured_against_perfect_play() {
+        // X on 1 and 2, O on 4 and 5, with X to move: 3 wins, and anything else loses.
+        let mut state = TicTacToe::new();
+        for square in [0, 3, 1, 4] {
+            state.push(TicTacToeMove::new(square));
+        }
+        let position = SolvedPosition::new(state, 5).unwrap();
+        assert_eq!(position.best_moves, [TicTacToeMove::new(2)]);
+        // every move of the empty board draws, so it does not test anything.
+        assert!(SolvedPosition::new(TicTacToe::new(), 9).is_none());
+
+        let positions = sample_positions(&TicTacToe::new(), 20, 9, &fastrand::Rng::with_seed(1));
+        assert_eq!(positions.len(), 20);
+        let behaviour = Behaviour {
+            readout: false,
+            hash_mb: 1,
+            deterministic: true,
+            ..Behaviour::for_game::<TicTacToe>()
+        };
+        let mut engine = MCTS::new(&behaviour);
+        assert!(correct_moves(&mut engine, &positions, 2000) >= 18);
+        assert_eq!(correct_moves(&mut engine, &[position], 2000), 1);
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    elo,
+    game::Game,
+    gamerunner::{GameRunner, Player},
+    json,
+    mcts::{parse_cpuct_factor, Behaviour, MCTSExt, RolloutPolicy, MCTS},
+    progress::Progress,
+};
+
+/// Parses the values of a swept parameter: either a list, as in `0.5,1,2`, or a range of
+/// evenly spaced values including both ends, as in `0.5:2:4` for four values from 0.5 to 2.
+fn parse_values(text: &str) -> Result<Vec<f64>, String> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite())
+            .ok_or_else(|| format!("not a number: {s}"))
+    };
+    let parts = text.split(':').collect::<Vec<_>>();
+    match parts[..] {
+        [low, high, steps] => {
+            let (low, high) = (number(low)?, number(high)?);
+            let steps = steps
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n >= 2)
+                .ok_or_else(|| format!("a range needs at least two steps: {text}"))?;
+            Ok((0..steps)
+                .map(|i| (high - low).mul_add(f64::from(i) / f64::from(steps - 1), low))
+                .collect())
+        }
+        [_] => text.split(',').map(number).collect(),
+        _ => Err(format!(
+            "expected a list a,b,c or a range low:high:steps: {text}"
+        )),
+    }
+}
+
+/// The values of each parameter that a sweep tries. A parameter with no values is left as
+/// the baseline has it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grid {
+    pub cpuct_factors: Vec<f32>,
+    pub rollout_policies: Vec<RolloutPolicy>,
+    pub expansion_thresholds: Vec<u32>,
+}
+
+impl Grid {
+    /// Parses the values of [`Behaviour::cpuct_factor`], as a list or a range.
+    pub fn parse_cpuct_factors(text: &str) -> Result<Vec<f32>, String> {
+        #[allow(clippy::cast_possible_truncation)]
+        parse_values(text)?
+            .into_iter()
+            .map(|x| parse_cpuct_factor(&(x as f32).to_string()))
+            .collect()
+    }
+
+    /// Parses a list of rollout policies.
+    pub fn parse_rollout_policies(text: &str) -> Result<Vec<RolloutPolicy>, String> {
+        text.split(',').map(str::parse).collect()
+    }
+
+    /// Parses the values of [`Behaviour::expansion_threshold`], as a list or a range,
+    /// whose values are rounded to whole numbers.
+    pub fn parse_expansion_thresholds(text: &str) -> Result<Vec<u32>, String> {
+        let mut thresholds = parse_values(text)?
+            .into_iter()
+            .map(|x| {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                (x >= 0.0 && x <= f64::from(u32::MAX))
+                    .then(|| x.round() as u32)
+                    .ok_or_else(|| format!("not an expansion threshold: {x}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        thresholds.dedup();
+        Ok(thresholds)
+    }
+
+    /// Every combination of the values of the grid, applied to `baseline`.
+    pub fn cells(&self, baseline: &Behaviour) -> Vec<Cell> {
+        let cpuct_factors = or_baseline(&self.cpuct_factors, baseline.cpuct_factor);
+        let policies = or_baseline(&self.rollout_policies, baseline.rollout_policy.clone());
+        let thresholds = or_baseline(&self.expansion_thresholds, baseline.expansion_threshold);
+        let mut cells = Vec::new();
+        for &cpuct_factor in &cpuct_factors {
+            for rollout_policy in &policies {
+                for &expansion_threshold in &thresholds {
+                    cells.push(Cell {
+                        cpuct_factor,
+                        rollout_policy: rollout_policy.clone(),
+                        expansion_threshold,
+                    });
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// `values`, or only `baseline` if there are none.
+fn or_baseline<T: Clone>(values: &[T], baseline: T) -> Vec<T> {
+    if values.is_empty() {
+        vec![baseline]
+    } else {
+        values.to_vec()
+    }
+}
+
+/// One combination of the parameters of a [`Grid`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub cpuct_factor: f32,
+    pub rollout_policy: RolloutPolicy,
+    pub expansion_threshold: u32,
+}
+
+impl Cell {
+    /// `baseline`, with the parameters of this cell.
+    pub fn apply(&self, baseline: &Behaviour) -> Behaviour {
+        Behaviour {
+            cpuct_factor: self.cpuct_factor,
+            rollout_policy: self.rollout_policy.clone(),
+            expansion_threshold: self.expansion_threshold,
+            ..baseline.clone()
+        }
+    }
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cpuct_factor={},rollout_policy={},expansion_threshold={}",
+            self.cpuct_factor, self.rollout_policy, self.expansion_threshold
+        )
+    }
+}
+
+/// The result of the match of a cell against the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellResult {
+    pub cell: Cell,
+    pub elo: elo::Difference,
+}
+
+/// Plays `games_per_cell` games of each cell of `grid` against `baseline` from `start`, each
+/// side moving first in half of them, and returns the results, best first. A sweep
+/// interrupted by Ctrl+C returns the results of the cells it finished.
+pub fn run<G: Game + MCTSExt>(
+    baseline: &Behaviour,
+    grid: &Grid,
+    games_per_cell: usize,
+    start: &G,
+    confidence: f64,
+) -> Vec<CellResult> {
+    let cells = grid.cells(baseline);
+    let mut progress = Progress::new((cells.len() * games_per_cell) as u64, "games");
+    let mut results = Vec::new();
+    'cells: for cell in cells {
+        let behaviour = cell.apply(baseline);
+        let mut runner = GameRunner::<G>::new(
+            Player::Computer(MCTS::new(&behaviour)),
+            Player::Computer(MCTS::new(baseline)),
+        );
+        // wins, draws, and losses of the cell.
+        let mut score = [0; 3];
+        for game in 0..games_per_cell {
+            let Some(trace) = runner.play_game(start.clone(), game % 2 == 1, None) else {
+                progress.println("interrupted: stopping after the cells played so far");
+                break 'cells;
+            };
+            let outcome = match trace.first_player_result() {
+                1 => 0,
+                0 => 1,
+                _ => 2,
+            };
+            score[outcome] += 1;
+            progress.set_message(format!(
+                "{cell} W-D-L {}-{}-{}",
+                score[0], score[1], score[2]
+            ));
+            progress.inc();
+        }
+        let [wins, draws, losses] = score;
+        let elo = elo::difference(wins, losses, draws, confidence);
+        progress.println(&format!(
+            "{cell}: W-D-L {wins}-{draws}-{losses}, Elo {:+.1} ± {:.1}",
+            elo.difference, elo.error
+        ));
+        results.push(CellResult { cell, elo });
+    }
+    progress.finish();
+    results.sort_by(|a, b| b.elo.difference.total_cmp(&a.elo.difference));
+    results
+}
+
+/// The results of a sweep, as a ranked table.
+pub struct Table<'a>(pub &'a [CellResult]);
+
+impl Display for Table<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "rank | cpuct_factor | rollout_policy       | expansion | W-D-L        |    Elo |  error"
+        )?;
+        for (rank, result) in self.0.iter().enumerate() {
+            let [wins, draws, losses] = result.elo.results;
+            writeln!(
+                f,
+                "{:>4} | {:>12} | {:<20} | {:>9} | {:<12} | {:>+6.1} | {:>6.1}",
+                rank + 1,
+                result.cell.cpuct_factor,
+                result.cell.rollout_policy.to_string(),
+                result.cell.expansion_threshold,
+                format!("{wins}-{draws}-{losses}"),
+                result.elo.difference,
+                result.elo.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The results of a sweep as a JSON array, one object per cell, best first.
+pub fn to_json(results: &[CellResult]) -> String {
+    // a cell that won or lost every game has no finite difference, which JSON cannot hold.
+    let number = |x: f64| {
+        if x.is_finite() {
+            format!("{x:.1}")
+        } else {
+            "null".to_string()
+        }
+    };
+    let cells = results
+        .iter()
+        .map(|result| {
+            let [wins, draws, losses] = result.elo.results;
+            format!(
+                "  {{\"cpuct_factor\": {}, \"rollout_policy\": {}, \"expansion_threshold\": {}, \"results\": [{wins}, {draws}, {losses}], \"elo\": {}, \"error\": {}}}",
+                result.cell.cpuct_factor,
+                json::string(&result.cell.rollout_policy.to_string()),
+                result.cell.expansion_threshold,
+                number(result.elo.difference),
+                number(result.elo.error)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[\n{}\n]\n", cells.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::tictactoe::TicTacToe;
+
+    #[test]
+    fn every_cell_of_the_grid_is_played() {
+        assert_eq!(parse_values("1,2.5").unwrap(), [1.0, 2.5]);
+        assert_eq!(parse_values("0.5:2:4").unwrap(), [0.5, 1.0, 1.5, 2.0]);
+        assert!(parse_values("0:1:1").is_err());
+        assert!(parse_values("1,x").is_err());
+        assert_eq!(
+            Grid::parse_expansion_thresholds("0:3:7").unwrap(),
+            [0, 1, 2, 3]
+        );
+        assert!(Grid::parse_cpuct_factors("-1").is_err());
+
+        let baseline = Behaviour {
+            readout: false,
+            hash_mb: 1,
+            limit: crate::mcts::Limit::Rollouts(100),
+            ..Behaviour::for_game::<TicTacToe>()
+        };
+        let grid = Grid {
+            cpuct_factors: vec![0.5, 2.0],
+            rollout_policies: Grid::parse_rollout_policies("random,decisive").unwrap(),
+            expansion_thresholds: Vec::new(),
+        };
+        let cells = grid.cells(&baseline);
+        assert_eq!(cells.len(), 4);
+        assert!(cells
+            .iter()
+            .all(|cell| cell.expansion_threshold == baseline.expansion_threshold));
+        assert_eq!(
+            cells[1].to_string(),
+            format!(
+                "cpuct_factor=0.5,rollout_policy=decisive,expansion_threshold={}",
+                baseline.expansion_threshold
+            )
+        );
+
+        let results = run(
+            &baseline,
+            &grid,
+            2,
+            &TicTacToe::new(),
+            elo::DEFAULT_CONFIDENCE,
+        );
+        assert_eq!(results.len(), 4);
+        assert!(results
+            .iter()
+            .all(|r| r.elo.results.iter().sum::<i32>() == 2));
+        assert!(results
+            .windows(2)
+            .all(|w| w[0].elo.difference >= w[1].elo.difference));
+        let json = json::parse(&to_json(&results)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 4);
+        assert_eq!(Table(&results).to_string().lines().count(), 5);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::game::Game;
+
+/// Groups `moves`, the legal moves of `state`, into classes of moves that lead to
+/// equivalent positions under the symmetries that leave `state` unchanged.
+///
+/// Returns, for every move, the position in `moves` of the first move of its class,
+/// so a move is the representative of its class exactly when it maps to itself.
+pub fn classes<G: Game>(state: &G, moves: &[G::Move]) -> Vec<usize> {
+    let symmetries = (1..G::SYMMETRIES)
+        .filter(|&sym| state.is_symmetric(sym))
+        .collect::<Vec<_>>();
+    moves
+        .iter()
+        .enumerate()
+        .map(|(i, &m)| {
+            symmetries
+                .iter()
+                .filter_map(|&sym| {
+                    let image = G::transform_move(m, sym);
+                    moves.iter().position(|&other| other == image)
+                })
+                .fold(i, usize::min)
+        })
+        .collect()
+}
+
+/// Shares the visits of each class representative in `distribution` out evenly
+/// between the members of its class, as given by [`classes`].
+/// Any remainder goes to the earliest members, so the total number of visits is unchanged.
+pub fn spread_visits(distribution: &mut [u64], classes: &[usize]) {
+    assert_eq!(distribution.len(), classes.len());
+    for rep in 0..classes.len() {
+        if classes[rep] != rep {
+            continue;
+        }
+        let members = || (0..classes.len()).filter(|&i| classes[i] == rep);
+        let size = members().count() as u64;
+        let visits = distribution[rep];
+        for (n, i) in members().enumerate() {
+            let extra = u64::from((n as u64) < visits % size);
+            distribution[i] = visits / size + extra;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::MoveBuffer,
+        games::{connectfour::Connect4, tictactoe::TicTacToe},
+    };
+
+    fn moves<G: Game>(state: &G) -> Vec<G::Move> {
+        let mut buffer = G::Buffer::default();
+        state.generate_moves(&mut buffer);
+        buffer.iter().copied().collect()
+    }
+
+    #[test]
+    fn empty_tictactoe_has_three_classes() {
+        let state = TicTacToe::new();
+        let classes = classes(&state, &moves(&state));
+        // corners, edges, and the centre.
+        assert_eq!(classes, [0, 1, 0, 1, 4, 1, 0, 1, 0]);
+
+        let mut distribution = vec![9, 6, 0, 0, 1, 0, 0, 0, 0];
+        spread_visits(&mut distribution, &classes);
+        assert_eq!(distribution, [3, 2, 2, 2, 1, 1, 2, 1, 2]);
+        assert_eq!(distribution.iter().sum::<u64>(), 16);
+    }
+
+    #[test]
+    fn asymmetric_positions_are_not_folded() {
+        let mut state = TicTacToe::new();
+        let mut buffer = <TicTacToe as Game>::Buffer::default();
+        for idx in [0, 1] {
+            buffer.clear();
+            state.generate_moves(&mut buffer);
+            state.push(buffer[idx]);
+        }
+        let legal = moves(&state);
+        assert_eq!(
+            classes(&state, &legal),
+            (0..legal.len()).collect::<Vec<_>>()
+        );
+
+        let state = Connect4::new();
+        let legal = moves(&state);
+        assert_eq!(
+            classes(&state, &legal),
+            (0..legal.len()).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    game::Game,
+    gamerunner::{GameRunner, Player},
+    interrupt,
+    mcts::{Behaviour, MCTSExt, MCTS},
+    progress::Progress,
+};
+
+/// One game of a [`Schedule`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fixture {
+    /// The round of the tournament, in which every player plays at most one pairing.
+    pub round: usize,
+    /// The indices of the players, the one moving first first.
+    pub players: [usize; 2],
+    /// The random seed for both engines of the game.
+    pub seed: u64,
+    /// The index of the opening to start from, if the tournament has openings.
+    pub opening: Option<usize>,
+}
+
+/// The games of a tournament, in the order they are reported.
+///
+/// The schedule depends only on its arguments, not on how or how many threads play it, so
+/// a tournament is reproducible: each game is played from its own opening with its own seed,
+/// so with deterministic searches (a rollout limit and one search thread) every game, and so
+/// the whole tournament, comes out the same every time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    fixtures: Vec<Fixture>,
+}
+
+/// Mixes `x` into a well-distributed 64-bit value, for deriving seeds (splitmix64).
+const fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The pairings of each round of a single round-robin of `players`, by the circle method.
+fn rounds(players: usize) -> Vec<Vec<[usize; 2]>> {
+    // with an odd number of players, whoever is paired with the extra one sits out.
+    let slots = players + players % 2;
+    let mut circle = (0..slots).collect::<Vec<_>>();
+    let mut rounds = Vec::new();
+    for _ in 1..slots {
+        let round = (0..slots / 2)
+            .map(|i| [circle[i], circle[slots - 1 - i]])
+            .filter(|pair| pair.iter().all(|&p| p < players))
+            .collect();
+        rounds.push(round);
+        // keep the first player fixed, and rotate the rest.
+        circle[1..].rotate_right(1);
+    }
+    rounds
+}
+
+impl Schedule {
+    /// A round-robin tournament of `players`, in which every pair of players plays
+    /// `games_per_pairing` games, in pairs with each player moving first once from the
+    /// same opening. Openings are dealt out in a shuffled order, if there are any.
+    pub fn round_robin(
+        players: usize,
+        games_per_pairing: usize,
+        openings: usize,
+        seed: u64,
+    ) -> Result<Self, String> {
+        if players < 2 {
+            return Err("a tournament needs at least two players".to_string());
+        }
+        if games_per_pairing == 0 || !games_per_pairing.is_multiple_of(2) {
+            return Err(format!(
+                "the number of games per pairing must be positive and even: {games_per_pairing}"
+            ));
+        }
+        let mut opening_order = (0..openings).collect::<Vec<_>>();
+        fastrand::Rng::with_seed(seed).shuffle(&mut opening_order);
+        let rounds = rounds(players);
+        let mut fixtures = Vec::new();
+        let mut game_pairs = 0u64;
+        for cycle in 0..games_per_pairing / 2 {
+            for (r, pairings) in rounds.iter().enumerate() {
+                for &[a, b] in pairings {
+                    let seed = mix(seed ^ mix(game_pairs));
+                    #[allow(clippy::cast_possible_truncation)]
+                    let opening = (!opening_order.is_empty())
+                        .then(|| opening_order[game_pairs as usize % opening_order.len()]);
+                    game_pairs += 1;
+                    // alternate who moves first in the first game of each pair.
+                    let first = if cycle % 2 == 0 { [a, b] } else { [b, a] };
+                    for players in [first, [first[1], first[0]]] {
+                        fixtures.push(Fixture {
+                            round: cycle * rounds.len() + r,
+                            players,
+                            seed,
+                            opening,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(Self { fixtures })
+    }
+
+    pub fn fixtures(&self) -> &[Fixture] {
+        &self.fixtures
+    }
+
+    /// Plays every fixture with `play` on `threads` threads, which each take the next
+    /// unplayed fixture when they finish one, so that the threads stay busy however
+    /// long the games of each pairing take. The results are in schedule order.
+    pub fn play<R, F>(&self, threads: usize, play: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(&Fixture) -> R + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let games = self.fixtures.len();
+        let results = Mutex::new((0..games).map(|_| None).collect::<Vec<_>>());
+        std::thread::scope(|s| {
+            for _ in 0..threads.clamp(1, games.max(1)) {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(fixture) = self.fixtures.get(i) else {
+                        break;
+                    };
+                    let result = play(fixture);
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("every fixture was played"))
+            .collect()
+    }
+}
+
+/// Plays `fixture` between the engines of `behaviours` it names, from `start` after the
+/// moves of its opening, with both engines seeded by its seed. Returns the result for the
+/// player moving first, or `None` if the game was interrupted by Ctrl+C.
+pub fn play_fixture<G: Game + MCTSExt>(
+    fixture: &Fixture,
+    behaviours: &[Behaviour],
+    start: &G,
+    openings: &[Vec<G::Move>],
+    adjudicate: Option<usize>,
+) -> Option<i8> {
+    let engine = |player: usize| {
+        let mut engine = MCTS::new(&behaviours[player]);
+        engine.set_seed(fixture.seed);
+        Player::Computer(engine)
+    };
+    let [first, second] = fixture.players;
+    let mut runner = GameRunner::<G>::new(engine(first), engine(second));
+    let mut start = start.clone();
+    for &m in fixture.opening.map_or(&[][..], |i| &openings[i]) {
+        start.push(m);
+    }
+    runner
+        .play_game(start, false, adjudicate)
+        .map(|trace| trace.first_player_result())
+}
+
+/// Plays every game of `schedule` on `threads` threads, showing the progress of the
+/// tournament, and returns the standings of the `behaviours`. The games that were not
+/// finished before Ctrl+C are left out.
+pub fn run<G: Game + MCTSExt>(
+    schedule: &Schedule,
+    behaviours: &[Behaviour],
+    start: &G,
+    openings: &[Vec<G::Move>],
+    threads: usize,
+    adjudicate: Option<usize>,
+) -> Standings {
+    let progress = Mutex::new(Progress::new(schedule.fixtures.len() as u64, "games"));
+    let results = schedule.play(threads, |fixture| {
+        // the games not yet started when Ctrl+C arrives are not played.
+        let result = (!interrupt::requested())
+            .then(|| play_fixture(fixture, behaviours, start, openings, adjudicate))
+            .flatten();
+        progress.lock().unwrap().inc();
+        result
+    });
+    progress.into_inner().unwrap().finish();
+    let mut standings = Standings::new(behaviours.len());
+    for (fixture, result) in schedule.fixtures.iter().zip(results) {
+        if let Some(result) = result {
+            standings.record(fixture.players, result);
+        }
+    }
+    standings
+}
+
+/// The wins, draws, and losses of every player of a tournament.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Standings {
+    results: Vec<[u32; 3]>,
+}
+
+impl Standings {
+    pub fn new(players: usize) -> Self {
+        Self {
+            results: vec![[0; 3]; players],
+        }
+    }
+
+    /// Records a game between `players`, with `result` for the one moving first.
+    pub fn record(&mut self, [first, second]: [usize; 2], result: i8) {
+        let outcome = |result: i8| match result {
+            1 => 0,
+            0 => 1,
+            _ => 2,
+        };
+        self.results[first][outcome(result)] += 1;
+        self.results[second][outcome(-result)] += 1;
+    }
+
+    /// The points of `player`, one for each win and a half for each draw.
+    pub fn points(&self, player: usize) -> f64 {
+        let [wins, draws, _] = self.results[player];
+        f64::from(wins) + f64::from(draws) / 2.0
+    }
+
+    /// The players from most points to fewest, in the order they were given when tied.
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut players = (0..self.results.len()).collect::<Vec<_>>();
+        players.sort_by(|&a, &b| self.points(b).total_cmp(&self.points(a)));
+        players
+    }
+}
+
+/// The standings of a tournament as a table of the players from first to last.
+pub struct Table<'a> {
+    pub standings: &'a Standings,
+    pub names: &'a [String],
+}
+
+impl Display for Table<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let width = self.names.iter().map(String::len).max().unwrap_or(0);
+        writeln!(f, "rank | {:<width$} | points | W-D-L", "player")?;
+        for (rank, player) in self.standings.ranking().into_iter().enumerate() {
+            let [wins, draws, losses] = self.standings.results[player];
+            writeln!(
+                f,
+                "{:>4} | {:<width$} | {:>6.1} | {wins}-{draws}-{losses}",
+                rank + 1,
+                self.names[player],
+                self.standings.points(player)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_pair_plays_each_colour_equally() {
+        let schedule = Schedule::round_robin(5, 4, 3, 7).unwrap();
+        // 10 pairings of 4 games.
+        assert_eq!(schedule.fixtures().len(), 40);
+        for a in 0..5 {
+            for b in (0..5).filter(|&b| b != a) {
+                let games = schedule
+                    .fixtures()
+                    .iter()
+                    .filter(|f| f.players == [a, b])
+                    .count();
+                assert_eq!(games, 2, "{a} against {b}");
+            }
+        }
+        // nobody plays more than one pairing, of two games, in a round.
+        for round in 0..=schedule.fixtures().last().unwrap().round {
+            let mut games = [0; 5];
+            for f in schedule.fixtures().iter().filter(|f| f.round == round) {
+                for p in f.players {
+                    games[p] += 1;
+                }
+            }
+            assert!(games.iter().all(|&n| n == 0 || n == 2), "{games:?}");
+        }
+        assert!(schedule.fixtures().iter().all(|f| f.opening.unwrap() < 3));
+        assert!(Schedule::round_robin(1, 2, 0, 7).is_err());
+        assert!(Schedule::round_robin(4, 3, 0, 7).is_err());
+    }
+
+    #[test]
+    fn schedules_and_results_are_reproducible() {
+        let schedule = Schedule::round_robin(4, 2, 0, 99).unwrap();
+        assert_eq!(schedule, Schedule::round_robin(4, 2, 0, 99).unwrap());
+        assert_ne!(schedule, Schedule::round_robin(4, 2, 0, 100).unwrap());
+        let play = |f: &Fixture| f.seed ^ f.players[0] as u64;
+        let one = schedule.play(1, play);
+        assert_eq!(one, schedule.play(4, play));
+        assert_eq!(one.len(), schedule.fixtures().len());
+    }
+
+    #[test]
+    fn standings_rank_players_by_points() {
+        let mut standings = Standings::new(3);
+        standings.record([0, 1], -1);
+        standings.record([1, 2], 0);
+        standings.record([2, 0], 1);
+        assert_eq!(standings.ranking(), [1, 2, 0]);
+        assert!((standings.points(1) - 1.5).abs() < f64::EPSILON);
+        assert!((standings.points(0)).abs() < f64::EPSILON);
+    }
+}
@@ -1,8 +1,28 @@
 #![allow(clippy::cast_precision_loss)]
 
-use std::{fmt::Display, ops::Range};
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+    ops::Range,
+};
 
-use crate::game::Game;
+use crate::{
+    atomics::{AtomicF32, AtomicF64, Counter, SmallCounter},
+    game::Game,
+};
+
+/// The game-theoretic value of a node, from the perspective of the player who chose it,
+/// if the search has proven it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Proof {
+    Unknown,
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The bit of the proof byte of a written node that says whether its state is terminal.
+const TERMINAL_FLAG: u8 = 0x10;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Node<G: Game> {
@@ -10,10 +30,24 @@ pub struct Node<G: Game> {
     n_children: u16,     // 2 bytes.
     parent: Option<u32>, // 5 bytes.
 
-    value: f32,      // 4 bytes.
-    visits: u32,     // 4 bytes.
+    // the statistics are atomics, as the threads of a parallel search update them at once.
+    value: AtomicF64, // 8 bytes. running mean of the values backpropagated through this node,
+    // or the value of its best child, with minimax backup.
+    visits: Counter,   // 8 bytes.
+    length: AtomicF32, // 4 bytes. running mean of the plies from this node to the end of
+    // the rollouts backpropagated through it.
     perspective: i8, // 1 byte.
-
+    proof: Proof,    // 1 byte.
+    terminal: bool,  // 1 byte. whether the state of this node is known to be terminal,
+    // in which case its proof is the result of the game.
+    virtual_loss: SmallCounter, // 2 bytes. the iterations of a parallel search that have passed
+    // through this node and not yet backpropagated, each counted
+    // as a loss in selection.
+    amaf_value: f64, // 8 bytes. running mean of the results credited to this node by RAVE,
+    // of the iterations in which its move was made later by the same player.
+    amaf_visits: u64, // 8 bytes.
+    prior: f32,       // 4 bytes. the weight of this node's move in the policy of its parent,
+    // as given by a network, or one if there is none.
     inbound_edge: G::Move, // ??? bytes.
 }
 
@@ -28,9 +62,16 @@ impl<G: Game> Node<G> {
             first_child: 0,
             n_children: 0,
             parent: parent.map(|p| p.try_into().unwrap()),
-            value: 0.0,
-            visits: 0,
+            value: AtomicF64::new(0.0),
+            visits: Counter::new(0),
+            length: AtomicF32::new(0.0),
             perspective,
+            proof: Proof::Unknown,
+            terminal: false,
+            virtual_loss: SmallCounter::new(0),
+            amaf_value: 0.0,
+            amaf_visits: 0,
+            prior: 1.0,
             inbound_edge,
         }
     }
@@ -47,12 +88,53 @@ impl<G: Game> Node<G> {
         -self.perspective
     }
 
-    pub const fn wins(&self) -> f32 {
-        self.value
+    /// The mean value of this node, in [0, 1], from the perspective of the player who chose it.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn q(&self) -> f32 {
+        self.value.get() as f32
+    }
+
+    pub fn visits(&self) -> u64 {
+        self.visits.get()
+    }
+
+    /// The visits and mean value of this node as selection sees them, with every iteration
+    /// still in flight through it counted as a visit that lost, so that the threads of a
+    /// parallel search spread out over the tree rather than all following the same line.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn selection_stats(&self) -> (u64, f32) {
+        let (value, visits) = (self.value.get(), self.visits());
+        let virtual_loss = self.virtual_loss.get();
+        if virtual_loss == 0 {
+            return (visits, value as f32);
+        }
+        let with_losses = visits + u64::from(virtual_loss);
+        let q = value * visits as f64 / with_losses as f64;
+        (with_losses, q as f32)
+    }
+
+    /// The AMAF visits and mean AMAF value of this node, see [`update_amaf`](Self::update_amaf).
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn amaf_stats(&self) -> (u64, f32) {
+        (self.amaf_visits, self.amaf_value as f32)
     }
 
-    pub const fn visits(&self) -> u32 {
-        self.visits
+    /// Counts an iteration passing through this node, until [`remove_virtual_loss`](Self::remove_virtual_loss).
+    pub fn add_virtual_loss(&self) {
+        self.virtual_loss.add(1);
+    }
+
+    pub fn remove_virtual_loss(&self) {
+        self.virtual_loss.sub(1);
+    }
+
+    /// The prior of this node, which weights the policy of its parent in selection.
+    pub const fn prior(&self) -> f32 {
+        self.prior
+    }
+
+    pub const fn set_prior(&mut self, prior: f32) {
+        self.prior = prior;
     }
 
     pub const fn inbound_edge(&self) -> G::Move {
@@ -60,31 +142,119 @@ impl<G: Game> Node<G> {
     }
 
     pub fn win_rate(&self) -> f64 {
-        if self.visits == 0 {
-            0.0
-        } else {
-            f64::from(self.value) / f64::from(self.visits)
+        self.value.get()
+    }
+
+    /// The mean number of plies from this node to the end of the game, over the rollouts
+    /// backpropagated through it. Rollouts cut off before the end count the plies played.
+    pub fn game_length(&self) -> f32 {
+        self.length.get()
+    }
+
+    /// The value of this node for the player who chose it: exact if it is proven,
+    /// otherwise its [`win_rate`](Self::win_rate), or `None` if it has never been visited.
+    pub fn known_value(&self) -> Option<f64> {
+        match self.proof {
+            Proof::Win => Some(1.0),
+            Proof::Loss => Some(0.0),
+            Proof::Draw => Some(0.5),
+            Proof::Unknown if self.visits() == 0 => None,
+            Proof::Unknown => Some(self.win_rate()),
         }
     }
 
+    /// Takes the visits, value, and rollout length of `other`, a node of the same state
+    /// reached by another line, whose statistics this one shares.
+    pub fn share_stats(&self, other: &Self) {
+        self.value.set(other.value.get());
+        self.visits.set(other.visits.get());
+        self.length.set(other.length.get());
+    }
+
+    /// Replaces the value of this node with `value`, the value of its best child
+    /// for the player who chose it, which is how minimax backup values a node.
+    pub fn set_minimax_value(&self, value: f64) {
+        self.value.set(value);
+    }
+
+    /// Folds a new value into the running mean. The mean is updated incrementally
+    /// rather than dividing an accumulated sum, which stops absorbing new values
+    /// once it grows past the precision of its float type. It is kept as an f64
+    /// because at millions of visits the increments fall below the resolution of an f32.
+    /// Threads that update the node at once each fold in their value with the count of visits
+    /// that their own visit made.
     #[inline]
-    pub fn update(&mut self, q: f32) {
-        self.visits += 1;
+    pub fn update(&self, q: f32) {
+        let value = self.value_of(q);
+        let visits = self.visits.add(1) as f64;
+        self.value.update(|mean| mean + (value - mean) / visits);
+    }
+
+    /// Folds a result in which the move of this node was made later in the iteration, by
+    /// the same player, into the all-moves-as-first mean that RAVE blends with the value.
+    #[inline]
+    pub fn update_amaf(&mut self, q: f32) {
+        self.amaf_visits += 1;
+        self.amaf_value += (self.value_of(q) - self.amaf_value) / self.amaf_visits as f64;
+    }
+
+    /// The value of the result `q`, in [-1, 1] from the first player's perspective,
+    /// for the player who chose this node, in [0, 1].
+    fn value_of(&self, q: f32) -> f64 {
         assert!(self.perspective == 1 || self.perspective == -1);
         // scale the range of q from [-1, 1] to [0, WIN_SCORE]
         let perspective_q = q * f32::from(self.perspective);
         // the whole negative-positive thing really sucks
         assert!((-1.0..=1.0).contains(&q), "q holds invalid value: {q}");
-        let value = (perspective_q + 1.0) / 2.0;
+        let value = f32::midpoint(perspective_q, 1.0);
         assert!(
             (0.0..=1.0).contains(&value),
             "computed value holds invalid value: expected in range [0, 1], got {value}"
         );
-        self.value += value;
+        f64::from(value)
+    }
+
+    /// Folds the length of the rollout of the latest [`update`](Self::update), the
+    /// plies from this node to its end, into the mean [`game_length`](Self::game_length).
+    #[inline]
+    pub fn record_length(&self, plies: f32) {
+        let visits = self.visits() as f32;
+        debug_assert!(visits > 0.0, "record_length called before update");
+        self.length
+            .update(|length| length + (plies - length) / visits);
+    }
+
+    pub const fn proof(&self) -> Proof {
+        self.proof
     }
 
-    pub fn set_win_score(&mut self, score: f32) {
-        self.value = score;
+    pub const fn set_proof(&mut self, proof: Proof) {
+        self.proof = proof;
+    }
+
+    /// Marks this node as proven, given the outcome of its (terminal) state,
+    /// where 1 means the first player won and -1 means the second player won.
+    /// The node remembers that its state is terminal, see [`terminal_outcome`](Self::terminal_outcome).
+    pub const fn prove_terminal(&mut self, outcome: i8) {
+        self.proof = match outcome * self.perspective {
+            1 => Proof::Win,
+            -1 => Proof::Loss,
+            _ => Proof::Draw,
+        };
+        self.terminal = true;
+    }
+
+    /// The outcome of the state of this node, as given to [`prove_terminal`](Self::prove_terminal),
+    /// if its state is known to be terminal, so that it need not be evaluated again.
+    pub const fn terminal_outcome(&self) -> Option<i8> {
+        if !self.terminal {
+            return None;
+        }
+        Some(match self.proof {
+            Proof::Win => self.perspective,
+            Proof::Loss => -self.perspective,
+            Proof::Draw | Proof::Unknown => 0,
+        })
     }
 
     pub fn add_children(&mut self, start: usize, count: usize) {
@@ -101,10 +271,174 @@ impl<G: Game> Node<G> {
     pub fn random_child(&self, rng: &fastrand::Rng) -> usize {
         rng.usize(self.children())
     }
+
+    /// Writes everything but the inbound edge, which is recovered by move generation
+    /// when the tree is read back, by [`SearchTree::read_from`].
+    ///
+    /// [`SearchTree::read_from`]: crate::searchtree::SearchTree::read_from
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.first_child.to_le_bytes())?;
+        w.write_all(&self.n_children.to_le_bytes())?;
+        w.write_all(&self.parent.unwrap_or(u32::MAX).to_le_bytes())?;
+        w.write_all(&self.win_rate().to_le_bytes())?;
+        w.write_all(&self.visits().to_le_bytes())?;
+        w.write_all(&self.game_length().to_le_bytes())?;
+        let proof = match self.proof {
+            Proof::Unknown => 0u8,
+            Proof::Win => 1,
+            Proof::Draw => 2,
+            Proof::Loss => 3,
+        };
+        let terminal = if self.terminal { TERMINAL_FLAG } else { 0 };
+        w.write_all(&[self.perspective.to_le_bytes()[0], proof | terminal])?;
+        w.write_all(&self.amaf_value.to_le_bytes())?;
+        w.write_all(&self.amaf_visits.to_le_bytes())?;
+        w.write_all(&self.prior.to_le_bytes())
+    }
+
+    /// Reads a node written by [`write_to`](Self::write_to), with a default inbound edge.
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = [0; 52];
+        r.read_exact(&mut buf)?;
+        let invalid =
+            |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid node, {msg}"));
+        let perspective = i8::from_le_bytes([buf[30]]);
+        if perspective != 1 && perspective != -1 {
+            return Err(invalid("perspective must be 1 or -1"));
+        }
+        let terminal = buf[31] & TERMINAL_FLAG != 0;
+        let proof = match buf[31] & !TERMINAL_FLAG {
+            0 => Proof::Unknown,
+            1 => Proof::Win,
+            2 => Proof::Draw,
+            3 => Proof::Loss,
+            _ => return Err(invalid("unknown proof")),
+        };
+        let parent = u32::from_le_bytes(buf[6..10].try_into().unwrap());
+        Ok(Self {
+            first_child: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            n_children: u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+            parent: (parent != u32::MAX).then_some(parent),
+            value: AtomicF64::new(f64::from_le_bytes(buf[10..18].try_into().unwrap())),
+            visits: Counter::new(u64::from_le_bytes(buf[18..26].try_into().unwrap())),
+            length: AtomicF32::new(f32::from_le_bytes(buf[26..30].try_into().unwrap())),
+            perspective,
+            proof,
+            terminal,
+            virtual_loss: SmallCounter::new(0),
+            amaf_value: f64::from_le_bytes(buf[32..40].try_into().unwrap()),
+            amaf_visits: u64::from_le_bytes(buf[40..48].try_into().unwrap()),
+            prior: f32::from_le_bytes(buf[48..52].try_into().unwrap()),
+            inbound_edge: G::Move::default(),
+        })
+    }
+
+    pub const fn set_inbound_edge(&mut self, m: G::Move) {
+        self.inbound_edge = m;
+    }
 }
 
 impl<G: Game> Display for Node<G> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Node {{ children: {:?}, parent: {:?}, wins: {}, visits: {}, to_move: {}, win_rate: {} }}", self.children(), self.parent, self.value, self.visits, self.to_move(), self.win_rate())
+        write!(
+            f,
+            "Node {{ children: {:?}, parent: {:?}, q: {}, visits: {}, to_move: {} }}",
+            self.children(),
+            self.parent,
+            self.win_rate(),
+            self.visits(),
+            self.to_move()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::tictactoe::{TicTacToe, TicTacToeMove};
+
+    fn node() -> Node<TicTacToe> {
+        // a node chosen by the first player
+        Node::new(-1, None, TicTacToeMove::default())
+    }
+
+    #[test]
+    fn mean_tracks_perspective() {
+        let node = node();
+        node.update(1.0);
+        node.update(-1.0);
+        node.update(1.0);
+        node.update(0.0);
+        assert_eq!(node.visits(), 4);
+        assert!((node.win_rate() - 0.625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mean_is_stable_at_high_visit_counts() {
+        // an accumulated f32 sum stops changing once it passes 2^24,
+        // so run well beyond that and check the mean still tracks the input.
+        let node = node();
+        for i in 0..(1u32 << 25) {
+            node.update(if i % 4 == 0 { -1.0 } else { 1.0 });
+        }
+        assert!(
+            (node.win_rate() - 0.75).abs() < 1e-6,
+            "mean drifted to {}",
+            node.win_rate()
+        );
+        for _ in 0..(1u32 << 23) {
+            node.update(-1.0);
+        }
+        // the true mean is now 0.75 * 32 / 40 = 0.6.
+        assert!(
+            (node.win_rate() - 0.6).abs() < 1e-6,
+            "mean drifted to {}",
+            node.win_rate()
+        );
+    }
+
+    #[test]
+    fn visits_count_past_u32_max() {
+        let mut node = node();
+        node.update(1.0);
+        node.visits.set(u64::from(u32::MAX));
+        node.update(1.0);
+        node.add_virtual_loss();
+        node.set_prior(0.25);
+        assert_eq!(node.visits(), u64::from(u32::MAX) + 1);
+        assert_eq!(node.selection_stats().0, u64::from(u32::MAX) + 2);
+        let mut bytes = Vec::new();
+        node.write_to(&mut bytes).unwrap();
+        let read = Node::<TicTacToe>::read_from(&mut bytes.as_slice()).unwrap();
+        read.add_virtual_loss();
+        assert_eq!(read, node);
+    }
+
+    #[test]
+    fn terminal_proofs_follow_perspective() {
+        let mut node = node();
+        assert_eq!(node.proof(), Proof::Unknown);
+        node.prove_terminal(1);
+        assert_eq!(node.proof(), Proof::Win);
+        node.prove_terminal(-1);
+        assert_eq!(node.proof(), Proof::Loss);
+        node.prove_terminal(0);
+        assert_eq!(node.proof(), Proof::Draw);
+    }
+
+    #[test]
+    fn terminal_states_are_remembered() {
+        let mut node = node();
+        node.set_proof(Proof::Win);
+        // proven by its children, not by its own state.
+        assert_eq!(node.terminal_outcome(), None);
+        for outcome in [1, -1, 0] {
+            node.prove_terminal(outcome);
+            assert_eq!(node.terminal_outcome(), Some(outcome));
+        }
+        let mut bytes = Vec::new();
+        node.write_to(&mut bytes).unwrap();
+        let read = Node::<TicTacToe>::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, node);
     }
 }
@@ -1,12 +1,78 @@
-use crate::{constants::NODE_UNVISITED_VALUE, game::Game, treenode::Node};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
 
-#[inline(never)]
-fn puct(parent_visits: u32, q_value: f32, visits: u32, _exp_factor: f32, policy: f32) -> f32 {
-    #![allow(clippy::cast_precision_loss)]
+use crate::{
+    constants::NODE_UNVISITED_VALUE,
+    game::Game,
+    rave,
+    treenode::{Node, Proof},
+};
+
+/// The formula that selection scores children by, see [`Behaviour::selection`].
+///
+/// [`Behaviour::selection`]: crate::mcts::Behaviour::selection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionPolicy {
+    /// `q + c * sqrt(ln N / n)`, for a child with `n` of its parent's `N` visits, where `c`
+    /// is [`Behaviour::exp_factor`]. Priors are not used.
+    ///
+    /// [`Behaviour::exp_factor`]: crate::mcts::Behaviour::exp_factor
+    Ucb1,
+    /// UCB1-Tuned (Auer et al., 2002), which bounds the exploration of UCB1 by the variance
+    /// of the child's results, as `q + sqrt(ln N / n * min(1/4, V))`, where
+    /// `V = var + sqrt(2 ln N / n)`. The variance is taken to be `q(1 - q)`, which it is for
+    /// results that are all wins and losses, and which bounds it otherwise, so that nodes
+    /// need not keep the squares of their results. Priors are not used.
+    Ucb1Tuned,
+    /// PUCT, as in `AlphaZero`, with the exploration constant of [`Cpuct`], which weights the
+    /// exploration of each child by its policy.
+    #[default]
+    Puct,
+}
+
+impl FromStr for SelectionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ucb1" => Ok(Self::Ucb1),
+            "ucb1_tuned" => Ok(Self::Ucb1Tuned),
+            "puct" => Ok(Self::Puct),
+            _ => Err(format!(
+                "unknown selection policy {s}, expected one of ucb1, ucb1_tuned, and puct"
+            )),
+        }
+    }
+}
 
-    if visits == 0 {
-        return NODE_UNVISITED_VALUE;
+impl Display for SelectionPolicy {
+    /// Writes the selection policy in the form accepted by [`SelectionPolicy::from_str`].
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Ucb1 => write!(f, "ucb1"),
+            Self::Ucb1Tuned => write!(f, "ucb1_tuned"),
+            Self::Puct => write!(f, "puct"),
+        }
     }
+}
+
+/// The exploration constant of PUCT, which grows logarithmically with the parent's visits,
+/// as `factor * ln((parent_visits + base + 1) / base)`.
+/// See [`Behaviour::cpuct_base`] and [`Behaviour::cpuct_factor`].
+///
+/// [`Behaviour::cpuct_base`]: crate::mcts::Behaviour::cpuct_base
+/// [`Behaviour::cpuct_factor`]: crate::mcts::Behaviour::cpuct_factor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cpuct {
+    pub base: f32,
+    pub factor: f32,
+}
+
+#[inline(never)]
+fn puct(parent_visits: u64, q_value: f32, visits: u64, cpuct: Cpuct, policy: f32) -> f32 {
+    #![allow(clippy::cast_precision_loss)]
 
     // let exploitation = f32::from(q_value) / f32::from(visits);
     // let exploration = f32::sqrt(f32::ln(f32::from(parent_visits)) / f32::from(visits));
@@ -15,7 +81,8 @@ fn puct(parent_visits: u32, q_value: f32, visits: u32, _exp_factor: f32, policy:
     // ^^^ the normal UCB1 formula
 
     // pb_c = math.log((parent.visit_count + config.pb_c_base + 1) / config.pb_c_base) + config.pb_c_init
-    let pb_c = fastapprox::faster::ln(((parent_visits as f32) + 1.8 + 1.0) / 1.8);
+    let pb_c = cpuct.factor
+        * fastapprox::faster::ln(((parent_visits as f32) + cpuct.base + 1.0) / cpuct.base);
     // pb_c *= math.sqrt(parent.visit_count) / (child.visit_count + 1)
     let pb_c = pb_c * f32::sqrt(parent_visits as f32) / (visits as f32 + 1.0);
 
@@ -24,28 +91,134 @@ fn puct(parent_visits: u32, q_value: f32, visits: u32, _exp_factor: f32, policy:
     // return prior_score + value_score
 
     let prior_score = pb_c * policy;
-    let value_score = q_value / visits as f32;
+    let value_score = q_value;
 
     prior_score + value_score
 }
 
+/// The exploration term of UCB1 and UCB1-Tuned, `ln N / n`. A child scored before it is
+/// visited, by first-play urgency or RAVE, counts as visited once.
+fn log_ratio(parent_visits: u64, visits: u64) -> f32 {
+    #![allow(clippy::cast_precision_loss)]
+    fastapprox::faster::ln(parent_visits.max(1) as f32) / visits.max(1) as f32
+}
+
+fn ucb1(parent_visits: u64, q_value: f32, visits: u64, exp_factor: f32) -> f32 {
+    exp_factor.mul_add(log_ratio(parent_visits, visits).sqrt(), q_value)
+}
+
+fn ucb1_tuned(parent_visits: u64, q_value: f32, visits: u64) -> f32 {
+    let log_ratio = log_ratio(parent_visits, visits);
+    let variance = q_value.mul_add(1.0 - q_value, (2.0 * log_ratio).sqrt());
+    q_value + (log_ratio * variance.min(0.25)).sqrt()
+}
+
+/// First-play urgency: the value given to unvisited children, see [`Behaviour::fpu_reduction`].
+///
+/// [`Behaviour::fpu_reduction`]: crate::mcts::Behaviour::fpu_reduction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fpu {
+    /// The value of the parent, from the perspective of the player choosing a child.
+    pub parent_value: f32,
+    pub reduction: f32,
+}
+
+/// How [`best`] scores children, beyond their statistics and priors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scoring {
+    pub selection: SelectionPolicy,
+    /// The exploration constant of UCB1.
+    pub exp_factor: f32,
+    pub cpuct: Cpuct,
+    pub fpu: Option<Fpu>,
+    /// The RAVE bias, if the AMAF values of children are blended into their values,
+    /// see [`Behaviour::rave_bias`].
+    ///
+    /// [`Behaviour::rave_bias`]: crate::mcts::Behaviour::rave_bias
+    pub rave_bias: Option<f32>,
+}
+
+impl Scoring {
+    /// The score of a child with `visits` visits, mean value `q`, and policy `policy`.
+    fn score(&self, parent_visits: u64, q: f32, visits: u64, policy: f32) -> f32 {
+        match self.selection {
+            SelectionPolicy::Ucb1 => ucb1(parent_visits, q, visits, self.exp_factor),
+            SelectionPolicy::Ucb1Tuned => ucb1_tuned(parent_visits, q, visits),
+            SelectionPolicy::Puct => puct(parent_visits, q, visits, self.cpuct, policy),
+        }
+    }
+}
+
+/// The index of the child in `nodes` with the highest score, by the formula of its
+/// [`SelectionPolicy`].
+/// The policy of a child is given by `priors`, or else by [`Game::policy`] weighted by the
+/// child's [`Node::prior`].
+/// With RAVE, the value of a child is blended with its AMAF value, which values children
+/// before they are visited. Other unvisited children are scored by the first-play urgency
+/// if it is given, and are otherwise chosen first.
+/// Ties go to the earliest child, so that selection is reproducible. `policies` is storage
+/// for the policy over the children, which is reused from one call to the next.
 #[inline(never)]
-pub fn best<G: Game>(parent: &G, nodes: &[Node<G>], parent_visits: u32, exp_factor: f32) -> usize {
+pub fn best<G: Game>(
+    parent: &G,
+    nodes: &[Node<G>],
+    parent_visits: u64,
+    priors: Option<&[f32]>,
+    excluded: Option<&[bool]>,
+    scoring: Scoring,
+    policies: &mut Vec<f32>,
+) -> usize {
+    let Scoring { fpu, rave_bias, .. } = scoring;
     assert!(!nodes.is_empty(), "ucb::best: nodes is empty");
+    // a proven win is always taken, and proven losses are only considered
+    // when every child is one.
+    if let Some(win) = nodes.iter().position(|node| node.proof() == Proof::Win) {
+        return win;
+    }
+    let avoid_losses = nodes.iter().any(|node| node.proof() != Proof::Loss);
     let mut best_value = f32::NEG_INFINITY;
     let mut best_index = 0;
-    let mut policies = Vec::with_capacity(nodes.len());
+    policies.clear();
 
-    // compute policies
-    for node in nodes {
-        policies.push(parent.policy(node));
+    // compute policies, unless they were supplied externally
+    if let Some(priors) = priors {
+        assert_eq!(priors.len(), nodes.len(), "ucb::best: priors do not match nodes");
+        policies.extend_from_slice(priors);
+    } else {
+        for node in nodes {
+            policies.push(parent.policy(node) * node.prior());
+        }
     }
     // normalise policies
     let sum: f32 = policies.iter().sum();
-    policies.iter_mut().for_each(|p| *p /= sum);
+    for p in policies.iter_mut() {
+        *p /= sum;
+    }
+    // the more of the policy that has been explored, the less promising the rest is.
+    let unvisited_q = fpu.map(|fpu| {
+        let explored = nodes
+            .iter()
+            .zip(policies.iter())
+            .filter(|(node, _)| node.selection_stats().0 > 0)
+            .map(|(_, &policy)| policy)
+            .sum::<f32>();
+        fpu.reduction.mul_add(-explored.sqrt(), fpu.parent_value)
+    });
 
-    for (i, (node, policy)) in nodes.iter().zip(policies).enumerate() {
-        let value = puct(parent_visits, node.wins(), node.visits(), exp_factor, policy);
+    for (i, (node, &policy)) in nodes.iter().zip(policies.iter()).enumerate() {
+        if avoid_losses && node.proof() == Proof::Loss || excluded.is_some_and(|ex| ex[i]) {
+            continue;
+        }
+        let (visits, q) = node.selection_stats();
+        let rave_q = rave_bias.and_then(|bias| {
+            let (amaf_visits, amaf_q) = node.amaf_stats();
+            rave::blend(bias, visits, q, amaf_visits, amaf_q)
+        });
+        let value = match (visits, rave_q, unvisited_q) {
+            (_, Some(q), _) | (0, None, Some(q)) => scoring.score(parent_visits, q, visits, policy),
+            (0, None, None) => NODE_UNVISITED_VALUE,
+            (visits, None, _) => scoring.score(parent_visits, q, visits, policy),
+        };
         if value > best_value {
             best_value = value;
             best_index = i;
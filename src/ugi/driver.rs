@@ -0,0 +1,321 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::PathBuf,
+    sync::{mpsc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    parser::{Command, GoParams, Position, Query},
+    GameOptions, UgiGame, UCI_VARIANTS, UGI_GAMES,
+};
+use crate::{
+    config::{self, Config},
+    constants::DEFAULT_AUTOSAVE_MINUTES,
+    mcts::{self, Autosave, Behaviour, MCTS},
+    score::DEFAULT_CP_SCALE,
+    NAME, VERSION,
+};
+
+/// The options, in lower case, that change the settings of the search, after which the
+/// engine is made anew.
+const SEARCH_OPTIONS: [&str; 3] = ["cpscale", "analysiscache", "fpureduction"];
+
+/// Sets one of the [`SEARCH_OPTIONS`] in `behaviour`.
+fn set_search_option(behaviour: &mut Behaviour, name: &str, value: &str) -> Result<(), String> {
+    if name.eq_ignore_ascii_case("cpscale") {
+        config::parse_cp_scale(value).map(|cp_scale| behaviour.cp_scale = cp_scale)
+    } else if name.eq_ignore_ascii_case("analysiscache") {
+        behaviour.analysis_cache =
+            (!value.is_empty() && value != "<empty>").then(|| value.to_string());
+        Ok(())
+    } else if name.eq_ignore_ascii_case("fpureduction") {
+        // without a reduction, every child is tried once before any is tried again.
+        behaviour.fpu_reduction = if value.is_empty() || value == "<empty>" {
+            None
+        } else {
+            Some(mcts::parse_fpu_reduction(value)?)
+        };
+        Ok(())
+    } else {
+        Err(format!("unknown option: {name}"))
+    }
+}
+
+/// Where `go infinite` saves its tree, set with the `Autosave` and `AutosaveMinutes` options.
+struct AutosaveOptions {
+    path: Option<PathBuf>,
+    minutes: u64,
+}
+
+impl AutosaveOptions {
+    /// Sets the `Autosave` or `AutosaveMinutes` option.
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if name.eq_ignore_ascii_case("autosave") {
+            self.path = (!value.is_empty() && value != "<empty>").then(|| PathBuf::from(value));
+            Ok(())
+        } else if name.eq_ignore_ascii_case("autosaveminutes") {
+            value
+                .parse()
+                .ok()
+                .filter(|&minutes| minutes > 0)
+                .map(|minutes| self.minutes = minutes)
+                .ok_or_else(|| {
+                    format!("expected a positive number of minutes for AutosaveMinutes: {value}")
+                })
+        } else {
+            Err(format!("unknown option: {name}"))
+        }
+    }
+
+    /// The autosave that is set, if any.
+    fn autosave(&self) -> Option<Autosave> {
+        self.path.clone().map(|path| Autosave {
+            path,
+            interval: Duration::from_mins(self.minutes),
+        })
+    }
+}
+
+impl Default for AutosaveOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            minutes: DEFAULT_AUTOSAVE_MINUTES,
+        }
+    }
+}
+
+/// The options set by the GUI that do not depend on the game, which are kept when it
+/// selects another.
+#[derive(Default)]
+pub struct Settings {
+    /// The [`SEARCH_OPTIONS`] that have been set, with their values, in the order set.
+    search: Vec<(String, String)>,
+    autosave: AutosaveOptions,
+}
+
+/// Why a game's command loop stopped.
+pub enum Exit {
+    Quit,
+    SwitchGame(String),
+}
+
+/// Responds to the `uci` or `ugi` handshake, advertising the selectable games.
+fn print_id_response<G: UgiGame>(game: &str, protocol: &str) {
+    println!("id name {NAME} {VERSION}");
+    println!("id author Cosmo");
+    print!("option name Game type combo default {game}");
+    for g in UGI_GAMES {
+        print!(" var {g}");
+    }
+    println!();
+    println!("option name CPScale type spin default {DEFAULT_CP_SCALE} min 1 max 10000");
+    println!("option name Autosave type string default <empty>");
+    println!(
+        "option name AutosaveMinutes type spin default {DEFAULT_AUTOSAVE_MINUTES} min 1 max 1440"
+    );
+    println!("option name AnalysisCache type string default <empty>");
+    println!("option name FPUReduction type string default <empty>");
+    if G::SUPPORTS_CHESS960 {
+        println!("option name UCI_Chess960 type check default false");
+    }
+    if G::IS_UCI_VARIANT {
+        print!("option name UCI_Variant type combo default {game}");
+        for variant in UCI_VARIANTS {
+            print!(" var {variant}");
+        }
+        println!();
+    }
+    println!("{protocol}ok");
+}
+
+/// Runs the command loop for one game, until the GUI quits or selects a different game,
+/// starting from, and leaving behind, the `settings` that carry over between games.
+pub fn game_loop<G: UgiGame>(
+    config: &Config,
+    stdin: &Mutex<mpsc::Receiver<String>>,
+    game: &str,
+    settings: &mut Settings,
+) -> Exit {
+    let mut driver = Driver::<G>::new(config, stdin, game, std::mem::take(settings));
+    let exit = loop {
+        std::io::stdout().flush().expect("couldn't flush stdout");
+        let line = if let Some(line) = driver.queue.pop_front() {
+            line
+        } else {
+            let received = stdin.lock().expect("failed to take lock on stdin").recv();
+            let Ok(line) = received else {
+                // stdin was closed, so there will be no more commands.
+                break Exit::Quit;
+            };
+            line
+        };
+        match Command::parse(&line).and_then(|command| driver.execute(command)) {
+            Ok(Some(exit)) => break exit,
+            Ok(None) => {}
+            Err(e) => println!("info string {e}"),
+        }
+    };
+    *settings = driver.settings;
+    exit
+}
+
+/// The engine and the position of one game, which commands are run against.
+struct Driver<'a, G: UgiGame> {
+    game: &'a str,
+    stdin: &'a Mutex<mpsc::Receiver<String>>,
+    behaviour: Behaviour,
+    engine: MCTS<'a, G>,
+    options: GameOptions,
+    settings: Settings,
+    pos: G,
+    /// Commands sent during a search, which are run after it, before any more are read.
+    queue: VecDeque<String>,
+}
+
+impl<'a, G: UgiGame> Driver<'a, G> {
+    fn new(
+        config: &Config,
+        stdin: &'a Mutex<mpsc::Receiver<String>>,
+        game: &'a str,
+        settings: Settings,
+    ) -> Self {
+        let mut behaviour = Behaviour::for_game::<G>();
+        config.apply(&mut behaviour);
+        for (name, value) in &settings.search {
+            set_search_option(&mut behaviour, name, value)
+                .expect("search options are only kept if they were valid");
+        }
+        let options = GameOptions::default();
+        Self {
+            game,
+            stdin,
+            engine: MCTS::new(&behaviour),
+            behaviour,
+            options,
+            settings,
+            pos: G::startpos(options),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Runs `command`, returning how the command loop exits, if it does.
+    fn execute(&mut self, command: Command) -> Result<Option<Exit>, String> {
+        match command {
+            Command::Handshake(protocol) => print_id_response::<G>(self.game, protocol),
+            Command::IsReady => println!("readyok"),
+            Command::Quit => return Ok(Some(Exit::Quit)),
+            // a stop that arrives during a search is read by the search itself,
+            // so one that arrives here has no search to interrupt.
+            Command::Stop => {}
+            Command::NewGame => self.engine.new_game(),
+            Command::Query(query) => println!("response {}", self.answer(query)),
+            Command::SetOption { name, value } => return self.set_option(name, value),
+            Command::Position(position) => self.pos = set_up(&position, self.options)?,
+            Command::Go(params) => self.go(params),
+        }
+        Ok(None)
+    }
+
+    fn answer(&self, query: Query) -> String {
+        match query {
+            Query::GameOver => self.pos.is_terminal().to_string(),
+            Query::P1Turn => (self.pos.turn() == 1).to_string(),
+            Query::Result => match (self.pos.is_terminal(), self.pos.evaluate()) {
+                (false, _) => "none",
+                (true, 1) => "p1win",
+                (true, -1) => "p2win",
+                (true, _) => "draw",
+            }
+            .to_string(),
+        }
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<Option<Exit>, String> {
+        if name.eq_ignore_ascii_case("game")
+            || G::IS_UCI_VARIANT && name.eq_ignore_ascii_case("uci_variant")
+        {
+            game_to_switch_to(name, value, self.game).map(|game| game.map(Exit::SwitchGame))
+        } else if G::SUPPORTS_CHESS960 && name.eq_ignore_ascii_case("uci_chess960") {
+            value
+                .parse()
+                .map(|chess960| self.options.chess960 = chess960)
+                .map_err(|_| format!("expected true or false for UCI_Chess960: {value}"))?;
+            Ok(None)
+        } else if SEARCH_OPTIONS
+            .iter()
+            .any(|option| name.eq_ignore_ascii_case(option))
+        {
+            set_search_option(&mut self.behaviour, name, value)?;
+            let search = &mut self.settings.search;
+            search.retain(|(option, _)| !option.eq_ignore_ascii_case(name));
+            search.push((name.to_string(), value.to_string()));
+            self.engine = MCTS::new(&self.behaviour);
+            Ok(None)
+        } else if name.to_lowercase().starts_with("autosave") {
+            self.settings.autosave.set(name, value).map(|()| None)
+        } else {
+            Err(format!("unknown option: {name}"))
+        }
+    }
+
+    fn go(&mut self, params: GoParams) {
+        if self.pos.is_terminal() {
+            // a go is always answered with a bestmove, here the null move.
+            println!("info string cannot search a position where the game is over");
+            println!("bestmove 0000");
+            return;
+        }
+        self.engine.set_limit(params.limit(self.behaviour.limit));
+        // only infinite searches are long enough to be worth saving.
+        self.engine.set_autosave(
+            self.settings
+                .autosave
+                .autosave()
+                .filter(|_| params.infinite),
+        );
+        self.engine.set_stdin(self.stdin);
+        // the search reads the commands still queued before those it has yet to receive.
+        self.engine
+            .set_unread_commands(std::mem::take(&mut self.queue));
+        let search_results = self.engine.search(&self.pos);
+        println!("bestmove {}", search_results.best_move);
+        self.queue = self.engine.take_pending_commands();
+    }
+}
+
+/// The game selected by setting the `Game` or `UCI_Variant` option to `value`,
+/// or `None` if it is `game`, the one being played.
+fn game_to_switch_to(option: &str, value: &str, game: &str) -> Result<Option<String>, String> {
+    let (choices, kind) = if option.eq_ignore_ascii_case("game") {
+        (&UGI_GAMES[..], "game")
+    } else {
+        (&UCI_VARIANTS[..], "variant")
+    };
+    let value = value.to_lowercase();
+    if value == game {
+        Ok(None)
+    } else if choices.contains(&value.as_str()) {
+        Ok(Some(value))
+    } else {
+        Err(format!("unknown {kind}: {value}"))
+    }
+}
+
+/// Sets up the position given by a `position` command.
+fn set_up<G: UgiGame>(position: &Position, options: GameOptions) -> Result<G, String> {
+    let mut pos = match &position.fen {
+        None => G::startpos(options),
+        Some(fen) => G::from_fen(fen, options)?,
+    };
+    for &text in &position.moves {
+        if pos.is_terminal() {
+            return Err(format!("the game is over before {text}"));
+        }
+        let m = pos.parse_move(text)?;
+        pos.push(m);
+    }
+    Ok(pos)
+}
@@ -0,0 +1,140 @@
+/// Runs the commands of the GUI against the engine of the game being played.
+mod driver;
+/// Reads each line from the GUI into a typed command, whatever the game.
+mod parser;
+
+use std::sync::{
+    atomic::{self, AtomicBool},
+    mpsc, Mutex,
+};
+
+use crate::{
+    config::Config,
+    game::Game,
+    games::{
+        antichess::Antichess, chess::Chess, connectfour::Connect4, gomoku::Gomoku,
+        tictactoe::TicTacToe,
+    },
+    mcts::MCTSExt,
+};
+use driver::{game_loop, Exit, Settings};
+
+static KEEP_RUNNING: AtomicBool = AtomicBool::new(true);
+
+/// The games that can be selected with `setoption name Game value <game>`.
+const UGI_GAMES: [&str; 8] = [
+    "chess",
+    "antichess",
+    "connect4",
+    "tictactoe",
+    "gomoku9",
+    "gomoku13",
+    "gomoku15",
+    "gomoku19",
+];
+
+/// The chess variants that can be selected with `setoption name UCI_Variant value <variant>`,
+/// each of which is also the name of its game.
+const UCI_VARIANTS: [&str; 2] = ["chess", "antichess"];
+
+/// Options set by the GUI that change how positions are set up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GameOptions {
+    pub chess960: bool,
+}
+
+/// A game that can be played over UCI/UGI.
+pub trait UgiGame: Game + MCTSExt {
+    /// Whether the game understands the `UCI_Chess960` option.
+    const SUPPORTS_CHESS960: bool = false;
+    /// Whether the game is one of the [`UCI_VARIANTS`], and so understands `UCI_Variant`.
+    const IS_UCI_VARIANT: bool = false;
+
+    /// The position given by `position startpos`.
+    fn startpos(_options: GameOptions) -> Self {
+        Self::default()
+    }
+
+    /// Parses the position given by `position fen <fen>`.
+    fn from_fen(_fen: &str, _options: GameOptions) -> Result<Self, String> {
+        Err("this game does not support fen positions".to_string())
+    }
+}
+
+impl UgiGame for Chess {
+    const SUPPORTS_CHESS960: bool = true;
+    const IS_UCI_VARIANT: bool = true;
+
+    fn startpos(options: GameOptions) -> Self {
+        Self::from_raw_board(cozy_chess::Board::startpos(), options.chess960)
+    }
+
+    fn from_fen(fen: &str, options: GameOptions) -> Result<Self, String> {
+        Self::from_fen(fen, options.chess960)
+    }
+}
+impl UgiGame for Antichess {
+    const IS_UCI_VARIANT: bool = true;
+
+    fn from_fen(fen: &str, _options: GameOptions) -> Result<Self, String> {
+        Self::from_fen(fen)
+    }
+}
+impl UgiGame for Connect4 {}
+impl UgiGame for TicTacToe {}
+impl<const N: usize> UgiGame for Gomoku<N> {}
+
+fn stdin_reader() -> mpsc::Receiver<String> {
+    let (sender, reciever) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("stdin-reader".into())
+        .spawn(|| stdin_reader_worker(sender))
+        .expect("Couldn't start stdin reader worker thread");
+    reciever
+}
+
+fn stdin_reader_worker(sender: mpsc::Sender<String>) {
+    let mut linebuf = String::with_capacity(128);
+    while std::io::stdin()
+        .read_line(&mut linebuf)
+        .is_ok_and(|read| read > 0)
+    {
+        let cmd = linebuf.trim();
+        if cmd.is_empty() {
+            linebuf.clear();
+            continue;
+        }
+        if sender.send(cmd.to_owned()).is_err() {
+            break;
+        }
+        if !KEEP_RUNNING.load(atomic::Ordering::SeqCst) {
+            break;
+        }
+        linebuf.clear();
+    }
+    std::mem::drop(sender);
+}
+
+pub fn main(config: &Config) {
+    let stdin = Mutex::new(stdin_reader());
+    let mut game = "chess".to_string();
+    let mut settings = Settings::default();
+    loop {
+        let exit = match game.as_str() {
+            "chess" => game_loop::<Chess>(config, &stdin, &game, &mut settings),
+            "antichess" => game_loop::<Antichess>(config, &stdin, &game, &mut settings),
+            "connect4" => game_loop::<Connect4>(config, &stdin, &game, &mut settings),
+            "tictactoe" => game_loop::<TicTacToe>(config, &stdin, &game, &mut settings),
+            "gomoku9" => game_loop::<Gomoku<9>>(config, &stdin, &game, &mut settings),
+            "gomoku13" => game_loop::<Gomoku<13>>(config, &stdin, &game, &mut settings),
+            "gomoku15" => game_loop::<Gomoku<15>>(config, &stdin, &game, &mut settings),
+            "gomoku19" => game_loop::<Gomoku<19>>(config, &stdin, &game, &mut settings),
+            _ => unreachable!("only games in UGI_GAMES can be selected"),
+        };
+        match exit {
+            Exit::Quit => break,
+            Exit::SwitchGame(new_game) => game = new_game,
+        }
+    }
+    KEEP_RUNNING.store(false, atomic::Ordering::SeqCst);
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use crate::mcts::Limit;
+
+/// A command from the GUI, as read from one line of input, before it is run against a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command<'a> {
+    /// `uci` or `ugi`, which is answered in the same protocol.
+    Handshake(&'a str),
+    IsReady,
+    Quit,
+    Stop,
+    /// `ucinewgame` or `uginewgame`.
+    NewGame,
+    Query(Query),
+    /// `setoption name <name> value <value>`.
+    SetOption {
+        name: &'a str,
+        value: &'a str,
+    },
+    Position(Position<'a>),
+    Go(GoParams),
+}
+
+/// The questions of the `query` command of UGI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Query {
+    GameOver,
+    P1Turn,
+    Result,
+}
+
+/// A position given by `position startpos [moves ...]` or `position fen <fen> [moves ...]`,
+/// whose moves are only parsed once the game is known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position<'a> {
+    /// The fen of the position the moves are played from, or `None` for the start position.
+    pub fen: Option<String>,
+    pub moves: Vec<&'a str>,
+}
+
+/// The parameters of `go` that the engine understands. Time controls are ignored, and the
+/// search runs to the configured limit unless one of these replaces it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GoParams {
+    pub infinite: bool,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+impl GoParams {
+    /// The limit of the search, where `infinite` takes precedence over `movetime`, and
+    /// `movetime` over `nodes`, which are counted as rollouts. Without any, it is `default`.
+    pub fn limit(&self, default: Limit) -> Limit {
+        if self.infinite {
+            Limit::Infinite
+        } else if let Some(movetime) = self.movetime {
+            Limit::Time(movetime)
+        } else {
+            self.nodes.map_or(default, Limit::Rollouts)
+        }
+    }
+}
+
+impl<'a> Command<'a> {
+    /// Parses one line of input from the GUI.
+    pub fn parse(input: &'a str) -> Result<Self, String> {
+        let input = input.trim();
+        let (word, rest) = input.split_once(' ').unwrap_or((input, ""));
+        match (word, rest.trim()) {
+            ("uci" | "ugi", "") => Ok(Self::Handshake(word)),
+            ("isready", "") => Ok(Self::IsReady),
+            ("quit", "") => Ok(Self::Quit),
+            ("stop", "") => Ok(Self::Stop),
+            ("ucinewgame" | "uginewgame", "") => Ok(Self::NewGame),
+            ("query", "gameover") => Ok(Self::Query(Query::GameOver)),
+            ("query", "p1turn") => Ok(Self::Query(Query::P1Turn)),
+            ("query", "result") => Ok(Self::Query(Query::Result)),
+            ("setoption", rest) => parse_setoption(rest)
+                .map(|(name, value)| Self::SetOption { name, value })
+                .ok_or_else(|| format!("expected 'setoption name <name> value <value>': {input}")),
+            ("position", rest) => parse_position(rest).map(Self::Position),
+            ("go", rest) => parse_go(rest).map(Self::Go),
+            _ => Err(format!("unknown command: {input}")),
+        }
+    }
+}
+
+/// Splits `name <name> value <value>`, the rest of a `setoption`, into the name and value.
+/// The value may be empty, which clears a string option.
+fn parse_setoption(rest: &str) -> Option<(&str, &str)> {
+    let rest = rest.strip_prefix("name")?;
+    let (name, value) = rest
+        .split_once(" value ")
+        .or_else(|| Some((rest.strip_suffix(" value")?, "")))?;
+    Some((name.trim(), value.trim()))
+}
+
+/// Parses `startpos [moves ...]` or `fen <fen> [moves ...]`, the rest of a `position`.
+fn parse_position(rest: &str) -> Result<Position<'_>, String> {
+    let mut words = rest.split_whitespace();
+    let fen = match words.next() {
+        Some("startpos") => None,
+        Some("fen") => Some(
+            words
+                .by_ref()
+                .take_while(|&w| w != "moves")
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => return Err("expected 'startpos' or 'fen' after 'position'".to_string()),
+    };
+    let moves = words.skip_while(|&w| w == "moves").collect();
+    Ok(Position { fen, moves })
+}
+
+/// Parses the rest of a `go`, skipping the parameters that the engine does not use.
+fn parse_go(rest: &str) -> Result<GoParams, String> {
+    let mut params = GoParams::default();
+    let mut words = rest.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "infinite" => params.infinite = true,
+            "movetime" => {
+                let millis = words
+                    .next()
+                    .and_then(|millis| millis.parse().ok())
+                    .ok_or("expected a number of milliseconds after 'movetime'")?;
+                params.movetime = Some(Duration::from_millis(millis));
+            }
+            "nodes" => {
+                let nodes = words
+                    .next()
+                    .and_then(|nodes| nodes.parse().ok())
+                    .ok_or("expected a number of nodes after 'nodes'")?;
+                params.nodes = Some(nodes);
+            }
+            _ => {}
+        }
+    }
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_parse_to_their_parts() {
+        assert_eq!(Command::parse("ugi"), Ok(Command::Handshake("ugi")));
+        assert_eq!(Command::parse(" isready "), Ok(Command::IsReady));
+        assert_eq!(
+            Command::parse("query p1turn"),
+            Ok(Command::Query(Query::P1Turn))
+        );
+        assert_eq!(
+            Command::parse("setoption name UCI_Chess960 value true"),
+            Ok(Command::SetOption {
+                name: "UCI_Chess960",
+                value: "true"
+            })
+        );
+        assert_eq!(
+            Command::parse("position startpos moves e2e4 e7e5"),
+            Ok(Command::Position(Position {
+                fen: None,
+                moves: vec!["e2e4", "e7e5"],
+            }))
+        );
+        assert_eq!(
+            Command::parse("position fen 8/8/8/8/8/8/8/K1k5 w - - 0 1 moves a1a2"),
+            Ok(Command::Position(Position {
+                fen: Some("8/8/8/8/8/8/8/K1k5 w - - 0 1".to_string()),
+                moves: vec!["a1a2"],
+            }))
+        );
+        assert_eq!(
+            Command::parse("setoption name Autosave value"),
+            Ok(Command::SetOption {
+                name: "Autosave",
+                value: ""
+            })
+        );
+        assert!(Command::parse("setoption name Hash").is_err());
+        assert!(Command::parse("position e2e4").is_err());
+        assert!(Command::parse("query moves").is_err());
+        assert!(Command::parse("gone").is_err());
+    }
+
+    #[test]
+    fn go_parameters_pick_the_limit() {
+        let default = Limit::Rollouts(800);
+        let go = |input| match Command::parse(input) {
+            Ok(Command::Go(params)) => params.limit(default),
+            other => panic!("{input} parsed as {other:?}"),
+        };
+        assert_eq!(go("go"), default);
+        assert_eq!(go("go wtime 1000 btime 1000"), default);
+        assert_eq!(go("go nodes 500"), Limit::Rollouts(500));
+        assert_eq!(
+            go("go nodes 500 movetime 250"),
+            Limit::Time(Duration::from_millis(250))
+        );
+        assert_eq!(go("go movetime 250 infinite"), Limit::Infinite);
+        assert!(Command::parse("go movetime soon").is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::game::{Game, MoveBuffer};
+
+/// The score of a won position, less the number of plies it takes to win,
+/// so that faster wins and slower losses are preferred.
+const WIN_SCORE: i32 = 1_000_000;
+
+/// The value of a position as found by a shallow alpha-beta search,
+/// from the perspective of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score(i32);
+
+impl Score {
+    /// 1 for a forced win, -1 for a forced loss, and 0 otherwise.
+    const fn result(self) -> i32 {
+        self.0.signum()
+    }
+
+    /// The number of plies to the end of the game, if the search found a forced result.
+    const fn plies(self) -> Option<i32> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(WIN_SCORE - self.0.abs())
+        }
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Some(plies) = self.plies() else {
+            return write!(f, "no forced result");
+        };
+        let result = if self.0 > 0 { "win" } else { "loss" };
+        let unit = if plies == 1 { "ply" } else { "plies" };
+        write!(f, "{result} in {plies} {unit}")
+    }
+}
+
+/// Negamax alpha-beta search to `depth` plies, using make/unmake.
+///
+/// No games have static evaluations yet, so positions at the horizon are scored as
+/// unknown (zero), the same as draws: the search only finds forced wins and losses.
+/// `horizon` is set if any position at the horizon was scored, so if it is left unset,
+/// a score of zero is a proven draw.
+fn negamax<G: Game>(
+    state: &mut G,
+    depth: usize,
+    ply: i32,
+    mut alpha: i32,
+    beta: i32,
+    horizon: &mut bool,
+) -> i32 {
+    if state.is_terminal() {
+        // the evaluation is from the first player's perspective.
+        return match state.evaluate() * state.turn() {
+            1 => WIN_SCORE - ply,
+            -1 => -(WIN_SCORE - ply),
+            _ => 0,
+        };
+    }
+    if depth == 0 {
+        *horizon = true;
+        return 0;
+    }
+    let mut moves = G::Buffer::default();
+    state.legal_moves(&mut moves);
+    let mut best = -WIN_SCORE;
+    for &m in moves.iter() {
+        state.push(m);
+        let score = -negamax(state, depth - 1, ply + 1, -beta, -alpha, horizon);
+        state.pop(m);
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+/// Scores every legal move of `state` with an alpha-beta search of `depth` plies,
+/// in move generation order. The game must support [`pop`](Game::pop).
+pub fn score_moves<G: Game>(state: &G, depth: usize) -> Vec<(G::Move, Score)> {
+    assert!(
+        G::CAN_POP,
+        "alpha-beta verification needs a game that can unmake moves"
+    );
+    let mut state = state.clone();
+    let mut moves = G::Buffer::default();
+    state.legal_moves(&mut moves);
+    moves
+        .iter()
+        .map(|&m| {
+            state.push(m);
+            let score = -negamax(
+                &mut state,
+                depth.saturating_sub(1),
+                1,
+                -WIN_SCORE,
+                WIN_SCORE,
+                &mut false,
+            );
+            state.pop(m);
+            (m, Score(score))
+        })
+        .collect()
+}
+
+/// The result of `state` with best play, if an alpha-beta search of `depth` plies proves it:
+/// 1 if the first player wins, -1 if the second player wins, and 0 for a draw.
+/// Wins and losses are proven within `depth` plies, but draws only if the game cannot
+/// last longer than that. The game must support [`pop`](Game::pop).
+pub fn solve<G: Game>(state: &G, depth: usize) -> Option<i8> {
+    assert!(G::CAN_POP, "solving needs a game that can unmake moves");
+    let mut state = state.clone();
+    let mut horizon = false;
+    let score = negamax(&mut state, depth, 0, -WIN_SCORE, WIN_SCORE, &mut horizon);
+    (score != 0 || !horizon).then(|| i8::try_from(score.signum()).unwrap() * state.turn())
+}
+
+/// The result of every legal move of `state` with best play, in move generation order:
+/// 1 if it wins for the side making it, -1 if it loses, and 0 if it draws. `None` if an
+/// alpha-beta search of `depth` plies cannot prove them all. The game must support
+/// [`pop`](Game::pop).
+pub fn solve_moves<G: Game>(state: &G, depth: usize) -> Option<Vec<(G::Move, i32)>> {
+    assert!(G::CAN_POP, "solving needs a game that can unmake moves");
+    let mut state = state.clone();
+    let mut moves = G::Buffer::default();
+    state.legal_moves(&mut moves);
+    moves
+        .iter()
+        .map(|&m| {
+            state.push(m);
+            let mut horizon = false;
+            let score = -negamax(
+                &mut state,
+                depth.saturating_sub(1),
+                1,
+                -WIN_SCORE,
+                WIN_SCORE,
+                &mut horizon,
+            );
+            state.pop(m);
+            (score != 0 || !horizon).then_some((m, Score(score).result()))
+        })
+        .collect()
+}
+
+/// A position where the move chosen by MCTS scored worse than the best move found by alpha-beta.
+pub struct Disagreement<M> {
+    pub chosen: (M, Score),
+    pub best: (M, Score),
+}
+
+/// Checks `chosen` against the alpha-beta scores of every move of `state`, returning the
+/// disagreement if alpha-beta proves that a move with a better result exists.
+/// Choosing a slower win, or a slower loss, is not a disagreement.
+pub fn check<G: Game>(state: &G, chosen: G::Move, depth: usize) -> Option<Disagreement<G::Move>> {
+    let scores = score_moves(state, depth);
+    let chosen = *scores
+        .iter()
+        .find(|(m, _)| *m == chosen)
+        .expect("the chosen move is not legal");
+    let best = *scores.iter().max_by_key(|(_, score)| *score)?;
+    (chosen.1.result() < best.1.result()).then_some(Disagreement { chosen, best })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::tictactoe::{TicTacToe, TicTacToeMove};
+
+    #[test]
+    fn missed_wins_are_reported() {
+        // X on squares 1 and 2, O on 4 and 5, with X to move: 3 wins at once.
+        let mut state = TicTacToe::new();
+        for square in [0, 3, 1, 4] {
+            state.push(TicTacToeMove::new(square));
+        }
+        let disagreement = check(&state, TicTacToeMove::new(8), 4).unwrap();
+        assert_eq!(
+            disagreement.best,
+            (TicTacToeMove::new(2), Score(WIN_SCORE - 1))
+        );
+        assert_eq!(disagreement.best.1.to_string(), "win in 1 ply");
+        // playing elsewhere lets O win on 6.
+        assert_eq!(disagreement.chosen.1.to_string(), "loss in 2 plies");
+        assert!(check(&state, TicTacToeMove::new(2), 4).is_none());
+    }
+
+    #[test]
+    fn results_are_solved_within_the_depth() {
+        let mut state = TicTacToe::new();
+        for square in [0, 3, 1, 4] {
+            state.push(TicTacToeMove::new(square));
+        }
+        // X wins at once, so the first player wins.
+        assert_eq!(solve(&state, 1), Some(1));
+        // if X misses the win, O wins at once instead.
+        state.push(TicTacToeMove::new(8));
+        assert_eq!(solve(&state, 0), None);
+        assert_eq!(solve(&state, 1), Some(-1));
+        // the empty board is a draw, but only a search to the end of the game proves it.
+        assert_eq!(solve(&TicTacToe::new(), 8), None);
+        assert_eq!(solve(&TicTacToe::new(), 9), Some(0));
+        // with X on 1 and 2 and O on 4 and 5, only 3 saves X from losing.
+        let mut state = TicTacToe::new();
+        for square in [0, 3, 1, 4] {
+            state.push(TicTacToeMove::new(square));
+        }
+        let results = solve_moves(&state, 5).unwrap();
+        assert!(results
+            .iter()
+            .all(|&(m, result)| (result == 1) == (m == TicTacToeMove::new(2))));
+        assert!(solve_moves(&TicTacToe::new(), 4).is_none());
+    }
+}
@@ -0,0 +1,42 @@
+//! Ctrl+C during long-running commands, sent as SIGINT to the engine binary.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn interrupted_analysis_reports_and_saves() {
+    let dir = std::env::temp_dir().join(format!("iridium-interrupt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    fs::write(&config, "limit = \"infinite\"\n").unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_iridium-oxide"))
+        .arg("--config")
+        .arg(&config)
+        .args(["analyse", "connect4", "4", "--autosave", "tree.bin"])
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start the engine");
+    thread::sleep(Duration::from_millis(500));
+    let pid = libc::pid_t::try_from(child.id()).unwrap();
+    // SAFETY: kill has no memory-safety requirements.
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGINT) }, 0);
+    let output = child.wait_with_output().unwrap();
+    let saved = dir.join("tree.bin").exists();
+    fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "the engine exited with {}:\n{stdout}",
+        output.status
+    );
+    assert!(stdout.contains("best move"), "{stdout}");
+    assert!(saved, "the tree was not saved");
+}
@@ -0,0 +1,394 @@
+//! Scripted UCI/UGI sessions, run against the engine binary.
+
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The outcome of a session: every line the engine printed, and how long it ran for.
+struct Session {
+    lines: Vec<String>,
+    elapsed: Duration,
+}
+
+impl Session {
+    /// Starts `iridium-oxide uci` with the given config file contents, sends `script`,
+    /// then closes stdin and waits for the engine to exit.
+    fn run(config: &str, script: &str) -> Self {
+        static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+        // each session gets its own directory, as searches write a log file to the working directory.
+        let dir = std::env::temp_dir().join(format!(
+            "iridium-protocol-{}-{}",
+            std::process::id(),
+            SESSIONS.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path: PathBuf = dir.join("config.toml");
+        fs::write(&config_path, config).unwrap();
+
+        let start = Instant::now();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_iridium-oxide"))
+            .arg("--config")
+            .arg(&config_path)
+            .arg("uci")
+            .current_dir(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to start the engine");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        let elapsed = start.elapsed();
+        fs::remove_dir_all(&dir).ok();
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            output.status.success(),
+            "the engine exited with {}:\n{stdout}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        // search progress is redrawn in place with carriage returns.
+        let lines = stdout
+            .split(['\n', '\r'])
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        Self { lines, elapsed }
+    }
+
+    fn position(&self, line: &str) -> Option<usize> {
+        self.lines.iter().position(|l| l == line)
+    }
+
+    fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.lines
+            .iter()
+            .filter_map(move |line| line.strip_prefix(prefix))
+    }
+
+    fn best_moves(&self) -> Vec<&str> {
+        self.with_prefix("bestmove ").collect()
+    }
+
+    fn errors(&self) -> Vec<&str> {
+        self.with_prefix("info string ").collect()
+    }
+}
+
+const FAST: &str = "limit = \"rollouts:200\"\n";
+
+#[test]
+fn handshake() {
+    let session = Session::run(FAST, "uci\nisready\nquit\n");
+    assert!(
+        session.lines[0].starts_with("id name "),
+        "{:?}",
+        session.lines
+    );
+    let option = session.position("option name UCI_Chess960 type check default false");
+    let uciok = session.position("uciok").expect("no uciok");
+    let readyok = session.position("readyok").expect("no readyok");
+    assert!(option.unwrap() < uciok && uciok < readyok);
+    assert!(session.errors().is_empty(), "{:?}", session.errors());
+}
+
+#[test]
+fn go_plays_a_legal_move() {
+    let session = Session::run(
+        FAST,
+        "uci\nucinewgame\nposition startpos moves e2e4 e7e5\ngo\nquit\n",
+    );
+    let best_moves = session.best_moves();
+    assert_eq!(best_moves.len(), 1, "{:?}", session.lines);
+    let mut board = cozy_chess::Board::default();
+    for m in ["e2e4", "e7e5"] {
+        board.play(m.parse().unwrap());
+    }
+    let chosen = best_moves[0].parse::<cozy_chess::Move>().unwrap();
+    assert!(board.try_play(chosen).is_ok(), "illegal bestmove {chosen}");
+    assert!(session.errors().is_empty(), "{:?}", session.errors());
+}
+
+#[test]
+fn positions_from_fen() {
+    // white mates at once with Ra8#.
+    let session = Session::run(
+        FAST,
+        "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo\nquit\n",
+    );
+    assert_eq!(session.best_moves(), ["a1a8"]);
+}
+
+#[test]
+fn malformed_commands_are_reported_and_survived() {
+    let session = Session::run(
+        FAST,
+        "frobnicate\n\
+         position\n\
+         position startpos moves e2e5\n\
+         position fen not/a/fen\n\
+         setoption name Colour value blue\n\
+         setoption Game connect4\n\
+         setoption name UCI_Chess960 value maybe\n\
+         setoption name FPUReduction value -1\n\
+         position startpos moves e2e4 e7e5 g8f6\n\
+         isready\n\
+         position startpos\n\
+         go\n\
+         quit\n",
+    );
+    let errors = session.errors();
+    assert_eq!(errors.len(), 9, "{errors:?}");
+    assert_eq!(errors[0], "unknown command: frobnicate");
+    assert_eq!(errors[2], "illegal move: e2e5");
+    assert_eq!(errors[4], "unknown option: Colour");
+    assert_eq!(errors[7], "fpu must be a non-negative number: -1");
+    assert_eq!(errors[8], "illegal move: g8f6");
+    assert!(session.position("readyok").is_some());
+    assert_eq!(session.best_moves().len(), 1);
+}
+
+#[test]
+fn searching_a_finished_game_gives_the_null_move() {
+    // black is checkmated.
+    let session = Session::run(
+        FAST,
+        "position startpos moves f2f3 e7e5 g2g4 d8h4\nquery gameover\nquery result\ngo\nquit\n",
+    );
+    assert_eq!(session.best_moves(), ["0000"]);
+    assert_eq!(
+        session.with_prefix("response ").collect::<Vec<_>>(),
+        ["true", "p2win"]
+    );
+    assert_eq!(
+        session.errors(),
+        ["cannot search a position where the game is over"]
+    );
+}
+
+#[test]
+fn moves_after_the_end_of_the_game_are_refused() {
+    // the fifty-move rule ends the game, though the pieces can still move.
+    let session = Session::run(
+        FAST,
+        "position fen 8/8/8/4k3/8/8/8/4K2R w - - 99 80 moves h1h2 h2h3\n\
+         position fen 8/8/8/4k3/8/8/8/4K2R w - - 99 80 moves h1h2\n\
+         query gameover\n\
+         quit\n",
+    );
+    assert_eq!(session.errors(), ["the game is over before h2h3"]);
+    assert_eq!(
+        session.with_prefix("response ").collect::<Vec<_>>(),
+        ["true"]
+    );
+}
+
+#[test]
+fn rapid_stop() {
+    let session = Session::run(
+        FAST,
+        "position startpos\ngo\nstop\ngo\nstop\nstop\nisready\nquit\n",
+    );
+    // every go is answered exactly once, and a stop with no search running is ignored.
+    assert_eq!(session.best_moves().len(), 2, "{:?}", session.lines);
+    assert!(session.errors().is_empty(), "{:?}", session.errors());
+    let last_bestmove = session
+        .lines
+        .iter()
+        .rposition(|line| line.starts_with("bestmove"))
+        .unwrap();
+    assert!(last_bestmove < session.position("readyok").unwrap());
+}
+
+#[test]
+fn games_can_be_switched() {
+    let session = Session::run(
+        FAST,
+        "setoption name Game value connect4\n\
+         uci\n\
+         position startpos moves 4 4 4\n\
+         query p1turn\n\
+         go\n\
+         setoption name Game value tictactoe\n\
+         position startpos moves 1 2 4 5 7\n\
+         query result\n\
+         quit\n",
+    );
+    assert!(session
+        .position(
+            "option name Game type combo default connect4 var chess var antichess var connect4 var tictactoe var gomoku9 var gomoku13 var gomoku15 var gomoku19"
+        )
+        .is_some());
+    // connect4 has no Chess960 option.
+    assert!(!session
+        .lines
+        .iter()
+        .any(|line| line.contains("UCI_Chess960")));
+    let best_moves = session.best_moves();
+    assert_eq!(best_moves.len(), 1);
+    assert!(
+        matches!(best_moves[0].parse::<u8>(), Ok(1..=7)),
+        "{best_moves:?}"
+    );
+    assert_eq!(
+        session.with_prefix("response ").collect::<Vec<_>>(),
+        ["false", "p1win"]
+    );
+}
+
+#[test]
+fn antichess_is_a_uci_variant() {
+    let session = Session::run(
+        FAST,
+        "setoption name UCI_Variant value antichess\n\
+         uci\n\
+         position startpos moves e2e3 b7b5\n\
+         go\n\
+         setoption name UCI_Variant value crazyhouse\n\
+         quit\n",
+    );
+    assert!(session
+        .position("option name UCI_Variant type combo default antichess var chess var antichess")
+        .is_some());
+    // the capture is compulsory.
+    assert_eq!(session.best_moves(), ["f1b5"]);
+    assert_eq!(session.errors(), ["unknown variant: crazyhouse"]);
+}
+
+#[test]
+fn time_limits_are_kept() {
+    let session = Session::run("limit = \"time:300\"\n", "position startpos\ngo\nquit\n");
+    assert_eq!(session.best_moves().len(), 1);
+    assert!(
+        session.elapsed >= Duration::from_millis(300),
+        "searched for only {:?}",
+        session.elapsed
+    );
+    assert!(
+        session.elapsed < Duration::from_secs(10),
+        "searched for {:?}",
+        session.elapsed
+    );
+}
+
+#[test]
+fn commands_sent_during_a_search_are_run_after_it() {
+    let session = Session::run(
+        "limit = \"time:300\"\n",
+        "position startpos\n\
+         go\n\
+         position startpos moves e2e4\n\
+         go\n\
+         quit\n\
+         isready\n",
+    );
+    let best_moves = session.best_moves();
+    assert_eq!(best_moves.len(), 2, "{:?}", session.lines);
+    assert!(session.elapsed >= Duration::from_millis(600));
+    let mut board = cozy_chess::Board::default();
+    board.play("e2e4".parse().unwrap());
+    let reply = best_moves[1].parse::<cozy_chess::Move>().unwrap();
+    assert!(board.try_play(reply).is_ok(), "illegal bestmove {reply}");
+    // the isready after quit is never read.
+    assert!(session.position("readyok").is_none());
+}
+
+#[test]
+fn infinite_searches_run_until_stopped() {
+    let session = Session::run(
+        FAST,
+        "setoption name Game value connect4\n\
+         setoption name Autosave value tree.bin\n\
+         setoption name AutosaveMinutes value 0\n\
+         position startpos\n\
+         go infinite\n\
+         isready\n\
+         stop\n\
+         go infinite\n\
+         quit\n",
+    );
+    assert_eq!(session.errors().len(), 1, "{:?}", session.errors());
+    // the search answers isready without stopping, and quit stops it before exiting.
+    assert_eq!(session.best_moves().len(), 2, "{:?}", session.lines);
+    let first_bestmove = session
+        .lines
+        .iter()
+        .position(|line| line.starts_with("bestmove"))
+        .unwrap();
+    assert!(session.position("readyok").unwrap() < first_bestmove);
+}
+
+#[test]
+fn threads_search_together_and_stop_together() {
+    let session = Session::run(
+        "limit = \"rollouts:200\"\nthreads = 4\n",
+        "setoption name Game value connect4\n\
+         position startpos\n\
+         go\n\
+         go infinite\n\
+         isready\n\
+         stop\n\
+         quit\n",
+    );
+    assert!(session.errors().is_empty(), "{:?}", session.errors());
+    assert_eq!(session.best_moves().len(), 2, "{:?}", session.lines);
+    assert!(session.position("readyok").is_some());
+}
+
+#[test]
+fn quit_and_end_of_input_exit_promptly() {
+    // commands after quit are never read.
+    let session = Session::run(FAST, "uci\nquit\nisready\n");
+    assert!(session.position("readyok").is_none());
+    assert!(
+        session.elapsed < Duration::from_secs(5),
+        "took {:?}",
+        session.elapsed
+    );
+
+    let session = Session::run(FAST, "uci\nisready\n");
+    assert!(session.position("readyok").is_some());
+    assert!(
+        session.elapsed < Duration::from_secs(5),
+        "took {:?}",
+        session.elapsed
+    );
+}
+
+#[test]
+fn options_are_kept_when_switching_games() {
+    let session = Session::run(
+        FAST,
+        "setoption name CPScale value 1
+\
+         setoption name Game value connect4
+\
+         position startpos moves 4 4 4
+\
+         go
+\
+         quit
+",
+    );
+    let scores = session
+        .lines
+        .iter()
+        .filter_map(|line| line.split(" score cp ").nth(1))
+        .map(|rest| rest.split(' ').next().unwrap().parse::<i32>().unwrap())
+        .collect::<Vec<_>>();
+    // at a scale of one, even a certain result is worth no more than 16 centipawns.
+    assert!(!scores.is_empty(), "{:?}", session.lines);
+    assert!(scores.iter().all(|cp| cp.abs() <= 16), "{scores:?}");
+}